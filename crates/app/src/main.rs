@@ -1,5 +1,11 @@
 #![cfg_attr(all(target_os = "windows", not(debug_assertions)), windows_subsystem = "windows")]
 
+use std::path::PathBuf;
+
 fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args_os().skip(1);
+    if args.next().is_some_and(|arg| arg == ui::MUX_SERVER_FLAG) {
+        return ui::run_mux_server(args.next().map(PathBuf::from));
+    }
     ui::run()
 }
@@ -0,0 +1,235 @@
+use egui::text::LayoutJob;
+use egui::{Color32, FontId, Pos2, Rect, Response, Sense, Stroke, Ui, Vec2};
+use terminal_core::grid::TextGrid;
+use terminal_core::style::CellAttrs;
+
+use crate::images;
+use crate::text_renderer::TerminalPalette;
+
+/// Live cell-grid view used while a program owns the alternate screen.
+pub struct GridView<'a> {
+    grid: &'a TextGrid,
+    cursor: Option<(usize, usize)>,
+    selection: Option<GridSelection>,
+    palette: &'a TerminalPalette,
+    font_id: FontId,
+}
+
+/// A local text selection between two `(col, row)` cells, inclusive, in
+/// reading order.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct GridSelection {
+    pub anchor: (usize, usize),
+    pub head: (usize, usize),
+}
+
+impl GridSelection {
+    pub fn new(cell: (usize, usize)) -> Self {
+        Self {
+            anchor: cell,
+            head: cell,
+        }
+    }
+
+    pub fn contains(&self, col: usize, row: usize) -> bool {
+        let key = |(col, row): (usize, usize)| (row, col);
+        let (start, end) = if key(self.anchor) <= key(self.head) {
+            (self.anchor, self.head)
+        } else {
+            (self.head, self.anchor)
+        };
+        (key(start)..=key(end)).contains(&(row, col))
+    }
+
+    pub fn text(&self, grid: &TextGrid) -> String {
+        grid.selection_text(self.anchor, self.head)
+    }
+}
+
+pub struct GridViewResponse {
+    pub response: Response,
+    pub cell_size: Vec2,
+}
+
+impl GridViewResponse {
+    /// Maps a screen position to a zero-based `(col, row)` cell, clamped to
+    /// the grid.
+    pub fn cell_at(&self, pos: Pos2, grid: &TextGrid) -> (usize, usize) {
+        let rel = pos - self.response.rect.min;
+        let col = (rel.x / self.cell_size.x).floor().max(0.0) as usize;
+        let row = (rel.y / self.cell_size.y).floor().max(0.0) as usize;
+        (
+            col.min(grid.width().saturating_sub(1)),
+            row.min(grid.height().saturating_sub(1)),
+        )
+    }
+}
+
+pub fn cell_size(ui: &Ui, font_id: &FontId) -> Vec2 {
+    ui.fonts(|fonts| {
+        Vec2::new(
+            fonts.glyph_width(font_id, 'M').max(1.0),
+            fonts.row_height(font_id).max(1.0),
+        )
+    })
+}
+
+/// Number of whole `(cols, rows)` cells that fit in `available`.
+pub fn fit_grid(cell: Vec2, available: Vec2) -> (u16, u16) {
+    let fit = |space: f32, step: f32, min: u16| {
+        let count = (space / step).floor();
+        if count.is_finite() {
+            (count.max(0.0) as u16).max(min)
+        } else {
+            min
+        }
+    };
+    (fit(available.x, cell.x, 2), fit(available.y, cell.y, 1))
+}
+
+impl<'a> GridView<'a> {
+    pub fn new(grid: &'a TextGrid, palette: &'a TerminalPalette, font_id: FontId) -> Self {
+        Self {
+            grid,
+            cursor: None,
+            selection: None,
+            palette,
+            font_id,
+        }
+    }
+
+    pub fn cursor(mut self, cursor: Option<(usize, usize)>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    pub fn selection(mut self, selection: Option<GridSelection>) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn show(self, ui: &mut Ui) -> GridViewResponse {
+        let cell = cell_size(ui, &self.font_id);
+        let size = Vec2::new(
+            cell.x * self.grid.width() as f32,
+            cell.y * self.grid.height() as f32,
+        );
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, self.palette.background);
+        let selection_fill = ui.visuals().selection.bg_fill.gamma_multiply(0.6);
+
+        for (y, row) in self.grid.rows().iter().enumerate() {
+            let top = rect.min.y + y as f32 * cell.y;
+            let mut run = String::new();
+            let mut run_attrs = CellAttrs::default();
+            let mut run_start = 0;
+            let column = |x: usize| Pos2::new(rect.min.x + x as f32 * cell.x, top);
+
+            for (x, c) in row.cells().iter().enumerate() {
+                if c.is_continuation() {
+                    continue;
+                }
+                let cell_rect = Rect::from_min_size(
+                    column(x),
+                    Vec2::new(cell.x * f32::from(c.width.max(1)), cell.y),
+                );
+                let format = self.palette.text_format(&c.attrs, self.font_id.clone());
+                if format.background != Color32::TRANSPARENT {
+                    painter.rect_filled(cell_rect, 0.0, format.background);
+                }
+                if self.selection.is_some_and(|sel| sel.contains(x, y)) {
+                    painter.rect_filled(cell_rect, 0.0, selection_fill);
+                }
+
+                // Wide glyphs and multi-codepoint clusters are placed
+                // individually so proportional CJK or emoji fallback fonts
+                // cannot shift the rest of the row.
+                let standalone = c.is_wide() || c.combining.is_some();
+                let breaks_run = standalone || c.attrs != run_attrs;
+                if breaks_run && !run.is_empty() {
+                    self.paint_run(&painter, &run, run_attrs, column(run_start));
+                    run.clear();
+                }
+                if standalone {
+                    self.paint_run(&painter, &c.grapheme(), c.attrs, column(x));
+                    continue;
+                }
+                if run.is_empty() {
+                    run_start = x;
+                    run_attrs = c.attrs;
+                }
+                run.push(c.ch);
+            }
+            if !run.trim_end().is_empty() {
+                self.paint_run(&painter, &run, run_attrs, column(run_start));
+            }
+        }
+
+        for (y, row) in self.grid.rows().iter().enumerate() {
+            for (x, c) in row.cells().iter().enumerate() {
+                if let Some(placement) = &c.image {
+                    let origin = rect.min + Vec2::new(x as f32 * cell.x, y as f32 * cell.y);
+                    images::paint_placement(&painter, placement, origin, cell);
+                }
+            }
+        }
+
+        if let Some((x, y)) = self.cursor
+            && x < self.grid.width()
+            && y < self.grid.height()
+        {
+            let cursor_rect = Rect::from_min_size(
+                Pos2::new(
+                    rect.min.x + x as f32 * cell.x,
+                    rect.min.y + y as f32 * cell.y,
+                ),
+                cell,
+            );
+            let color = self.palette.foreground;
+            if response.has_focus() {
+                painter.rect_filled(cursor_rect, 0.0, color.gamma_multiply(0.6));
+            } else {
+                painter.rect_stroke(
+                    cursor_rect,
+                    0.0,
+                    Stroke::new(1.0, color),
+                    egui::StrokeKind::Inside,
+                );
+            }
+        }
+
+        GridViewResponse {
+            response,
+            cell_size: cell,
+        }
+    }
+
+    fn paint_run(&self, painter: &egui::Painter, text: &str, attrs: CellAttrs, pos: Pos2) {
+        if text.trim().is_empty() && !attrs.underline && !attrs.strikethrough {
+            return;
+        }
+        let mut format = self.palette.text_format(&attrs, self.font_id.clone());
+        // Backgrounds were already filled per cell.
+        format.background = Color32::TRANSPARENT;
+        let mut job = LayoutJob::single_section(text.to_owned(), format);
+        job.wrap.max_width = f32::INFINITY;
+        let galley = painter.layout_job(job);
+        painter.galley(pos, galley, Color32::PLACEHOLDER);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fit_grid;
+    use egui::Vec2;
+
+    #[test]
+    fn grid_fits_whole_cells_with_a_minimum() {
+        assert_eq!(
+            fit_grid(Vec2::new(8.0, 16.0), Vec2::new(803.0, 330.0)),
+            (100, 20)
+        );
+        assert_eq!(fit_grid(Vec2::new(8.0, 16.0), Vec2::new(-5.0, 0.0)), (2, 1));
+    }
+}
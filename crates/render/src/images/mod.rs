@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use egui::{
+    Color32, ColorImage, Context, Id, Painter, Pos2, Rect, TextureHandle, TextureId,
+    TextureOptions, Vec2,
+};
+use terminal_core::graphics::{ImagePlacement, InlineImage};
+
+// Textures of images that have not been painted for this long are released.
+const IDLE_SECS: f64 = 30.0;
+
+// Uploaded textures by image id, kept in egui's temporary memory.
+#[derive(Clone, Default)]
+struct ImageTextures {
+    entries: HashMap<u64, (TextureHandle, f64)>,
+}
+
+fn cache_id() -> Id {
+    Id::new("inline_image_textures")
+}
+
+/// Paints `placement` from `origin`, scaled to fit its cells with the aspect
+/// ratio kept.
+pub fn paint_placement(painter: &Painter, placement: &ImagePlacement, origin: Pos2, cell: Vec2) {
+    let image = &placement.image;
+    let area = Vec2::new(
+        cell.x * placement.columns as f32,
+        cell.y * placement.rows as f32,
+    );
+    let scale = (area.x / image.width as f32).min(area.y / image.height as f32);
+    let size = Vec2::new(image.width as f32 * scale, image.height as f32 * scale);
+    let texture = texture_id(painter.ctx(), image);
+    painter.image(
+        texture,
+        Rect::from_min_size(origin, size),
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+        Color32::WHITE,
+    );
+}
+
+/// Size of the area `paint_placement` may cover.
+pub fn placement_size(placement: &ImagePlacement, cell: Vec2) -> Vec2 {
+    Vec2::new(
+        cell.x * placement.columns as f32,
+        cell.y * placement.rows as f32,
+    )
+}
+
+/// Frees the textures of images that were not painted recently. Call once
+/// per frame.
+pub fn release_idle_textures(ctx: &Context) {
+    let now = ctx.input(|input| input.time);
+    let idle = ctx.data_mut(|data| {
+        let cache = data.get_temp_mut_or_default::<ImageTextures>(cache_id());
+        let ids: Vec<u64> = cache
+            .entries
+            .iter()
+            .filter(|(_, (_, used))| now - used > IDLE_SECS)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.iter()
+            .filter_map(|id| cache.entries.remove(id))
+            .collect::<Vec<_>>()
+    });
+    // Handles are dropped outside the memory lock.
+    drop(idle);
+}
+
+fn texture_id(ctx: &Context, image: &InlineImage) -> TextureId {
+    let now = ctx.input(|input| input.time);
+    let cached = ctx.data_mut(|data| {
+        let cache = data.get_temp_mut_or_default::<ImageTextures>(cache_id());
+        let (texture, used) = cache.entries.get_mut(&image.id)?;
+        *used = now;
+        Some(texture.id())
+    });
+    if let Some(id) = cached {
+        return id;
+    }
+
+    let pixels = ColorImage::from_rgba_unmultiplied(
+        [image.width as usize, image.height as usize],
+        &image.rgba,
+    );
+    let texture = ctx.load_texture(
+        format!("inline-image-{}", image.id),
+        pixels,
+        TextureOptions::LINEAR,
+    );
+    let id = texture.id();
+    ctx.data_mut(|data| {
+        data.get_temp_mut_or_default::<ImageTextures>(cache_id())
+            .entries
+            .insert(image.id, (texture, now));
+    });
+    id
+}
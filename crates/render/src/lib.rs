@@ -1,3 +1,6 @@
 pub mod atlas;
 pub mod font;
+pub mod grid_view;
+pub mod images;
+pub mod links;
 pub mod text_renderer;
@@ -0,0 +1,300 @@
+use std::ops::Range;
+
+use egui::text::LayoutJob;
+use egui::text_selection::LabelSelectionState;
+use egui::{CursorIcon, Galley, Label, Pos2, Stroke, Ui};
+use terminal_core::style::LinkRange;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LinkTarget {
+    /// An OSC 8 target or a detected `http(s)://` / `file://` URL.
+    Url(String),
+    /// A filesystem path token, optionally followed by `:line[:col]`.
+    Path {
+        path: String,
+        line: Option<u32>,
+        column: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DetectedLink {
+    pub range: Range<usize>,
+    pub target: LinkTarget,
+}
+
+const URL_SCHEMES: [&str; 3] = ["http://", "https://", "file://"];
+
+/// Finds the links in `text`. Explicit OSC 8 ranges come first; URLs and
+/// path tokens are detected in the remaining text.
+pub fn detect_links(text: &str, explicit: &[LinkRange]) -> Vec<DetectedLink> {
+    let mut links: Vec<DetectedLink> = explicit
+        .iter()
+        .filter(|link| {
+            link.start < link.end
+                && link.end <= text.len()
+                && text.is_char_boundary(link.start)
+                && text.is_char_boundary(link.end)
+        })
+        .map(|link| DetectedLink {
+            range: link.start..link.end,
+            target: LinkTarget::Url(link.uri.clone()),
+        })
+        .collect();
+
+    for (range, token) in tokens(text) {
+        let overlaps = links
+            .iter()
+            .any(|link| link.range.start < range.end && range.start < link.range.end);
+        if overlaps {
+            continue;
+        }
+        if let Some(target) = classify(token) {
+            links.push(DetectedLink { range, target });
+        }
+    }
+    links.sort_by_key(|link| link.range.start);
+    links
+}
+
+// Whitespace-separated tokens with surrounding quotes, brackets and trailing
+// sentence punctuation trimmed off.
+fn tokens(text: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    text.split_whitespace().filter_map(move |word| {
+        let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+        let lead = word.len()
+            - word
+                .trim_start_matches(['"', '\'', '(', '[', '{', '<', '`'])
+                .len();
+        let token = word[lead..].trim_end_matches([
+            '"', '\'', ')', ']', '}', '>', '`', '.', ',', ';', ':', '!', '?',
+        ]);
+        (!token.is_empty()).then(|| (offset + lead..offset + lead + token.len(), token))
+    })
+}
+
+fn classify(token: &str) -> Option<LinkTarget> {
+    for scheme in URL_SCHEMES {
+        if token.len() > scheme.len()
+            && token
+                .get(..scheme.len())
+                .is_some_and(|head| head.eq_ignore_ascii_case(scheme))
+        {
+            return Some(LinkTarget::Url(token.to_owned()));
+        }
+    }
+    if token.contains("://") {
+        return None;
+    }
+
+    let mut rest = token;
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        match rest.rsplit_once(':') {
+            Some((head, tail))
+                if !head.is_empty()
+                    && !tail.is_empty()
+                    && tail.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                numbers.push(tail.parse::<u32>().ok()?);
+                rest = head;
+            }
+            _ => break,
+        }
+    }
+    let (line, column) = match numbers.as_slice() {
+        [] => (None, None),
+        [line] => (Some(*line), None),
+        [column, line, ..] => (Some(*line), Some(*column)),
+    };
+
+    let has_separator = rest.contains(['/', '\\']);
+    let has_extension = rest
+        .rsplit_once('.')
+        .is_some_and(|(stem, ext)| !stem.is_empty() && ext.chars().all(char::is_alphanumeric));
+    let plausible = rest.chars().any(char::is_alphabetic)
+        && (has_separator || (line.is_some() && has_extension));
+    plausible.then(|| LinkTarget::Path {
+        path: rest.to_owned(),
+        line,
+        column,
+    })
+}
+
+/// A wrapped, selectable text line whose links are underlined on hover and
+/// open on Ctrl/Cmd+click.
+pub struct LinkLabel<'a> {
+    job: LayoutJob,
+    links: &'a [DetectedLink],
+}
+
+impl<'a> LinkLabel<'a> {
+    pub fn new(job: LayoutJob, links: &'a [DetectedLink]) -> Self {
+        Self { job, links }
+    }
+
+    /// Shows the line and returns the link that was Ctrl/Cmd+clicked, if any.
+    pub fn show(self, ui: &mut Ui) -> Option<&'a DetectedLink> {
+        if self.links.is_empty() {
+            ui.label(self.job);
+            return None;
+        }
+
+        let (pos, galley, response) = Label::new(self.job).layout_in_ui(ui);
+        let hovered = response
+            .hover_pos()
+            .and_then(|pointer| char_at(&galley, pos, pointer))
+            .and_then(|char_index| {
+                let text = galley.text();
+                let byte = text
+                    .char_indices()
+                    .nth(char_index)
+                    .map_or(text.len(), |(byte, _)| byte);
+                self.links.iter().find(|link| link.range.contains(&byte))
+            });
+
+        let galley = match hovered {
+            Some(link) => {
+                let mut job = (*galley.job).clone();
+                underline(&mut job, &link.range);
+                ui.fonts(|fonts| fonts.layout_job(job))
+            }
+            None => galley,
+        };
+        let open_modifier = ui.input(|input| input.modifiers.command);
+        if hovered.is_some() && open_modifier {
+            ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+        }
+
+        if ui.is_rect_visible(response.rect) {
+            let color = ui.style().visuals.text_color();
+            if ui.style().interaction.selectable_labels {
+                LabelSelectionState::label_text_selection(
+                    ui,
+                    &response,
+                    pos,
+                    galley,
+                    color,
+                    Stroke::NONE,
+                );
+            } else {
+                ui.painter().galley(pos, galley, color);
+            }
+        }
+
+        hovered.filter(|_| response.clicked() && open_modifier)
+    }
+}
+
+fn char_at(galley: &Galley, origin: Pos2, pointer: Pos2) -> Option<usize> {
+    let local = (pointer - origin).to_pos2();
+    let mut char_index = 0;
+    for row in &galley.rows {
+        if row.rect.contains(local) {
+            return row
+                .glyphs
+                .iter()
+                .position(|glyph| local.x < glyph.pos.x + glyph.advance_width)
+                .map(|glyph| char_index + glyph);
+        }
+        char_index += row.char_count_including_newline();
+    }
+    None
+}
+
+// Splits the sections overlapping `range` so only that span is underlined.
+fn underline(job: &mut LayoutJob, range: &Range<usize>) {
+    let sections = std::mem::take(&mut job.sections);
+    for section in sections {
+        let bytes = section.byte_range.clone();
+        let start = range.start.clamp(bytes.start, bytes.end);
+        let end = range.end.clamp(bytes.start, bytes.end);
+        if start >= end {
+            job.sections.push(section);
+            continue;
+        }
+        for (piece, underlined) in [
+            (bytes.start..start, false),
+            (start..end, true),
+            (end..bytes.end, false),
+        ] {
+            if piece.is_empty() {
+                continue;
+            }
+            let mut part = section.clone();
+            if piece.start != bytes.start {
+                part.leading_space = 0.0;
+            }
+            part.byte_range = piece;
+            if underlined {
+                part.format.underline = Stroke::new(1.0, part.format.color);
+            }
+            job.sections.push(part);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkTarget, detect_links};
+    use terminal_core::style::LinkRange;
+
+    #[test]
+    fn detects_urls_and_paths_with_positions() {
+        let text = "see (https://example.com/x), src/main.rs:12:5 and C:\\tmp\\a.log.";
+        let links = detect_links(text, &[]);
+        let found: Vec<(&str, &LinkTarget)> = links
+            .iter()
+            .map(|link| (&text[link.range.clone()], &link.target))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "https://example.com/x",
+                    &LinkTarget::Url("https://example.com/x".to_owned())
+                ),
+                (
+                    "src/main.rs:12:5",
+                    &LinkTarget::Path {
+                        path: "src/main.rs".to_owned(),
+                        line: Some(12),
+                        column: Some(5),
+                    }
+                ),
+                (
+                    "C:\\tmp\\a.log",
+                    &LinkTarget::Path {
+                        path: "C:\\tmp\\a.log".to_owned(),
+                        line: None,
+                        column: None,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_words_and_numbers_are_not_links() {
+        assert!(detect_links("error: 3/4 tests failed at 10:30", &[]).is_empty());
+        assert!(detect_links("ssh://host is not opened", &[]).is_empty());
+    }
+
+    #[test]
+    fn osc8_ranges_take_precedence() {
+        let text = "docs at https://a.example";
+        let explicit = [LinkRange {
+            start: 0,
+            end: 4,
+            uri: "https://docs.example".to_owned(),
+        }];
+        let links = detect_links(text, &explicit);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].range, 0..4);
+        assert_eq!(
+            links[0].target,
+            LinkTarget::Url("https://docs.example".to_owned())
+        );
+        assert_eq!(&text[links[1].range.clone()], "https://a.example");
+    }
+}
@@ -1,30 +1,232 @@
-use egui::{RichText, ScrollArea, Ui};
+use egui::text::{LayoutJob, TextFormat};
+use egui::{Color32, FontId, ScrollArea, Stroke, Ui};
+use terminal_core::emulator::ReportedColors;
+use terminal_core::style::{CellAttrs, Color, StyledLine, StyledRange, indexed_rgb};
+
+#[derive(Debug, Clone, Copy)]
+pub struct TerminalPalette {
+    pub foreground: Color32,
+    pub background: Color32,
+    pub ansi: [Color32; 16],
+}
+
+impl Default for TerminalPalette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TerminalPalette {
+    pub const DEFAULT: Self = Self {
+        foreground: Color32::from_rgb(212, 212, 212),
+        background: Color32::from_rgb(30, 30, 30),
+        ansi: [
+            Color32::from_rgb(0x1e, 0x1e, 0x1e),
+            Color32::from_rgb(0xcd, 0x31, 0x31),
+            Color32::from_rgb(0x0d, 0xbc, 0x79),
+            Color32::from_rgb(0xe5, 0xe5, 0x10),
+            Color32::from_rgb(0x24, 0x72, 0xc8),
+            Color32::from_rgb(0xbc, 0x3f, 0xbc),
+            Color32::from_rgb(0x11, 0xa8, 0xcd),
+            Color32::from_rgb(0xe5, 0xe5, 0xe5),
+            Color32::from_rgb(0x66, 0x66, 0x66),
+            Color32::from_rgb(0xf1, 0x4c, 0x4c),
+            Color32::from_rgb(0x23, 0xd1, 0x8b),
+            Color32::from_rgb(0xf5, 0xf5, 0x43),
+            Color32::from_rgb(0x3b, 0x8e, 0xea),
+            Color32::from_rgb(0xd6, 0x70, 0xd6),
+            Color32::from_rgb(0x29, 0xb8, 0xdb),
+            Color32::from_rgb(0xff, 0xff, 0xff),
+        ],
+    };
+
+    pub fn resolve(&self, color: Color, default: Color32) -> Color32 {
+        match color {
+            Color::Default => default,
+            Color::Indexed(index) if index < 16 => self.ansi[usize::from(index)],
+            Color::Indexed(index) => {
+                let (r, g, b) = indexed_rgb(index);
+                Color32::from_rgb(r, g, b)
+            }
+            Color::Rgb(r, g, b) => Color32::from_rgb(r, g, b),
+        }
+    }
+
+    pub fn reported_colors(&self) -> ReportedColors {
+        let rgb = |color: Color32| (color.r(), color.g(), color.b());
+        ReportedColors {
+            foreground: rgb(self.foreground),
+            background: rgb(self.background),
+        }
+    }
+
+    pub fn text_format(&self, attrs: &CellAttrs, font_id: FontId) -> TextFormat {
+        // Bold text in the base eight colours is shown with the bright
+        // variant, since the monospace font has no bold face.
+        let fg_color = match attrs.fg {
+            Color::Indexed(index) if attrs.bold && index < 8 => Color::Indexed(index + 8),
+            color => color,
+        };
+        let mut fg = self.resolve(fg_color, self.foreground);
+        let mut bg = self.resolve(attrs.bg, Color32::TRANSPARENT);
+        if attrs.inverse {
+            let inverse_bg = if bg == Color32::TRANSPARENT {
+                self.background
+            } else {
+                bg
+            };
+            bg = fg;
+            fg = inverse_bg;
+        }
+        if attrs.dim {
+            fg = fg.gamma_multiply(0.6);
+        }
+        if attrs.hidden {
+            fg = Color32::TRANSPARENT;
+        }
+
+        TextFormat {
+            font_id,
+            color: fg,
+            background: bg,
+            italics: attrs.italic,
+            underline: if attrs.underline {
+                Stroke::new(1.0, fg)
+            } else {
+                Stroke::NONE
+            },
+            strikethrough: if attrs.strikethrough {
+                Stroke::new(1.0, fg)
+            } else {
+                Stroke::NONE
+            },
+            ..TextFormat::default()
+        }
+    }
+
+    /// Builds a layout job for `text`, applying `ranges` on top of
+    /// `base_color`. Ranges that do not fall on char boundaries are ignored.
+    pub fn layout_line(
+        &self,
+        text: &str,
+        ranges: &[StyledRange],
+        font_id: FontId,
+        base_color: Color32,
+    ) -> LayoutJob {
+        let plain = TextFormat::simple(font_id.clone(), base_color);
+        let mut job = LayoutJob::default();
+        let mut cursor = 0;
+        for range in ranges {
+            let end = range.end.min(text.len());
+            if range.start < cursor
+                || range.start >= end
+                || !text.is_char_boundary(range.start)
+                || !text.is_char_boundary(end)
+            {
+                continue;
+            }
+            if range.start > cursor {
+                job.append(&text[cursor..range.start], 0.0, plain.clone());
+            }
+            let mut format = self.text_format(&range.attrs, font_id.clone());
+            if range.attrs.fg == Color::Default && !range.attrs.inverse && !range.attrs.hidden {
+                format.color = if range.attrs.dim {
+                    base_color.gamma_multiply(0.6)
+                } else {
+                    base_color
+                };
+            }
+            job.append(&text[range.start..end], 0.0, format);
+            cursor = end;
+        }
+        if cursor < text.len() || text.is_empty() {
+            job.append(&text[cursor..], 0.0, plain);
+        }
+        job
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct TerminalRenderData {
-    pub lines: Vec<String>,
+    pub lines: Vec<StyledLine>,
 }
 
 #[derive(Debug, Clone)]
 pub struct BasicGridRenderer {
     pub row_height: f32,
+    pub palette: TerminalPalette,
 }
 
 impl Default for BasicGridRenderer {
     fn default() -> Self {
-        Self { row_height: 18.0 }
+        Self {
+            row_height: 18.0,
+            palette: TerminalPalette::default(),
+        }
     }
 }
 
 impl BasicGridRenderer {
     pub fn render(&self, ui: &mut Ui, data: &TerminalRenderData) {
+        let font_id = FontId::monospace(self.row_height - 4.0);
         ScrollArea::vertical()
             .stick_to_bottom(true)
             .auto_shrink([false, false])
             .show(ui, |ui| {
                 for line in &data.lines {
-                    ui.label(RichText::new(line).monospace().size(self.row_height - 4.0));
+                    let job = self.palette.layout_line(
+                        &line.text,
+                        &line.ranges,
+                        font_id.clone(),
+                        self.palette.foreground,
+                    );
+                    ui.label(job);
                 }
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TerminalPalette;
+    use egui::{Color32, FontId};
+    use terminal_core::style::{CellAttrs, Color, StyledRange};
+
+    #[test]
+    fn layout_splits_text_into_styled_sections() {
+        let palette = TerminalPalette::default();
+        let ranges = [StyledRange {
+            start: 7,
+            end: 11,
+            attrs: CellAttrs {
+                fg: Color::Indexed(1),
+                underline: true,
+                ..CellAttrs::default()
+            },
+        }];
+        let job = palette.layout_line(
+            "error: boom ok",
+            &ranges,
+            FontId::monospace(12.0),
+            palette.foreground,
+        );
+
+        assert_eq!(job.sections.len(), 3);
+        assert_eq!(job.sections[1].byte_range, 7..11);
+        assert_eq!(job.sections[1].format.color, palette.ansi[1]);
+        assert_ne!(job.sections[1].format.underline.color, Color32::TRANSPARENT);
+        assert_eq!(job.sections[2].format.color, palette.foreground);
+    }
+
+    #[test]
+    fn inverse_swaps_foreground_and_background() {
+        let palette = TerminalPalette::default();
+        let attrs = CellAttrs {
+            inverse: true,
+            ..CellAttrs::default()
+        };
+        let format = palette.text_format(&attrs, FontId::monospace(12.0));
+        assert_eq!(format.color, palette.background);
+        assert_eq!(format.background, palette.foreground);
+    }
+}
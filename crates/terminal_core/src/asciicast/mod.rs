@@ -0,0 +1,532 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::TerminalEmulator;
+
+pub const FILE_EXTENSION: &str = "cast";
+
+// About 30 years; later timestamps cannot come from a real session.
+const MAX_EVENT_TIME: f64 = 1e9;
+// Events are written through a buffer that is flushed at most this often,
+// and when the recording stops.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// First line of an asciicast v2 file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Header {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            version: 2,
+            width,
+            height,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_secs()),
+            idle_time_limit: None,
+            title: None,
+            env: BTreeMap::from([
+                ("TERM".to_owned(), "xterm-256color".to_owned()),
+                (
+                    "SHELL".to_owned(),
+                    std::env::var("SHELL").unwrap_or_default(),
+                ),
+            ]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EventKind {
+    Output,
+    Input,
+    Resize,
+    Marker,
+}
+
+impl EventKind {
+    fn code(self) -> &'static str {
+        match self {
+            Self::Output => "o",
+            Self::Input => "i",
+            Self::Resize => "r",
+            Self::Marker => "m",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "o" => Some(Self::Output),
+            "i" => Some(Self::Input),
+            "r" => Some(Self::Resize),
+            "m" => Some(Self::Marker),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Seconds since the start of the recording.
+    pub time: f64,
+    pub kind: EventKind,
+    pub data: String,
+}
+
+/// Writes one session as asciicast v2, one JSON event per line.
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    started: Instant,
+    last_flush: Instant,
+    record_input: bool,
+    // Trailing bytes of a UTF-8 sequence split across reads.
+    output_tail: Vec<u8>,
+    input_tail: Vec<u8>,
+}
+
+impl Recorder {
+    pub fn new(
+        mut out: Box<dyn Write + Send>,
+        header: &Header,
+        record_input: bool,
+    ) -> Result<Self> {
+        let line = serde_json::to_string(header).context("failed to encode asciicast header")?;
+        writeln!(out, "{line}").context("failed writing asciicast header")?;
+        out.flush().context("failed flushing asciicast header")?;
+        Ok(Self {
+            out,
+            started: Instant::now(),
+            last_flush: Instant::now(),
+            record_input,
+            output_tail: Vec::new(),
+            input_tail: Vec::new(),
+        })
+    }
+
+    /// Creates the file, and its directory, and writes the header.
+    pub fn create(path: &Path, header: &Header, record_input: bool) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        Self::new(Box::new(BufWriter::new(file)), header, record_input)
+    }
+
+    pub fn output(&mut self, bytes: &[u8]) -> Result<()> {
+        let data = take_utf8(&mut self.output_tail, bytes);
+        self.write_event(EventKind::Output, &data)
+    }
+
+    /// Ignored unless the recorder was created to record input.
+    pub fn input(&mut self, bytes: &[u8]) -> Result<()> {
+        if !self.record_input {
+            return Ok(());
+        }
+        let data = take_utf8(&mut self.input_tail, bytes);
+        self.write_event(EventKind::Input, &data)
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        self.write_event(EventKind::Resize, &format!("{cols}x{rows}"))
+    }
+
+    fn write_event(&mut self, kind: EventKind, data: &str) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let time = (self.started.elapsed().as_secs_f64() * 1e6).round() / 1e6;
+        let line = serde_json::to_string(&(time, kind.code(), data))
+            .context("failed to encode asciicast event")?;
+        writeln!(self.out, "{line}").context("failed writing asciicast event")?;
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.out
+                .flush()
+                .context("failed flushing asciicast events")?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Writes out what is still buffered and closes the recording.
+    pub fn finish(mut self) -> Result<()> {
+        self.out.flush().context("failed flushing asciicast events")
+    }
+}
+
+/// Appends `bytes` to `tail` and returns the decodable prefix, keeping an
+/// incomplete trailing sequence for the next call. Invalid bytes become
+/// U+FFFD since asciicast data must be a JSON string.
+fn take_utf8(tail: &mut Vec<u8>, bytes: &[u8]) -> String {
+    tail.extend_from_slice(bytes);
+    let keep = match std::str::from_utf8(tail) {
+        Ok(_) => 0,
+        Err(err) if err.error_len().is_none() => tail.len() - err.valid_up_to(),
+        Err(_) => {
+            // Only the last sequence can be incomplete; everything before it
+            // is decoded lossily.
+            let start = tail.len().saturating_sub(3);
+            (start..tail.len())
+                .find(|&idx| {
+                    tail[idx] >= 0xc0
+                        && std::str::from_utf8(&tail[idx..])
+                            .err()
+                            .is_some_and(|err| err.valid_up_to() == 0 && err.error_len().is_none())
+                })
+                .map_or(0, |idx| tail.len() - idx)
+        }
+    };
+    let rest = tail.split_off(tail.len() - keep);
+    let text = String::from_utf8_lossy(tail).into_owned();
+    *tail = rest;
+    text
+}
+
+/// A parsed recording with idle gaps already capped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast {
+    pub header: Header,
+    pub events: Vec<Event>,
+}
+
+impl Cast {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Unknown event codes are skipped; out of order timestamps are clamped
+    /// so playback never goes backwards. Negative, non-finite or absurdly
+    /// large timestamps make the file invalid.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let (_, header_line) = lines
+            .next()
+            .ok_or_else(|| anyhow!("empty asciicast file"))?;
+        let header: Header =
+            serde_json::from_str(header_line).context("invalid asciicast header")?;
+        if header.version != 2 {
+            bail!("unsupported asciicast version {}", header.version);
+        }
+        if header.width == 0 || header.height == 0 {
+            bail!("asciicast header has an empty terminal size");
+        }
+        let idle_limit = header
+            .idle_time_limit
+            .filter(|limit| limit.is_finite() && *limit > 0.0);
+
+        let mut events = Vec::new();
+        let (mut last_raw, mut last_time) = (0.0_f64, 0.0_f64);
+        for (idx, line) in lines {
+            let (raw, code, data): (f64, String, String) = serde_json::from_str(line)
+                .with_context(|| format!("invalid asciicast event on line {}", idx + 1))?;
+            if !(0.0..=MAX_EVENT_TIME).contains(&raw) {
+                bail!("invalid asciicast timestamp {raw} on line {}", idx + 1);
+            }
+            let Some(kind) = EventKind::from_code(&code) else {
+                continue;
+            };
+            let mut gap = (raw - last_raw).max(0.0);
+            if let Some(limit) = idle_limit {
+                gap = gap.min(limit);
+            }
+            last_raw = raw.max(last_raw);
+            last_time += gap;
+            events.push(Event {
+                time: last_time,
+                kind,
+                data,
+            });
+        }
+        Ok(Self { header, events })
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |event| event.time)
+    }
+}
+
+/// Parses the `COLSxROWS` data of a resize event.
+pub fn parse_size(data: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = data.split_once('x')?;
+    let cols = cols.trim().parse().ok().filter(|cols| *cols > 0)?;
+    let rows = rows.trim().parse().ok().filter(|rows| *rows > 0)?;
+    Some((cols, rows))
+}
+
+/// Plays a [`Cast`] through an emulator of its own. Replies the emulator
+/// produces are dropped, so playback never talks to a shell.
+pub struct Player {
+    cast: Cast,
+    emulator: TerminalEmulator,
+    position: f64,
+    next: usize,
+    speed: f64,
+    paused: bool,
+}
+
+impl Player {
+    pub fn new(cast: Cast) -> Self {
+        let emulator = new_emulator(&cast.header);
+        Self {
+            cast,
+            emulator,
+            position: 0.0,
+            next: 0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.cast.header
+    }
+
+    pub fn emulator(&self) -> &TerminalEmulator {
+        &self.emulator
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.cast.duration()
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed.is_finite() && speed > 0.0 {
+            self.speed = speed;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.cast.events.len()
+    }
+
+    /// Playing a finished recording starts it over.
+    pub fn set_paused(&mut self, paused: bool) {
+        if !paused && self.is_finished() {
+            self.seek(0.0);
+        }
+        self.paused = paused;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    /// Moves playback forward by wall-clock `elapsed`, scaled by the speed.
+    /// Returns whether the screen may have changed.
+    pub fn advance(&mut self, elapsed: Duration) -> bool {
+        if self.paused {
+            return false;
+        }
+        let target = self.position + elapsed.as_secs_f64() * self.speed;
+        let changed = self.play_until(target);
+        self.position = target.min(self.duration());
+        if self.is_finished() {
+            self.paused = true;
+        }
+        changed
+    }
+
+    /// Jumps to `position` seconds. Going backwards replays the recording
+    /// from the start, since the emulator cannot be rewound.
+    pub fn seek(&mut self, position: f64) {
+        let position = position.clamp(0.0, self.duration());
+        if position < self.position {
+            self.emulator = new_emulator(&self.cast.header);
+            self.next = 0;
+        }
+        self.play_until(position);
+        self.position = position;
+    }
+
+    /// Time until the next event at the current speed, for scheduling a
+    /// repaint.
+    pub fn time_to_next_event(&self) -> Option<Duration> {
+        if self.paused {
+            return None;
+        }
+        let event = self.cast.events.get(self.next)?;
+        let wait = ((event.time - self.position) / self.speed).max(0.0);
+        Some(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+    }
+
+    fn play_until(&mut self, time: f64) -> bool {
+        let mut changed = false;
+        while let Some(event) = self.cast.events.get(self.next) {
+            if event.time > time {
+                break;
+            }
+            match event.kind {
+                EventKind::Output => {
+                    self.emulator.feed(event.data.as_bytes());
+                    changed = true;
+                }
+                EventKind::Resize => {
+                    if let Some((cols, rows)) = parse_size(&event.data) {
+                        self.emulator.resize(usize::from(cols), usize::from(rows));
+                        changed = true;
+                    }
+                }
+                EventKind::Input | EventKind::Marker => {}
+            }
+            self.next += 1;
+        }
+        changed
+    }
+}
+
+fn new_emulator(header: &Header) -> TerminalEmulator {
+    TerminalEmulator::new(usize::from(header.width), usize::from(header.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Cast, EventKind, Header, Player, Recorder, parse_size, take_utf8};
+
+    #[test]
+    fn recordings_roundtrip_through_the_file_format() {
+        let path = std::env::temp_dir().join(format!(
+            "ctyterm-asciicast-test-{}.cast",
+            std::process::id()
+        ));
+        let mut header = Header::new(80, 24);
+        header.title = Some("demo".to_owned());
+        let mut recorder = Recorder::create(&path, &header, false).expect("create recorder");
+        recorder.output(b"hello \xea\xb0").expect("write output");
+        recorder.output(b"\x80\r\n").expect("write output");
+        recorder.input(b"ls\r").expect("skip input");
+        recorder.resize(100, 30).expect("write resize");
+        recorder.finish().expect("flush recording");
+
+        let cast = Cast::load(&path).expect("load cast");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(cast.header, header);
+        let events = cast
+            .events
+            .iter()
+            .map(|event| (event.kind, event.data.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (EventKind::Output, "hello "),
+                (EventKind::Output, "가\r\n"),
+                (EventKind::Resize, "100x30"),
+            ]
+        );
+        assert!(
+            cast.events
+                .windows(2)
+                .all(|pair| pair[0].time <= pair[1].time)
+        );
+    }
+
+    #[test]
+    fn split_utf8_is_carried_and_invalid_bytes_are_replaced() {
+        let mut tail = Vec::new();
+        assert_eq!(take_utf8(&mut tail, b"a\xff\xea"), "a\u{fffd}");
+        assert_eq!(take_utf8(&mut tail, b"\xb0\x80"), "가");
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn parsing_caps_idle_gaps_and_skips_unknown_events() {
+        let cast = Cast::parse(
+            "{\"version\": 2, \"width\": 10, \"height\": 2, \"idle_time_limit\": 1.5}\n\
+             [0.5, \"o\", \"a\"]\n\
+             [10.0, \"x\", \"ignored\"]\n\
+             [12.0, \"o\", \"b\"]\n\
+             [12.25, \"r\", \"20x4\"]\n",
+        )
+        .expect("parse cast");
+        let times = cast
+            .events
+            .iter()
+            .map(|event| event.time)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![0.5, 2.0, 2.25]);
+        assert_eq!(parse_size("20x4"), Some((20, 4)));
+        assert_eq!(parse_size("0x4"), None);
+        assert!(Cast::parse("{\"version\": 1, \"width\": 10, \"height\": 2}").is_err());
+        for time in ["1e20", "-1", "1e400"] {
+            let text = format!(
+                "{{\"version\": 2, \"width\": 10, \"height\": 2}}\n[{time}, \"o\", \"x\"]\n"
+            );
+            assert!(Cast::parse(&text).is_err(), "{time} should be rejected");
+        }
+    }
+
+    #[test]
+    fn player_seeks_both_ways_and_honours_speed() {
+        let cast = Cast::parse(
+            "{\"version\": 2, \"width\": 10, \"height\": 2}\n\
+             [1.0, \"o\", \"one\"]\n\
+             [2.0, \"o\", \" two\"]\n\
+             [3.0, \"r\", \"12x3\"]\n",
+        )
+        .expect("parse cast");
+        let mut player = Player::new(cast);
+        let screen = |player: &Player| player.emulator().grid().row(0).text();
+
+        assert!(player.advance(Duration::from_millis(1500)));
+        assert_eq!(screen(&player).trim_end(), "one");
+        player.set_speed(2.0);
+        assert_eq!(
+            player.time_to_next_event(),
+            Some(Duration::from_millis(250))
+        );
+        player.advance(Duration::from_millis(500));
+        assert_eq!(screen(&player).trim_end(), "one two");
+
+        player.seek(1.2);
+        assert_eq!(screen(&player).trim_end(), "one");
+        player.seek(10.0);
+        assert_eq!(player.emulator().cols(), 12);
+        assert_eq!(player.position(), 3.0);
+
+        player.advance(Duration::from_millis(1));
+        assert!(player.is_paused() && player.is_finished());
+        player.toggle_pause();
+        assert_eq!(player.position(), 0.0);
+        assert_eq!(screen(&player).trim_end(), "");
+    }
+}
@@ -0,0 +1,148 @@
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// An OSC 52 clipboard access from the application.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ClipboardRequest {
+    Write {
+        selection: String,
+        text: String,
+    },
+    /// `OSC 52 ; Pc ; ?` — answered with [`osc52_reply`].
+    Read {
+        selection: String,
+        bell_terminated: bool,
+    },
+}
+
+/// Parses `OSC 52 ; Pc ; Pd`. An empty `Pc` means `s0`, as in xterm; payloads
+/// that are not valid base64 or UTF-8 are dropped.
+pub fn parse_osc52(params: &[&[u8]], bell_terminated: bool) -> Option<ClipboardRequest> {
+    let selection = std::str::from_utf8(params.get(1)?).ok()?;
+    let selection = if selection.is_empty() {
+        "s0".to_owned()
+    } else {
+        selection.to_owned()
+    };
+    let data = params.get(2)?;
+    if *data == b"?" {
+        return Some(ClipboardRequest::Read {
+            selection,
+            bell_terminated,
+        });
+    }
+    let text = String::from_utf8(decode_base64(data)?).ok()?;
+    Some(ClipboardRequest::Write { selection, text })
+}
+
+/// The answer to a [`ClipboardRequest::Read`].
+pub fn osc52_reply(selection: &str, text: &str, bell_terminated: bool) -> Vec<u8> {
+    let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+    format!(
+        "\x1b]52;{selection};{}{terminator}",
+        encode_base64(text.as_bytes())
+    )
+    .into_bytes()
+}
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (idx, byte)| {
+            acc | u32::from(*byte) << (16 - idx * 8)
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(char::from(
+                    BASE64_ALPHABET[(n >> (18 - idx * 6)) as usize & 0x3f],
+                ));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes standard base64. Padding is optional and ASCII whitespace is
+/// skipped.
+pub fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+    for &byte in input {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            _ if byte.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        if padding > 0 {
+            return None;
+        }
+        acc = acc << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    // A single leftover sextet cannot encode a byte.
+    (bits < 6 && padding <= 2).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClipboardRequest, decode_base64, encode_base64, osc52_reply, parse_osc52};
+
+    #[test]
+    fn base64_roundtrips_with_and_without_padding() {
+        for text in ["", "f", "fo", "foo", "foob", "fooba", "foobar", "한글 ✓"] {
+            let encoded = encode_base64(text.as_bytes());
+            assert_eq!(
+                decode_base64(encoded.as_bytes()).as_deref(),
+                Some(text.as_bytes())
+            );
+            let trimmed = encoded.trim_end_matches('=');
+            assert_eq!(
+                decode_base64(trimmed.as_bytes()).as_deref(),
+                Some(text.as_bytes())
+            );
+        }
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(decode_base64(b"Zm9v\nYg=="), Some(b"foob".to_vec()));
+        assert_eq!(decode_base64(b"Zm9v!"), None);
+        assert_eq!(decode_base64(b"Z"), None);
+    }
+
+    #[test]
+    fn osc52_writes_and_reads_are_parsed() {
+        assert_eq!(
+            parse_osc52(&[b"52", b"c", b"aGVsbG8="], false),
+            Some(ClipboardRequest::Write {
+                selection: "c".to_owned(),
+                text: "hello".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse_osc52(&[b"52", b"", b"?"], true),
+            Some(ClipboardRequest::Read {
+                selection: "s0".to_owned(),
+                bell_terminated: true,
+            })
+        );
+        assert_eq!(parse_osc52(&[b"52", b"c", b"***"], false), None);
+        assert_eq!(
+            osc52_reply("c", "hi", false),
+            b"\x1b]52;c;aGk=\x1b\\".to_vec()
+        );
+    }
+}
@@ -0,0 +1,2168 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clipboard::{ClipboardRequest, parse_osc52};
+use crate::graphics::{
+    ImagePlacement, InlineImage, KittyCommand, KittyImages, SixelDecoder, parse_iterm_file,
+    parse_kitty_command,
+};
+use crate::grid::{Cell, ReflowCursor, Row, TextGrid, char_width, styled_line};
+use crate::input::KITTY_SUPPORTED_FLAGS;
+use crate::shell_integration::{ShellMark, parse_osc7, parse_osc133};
+use crate::style::{CellAttrs, Color, StyledLine};
+use crate::tmux::{self, ControlParser};
+use crate::vt_parser::{Params, Perform, VtParser};
+
+const TAB_WIDTH: usize = 8;
+const MAX_TITLE_CHARS: usize = 256;
+const MAX_NOTIFICATION_CHARS: usize = 1024;
+// Entries kept per screen by the Kitty keyboard protocol; pushing past it
+// drops the oldest.
+const MAX_KEYBOARD_STACK: usize = 16;
+// Pixel size of one cell until the embedder reports the real one.
+const DEFAULT_CELL_PIXELS: (u32, u32) = (8, 16);
+// A synchronized update that is not ended within this time is shown as it
+// is, so a program that never sends the end marker cannot freeze its pane.
+const SYNC_UPDATE_TIMEOUT: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TerminalEvent {
+    Line(StyledLine),
+    ShellMark(ShellMark),
+    WorkingDirectory(String),
+    AlternateScreenEntered,
+    AlternateScreenExited {
+        last_screen: Vec<String>,
+    },
+    /// Bytes to write back to the PTY in answer to a terminal query.
+    Reply(Vec<u8>),
+    /// OSC 52; whether it is honoured is up to the embedder.
+    Clipboard(ClipboardRequest),
+    /// BEL outside of a control string.
+    Bell,
+    /// A desktop notification from OSC 9 (iTerm2) or OSC 777 `notify`.
+    Notification {
+        title: Option<String>,
+        body: String,
+    },
+    /// A line of the tmux control-mode protocol (`tmux -CC`).
+    Tmux(tmux::Notification),
+}
+
+/// Colours reported to applications through OSC 10/11 queries.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ReportedColors {
+    pub foreground: (u8, u8, u8),
+    pub background: (u8, u8, u8),
+}
+
+impl Default for ReportedColors {
+    fn default() -> Self {
+        Self {
+            foreground: (212, 212, 212),
+            background: (30, 30, 30),
+        }
+    }
+}
+
+/// Task progress reported through the ConEmu / Windows Terminal OSC 9;4
+/// sequence. Percentages are clamped to 0..=100.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Progress {
+    Normal(u8),
+    Error(u8),
+    Indeterminate,
+    Paused(u8),
+}
+
+impl Progress {
+    fn parse(state: &[u8], value: Option<&[u8]>) -> Option<Self> {
+        let percent = value
+            .and_then(|value| std::str::from_utf8(value).ok())
+            .and_then(|value| value.parse::<u32>().ok())
+            .map_or(0, |value| value.min(100) as u8);
+        match state {
+            b"1" => Some(Self::Normal(percent)),
+            b"2" => Some(Self::Error(percent)),
+            b"3" => Some(Self::Indeterminate),
+            b"4" => Some(Self::Paused(percent)),
+            _ => None,
+        }
+    }
+
+    pub fn percent(self) -> Option<u8> {
+        match self {
+            Self::Normal(percent) | Self::Error(percent) | Self::Paused(percent) => Some(percent),
+            Self::Indeterminate => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum MouseTracking {
+    #[default]
+    Off,
+    // DECSET 9: report button presses only, without modifiers.
+    X10,
+    // DECSET 1000: report button presses and releases.
+    Normal,
+    // DECSET 1002: also report motion while a button is held.
+    ButtonMotion,
+    // DECSET 1003: report all motion.
+    AnyMotion,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Cursor {
+    pub x: usize,
+    pub y: usize,
+    pub attrs: CellAttrs,
+    // Set after printing into the last column; the next printable character
+    // wraps to the following row first (DEC "last column flag").
+    pending_wrap: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+struct SavedCursor {
+    x: usize,
+    y: usize,
+    attrs: CellAttrs,
+    origin_mode: bool,
+    charsets: [Charset; 2],
+    active_charset: usize,
+}
+
+// The most recently printed cell. Code points that continue its grapheme
+// cluster are attached to it instead of taking a column of their own.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct ClusterTail {
+    x: usize,
+    y: usize,
+    last: char,
+    // The cell holds a lone regional indicator waiting for its flag partner.
+    open_flag: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+enum Charset {
+    #[default]
+    Ascii,
+    DecSpecialGraphics,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TerminalModes {
+    pub autowrap: bool,
+    pub origin: bool,
+    pub insert: bool,
+    pub linefeed_newline: bool,
+    pub cursor_visible: bool,
+    pub application_cursor_keys: bool,
+    pub application_keypad: bool,
+    pub bracketed_paste: bool,
+    pub mouse_tracking: MouseTracking,
+    pub sgr_mouse: bool,
+    pub urxvt_mouse: bool,
+    /// Active Kitty keyboard protocol flags; 0 means legacy encoding.
+    pub keyboard_flags: u8,
+    /// xterm modifyOtherKeys level (`CSI > 4 ; Pv m`).
+    pub modify_other_keys: u8,
+    /// DECSET 1004: report focus changes with `CSI I` / `CSI O`.
+    pub focus_reporting: bool,
+    /// DECSET 2026: the program is drawing a synchronized update.
+    pub synchronized_output: bool,
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        Self {
+            autowrap: true,
+            origin: false,
+            insert: false,
+            linefeed_newline: false,
+            cursor_visible: true,
+            application_cursor_keys: false,
+            application_keypad: false,
+            bracketed_paste: false,
+            mouse_tracking: MouseTracking::Off,
+            sgr_mouse: false,
+            urxvt_mouse: false,
+            keyboard_flags: 0,
+            modify_other_keys: 0,
+            focus_reporting: false,
+            synchronized_output: false,
+        }
+    }
+}
+
+// The screen as it was when a synchronized update began.
+struct FrozenScreen {
+    grid: TextGrid,
+    cursor: Option<(usize, usize)>,
+    since: Instant,
+}
+
+struct ScreenState {
+    primary: TextGrid,
+    alternate: TextGrid,
+    alternate_active: bool,
+    cursor: Cursor,
+    saved_primary: SavedCursor,
+    saved_alternate: SavedCursor,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    modes: TerminalModes,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    last_printed: Option<char>,
+    cluster_tail: Option<ClusterTail>,
+    tab_stops: Vec<bool>,
+    // Target of the open OSC 8 hyperlink, applied to every printed cell.
+    hyperlink: Option<Arc<str>>,
+    title: Option<String>,
+    progress: Option<Progress>,
+    // Cells of soft-wrapped rows that scrolled off the top of the primary
+    // screen before their logical line was terminated.
+    wrapped_prefix: Vec<Cell>,
+    reported_colors: ReportedColors,
+    cell_pixels: (u32, u32),
+    sixel: Option<SixelDecoder>,
+    tmux: Option<ControlParser>,
+    kitty_images: KittyImages,
+    // Kitty keyboard flag stacks of the primary and alternate screens.
+    keyboard_stacks: [Vec<u8>; 2],
+    frozen: Option<FrozenScreen>,
+    events: Vec<TerminalEvent>,
+}
+
+/// Cell-grid terminal emulator fed with raw PTY output.
+///
+/// Besides maintaining the primary and alternate screens, the emulator
+/// records every logical line terminated by a line feed on the primary screen
+/// so the block timeline can keep consuming completed output lines.
+pub struct TerminalEmulator {
+    parser: VtParser,
+    state: ScreenState,
+}
+
+impl TerminalEmulator {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let primary = TextGrid::new(cols, rows);
+        let alternate = TextGrid::new(cols, rows);
+        let height = primary.height();
+        let tab_stops = default_tab_stops(primary.width());
+        Self {
+            parser: VtParser::default(),
+            state: ScreenState {
+                primary,
+                alternate,
+                alternate_active: false,
+                cursor: Cursor::default(),
+                saved_primary: SavedCursor::default(),
+                saved_alternate: SavedCursor::default(),
+                scroll_top: 0,
+                scroll_bottom: height - 1,
+                modes: TerminalModes::default(),
+                charsets: [Charset::Ascii; 2],
+                active_charset: 0,
+                last_printed: None,
+                cluster_tail: None,
+                tab_stops,
+                hyperlink: None,
+                title: None,
+                progress: None,
+                wrapped_prefix: Vec::new(),
+                reported_colors: ReportedColors::default(),
+                cell_pixels: DEFAULT_CELL_PIXELS,
+                sixel: None,
+                tmux: None,
+                kitty_images: KittyImages::default(),
+                keyboard_stacks: Default::default(),
+                frozen: None,
+                events: Vec::new(),
+            },
+        }
+    }
+
+    /// Feeds PTY output and returns, in stream order, the logical lines
+    /// completed on the primary screen, shell integration reports and replies
+    /// to terminal queries.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<TerminalEvent> {
+        self.parser.advance(&mut self.state, chunk);
+        std::mem::take(&mut self.state.events)
+    }
+
+    /// Text of the not yet terminated logical line under the cursor.
+    pub fn current_line(&self) -> String {
+        if self.state.alternate_active {
+            return String::new();
+        }
+        styled_line(&self.state.logical_line_cells(self.state.cursor.y)).text
+    }
+
+    pub fn grid(&self) -> &TextGrid {
+        self.state.grid()
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.state.cursor
+    }
+
+    pub fn modes(&self) -> &TerminalModes {
+        &self.state.modes
+    }
+
+    /// Grid and visible cursor position to draw. During a synchronized update
+    /// this is the screen from before the update, until it ends or times out.
+    pub fn presented_screen(&self) -> (&TextGrid, Option<(usize, usize)>) {
+        match self.frozen_at(Instant::now()) {
+            Some(frozen) => (&frozen.grid, frozen.cursor),
+            None => (self.state.grid(), self.state.visible_cursor()),
+        }
+    }
+
+    /// When the synchronized update in progress stops holding the screen.
+    pub fn sync_deadline(&self) -> Option<Instant> {
+        self.frozen_at(Instant::now())
+            .map(|frozen| frozen.since + SYNC_UPDATE_TIMEOUT)
+    }
+
+    fn frozen_at(&self, now: Instant) -> Option<&FrozenScreen> {
+        self.state
+            .frozen
+            .as_ref()
+            .filter(|frozen| now.duration_since(frozen.since) < SYNC_UPDATE_TIMEOUT)
+    }
+
+    pub fn set_reported_colors(&mut self, colors: ReportedColors) {
+        self.state.reported_colors = colors;
+    }
+
+    /// Pixel size of a cell, used to size inline images and answer
+    /// `CSI 14/16 t`.
+    pub fn set_cell_pixel_size(&mut self, width: u32, height: u32) {
+        self.state.cell_pixels = (width.max(1), height.max(1));
+    }
+
+    /// Title set through OSC 0/1/2, if the program set a non-empty one.
+    pub fn title(&self) -> Option<&str> {
+        self.state.title.as_deref()
+    }
+
+    pub fn progress(&self) -> Option<Progress> {
+        self.state.progress
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.state.alternate_active
+    }
+
+    pub fn cols(&self) -> usize {
+        self.state.primary.width()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.state.primary.height()
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let state = &mut self.state;
+        // The primary screen is rewrapped; full-screen programs redraw the
+        // alternate screen themselves after SIGWINCH.
+        if state.alternate_active {
+            let saved = &mut state.saved_primary;
+            let cursor = ReflowCursor {
+                x: saved.x,
+                y: saved.y,
+                pending_wrap: false,
+            };
+            let cursor = state
+                .primary
+                .reflow(cols, rows, cursor, &mut state.wrapped_prefix);
+            (saved.x, saved.y) = (cursor.x, cursor.y);
+            state.alternate.resize(cols, rows);
+            state.cursor.x = state.cursor.x.min(state.alternate.width() - 1);
+            state.cursor.y = state.cursor.y.min(state.alternate.height() - 1);
+            state.cursor.pending_wrap = false;
+        } else {
+            let cursor = ReflowCursor {
+                x: state.cursor.x,
+                y: state.cursor.y,
+                pending_wrap: state.cursor.pending_wrap,
+            };
+            let cursor = state
+                .primary
+                .reflow(cols, rows, cursor, &mut state.wrapped_prefix);
+            state.cursor.x = cursor.x;
+            state.cursor.y = cursor.y;
+            state.cursor.pending_wrap = cursor.pending_wrap;
+            state.alternate.resize(cols, rows);
+        }
+        state.scroll_top = 0;
+        state.scroll_bottom = state.primary.height() - 1;
+        state.cluster_tail = None;
+        // A held screen of the old size is of no use; the program redraws.
+        state.frozen = None;
+        // New columns get the default stops; existing ones keep HTS/TBC edits.
+        let width = state.primary.width();
+        let old_width = state.tab_stops.len();
+        state.tab_stops.truncate(width);
+        state
+            .tab_stops
+            .extend((old_width..width).map(|x| x % TAB_WIDTH == 0 && x > 0));
+    }
+}
+
+fn default_tab_stops(width: usize) -> Vec<bool> {
+    (0..width).map(|x| x % TAB_WIDTH == 0 && x > 0).collect()
+}
+
+fn is_regional_indicator(ch: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&ch)
+}
+
+fn is_emoji_modifier(ch: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&ch)
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+impl ScreenState {
+    fn grid(&self) -> &TextGrid {
+        if self.alternate_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    fn grid_mut(&mut self) -> &mut TextGrid {
+        if self.alternate_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.primary.width()
+    }
+
+    fn height(&self) -> usize {
+        self.primary.height()
+    }
+
+    // Erased cells take the current background colour, like xterm.
+    fn blank_attrs(&self) -> CellAttrs {
+        CellAttrs {
+            bg: self.cursor.attrs.bg,
+            ..CellAttrs::default()
+        }
+    }
+
+    fn logical_line_cells(&self, y: usize) -> Vec<Cell> {
+        let grid = &self.primary;
+        let mut start = y;
+        while start > 0 && grid.row(start - 1).wrapped {
+            start -= 1;
+        }
+        let mut cells = if start == 0 {
+            self.wrapped_prefix.clone()
+        } else {
+            Vec::new()
+        };
+        for row_idx in start..=y {
+            cells.extend_from_slice(grid.row(row_idx).cells());
+        }
+        cells
+    }
+
+    fn commit_current_line(&mut self) {
+        if self.alternate_active {
+            return;
+        }
+        let line = styled_line(&self.logical_line_cells(self.cursor.y));
+        self.wrapped_prefix.clear();
+        self.events.push(TerminalEvent::Line(line));
+    }
+
+    fn keep_scrolled_rows(&mut self, rows: Vec<Row>) {
+        if self.alternate_active {
+            return;
+        }
+        for row in rows {
+            if row.wrapped {
+                self.wrapped_prefix.extend_from_slice(row.cells());
+            } else {
+                self.wrapped_prefix.clear();
+            }
+        }
+    }
+
+    fn scroll_up(&mut self, count: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let attrs = self.blank_attrs();
+        let removed = self.grid_mut().scroll_up(top, bottom, count, attrs);
+        if top == 0 {
+            self.keep_scrolled_rows(removed);
+        }
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let attrs = self.blank_attrs();
+        self.grid_mut().scroll_down(top, bottom, count, attrs);
+    }
+
+    fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.y == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.y + 1 < self.height() {
+            self.cursor.y += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.y == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.y > 0 {
+            self.cursor.y -= 1;
+        }
+    }
+
+    fn carriage_return(&mut self) {
+        self.cursor.x = 0;
+        self.cursor.pending_wrap = false;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor.pending_wrap {
+            self.cursor.pending_wrap = false;
+        } else if self.cursor.x > 0 {
+            self.cursor.x -= 1;
+        }
+    }
+
+    fn tab_forward(&mut self, count: usize) {
+        let last = self.width() - 1;
+        for _ in 0..count {
+            let from = self.cursor.x + 1;
+            self.cursor.x = (from..last)
+                .find(|&x| self.tab_stops.get(x).copied().unwrap_or(false))
+                .unwrap_or(last);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn tab_backward(&mut self, count: usize) {
+        for _ in 0..count {
+            self.cursor.x = (1..self.cursor.x)
+                .rev()
+                .find(|&x| self.tab_stops.get(x).copied().unwrap_or(false))
+                .unwrap_or(0);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn clear_tab_stops(&mut self, mode: u16) {
+        match mode {
+            0 => {
+                if let Some(stop) = self.tab_stops.get_mut(self.cursor.x) {
+                    *stop = false;
+                }
+            }
+            3 => self.tab_stops.fill(false),
+            _ => {}
+        }
+    }
+
+    /// Attaches `ch` to the previous cell when it continues that cell's
+    /// grapheme cluster: zero-width marks, ZWJ sequences, emoji modifiers and
+    /// the second half of a regional indicator flag.
+    fn join_cluster(&mut self, ch: char, width: usize) -> bool {
+        let Some(tail) = self.cluster_tail else {
+            return false;
+        };
+        let joins = width == 0
+            || tail.last == ZERO_WIDTH_JOINER
+            || is_emoji_modifier(ch)
+            || (tail.open_flag && is_regional_indicator(ch));
+        if !joins {
+            return false;
+        }
+        self.grid_mut().row_mut(tail.y).attach(tail.x, ch);
+        self.cluster_tail = Some(ClusterTail {
+            last: ch,
+            open_flag: false,
+            ..tail
+        });
+        true
+    }
+
+    fn print_char(&mut self, ch: char) {
+        let ch = self.translate_charset(ch);
+        let width = char_width(ch);
+        if self.join_cluster(ch, width) {
+            return;
+        }
+        if width == 0 {
+            return;
+        }
+
+        let cols = self.width();
+        if self.cursor.pending_wrap {
+            if self.modes.autowrap {
+                let y = self.cursor.y;
+                self.grid_mut().row_mut(y).wrapped = true;
+                self.carriage_return();
+                self.linefeed();
+            } else {
+                self.cursor.pending_wrap = false;
+            }
+        }
+
+        if width == 2 && self.cursor.x + 1 >= cols {
+            if self.modes.autowrap && cols > 1 {
+                let (x, y) = (self.cursor.x, self.cursor.y);
+                let attrs = self.cursor.attrs;
+                let row = self.grid_mut().row_mut(y);
+                row.erase(x, x + 1, attrs);
+                row.wrapped = true;
+                self.carriage_return();
+                self.linefeed();
+            } else {
+                return;
+            }
+        }
+
+        let (x, y, attrs) = (self.cursor.x, self.cursor.y, self.cursor.attrs);
+        let insert = self.modes.insert;
+        let link = self.hyperlink.clone();
+        let row = self.grid_mut().row_mut(y);
+        if insert {
+            row.insert_blank(x, width, attrs);
+        }
+        row.put(x, ch, width, attrs);
+        if link.is_some() {
+            row.set_link(x, link);
+        }
+        self.last_printed = Some(ch);
+        self.cluster_tail = Some(ClusterTail {
+            x,
+            y,
+            last: ch,
+            open_flag: is_regional_indicator(ch),
+        });
+
+        if x + width >= cols {
+            self.cursor.x = cols - 1;
+            self.cursor.pending_wrap = true;
+        } else {
+            self.cursor.x = x + width;
+        }
+    }
+
+    fn translate_charset(&self, ch: char) -> char {
+        if self.charsets[self.active_charset] != Charset::DecSpecialGraphics {
+            return ch;
+        }
+        match ch {
+            '`' => '◆',
+            'a' => '▒',
+            'f' => '°',
+            'g' => '±',
+            'j' => '┘',
+            'k' => '┐',
+            'l' => '┌',
+            'm' => '└',
+            'n' => '┼',
+            'o' => '⎺',
+            'p' => '⎻',
+            'q' => '─',
+            'r' => '⎼',
+            's' => '⎽',
+            't' => '├',
+            'u' => '┤',
+            'v' => '┴',
+            'w' => '┬',
+            'x' => '│',
+            'y' => '≤',
+            'z' => '≥',
+            '{' => 'π',
+            '|' => '≠',
+            '}' => '£',
+            '~' => '·',
+            _ => ch,
+        }
+    }
+
+    fn goto(&mut self, x: usize, y: usize) {
+        let (min_y, max_y) = if self.modes.origin {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.height() - 1)
+        };
+        let y = if self.modes.origin { y + min_y } else { y };
+        self.cursor.x = x.min(self.width() - 1);
+        self.cursor.y = y.clamp(min_y, max_y);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn goto_row(&mut self, y: usize) {
+        let x = self.cursor.x;
+        self.goto(x, y);
+    }
+
+    fn move_up(&mut self, count: usize) {
+        let top = if self.cursor.y >= self.scroll_top {
+            self.scroll_top
+        } else {
+            0
+        };
+        self.cursor.y = self.cursor.y.saturating_sub(count).max(top);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_down(&mut self, count: usize) {
+        let bottom = if self.cursor.y <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.height() - 1
+        };
+        self.cursor.y = (self.cursor.y + count).min(bottom);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_forward(&mut self, count: usize) {
+        self.cursor.x = (self.cursor.x + count).min(self.width() - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_backward(&mut self, count: usize) {
+        self.cursor.x = self.cursor.x.saturating_sub(count);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let attrs = self.blank_attrs();
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        let width = self.width();
+        let height = self.height();
+        let grid = self.grid_mut();
+        match mode {
+            0 => {
+                let row = grid.row_mut(y);
+                row.erase(x, width, attrs);
+                row.wrapped = false;
+                for row_idx in y + 1..height {
+                    grid.row_mut(row_idx).clear(attrs);
+                }
+            }
+            1 => {
+                for row_idx in 0..y {
+                    grid.row_mut(row_idx).clear(attrs);
+                }
+                grid.row_mut(y).erase(0, x + 1, attrs);
+            }
+            2 => grid.clear(attrs),
+            3 if !self.alternate_active => self.wrapped_prefix.clear(),
+            _ => {}
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let attrs = self.blank_attrs();
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        let width = self.width();
+        let row = self.grid_mut().row_mut(y);
+        match mode {
+            0 => {
+                row.erase(x, width, attrs);
+                row.wrapped = false;
+            }
+            1 => row.erase(0, x + 1, attrs),
+            2 => row.clear(attrs),
+            _ => {}
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if self.cursor.y < self.scroll_top || self.cursor.y > self.scroll_bottom {
+            return;
+        }
+        let (top, bottom) = (self.cursor.y, self.scroll_bottom);
+        let attrs = self.blank_attrs();
+        self.grid_mut().scroll_down(top, bottom, count, attrs);
+        self.cursor.x = 0;
+        self.cursor.pending_wrap = false;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.cursor.y < self.scroll_top || self.cursor.y > self.scroll_bottom {
+            return;
+        }
+        let (top, bottom) = (self.cursor.y, self.scroll_bottom);
+        let attrs = self.blank_attrs();
+        self.grid_mut().scroll_up(top, bottom, count, attrs);
+        self.cursor.x = 0;
+        self.cursor.pending_wrap = false;
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let height = self.height();
+        let bottom = if bottom == 0 {
+            height
+        } else {
+            bottom.min(height)
+        };
+        let top = top.max(1);
+        if top >= bottom {
+            return;
+        }
+        self.scroll_top = top - 1;
+        self.scroll_bottom = bottom - 1;
+        self.goto(0, 0);
+    }
+
+    fn save_cursor(&mut self) {
+        let saved = SavedCursor {
+            x: self.cursor.x,
+            y: self.cursor.y,
+            attrs: self.cursor.attrs,
+            origin_mode: self.modes.origin,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        };
+        if self.alternate_active {
+            self.saved_alternate = saved;
+        } else {
+            self.saved_primary = saved;
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = if self.alternate_active {
+            self.saved_alternate
+        } else {
+            self.saved_primary
+        };
+        self.cursor.x = saved.x.min(self.width() - 1);
+        self.cursor.y = saved.y.min(self.height() - 1);
+        self.cursor.attrs = saved.attrs;
+        self.cursor.pending_wrap = false;
+        self.modes.origin = saved.origin_mode;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+    }
+
+    fn enter_alternate_screen(&mut self, save_cursor: bool, clear: bool) {
+        if self.alternate_active {
+            return;
+        }
+        if save_cursor {
+            self.save_cursor();
+        }
+        self.alternate_active = true;
+        self.sync_keyboard_flags();
+        if clear {
+            let attrs = self.blank_attrs();
+            self.alternate.clear(attrs);
+        }
+        self.cursor.pending_wrap = false;
+        self.events.push(TerminalEvent::AlternateScreenEntered);
+    }
+
+    fn leave_alternate_screen(&mut self, restore_cursor: bool) {
+        if !self.alternate_active {
+            return;
+        }
+        self.alternate_active = false;
+        self.sync_keyboard_flags();
+        if restore_cursor {
+            self.restore_cursor();
+        }
+        self.cursor.pending_wrap = false;
+        self.events.push(TerminalEvent::AlternateScreenExited {
+            last_screen: self.alternate.lines(),
+        });
+    }
+
+    fn reset(&mut self) {
+        self.leave_alternate_screen(false);
+        let (cols, rows) = (self.width(), self.height());
+        self.primary = TextGrid::new(cols, rows);
+        self.alternate = TextGrid::new(cols, rows);
+        self.alternate_active = false;
+        self.cursor = Cursor::default();
+        self.saved_primary = SavedCursor::default();
+        self.saved_alternate = SavedCursor::default();
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.modes = TerminalModes::default();
+        self.charsets = [Charset::Ascii; 2];
+        self.active_charset = 0;
+        self.last_printed = None;
+        self.cluster_tail = None;
+        self.tab_stops = default_tab_stops(cols);
+        self.hyperlink = None;
+        self.title = None;
+        self.progress = None;
+        self.wrapped_prefix.clear();
+        self.sixel = None;
+        self.kitty_images.clear();
+        self.keyboard_stacks = Default::default();
+        self.frozen = None;
+    }
+
+    fn visible_cursor(&self) -> Option<(usize, usize)> {
+        self.modes
+            .cursor_visible
+            .then_some((self.cursor.x, self.cursor.y))
+    }
+
+    fn keyboard_stack(&mut self) -> &mut Vec<u8> {
+        &mut self.keyboard_stacks[usize::from(self.alternate_active)]
+    }
+
+    fn sync_keyboard_flags(&mut self) {
+        self.modes.keyboard_flags = self.keyboard_stack().last().copied().unwrap_or(0);
+    }
+
+    // CSI > u pushes, CSI < u pops, CSI = u updates the top entry and CSI ? u
+    // reports it.
+    fn kitty_keyboard(&mut self, marker: u8, params: &Params) {
+        let flags = params.get(0).unwrap_or(0) as u8 & KITTY_SUPPORTED_FLAGS;
+        match marker {
+            b'>' => {
+                let stack = self.keyboard_stack();
+                if stack.len() >= MAX_KEYBOARD_STACK {
+                    stack.remove(0);
+                }
+                stack.push(flags);
+            }
+            b'<' => {
+                let count = usize::from(params.get_or(0, 1).max(1));
+                let stack = self.keyboard_stack();
+                stack.truncate(stack.len().saturating_sub(count));
+            }
+            b'=' => {
+                let current = self.modes.keyboard_flags;
+                let updated = match params.get_or(1, 1) {
+                    1 => flags,
+                    2 => current | flags,
+                    3 => current & !flags,
+                    _ => return,
+                };
+                let stack = self.keyboard_stack();
+                match stack.last_mut() {
+                    Some(top) => *top = updated,
+                    None => stack.push(updated),
+                }
+            }
+            b'?' => {
+                let current = self.modes.keyboard_flags;
+                self.reply(format!("\x1b[?{current}u"));
+                return;
+            }
+            _ => return,
+        }
+        self.sync_keyboard_flags();
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            1 => self.modes.application_cursor_keys = enabled,
+            6 => {
+                self.modes.origin = enabled;
+                self.goto(0, 0);
+            }
+            7 => self.modes.autowrap = enabled,
+            25 => self.modes.cursor_visible = enabled,
+            47 | 1047 => {
+                if enabled {
+                    self.enter_alternate_screen(false, mode == 1047);
+                } else {
+                    self.leave_alternate_screen(false);
+                    if mode == 1047 {
+                        let attrs = self.blank_attrs();
+                        self.alternate.clear(attrs);
+                    }
+                }
+            }
+            1048 => {
+                if enabled {
+                    self.save_cursor();
+                } else {
+                    self.restore_cursor();
+                }
+            }
+            1049 => {
+                if enabled {
+                    self.enter_alternate_screen(true, true);
+                } else {
+                    self.leave_alternate_screen(true);
+                }
+            }
+            9 | 1000 | 1002 | 1003 => {
+                self.modes.mouse_tracking = match (enabled, mode) {
+                    (false, _) => MouseTracking::Off,
+                    (true, 9) => MouseTracking::X10,
+                    (true, 1000) => MouseTracking::Normal,
+                    (true, 1002) => MouseTracking::ButtonMotion,
+                    _ => MouseTracking::AnyMotion,
+                };
+            }
+            1006 => self.modes.sgr_mouse = enabled,
+            1015 => self.modes.urxvt_mouse = enabled,
+            1004 => self.modes.focus_reporting = enabled,
+            2004 => self.modes.bracketed_paste = enabled,
+            2026 => {
+                self.modes.synchronized_output = enabled;
+                if !enabled {
+                    self.frozen = None;
+                } else if self.frozen.is_none() {
+                    self.frozen = Some(FrozenScreen {
+                        grid: self.grid().clone(),
+                        cursor: self.visible_cursor(),
+                        since: Instant::now(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The image covers the cells right and below the cursor. The cursor ends
+    // on its last row, after it unless `advance` is off; the rows it passes
+    // are committed like text lines.
+    fn place_image(&mut self, placement: ImagePlacement, advance: bool) {
+        self.cursor.pending_wrap = false;
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        let (columns, rows) = (placement.columns, placement.rows);
+        self.grid_mut()
+            .row_mut(y)
+            .set_image(x, Some(Arc::new(placement)));
+        // The anchor line carries the image; the rows it covers are left
+        // blank on screen but not recorded as output, since the block view
+        // reserves the image's height below its line.
+        if rows > 1 {
+            self.commit_current_line();
+        }
+        for _ in 1..rows {
+            self.linefeed();
+        }
+        self.cursor.x = if advance {
+            (x + columns).min(self.width() - 1)
+        } else {
+            x
+        };
+    }
+
+    fn iterm_image(&mut self, params: &[&[u8]]) {
+        let Some(file) = parse_iterm_file(&params[1..].join(&b';')) else {
+            return;
+        };
+        if !file.inline {
+            return;
+        }
+        let Some(image) = InlineImage::decode(&file.data) else {
+            return;
+        };
+        let (cell_w, cell_h) = self.cell_pixels;
+        let columns = file.width.cells(self.width(), cell_w);
+        let rows = file.height.cells(self.height(), cell_h);
+        let placement = ImagePlacement::fit(Arc::new(image), columns, rows, self.cell_pixels);
+        self.place_image(placement, true);
+    }
+
+    fn kitty_graphics(&mut self, command: KittyCommand, payload: &[u8]) {
+        let (command, payload) = match self.kitty_images.receive(command, payload) {
+            None => return,
+            Some(Ok(received)) => received,
+            Some(Err(command)) => {
+                self.kitty_reply(&command, Err("EFBIG:upload too large"));
+                return;
+            }
+        };
+        let result = match command.action {
+            b't' | b'T' | b'q' => command.decode_image(&payload).map(|image| {
+                if command.action == b'q' {
+                    return;
+                }
+                let image = Arc::new(image);
+                if command.image_id != 0 {
+                    self.kitty_images.insert(command.image_id, image.clone());
+                }
+                if command.action == b'T' {
+                    self.place_kitty_image(&command, image);
+                }
+            }),
+            b'p' => match self.kitty_images.get(command.image_id) {
+                Some(image) => {
+                    self.place_kitty_image(&command, image);
+                    Ok(())
+                }
+                None => Err("ENOENT:no such image"),
+            },
+            b'd' => {
+                self.delete_kitty_images(&command);
+                return;
+            }
+            _ => Err("EINVAL:unsupported action"),
+        };
+        self.kitty_reply(&command, result);
+    }
+
+    fn place_kitty_image(&mut self, command: &KittyCommand, image: Arc<InlineImage>) {
+        let placement = ImagePlacement::fit(image, command.columns, command.rows, self.cell_pixels);
+        if command.keep_cursor {
+            let cursor = self.cursor;
+            let row = self.grid_mut().row_mut(cursor.y);
+            row.set_image(cursor.x, Some(Arc::new(placement)));
+        } else {
+            self.place_image(placement, true);
+        }
+    }
+
+    // `d=a` clears every visible placement and `d=i` those of one image; the
+    // upper-case forms also forget the stored image data.
+    fn delete_kitty_images(&mut self, command: &KittyCommand) {
+        let free = command.delete.is_ascii_uppercase();
+        match command.delete.to_ascii_lowercase() {
+            b'a' => {
+                self.grid_mut().remove_images(|_| true);
+                if free {
+                    self.kitty_images.clear();
+                }
+            }
+            b'i' => {
+                let Some(image) = self.kitty_images.get(command.image_id) else {
+                    return;
+                };
+                self.grid_mut()
+                    .remove_images(|placement| placement.image.id == image.id);
+                if free {
+                    self.kitty_images.remove(command.image_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn kitty_reply(&mut self, command: &KittyCommand, result: Result<(), &str>) {
+        if let Some(reply) = command.reply(result) {
+            self.reply(reply);
+        }
+    }
+
+    fn notify(&mut self, title: Option<String>, body: String) {
+        if title.is_some() || !body.trim().is_empty() {
+            self.events
+                .push(TerminalEvent::Notification { title, body });
+        }
+    }
+
+    fn reply(&mut self, bytes: impl Into<Vec<u8>>) {
+        self.events.push(TerminalEvent::Reply(bytes.into()));
+    }
+
+    // DECRPM status: 0 = not recognised, 1 = set, 2 = reset.
+    fn private_mode_status(&self, mode: u16) -> u8 {
+        let enabled = match mode {
+            1 => self.modes.application_cursor_keys,
+            6 => self.modes.origin,
+            7 => self.modes.autowrap,
+            25 => self.modes.cursor_visible,
+            47 | 1047 | 1049 => self.alternate_active,
+            9 => self.modes.mouse_tracking == MouseTracking::X10,
+            66 => self.modes.application_keypad,
+            1000 => self.modes.mouse_tracking == MouseTracking::Normal,
+            1002 => self.modes.mouse_tracking == MouseTracking::ButtonMotion,
+            1003 => self.modes.mouse_tracking == MouseTracking::AnyMotion,
+            1006 => self.modes.sgr_mouse,
+            1004 => self.modes.focus_reporting,
+            1015 => self.modes.urxvt_mouse,
+            2004 => self.modes.bracketed_paste,
+            2026 => self.modes.synchronized_output,
+            _ => return 0,
+        };
+        if enabled { 1 } else { 2 }
+    }
+
+    fn ansi_mode_status(&self, mode: u16) -> u8 {
+        let enabled = match mode {
+            4 => self.modes.insert,
+            20 => self.modes.linefeed_newline,
+            _ => return 0,
+        };
+        if enabled { 1 } else { 2 }
+    }
+
+    fn report_cursor_position(&mut self, private: bool) {
+        let row = if self.modes.origin {
+            self.cursor.y.saturating_sub(self.scroll_top)
+        } else {
+            self.cursor.y
+        } + 1;
+        let col = self.cursor.x + 1;
+        let marker = if private { "?" } else { "" };
+        self.reply(format!("\x1b[{marker}{row};{col}R"));
+    }
+
+    fn report_dynamic_colors(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let Some(first) = std::str::from_utf8(params[0])
+            .ok()
+            .and_then(|code| code.parse::<u16>().ok())
+        else {
+            return;
+        };
+        let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+        // `OSC 10 ; ? ; ?` queries 10 and then 11.
+        for (offset, param) in params[1..].iter().enumerate() {
+            if *param != b"?" {
+                continue;
+            }
+            let code = first + offset as u16;
+            let (r, g, b) = match code {
+                10 => self.reported_colors.foreground,
+                11 => self.reported_colors.background,
+                _ => continue,
+            };
+            self.reply(format!(
+                "\x1b]{code};rgb:{r:02x}{r:02x}/{g:02x}{g:02x}/{b:02x}{b:02x}{terminator}"
+            ));
+        }
+    }
+
+    fn set_ansi_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            4 => self.modes.insert = enabled,
+            20 => self.modes.linefeed_newline = enabled,
+            _ => {}
+        }
+    }
+
+    fn apply_sgr(&mut self, params: &Params) {
+        if params.is_empty() {
+            self.cursor.attrs = CellAttrs::default();
+            return;
+        }
+
+        let groups: Vec<&[u16]> = params.iter().collect();
+        let mut idx = 0;
+        while idx < groups.len() {
+            let group = groups[idx];
+            let attrs = &mut self.cursor.attrs;
+            match group[0] {
+                0 => *attrs = CellAttrs::default(),
+                1 => attrs.bold = true,
+                2 => attrs.dim = true,
+                3 => attrs.italic = true,
+                4 => attrs.underline = group.get(1).is_none_or(|style| *style != 0),
+                5 | 6 => attrs.blink = true,
+                7 => attrs.inverse = true,
+                8 => attrs.hidden = true,
+                9 => attrs.strikethrough = true,
+                21 => attrs.underline = true,
+                22 => {
+                    attrs.bold = false;
+                    attrs.dim = false;
+                }
+                23 => attrs.italic = false,
+                24 => attrs.underline = false,
+                25 => attrs.blink = false,
+                27 => attrs.inverse = false,
+                28 => attrs.hidden = false,
+                29 => attrs.strikethrough = false,
+                code @ 30..=37 => attrs.fg = Color::Indexed((code - 30) as u8),
+                code @ 90..=97 => attrs.fg = Color::Indexed((code - 90 + 8) as u8),
+                39 => attrs.fg = Color::Default,
+                code @ 40..=47 => attrs.bg = Color::Indexed((code - 40) as u8),
+                code @ 100..=107 => attrs.bg = Color::Indexed((code - 100 + 8) as u8),
+                49 => attrs.bg = Color::Default,
+                code @ (38 | 48 | 58) => {
+                    let (color, consumed) = if group.len() > 1 {
+                        (extended_color(&group[1..]), 0)
+                    } else {
+                        let rest: Vec<u16> =
+                            groups[idx + 1..].iter().take(4).map(|g| g[0]).collect();
+                        let color = extended_color(&rest);
+                        let consumed = match rest.first() {
+                            Some(5) => 2,
+                            Some(2) => 4,
+                            _ => 0,
+                        };
+                        (color, consumed)
+                    };
+                    if let Some(color) = color {
+                        match code {
+                            38 => attrs.fg = color,
+                            48 => attrs.bg = color,
+                            _ => {}
+                        }
+                    }
+                    idx += consumed;
+                }
+                _ => {}
+            }
+            idx += 1;
+        }
+    }
+}
+
+// Parses the arguments following 38/48/58: `5;n` or `2;r;g;b`. The colon form
+// `2:cs:r:g:b` carries an optional colour-space id before the components.
+fn extended_color(args: &[u16]) -> Option<Color> {
+    let component = |value: u16| value.min(255) as u8;
+    match args {
+        [5, index, ..] => Some(Color::Indexed(component(*index))),
+        [2, _, r, g, b, ..] => Some(Color::Rgb(component(*r), component(*g), component(*b))),
+        [2, r, g, b] => Some(Color::Rgb(component(*r), component(*g), component(*b))),
+        _ => None,
+    }
+}
+
+impl Perform for ScreenState {
+    fn print(&mut self, ch: char) {
+        self.print_char(ch);
+    }
+
+    fn dcs_hook(&mut self, params: &Params, intermediates: &[u8], action: u8) {
+        self.cluster_tail = None;
+        if intermediates.is_empty() && action == b'q' {
+            self.sixel = Some(SixelDecoder::new(params.get(1) == Some(1)));
+        }
+        if intermediates.is_empty()
+            && action == b'p'
+            && params.get(0) == Some(tmux::CONTROL_MODE_PARAM)
+        {
+            self.tmux = Some(ControlParser::default());
+        }
+    }
+
+    fn dcs_put(&mut self, byte: u8) {
+        if let Some(sixel) = &mut self.sixel {
+            sixel.put(byte);
+        }
+        if let Some(notification) = self.tmux.as_mut().and_then(|tmux| tmux.put(byte)) {
+            self.events.push(TerminalEvent::Tmux(notification));
+        }
+    }
+
+    fn dcs_unhook(&mut self) {
+        if let Some(notification) = self.tmux.take().and_then(|mut tmux| tmux.finish()) {
+            self.events.push(TerminalEvent::Tmux(notification));
+        }
+        let Some(image) = self.sixel.take().and_then(SixelDecoder::finish) else {
+            return;
+        };
+        let placement = ImagePlacement::fit(Arc::new(image), None, None, self.cell_pixels);
+        self.place_image(placement, false);
+    }
+
+    fn apc_dispatch(&mut self, data: &[u8]) {
+        self.cluster_tail = None;
+        if let Some((command, payload)) = parse_kitty_command(data) {
+            self.kitty_graphics(command, payload);
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        self.cluster_tail = None;
+        match byte {
+            0x08 => self.backspace(),
+            0x09 => self.tab_forward(1),
+            0x0A..=0x0C => {
+                self.commit_current_line();
+                if self.modes.linefeed_newline {
+                    self.carriage_return();
+                }
+                self.linefeed();
+            }
+            0x07 => self.events.push(TerminalEvent::Bell),
+            0x0D => self.carriage_return(),
+            0x0E => self.active_charset = 1,
+            0x0F => self.active_charset = 0,
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: u8) {
+        let count = usize::from(params.get_or(0, 1));
+        let private = intermediates.first() == Some(&b'?');
+        self.cluster_tail = None;
+
+        match (intermediates, action) {
+            ([], b'A') => self.move_up(count),
+            ([], b'B') | ([], b'e') => self.move_down(count),
+            ([], b'C') | ([], b'a') => self.move_forward(count),
+            ([], b'D') => self.move_backward(count),
+            ([], b'E') => {
+                self.move_down(count);
+                self.carriage_return();
+            }
+            ([], b'F') => {
+                self.move_up(count);
+                self.carriage_return();
+            }
+            ([], b'G') | ([], b'`') => {
+                self.cursor.x = (count - 1).min(self.width() - 1);
+                self.cursor.pending_wrap = false;
+            }
+            ([], b'H') | ([], b'f') => {
+                let row = usize::from(params.get_or(0, 1)) - 1;
+                let col = usize::from(params.get_or(1, 1)) - 1;
+                self.goto(col, row);
+            }
+            ([], b'd') => self.goto_row(count - 1),
+            ([], b'J') => self.erase_in_display(params.get(0).unwrap_or(0)),
+            ([], b'K') => self.erase_in_line(params.get(0).unwrap_or(0)),
+            ([], b'X') => {
+                let (x, y) = (self.cursor.x, self.cursor.y);
+                let attrs = self.blank_attrs();
+                self.grid_mut().row_mut(y).erase(x, x + count, attrs);
+                self.cursor.pending_wrap = false;
+            }
+            ([], b'@') => {
+                let (x, y) = (self.cursor.x, self.cursor.y);
+                let attrs = self.blank_attrs();
+                self.grid_mut().row_mut(y).insert_blank(x, count, attrs);
+                self.cursor.pending_wrap = false;
+            }
+            ([], b'P') => {
+                let (x, y) = (self.cursor.x, self.cursor.y);
+                let attrs = self.blank_attrs();
+                self.grid_mut().row_mut(y).delete(x, count, attrs);
+                self.cursor.pending_wrap = false;
+            }
+            ([], b'I') => self.tab_forward(count),
+            ([], b'Z') => self.tab_backward(count),
+            ([], b'g') => self.clear_tab_stops(params.get(0).unwrap_or(0)),
+            ([b'?'], b'W') if params.get(0) == Some(5) => {
+                self.tab_stops = default_tab_stops(self.width());
+            }
+            ([], b'L') => self.insert_lines(count),
+            ([], b'M') => self.delete_lines(count),
+            ([], b'S') => self.scroll_up(count),
+            ([], b'T') => self.scroll_down(count),
+            ([], b'b') => {
+                if let Some(ch) = self.last_printed {
+                    for _ in 0..count.min(self.width() * self.height()) {
+                        self.print_char(ch);
+                    }
+                }
+            }
+            ([], b'r') => {
+                let top = usize::from(params.get_or(0, 1));
+                let bottom = usize::from(params.get(1).unwrap_or(0));
+                self.set_scroll_region(top, bottom);
+            }
+            ([], b's') => self.save_cursor(),
+            ([], b'u') => self.restore_cursor(),
+            ([marker @ (b'>' | b'<' | b'=' | b'?')], b'u') => self.kitty_keyboard(*marker, params),
+            // CSI > 4 ; Pv m sets modifyOtherKeys; without a value, or through
+            // CSI > 4 n, it goes back to 0.
+            ([b'>'], b'm') if params.get(0) == Some(4) => {
+                self.modes.modify_other_keys = params.get(1).unwrap_or(0).min(2) as u8;
+            }
+            ([b'>'], b'n') if params.get(0) == Some(4) => self.modes.modify_other_keys = 0,
+            ([], b'm') => self.apply_sgr(params),
+            ([], b'n') | ([b'?'], b'n') => match params.get(0) {
+                Some(5) => self.reply(b"\x1b[0n".to_vec()),
+                Some(6) => self.report_cursor_position(private),
+                _ => {}
+            },
+            ([], b'c') if params.get(0).unwrap_or(0) == 0 => {
+                // VT220 with sixel graphics and ANSI colour.
+                self.reply(b"\x1b[?62;4;22c".to_vec());
+            }
+            ([b'>'], b'c') if params.get(0).unwrap_or(0) == 0 => {
+                self.reply(format!("\x1b[>1;{};0c", version_number()));
+            }
+            ([b'>'], b'q') if params.get(0).unwrap_or(0) == 0 => {
+                self.reply(format!(
+                    "\x1bP>|CtyTerm {}\x1b\\",
+                    env!("CARGO_PKG_VERSION")
+                ));
+            }
+            ([], b't') if params.get(0) == Some(18) => {
+                let (rows, cols) = (self.height(), self.width());
+                self.reply(format!("\x1b[8;{rows};{cols}t"));
+            }
+            ([], b't') if params.get(0) == Some(14) => {
+                let (cell_w, cell_h) = self.cell_pixels;
+                let height = cell_h as usize * self.height();
+                let width = cell_w as usize * self.width();
+                self.reply(format!("\x1b[4;{height};{width}t"));
+            }
+            ([], b't') if params.get(0) == Some(16) => {
+                let (cell_w, cell_h) = self.cell_pixels;
+                self.reply(format!("\x1b[6;{cell_h};{cell_w}t"));
+            }
+            ([b'?', b'$'], b'p') | ([b'$'], b'p') => {
+                let mode = params.get(0).unwrap_or(0);
+                let (marker, status) = if private {
+                    ("?", self.private_mode_status(mode))
+                } else {
+                    ("", self.ansi_mode_status(mode))
+                };
+                self.reply(format!("\x1b[{marker}{mode};{status}$y"));
+            }
+            (_, b'h') | (_, b'l') => {
+                let enabled = action == b'h';
+                for group in params.iter() {
+                    if private {
+                        self.set_private_mode(group[0], enabled);
+                    } else if intermediates.is_empty() {
+                        self.set_ansi_mode(group[0], enabled);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8) {
+        self.cluster_tail = None;
+        match (intermediates, byte) {
+            ([], b'H') => {
+                if let Some(stop) = self.tab_stops.get_mut(self.cursor.x) {
+                    *stop = true;
+                }
+            }
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.linefeed(),
+            ([], b'E') => {
+                self.commit_current_line();
+                self.carriage_return();
+                self.linefeed();
+            }
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            ([], b'=') => self.modes.application_keypad = true,
+            ([], b'>') => self.modes.application_keypad = false,
+            ([b'('], b'0') => self.charsets[0] = Charset::DecSpecialGraphics,
+            ([b'('], _) => self.charsets[0] = Charset::Ascii,
+            ([b')'], b'0') => self.charsets[1] = Charset::DecSpecialGraphics,
+            ([b')'], _) => self.charsets[1] = Charset::Ascii,
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        match params.first().copied() {
+            Some(b"0") | Some(b"1") | Some(b"2") => {
+                let title = printable_text(&params[1..], MAX_TITLE_CHARS);
+                self.title = (!title.trim().is_empty()).then_some(title);
+            }
+            // OSC 9;4;state;percent — state 0 clears the indicator.
+            Some(b"9") if params.get(1) == Some(&&b"4"[..]) => {
+                let state = params.get(2).copied().unwrap_or(b"0");
+                self.progress = Progress::parse(state, params.get(3).copied());
+            }
+            // Other numeric OSC 9 arguments are ConEmu commands; anything
+            // else is an iTerm2 notification message.
+            Some(b"9") if params.len() >= 2 => {
+                if params[1].iter().all(u8::is_ascii_digit) {
+                    return;
+                }
+                let body = printable_text(&params[1..], MAX_NOTIFICATION_CHARS);
+                self.notify(None, body);
+            }
+            // OSC 777;notify;title;body
+            Some(b"777") if params.get(1) == Some(&&b"notify"[..]) => {
+                let title = printable_text(params.get(2..3).unwrap_or_default(), MAX_TITLE_CHARS);
+                let body =
+                    printable_text(params.get(3..).unwrap_or_default(), MAX_NOTIFICATION_CHARS);
+                self.notify(Some(title).filter(|title| !title.trim().is_empty()), body);
+            }
+            Some(b"10") | Some(b"11") => self.report_dynamic_colors(params, bell_terminated),
+            Some(b"133") => {
+                if let Some(mark) = parse_osc133(params) {
+                    self.events.push(TerminalEvent::ShellMark(mark));
+                }
+            }
+            Some(b"1337") => self.iterm_image(params),
+            Some(b"52") => {
+                if let Some(request) = parse_osc52(params, bell_terminated) {
+                    self.events.push(TerminalEvent::Clipboard(request));
+                }
+            }
+            Some(b"7") => {
+                if let Some(cwd) = parse_osc7(params) {
+                    self.events.push(TerminalEvent::WorkingDirectory(cwd));
+                }
+            }
+            // OSC 8 ; params ; URI — an empty URI closes the link. The URI
+            // itself may contain semicolons.
+            Some(b"8") if params.len() >= 3 => {
+                let uri = params[2..].join(&b';');
+                self.hyperlink =
+                    (!uri.is_empty()).then(|| Arc::from(String::from_utf8_lossy(&uri).as_ref()));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Joins OSC parameters back with `;`, dropping control characters.
+fn printable_text(params: &[&[u8]], limit: usize) -> String {
+    String::from_utf8_lossy(&params.join(&b';'))
+        .chars()
+        .filter(|ch| !ch.is_control())
+        .take(limit)
+        .collect()
+}
+
+// DA2 firmware version: 0.1.0 reports as 100.
+fn version_number() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .map(|part| part.parse::<u32>().unwrap_or(0))
+        .fold(0, |acc, part| acc * 100 + part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Progress, ReportedColors, SYNC_UPDATE_TIMEOUT, TerminalEmulator, TerminalEvent};
+    use crate::clipboard::{ClipboardRequest, encode_base64};
+    use crate::shell_integration::ShellMark;
+    use crate::style::{Color, StyledLine};
+    use crate::tmux::Notification;
+
+    fn screen(term: &TerminalEmulator) -> Vec<String> {
+        term.grid().lines()
+    }
+
+    fn styled(events: Vec<TerminalEvent>) -> Vec<StyledLine> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                TerminalEvent::Line(line) => Some(line),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn texts(events: Vec<TerminalEvent>) -> Vec<String> {
+        styled(events).into_iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn sgr_colors_become_styled_ranges() {
+        let mut term = TerminalEmulator::new(80, 24);
+        let lines = styled(term.feed(b"error: \x1b[1;31mboom\x1b[0m ok\n"));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "error: boom ok");
+        assert_eq!(lines[0].ranges.len(), 1);
+        let range = lines[0].ranges[0];
+        assert_eq!(&lines[0].text[range.start..range.end], "boom");
+        assert_eq!(range.attrs.fg, Color::Indexed(1));
+        assert!(range.attrs.bold);
+    }
+
+    #[test]
+    fn extended_colors_in_semicolon_and_colon_forms() {
+        let mut term = TerminalEmulator::new(20, 2);
+        term.feed(b"\x1b[38;5;208;48;2;1;2;3mA\x1b[0;38:2::10:20:30mB\x1b[38:5:99mC");
+        let cell = |x| term.grid().cell(x, 0).expect("cell").attrs;
+        assert_eq!(cell(0).fg, Color::Indexed(208));
+        assert_eq!(cell(0).bg, Color::Rgb(1, 2, 3));
+        assert_eq!(cell(1).fg, Color::Rgb(10, 20, 30));
+        assert_eq!(cell(1).bg, Color::Default);
+        assert_eq!(cell(2).fg, Color::Indexed(99));
+    }
+
+    #[test]
+    fn handles_crlf_without_dropping_line_content() {
+        let mut term = TerminalEmulator::new(80, 24);
+        let lines = texts(term.feed(b"alpha\r\nbeta\r\n"));
+        assert_eq!(lines, vec!["alpha", "beta"]);
+    }
+
+    #[test]
+    fn carriage_return_rewrites_the_same_line() {
+        let mut term = TerminalEmulator::new(80, 24);
+        let lines = texts(term.feed(b"first\rsecond\n"));
+        assert_eq!(lines, vec!["second"]);
+    }
+
+    #[test]
+    fn parses_utf8_text() {
+        let mut term = TerminalEmulator::new(80, 24);
+        let lines = texts(term.feed("한글 출력\n".as_bytes()));
+        assert_eq!(lines, vec!["한글 출력"]);
+    }
+
+    #[test]
+    fn keeps_utf8_sequences_split_across_chunks() {
+        let mut term = TerminalEmulator::new(80, 24);
+        let text = "가\n".as_bytes();
+        let lines = texts(term.feed(&text[..1]));
+        assert!(lines.is_empty());
+        let lines = texts(term.feed(&text[1..]));
+        assert_eq!(lines, vec!["가"]);
+    }
+
+    #[test]
+    fn progress_bar_redraw_commits_final_state() {
+        let mut term = TerminalEmulator::new(80, 24);
+        let lines = texts(term.feed(b"[#    ] 20%\r[##   ] 40%\r\x1b[K[#####] done\r\n"));
+        assert_eq!(lines, vec!["[#####] done"]);
+    }
+
+    #[test]
+    fn cursor_positioning_and_erase_in_line() {
+        let mut term = TerminalEmulator::new(10, 3);
+        term.feed(b"abcdefgh\x1b[1;4H\x1b[K\x1b[2;2HXY");
+        assert_eq!(screen(&term), vec!["abc", " XY", ""]);
+        assert_eq!((term.cursor().x, term.cursor().y), (3, 1));
+    }
+
+    #[test]
+    fn long_lines_wrap_and_commit_as_one_logical_line() {
+        let mut term = TerminalEmulator::new(4, 3);
+        let lines = texts(term.feed(b"abcdefghij\r\n"));
+        assert_eq!(lines, vec!["abcdefghij"]);
+    }
+
+    #[test]
+    fn scroll_region_keeps_rows_outside_region() {
+        let mut term = TerminalEmulator::new(5, 4);
+        term.feed(b"top\x1b[2;3r\x1b[2;1Ha\r\nb\r\nc\x1b[4;1Hbot");
+        assert_eq!(screen(&term), vec!["top", "b", "c", "bot"]);
+    }
+
+    #[test]
+    fn alternate_screen_preserves_primary_and_suppresses_lines() {
+        let mut term = TerminalEmulator::new(10, 3);
+        term.feed(b"shell$ ");
+        let lines = texts(term.feed(b"\x1b[?1049h\x1b[Hvim text\r\nmore\r\n"));
+        assert!(lines.is_empty());
+        assert!(term.is_alternate_screen());
+        assert_eq!(screen(&term)[0], "vim text");
+
+        let events = term.feed(b"\x1b[?1049l");
+        assert!(matches!(
+            &events[..],
+            [TerminalEvent::AlternateScreenExited { last_screen }] if last_screen[0] == "vim text"
+        ));
+        assert!(!term.is_alternate_screen());
+        assert_eq!(screen(&term)[0], "shell$");
+        assert_eq!(term.cursor().x, 7);
+    }
+
+    #[test]
+    fn wide_characters_advance_two_columns() {
+        let mut term = TerminalEmulator::new(6, 2);
+        term.feed("한a".as_bytes());
+        assert_eq!(term.cursor().x, 3);
+        assert!(term.grid().cell(0, 0).expect("cell").is_wide());
+    }
+
+    #[test]
+    fn wide_character_at_last_column_wraps_early() {
+        let mut term = TerminalEmulator::new(3, 2);
+        term.feed("ab한".as_bytes());
+        assert_eq!(screen(&term), vec!["ab", "한"]);
+    }
+
+    #[test]
+    fn resize_reflows_the_pending_line_and_committed_rows() {
+        let mut term = TerminalEmulator::new(10, 3);
+        term.feed(b"0123456789abcde\r\n$ typed");
+        assert_eq!(screen(&term), vec!["0123456789", "abcde", "$ typed"]);
+
+        term.resize(20, 3);
+        assert_eq!(screen(&term), vec!["0123456789abcde", "$ typed", ""]);
+        assert_eq!((term.cursor().x, term.cursor().y), (7, 1));
+
+        term.resize(4, 3);
+        assert_eq!(screen(&term)[1..], ["$ ty", "ped"]);
+        assert_eq!((term.cursor().x, term.cursor().y), (3, 2));
+
+        let lines = texts(term.feed(b" more\n"));
+        assert_eq!(lines, vec!["$ typed more"]);
+    }
+
+    #[test]
+    fn osc8_hyperlinks_become_link_ranges() {
+        let mut term = TerminalEmulator::new(40, 2);
+        let lines = styled(
+            term.feed(b"see \x1b]8;id=1;https://example.com/a;b\x1b\\docs\x1b]8;;\x1b\\ now\n"),
+        );
+        assert_eq!(lines[0].text, "see docs now");
+        assert_eq!(lines[0].links.len(), 1);
+        let link = &lines[0].links[0];
+        assert_eq!((link.start, link.end), (4, 8));
+        assert_eq!(link.uri, "https://example.com/a;b");
+        let cell = |x| term.grid().cell(x, 0).expect("cell").link.clone();
+        assert_eq!(cell(7).as_deref(), Some("https://example.com/a;b"));
+        assert_eq!(cell(9), None);
+    }
+
+    #[test]
+    fn inline_images_are_anchored_to_cells_and_committed_lines() {
+        let mut term = TerminalEmulator::new(20, 6);
+        term.set_cell_pixel_size(4, 6);
+
+        // An 8x12 sixel covers 2x2 cells; the cursor stays in its column.
+        let sixel = b"ab\x1bPq\"1;1;8;12#0!8~-!8~\x1b\\\n";
+        let lines = styled(term.feed(sixel));
+        assert_eq!(lines[0].text, "ab");
+        let anchor = &lines[0].images[0];
+        assert_eq!(anchor.column, 2);
+        assert_eq!((anchor.placement.columns, anchor.placement.rows), (2, 2));
+        assert_eq!(term.cursor().x, 2);
+
+        // Kitty raw pixels with an explicit size, followed by a query.
+        let pixels = encode_base64(&[9; 4 * 4]);
+        let events = term.feed(
+            format!("\r\x1b_Ga=T,f=32,s=2,v=2,i=5,c=3,r=1;{pixels}\x1b\\\x1b_Ga=q,i=6,s=1,v=1,f=24;AAAA\x1b\\")
+                .as_bytes(),
+        );
+        assert_eq!(
+            replies(events),
+            vec!["\x1b_Gi=5;OK\x1b\\", "\x1b_Gi=6;OK\x1b\\"]
+        );
+        let placement = term
+            .grid()
+            .cell(0, term.cursor().y)
+            .unwrap()
+            .image
+            .clone()
+            .unwrap();
+        assert_eq!((placement.columns, placement.rows), (3, 1));
+        assert_eq!(term.cursor().x, 3);
+
+        // iTerm2 inline PNG, sized in cells.
+        let mut png = Vec::new();
+        image::RgbaImage::new(3, 3)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let osc = format!(
+            "\r\n\x1b]1337;File=inline=1;width=4;height=1:{}\x07",
+            encode_base64(&png)
+        );
+        term.feed(osc.as_bytes());
+        let placement = term
+            .grid()
+            .cell(0, term.cursor().y)
+            .unwrap()
+            .image
+            .clone()
+            .unwrap();
+        assert_eq!(
+            (placement.image.width, placement.columns, placement.rows),
+            (3, 4, 1)
+        );
+
+        // A tall image records its anchor line only.
+        let osc = format!(
+            "\r\n\x1b]1337;File=inline=1;width=2;height=500:{}\x07",
+            encode_base64(&png)
+        );
+        let lines = styled(term.feed(osc.as_bytes()));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].images[0].placement.rows, 500);
+
+        term.feed(b"\x1b_Ga=d,d=A\x1b\\");
+        assert!(
+            term.grid()
+                .rows()
+                .iter()
+                .flat_map(|row| row.cells())
+                .all(|cell| cell.image.is_none())
+        );
+    }
+
+    #[test]
+    fn osc52_is_reported_as_a_clipboard_event() {
+        let mut term = TerminalEmulator::new(20, 4);
+        let events = term.feed(b"\x1b]52;c;Y29weQ==\x07\x1b]52;c;?\x1b\\");
+        assert_eq!(
+            events,
+            vec![
+                TerminalEvent::Clipboard(ClipboardRequest::Write {
+                    selection: "c".to_owned(),
+                    text: "copy".to_owned(),
+                }),
+                TerminalEvent::Clipboard(ClipboardRequest::Read {
+                    selection: "c".to_owned(),
+                    bell_terminated: false,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn osc_title_and_progress_are_tracked() {
+        let mut term = TerminalEmulator::new(20, 2);
+        term.feed(b"\x1b]2;vim: a;b\x07\x1b]9;4;1;42\x1b\\");
+        assert_eq!(term.title(), Some("vim: a;b"));
+        assert_eq!(term.progress(), Some(Progress::Normal(42)));
+
+        term.feed(b"\x1b]9;4;2;250\x07");
+        assert_eq!(term.progress(), Some(Progress::Error(100)));
+        term.feed(b"\x1b]9;4;3\x07");
+        assert_eq!(term.progress(), Some(Progress::Indeterminate));
+        term.feed(b"\x1b]9;4;0;0\x07\x1b]0;\x07");
+        assert_eq!(term.progress(), None);
+        assert_eq!(term.title(), None);
+    }
+
+    #[test]
+    fn bells_and_notifications_are_reported() {
+        let mut term = TerminalEmulator::new(20, 2);
+        let events = term.feed(
+            b"a\x07\x1b]9;build done\x07\x1b]777;notify;make;ok; 0 errors\x1b\\\x1b]9;1;500\x07",
+        );
+        let relevant: Vec<_> = events
+            .into_iter()
+            .filter(|event| !matches!(event, TerminalEvent::Line(_)))
+            .collect();
+        assert_eq!(
+            relevant,
+            vec![
+                TerminalEvent::Bell,
+                TerminalEvent::Notification {
+                    title: None,
+                    body: "build done".to_owned(),
+                },
+                TerminalEvent::Notification {
+                    title: Some("make".to_owned()),
+                    body: "ok; 0 errors".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn grapheme_clusters_share_one_cell() {
+        let mut term = TerminalEmulator::new(20, 2);
+        // Decomposed Hangul jamo, a combining accent, a ZWJ family and a flag.
+        term.feed(
+            "\u{1112}\u{1161}\u{11AB}e\u{301}\u{1F468}\u{200D}\u{1F469}\u{1F1F0}\u{1F1F7}|"
+                .as_bytes(),
+        );
+        let cell = |x| term.grid().cell(x, 0).expect("cell").grapheme();
+        assert_eq!(cell(0), "\u{1112}\u{1161}\u{11AB}");
+        assert_eq!(cell(2), "e\u{301}");
+        assert_eq!(cell(3), "\u{1F468}\u{200D}\u{1F469}");
+        assert_eq!(cell(5), "\u{1F1F0}\u{1F1F7}");
+        assert_eq!(term.cursor().x, 7);
+        assert_eq!(
+            screen(&term)[0],
+            "\u{1112}\u{1161}\u{11AB}e\u{301}\u{1F468}\u{200D}\u{1F469}\u{1F1F0}\u{1F1F7}|"
+        );
+    }
+
+    #[test]
+    fn tab_stops_can_be_set_cleared_and_walked() {
+        let mut term = TerminalEmulator::new(40, 2);
+        term.feed(b"a\tb");
+        assert_eq!(term.cursor().x, 9);
+
+        // Clear every stop, set one at the third column, then tab forward and back.
+        term.feed(b"\r\x1b[3g\x1b[3G\x1bH\r\tX\x1b[2I");
+        assert_eq!(screen(&term)[0], "a X     b");
+        assert_eq!(term.cursor().x, 39);
+        term.feed(b"\x1b[Z");
+        assert_eq!(term.cursor().x, 2);
+
+        term.feed(b"\x1b[?5W\r\t");
+        assert_eq!(term.cursor().x, 8);
+    }
+
+    #[test]
+    fn sgr_attributes_apply_to_cells() {
+        let mut term = TerminalEmulator::new(10, 2);
+        term.feed(b"\x1b[1;4;38;5;196mA\x1b[22mB");
+        let a = term.grid().cell(0, 0).expect("cell").attrs;
+        let b = term.grid().cell(1, 0).expect("cell").attrs;
+        assert!(a.bold && a.underline && !a.blink);
+        assert!(!b.bold && b.underline);
+    }
+
+    #[test]
+    fn line_drawing_charset_maps_box_characters() {
+        let mut term = TerminalEmulator::new(10, 2);
+        term.feed(b"\x1b(0lqk\x1b(B");
+        assert_eq!(screen(&term)[0], "┌─┐");
+    }
+
+    #[test]
+    fn shell_marks_are_reported_in_stream_order() {
+        let mut term = TerminalEmulator::new(40, 5);
+        let events = term.feed(
+            b"\x1b]7;file://host/tmp\x07\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a.txt\r\n\x1b]133;D;0\x07",
+        );
+        assert_eq!(
+            events,
+            vec![
+                TerminalEvent::WorkingDirectory("/tmp".to_owned()),
+                TerminalEvent::ShellMark(ShellMark::PromptStart),
+                TerminalEvent::ShellMark(ShellMark::CommandStart),
+                TerminalEvent::Line(StyledLine::plain("$ ls")),
+                TerminalEvent::ShellMark(ShellMark::CommandExecuted),
+                TerminalEvent::Line(StyledLine::plain("a.txt")),
+                TerminalEvent::ShellMark(ShellMark::CommandFinished { exit_code: Some(0) }),
+            ]
+        );
+    }
+
+    fn replies(events: Vec<TerminalEvent>) -> Vec<String> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                TerminalEvent::Reply(bytes) => Some(String::from_utf8(bytes).unwrap()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn queries_are_answered_from_emulator_state() {
+        let mut term = TerminalEmulator::new(100, 30);
+        term.feed(b"\x1b[5;12H");
+        assert_eq!(replies(term.feed(b"\x1b[6n")), vec!["\x1b[5;12R"]);
+        assert_eq!(replies(term.feed(b"\x1b[?6n")), vec!["\x1b[?5;12R"]);
+        assert_eq!(replies(term.feed(b"\x1b[5n")), vec!["\x1b[0n"]);
+        assert_eq!(replies(term.feed(b"\x1b[c")), vec!["\x1b[?62;4;22c"]);
+        assert_eq!(replies(term.feed(b"\x1b[>c")), vec!["\x1b[>1;100;0c"]);
+        assert!(replies(term.feed(b"\x1b[>q"))[0].starts_with("\x1bP>|CtyTerm "));
+        assert_eq!(replies(term.feed(b"\x1b[18t")), vec!["\x1b[8;30;100t"]);
+        term.set_cell_pixel_size(9, 18);
+        assert_eq!(replies(term.feed(b"\x1b[16t")), vec!["\x1b[6;18;9t"]);
+        assert_eq!(replies(term.feed(b"\x1b[14t")), vec!["\x1b[4;540;900t"]);
+    }
+
+    #[test]
+    fn mode_and_color_queries_report_current_settings() {
+        let mut term = TerminalEmulator::new(80, 24);
+        term.set_reported_colors(ReportedColors {
+            foreground: (0xff, 0x80, 0x00),
+            background: (0x10, 0x20, 0x30),
+        });
+        term.feed(b"\x1b[?2004h");
+        assert_eq!(
+            replies(term.feed(b"\x1b[?2004$p\x1b[?1$p\x1b[?9999$p\x1b[4$p")),
+            vec![
+                "\x1b[?2004;1$y",
+                "\x1b[?1;2$y",
+                "\x1b[?9999;0$y",
+                "\x1b[4;2$y"
+            ]
+        );
+        term.feed(b"\x1b[?9h\x1b[?1015h");
+        assert_eq!(
+            replies(term.feed(b"\x1b[?9$p\x1b[?1000$p\x1b[?1015$p")),
+            vec!["\x1b[?9;1$y", "\x1b[?1000;2$y", "\x1b[?1015;1$y"]
+        );
+        assert_eq!(
+            replies(term.feed(b"\x1b]10;?;?\x1b\\")),
+            vec![
+                "\x1b]10;rgb:ffff/8080/0000\x1b\\",
+                "\x1b]11;rgb:1010/2020/3030\x1b\\"
+            ]
+        );
+        assert_eq!(
+            replies(term.feed(b"\x1b]11;?\x07")),
+            vec!["\x1b]11;rgb:1010/2020/3030\x07"]
+        );
+    }
+
+    #[test]
+    fn keyboard_flags_are_stacked_per_screen() {
+        let mut term = TerminalEmulator::new(80, 24);
+        assert_eq!(replies(term.feed(b"\x1b[?u")), vec!["\x1b[?0u"]);
+        term.feed(b"\x1b[>1u\x1b[>11u");
+        assert_eq!(term.modes().keyboard_flags, 11);
+        term.feed(b"\x1b[=2;3u");
+        assert_eq!(replies(term.feed(b"\x1b[?u")), vec!["\x1b[?9u"]);
+        term.feed(b"\x1b[<u");
+        assert_eq!(term.modes().keyboard_flags, 1);
+
+        term.feed(b"\x1b[?1049h");
+        assert_eq!(term.modes().keyboard_flags, 0);
+        term.feed(b"\x1b[>31u");
+        assert_eq!(replies(term.feed(b"\x1b[?u")), vec!["\x1b[?11u"]);
+        term.feed(b"\x1b[?1049l");
+        assert_eq!(term.modes().keyboard_flags, 1);
+        term.feed(b"\x1b[<5u");
+        assert_eq!(term.modes().keyboard_flags, 0);
+
+        term.feed(b"\x1b[>4;2m");
+        assert_eq!(term.modes().modify_other_keys, 2);
+        term.feed(b"\x1b[>4n");
+        assert_eq!(term.modes().modify_other_keys, 0);
+    }
+
+    #[test]
+    fn synchronized_updates_hold_the_presented_screen() {
+        let mut term = TerminalEmulator::new(10, 3);
+        term.feed(b"old");
+        term.feed(b"\x1b[?2026h\x1b[2J\x1b[Hnew\x1b[?25l");
+        let (grid, cursor) = term.presented_screen();
+        assert_eq!(grid.lines()[0], "old");
+        assert_eq!(cursor, Some((3, 0)));
+        assert!(term.sync_deadline().is_some());
+        assert_eq!(replies(term.feed(b"\x1b[?2026$p")), vec!["\x1b[?2026;1$y"]);
+
+        // A nested begin keeps the original snapshot.
+        term.feed(b"\x1b[?2026h");
+        let since = term.state.frozen.as_ref().unwrap().since;
+        assert!(term.frozen_at(since + SYNC_UPDATE_TIMEOUT).is_none());
+        assert_eq!(term.presented_screen().0.lines()[0], "old");
+
+        term.feed(b"\x1b[?2026l");
+        let (grid, cursor) = term.presented_screen();
+        assert_eq!(grid.lines()[0], "new");
+        assert_eq!(cursor, None);
+        assert!(term.sync_deadline().is_none());
+    }
+
+    #[test]
+    fn focus_reporting_is_a_private_mode() {
+        let mut term = TerminalEmulator::new(10, 3);
+        assert_eq!(replies(term.feed(b"\x1b[?1004$p")), vec!["\x1b[?1004;2$y"]);
+        term.feed(b"\x1b[?1004h");
+        assert!(term.modes().focus_reporting);
+        term.feed(b"\x1bc");
+        assert!(!term.modes().focus_reporting);
+    }
+
+    #[test]
+    fn tmux_control_mode_is_reported_not_printed() {
+        let mut term = TerminalEmulator::new(20, 3);
+        let events = term
+            .feed(b"$ tmux -CC\r\n\x1bP1000p%begin 1 2 0\r\n%end 1 2 0\r\n%output %1 hi\\015\r\n");
+        assert_eq!(
+            events,
+            vec![
+                TerminalEvent::Line(StyledLine::plain("$ tmux -CC")),
+                TerminalEvent::Tmux(Notification::Reply {
+                    lines: Vec::new(),
+                    success: true
+                }),
+                TerminalEvent::Tmux(Notification::Output {
+                    pane: 1,
+                    data: b"hi\r".to_vec()
+                }),
+            ]
+        );
+        // tmux went away without `%exit`.
+        let events = term.feed(b"\x1b\\$ ");
+        assert_eq!(
+            events,
+            vec![TerminalEvent::Tmux(Notification::Exit { reason: None })]
+        );
+        assert_eq!(screen(&term)[1], "$");
+    }
+}
@@ -0,0 +1,612 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::clipboard::decode_base64;
+
+/// Images with more pixels than this (64 MiB of RGBA) are rejected.
+pub const MAX_IMAGE_PIXELS: usize = 4096 * 4096;
+const MAX_SIXEL_SIDE: usize = 4096;
+// Upper bound on the cells one placement may cover.
+const MAX_SPAN_CELLS: usize = 1024;
+// Kitty keeps transmitted images for later `a=p` placements; the oldest are
+// dropped past this.
+const MAX_KITTY_STORE_BYTES: usize = 256 * 1024 * 1024;
+const MAX_KITTY_UPLOAD_BYTES: usize = 96 * 1024 * 1024;
+
+static NEXT_IMAGE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// RGBA8 pixels received through Sixel, iTerm2 or Kitty graphics.
+#[derive(Debug, Eq, PartialEq)]
+pub struct InlineImage {
+    /// Unique within the process; renderers cache textures by it.
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl InlineImage {
+    pub fn new(width: u32, height: u32, rgba: Vec<u8>) -> Option<Self> {
+        let pixels = width as usize * height as usize;
+        if pixels == 0 || pixels > MAX_IMAGE_PIXELS || rgba.len() != pixels * 4 {
+            return None;
+        }
+        Some(Self {
+            id: NEXT_IMAGE_ID.fetch_add(1, Ordering::Relaxed),
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    /// Decodes an encoded file (PNG, JPEG, GIF, ...).
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let decoded = image::load_from_memory(bytes).ok()?.into_rgba8();
+        let (width, height) = decoded.dimensions();
+        Self::new(width, height, decoded.into_raw())
+    }
+}
+
+/// An image drawn over `columns` x `rows` cells whose top-left corner is the
+/// cell holding the placement.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ImagePlacement {
+    pub image: Arc<InlineImage>,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl ImagePlacement {
+    /// Sizes the placement in cells. A missing dimension follows the aspect
+    /// ratio; with neither, the image keeps its pixel size.
+    pub fn fit(
+        image: Arc<InlineImage>,
+        columns: Option<usize>,
+        rows: Option<usize>,
+        cell: (u32, u32),
+    ) -> Self {
+        let (cell_w, cell_h) = (f64::from(cell.0.max(1)), f64::from(cell.1.max(1)));
+        let (width, height) = (f64::from(image.width), f64::from(image.height));
+        let span = |value: f64| (value.ceil() as usize).clamp(1, MAX_SPAN_CELLS);
+        let (columns, rows) = match (columns, rows) {
+            (Some(columns), Some(rows)) => (columns, rows),
+            (Some(columns), None) => (
+                columns,
+                span(columns as f64 * cell_w * height / width / cell_h),
+            ),
+            (None, Some(rows)) => (span(rows as f64 * cell_h * width / height / cell_w), rows),
+            (None, None) => (span(width / cell_w), span(height / cell_h)),
+        };
+        Self {
+            image,
+            columns: columns.clamp(1, MAX_SPAN_CELLS),
+            rows: rows.clamp(1, MAX_SPAN_CELLS),
+        }
+    }
+}
+
+// VT340 default colour registers, in percent.
+const SIXEL_DEFAULT_PALETTE: [(u32, u32, u32); 16] = [
+    (0, 0, 0),
+    (20, 20, 80),
+    (80, 13, 13),
+    (20, 80, 20),
+    (80, 20, 80),
+    (20, 80, 80),
+    (80, 80, 20),
+    (53, 53, 53),
+    (26, 26, 26),
+    (33, 33, 60),
+    (60, 26, 26),
+    (33, 60, 33),
+    (60, 33, 60),
+    (33, 60, 60),
+    (60, 60, 33),
+    (80, 80, 80),
+];
+
+/// Incremental decoder for the body of a `DCS P1 ; P2 ; P3 q ... ST` sixel
+/// sequence.
+pub struct SixelDecoder {
+    palette: Vec<[u8; 4]>,
+    color: usize,
+    x: usize,
+    y: usize,
+    rows: Vec<Vec<[u8; 4]>>,
+    declared: (usize, usize),
+    background: [u8; 4],
+    command: Option<u8>,
+    args: Vec<u32>,
+    repeat: usize,
+}
+
+impl SixelDecoder {
+    /// `P2 = 1` leaves unpainted pixels transparent; otherwise they take
+    /// colour register 0.
+    pub fn new(transparent: bool) -> Self {
+        let mut palette = vec![[0, 0, 0, 255]; 256];
+        for (slot, (r, g, b)) in palette.iter_mut().zip(SIXEL_DEFAULT_PALETTE) {
+            *slot = [percent(r), percent(g), percent(b), 255];
+        }
+        let background = if transparent { [0; 4] } else { palette[0] };
+        Self {
+            palette,
+            color: 0,
+            x: 0,
+            y: 0,
+            rows: Vec::new(),
+            declared: (0, 0),
+            background,
+            command: None,
+            args: Vec::new(),
+            repeat: 1,
+        }
+    }
+
+    pub fn put(&mut self, byte: u8) {
+        if self.command.is_some() {
+            match byte {
+                b'0'..=b'9' => {
+                    if self.args.is_empty() {
+                        self.args.push(0);
+                    }
+                    if let Some(arg) = self.args.last_mut() {
+                        *arg = arg
+                            .saturating_mul(10)
+                            .saturating_add(u32::from(byte - b'0'));
+                    }
+                    return;
+                }
+                b';' => {
+                    if self.args.is_empty() {
+                        self.args.push(0);
+                    }
+                    self.args.push(0);
+                    return;
+                }
+                _ => self.finish_command(),
+            }
+        }
+        match byte {
+            b'"' | b'#' | b'!' => {
+                self.command = Some(byte);
+                self.args.clear();
+            }
+            b'$' => self.x = 0,
+            b'-' => {
+                self.x = 0;
+                self.y += 6;
+            }
+            b'?'..=b'~' => {
+                let repeat = std::mem::replace(&mut self.repeat, 1);
+                self.draw(byte - b'?', repeat);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn finish(mut self) -> Option<InlineImage> {
+        self.finish_command();
+        let width = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+            .max(self.declared.0)
+            .min(MAX_SIXEL_SIDE);
+        let height = self.rows.len().max(self.declared.1).min(MAX_SIXEL_SIDE);
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let row = self.rows.get(y).map(Vec::as_slice).unwrap_or_default();
+            for x in 0..width {
+                rgba.extend_from_slice(row.get(x).unwrap_or(&self.background));
+            }
+        }
+        InlineImage::new(width as u32, height as u32, rgba)
+    }
+
+    fn finish_command(&mut self) {
+        let Some(command) = self.command.take() else {
+            return;
+        };
+        let arg = |idx: usize| self.args.get(idx).copied().unwrap_or(0);
+        match command {
+            b'!' => self.repeat = (arg(0) as usize).clamp(1, MAX_SIXEL_SIDE),
+            b'"' => {
+                self.declared = (
+                    (arg(2) as usize).min(MAX_SIXEL_SIDE),
+                    (arg(3) as usize).min(MAX_SIXEL_SIDE),
+                );
+            }
+            b'#' => {
+                let register = (arg(0) as usize).min(self.palette.len() - 1);
+                if self.args.len() >= 5 {
+                    let (x, y, z) = (arg(2), arg(3), arg(4));
+                    self.palette[register] = match arg(1) {
+                        1 => hls_to_rgba(x, y, z),
+                        _ => [percent(x), percent(y), percent(z), 255],
+                    };
+                }
+                self.color = register;
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, bits: u8, repeat: usize) {
+        let color = self.palette[self.color];
+        for bit in 0..6 {
+            let y = self.y + bit;
+            if bits & (1 << bit) == 0 || y >= MAX_SIXEL_SIDE {
+                continue;
+            }
+            let end = (self.x + repeat).min(MAX_SIXEL_SIDE);
+            if self.x >= end {
+                continue;
+            }
+            if self.rows.len() <= y {
+                self.rows.resize(y + 1, Vec::new());
+            }
+            let row = &mut self.rows[y];
+            if row.len() < end {
+                row.resize(end, self.background);
+            }
+            row[self.x..end].fill(color);
+        }
+        self.x += repeat;
+    }
+}
+
+fn percent(value: u32) -> u8 {
+    (value.min(100) * 255 / 100) as u8
+}
+
+// Sixel HLS puts blue at 0 degrees, red at 120 and green at 240.
+fn hls_to_rgba(hue: u32, lightness: u32, saturation: u32) -> [u8; 4] {
+    let h = f64::from((hue + 240) % 360) / 360.0;
+    let l = f64::from(lightness.min(100)) / 100.0;
+    let s = f64::from(saturation.min(100)) / 100.0;
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f64| {
+        let t = t.rem_euclid(1.0);
+        let value = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        (value * 255.0).round() as u8
+    };
+    [
+        channel(h + 1.0 / 3.0),
+        channel(h),
+        channel(h - 1.0 / 3.0),
+        255,
+    ]
+}
+
+/// A width or height argument of an iTerm2 `File=` sequence.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ItermSize {
+    Auto,
+    Cells(usize),
+    Pixels(u32),
+    Percent(u32),
+}
+
+impl ItermSize {
+    fn parse(value: &str) -> Self {
+        let parsed = if let Some(px) = value.strip_suffix("px") {
+            px.parse().ok().map(Self::Pixels)
+        } else if let Some(pct) = value.strip_suffix('%') {
+            pct.parse().ok().map(Self::Percent)
+        } else {
+            value.parse().ok().map(Self::Cells)
+        };
+        parsed.unwrap_or(Self::Auto)
+    }
+
+    /// Cells covered along an axis with `total` cells of `cell` pixels.
+    pub fn cells(self, total: usize, cell: u32) -> Option<usize> {
+        match self {
+            Self::Auto => None,
+            Self::Cells(count) => Some(count),
+            Self::Pixels(px) => Some(px.div_ceil(cell.max(1)) as usize),
+            Self::Percent(pct) => Some((total * pct.min(100) as usize).div_ceil(100)),
+        }
+    }
+}
+
+/// `OSC 1337 ; File=args : base64 ST`, with the payload already decoded.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ItermFile {
+    pub inline: bool,
+    pub width: ItermSize,
+    pub height: ItermSize,
+    pub data: Vec<u8>,
+}
+
+/// Parses the text after `1337;`. Other iTerm2 commands return `None`.
+pub fn parse_iterm_file(body: &[u8]) -> Option<ItermFile> {
+    let body = body.strip_prefix(b"File=")?;
+    let split = body.iter().position(|byte| *byte == b':')?;
+    let (args, data) = (&body[..split], &body[split + 1..]);
+    let mut file = ItermFile {
+        inline: false,
+        width: ItermSize::Auto,
+        height: ItermSize::Auto,
+        data: decode_base64(data)?,
+    };
+    for arg in String::from_utf8_lossy(args).split(';') {
+        let Some((key, value)) = arg.split_once('=') else {
+            continue;
+        };
+        match key {
+            "inline" => file.inline = value == "1",
+            "width" => file.width = ItermSize::parse(value),
+            "height" => file.height = ItermSize::parse(value),
+            _ => {}
+        }
+    }
+    Some(file)
+}
+
+/// The control keys of one Kitty graphics command (`ESC _ G keys ; payload
+/// ESC \`).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct KittyCommand {
+    pub action: u8,
+    pub format: u32,
+    pub medium: u8,
+    pub compressed: bool,
+    pub width: u32,
+    pub height: u32,
+    pub image_id: u32,
+    pub more: bool,
+    pub columns: Option<usize>,
+    pub rows: Option<usize>,
+    pub quiet: u8,
+    pub keep_cursor: bool,
+    pub delete: u8,
+}
+
+/// Splits an APC string into a Kitty command and its base64 payload.
+pub fn parse_kitty_command(apc: &[u8]) -> Option<(KittyCommand, &[u8])> {
+    let body = apc.strip_prefix(b"G")?;
+    let (keys, payload) = match body.iter().position(|byte| *byte == b';') {
+        Some(split) => (&body[..split], &body[split + 1..]),
+        None => (body, &b""[..]),
+    };
+    let mut command = KittyCommand {
+        action: b't',
+        format: 32,
+        medium: b'd',
+        delete: b'a',
+        ..KittyCommand::default()
+    };
+    for pair in keys.split(|byte| *byte == b',') {
+        let [key, b'=', value @ ..] = pair else {
+            continue;
+        };
+        let number = || std::str::from_utf8(value).ok()?.parse::<u32>().ok();
+        let letter = value.first().copied().unwrap_or_default();
+        match key {
+            b'a' => command.action = letter,
+            b'f' => command.format = number().unwrap_or(32),
+            b't' => command.medium = letter,
+            b'o' => command.compressed = letter == b'z',
+            b's' => command.width = number().unwrap_or(0),
+            b'v' => command.height = number().unwrap_or(0),
+            b'i' => command.image_id = number().unwrap_or(0),
+            b'm' => command.more = number() == Some(1),
+            b'c' => command.columns = number().filter(|n| *n > 0).map(|n| n as usize),
+            b'r' => command.rows = number().filter(|n| *n > 0).map(|n| n as usize),
+            b'q' => command.quiet = number().unwrap_or(0) as u8,
+            b'C' => command.keep_cursor = number() == Some(1),
+            b'd' => command.delete = letter,
+            _ => {}
+        }
+    }
+    Some((command, payload))
+}
+
+impl KittyCommand {
+    /// Decodes a directly transmitted (`t=d`) payload. Errors are Kitty
+    /// error replies such as `EINVAL:...`.
+    pub fn decode_image(&self, payload: &[u8]) -> Result<InlineImage, &'static str> {
+        if self.medium != b'd' {
+            return Err("ENOTSUP:only direct transmission is supported");
+        }
+        let mut data = decode_base64(payload).ok_or("EINVAL:bad base64 payload")?;
+        if self.compressed {
+            let mut inflated = Vec::new();
+            flate2::read::ZlibDecoder::new(data.as_slice())
+                .take((MAX_IMAGE_PIXELS * 4 + 1) as u64)
+                .read_to_end(&mut inflated)
+                .map_err(|_| "EINVAL:bad zlib data")?;
+            data = inflated;
+        }
+        let pixels = self.width as usize * self.height as usize;
+        let rgba = match self.format {
+            100 => return InlineImage::decode(&data).ok_or("EBADPNG:could not decode image"),
+            32 if data.len() == pixels * 4 => data,
+            24 if data.len() == pixels * 3 => data
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            32 | 24 => return Err("ENODATA:payload does not match s and v"),
+            _ => return Err("EINVAL:unsupported format"),
+        };
+        InlineImage::new(self.width, self.height, rgba).ok_or("EINVAL:bad image size")
+    }
+
+    /// The `ESC _ G ... ESC \` answer, or `None` when `q` silences it or the
+    /// client gave no id to answer to.
+    pub fn reply(&self, result: Result<(), &str>) -> Option<Vec<u8>> {
+        let silenced = match result {
+            Ok(()) => self.quiet >= 1,
+            Err(_) => self.quiet >= 2,
+        };
+        if self.image_id == 0 || silenced {
+            return None;
+        }
+        let message = result.err().unwrap_or("OK");
+        Some(format!("\x1b_Gi={};{message}\x1b\\", self.image_id).into_bytes())
+    }
+}
+
+/// Images transmitted with a Kitty id, kept for later placements.
+#[derive(Default)]
+pub struct KittyImages {
+    images: HashMap<u32, Arc<InlineImage>>,
+    order: VecDeque<u32>,
+    bytes: usize,
+    // A chunked (`m=1`) transmission: the first command and the payload so far.
+    upload: Option<(KittyCommand, Vec<u8>)>,
+}
+
+impl KittyImages {
+    /// Feeds one command. Returns the complete command and payload once the
+    /// last chunk of a transmission arrives.
+    pub fn receive(
+        &mut self,
+        command: KittyCommand,
+        payload: &[u8],
+    ) -> Option<Result<(KittyCommand, Vec<u8>), KittyCommand>> {
+        if let Some((first, mut buffered)) = self.upload.take() {
+            if buffered.len() + payload.len() > MAX_KITTY_UPLOAD_BYTES {
+                return Some(Err(first));
+            }
+            buffered.extend_from_slice(payload);
+            if command.more {
+                self.upload = Some((first, buffered));
+                return None;
+            }
+            return Some(Ok((first, buffered)));
+        }
+        if command.more {
+            self.upload = Some((command, payload.to_vec()));
+            return None;
+        }
+        Some(Ok((command, payload.to_vec())))
+    }
+
+    pub fn insert(&mut self, id: u32, image: Arc<InlineImage>) {
+        self.remove(id);
+        self.bytes += image.rgba.len();
+        self.images.insert(id, image);
+        self.order.push_back(id);
+        while self.bytes > MAX_KITTY_STORE_BYTES {
+            let Some(oldest) = self.order.front().copied() else {
+                break;
+            };
+            self.remove(oldest);
+        }
+    }
+
+    pub fn get(&self, id: u32) -> Option<Arc<InlineImage>> {
+        self.images.get(&id).cloned()
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        if let Some(image) = self.images.remove(&id) {
+            self.bytes -= image.rgba.len();
+            self.order.retain(|stored| *stored != id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        ImagePlacement, InlineImage, ItermSize, KittyImages, SixelDecoder, parse_iterm_file,
+        parse_kitty_command,
+    };
+    use crate::clipboard::encode_base64;
+
+    #[test]
+    fn sixel_bands_repeats_and_colour_registers_decode() {
+        let mut decoder = SixelDecoder::new(true);
+        // Register 1 as pure red, then a 3-pixel wide, 6-pixel tall block,
+        // a second band with one pixel of register 0.
+        for byte in b"\"1;1;4;8#1;2;100;0;0#1!3~-#0@" {
+            decoder.put(*byte);
+        }
+        let image = decoder.finish().expect("image");
+        assert_eq!((image.width, image.height), (4, 8));
+        let pixel = |x: usize, y: usize| {
+            let idx = (y * 4 + x) * 4;
+            image.rgba[idx..idx + 4].to_vec()
+        };
+        assert_eq!(pixel(0, 0), vec![255, 0, 0, 255]);
+        assert_eq!(pixel(2, 5), vec![255, 0, 0, 255]);
+        assert_eq!(pixel(3, 0), vec![0, 0, 0, 0]);
+        assert_eq!(pixel(0, 6), vec![0, 0, 0, 255]);
+        assert_eq!(pixel(0, 7), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn iterm_file_arguments_and_payload_parse() {
+        let body = format!(
+            "File=name=eC5wbmc=;width=50%;height=3;inline=1:{}",
+            encode_base64(b"PNG")
+        );
+        let file = parse_iterm_file(body.as_bytes()).expect("file");
+        assert!(file.inline);
+        assert_eq!(file.width, ItermSize::Percent(50));
+        assert_eq!(file.height, ItermSize::Cells(3));
+        assert_eq!(file.data, b"PNG");
+        assert_eq!(file.width.cells(80, 8), Some(40));
+        assert_eq!(ItermSize::Pixels(20).cells(80, 8), Some(3));
+    }
+
+    #[test]
+    fn kitty_chunks_are_joined_and_raw_pixels_decode() {
+        let pixels = encode_base64(&[1, 2, 3, 4, 5, 6]);
+        let (first, second) = pixels.split_at(4);
+        let mut images = KittyImages::default();
+
+        let first = format!("Ga=T,f=24,s=2,v=1,i=7,m=1;{first}");
+        let (command, payload) = parse_kitty_command(first.as_bytes()).unwrap();
+        assert!(images.receive(command, payload).is_none());
+        let second = format!("Gm=0;{second}");
+        let (command, payload) = parse_kitty_command(second.as_bytes()).unwrap();
+        let (command, payload) = images.receive(command, payload).unwrap().unwrap();
+        assert_eq!((command.action, command.image_id), (b'T', 7));
+
+        let image = command.decode_image(&payload).expect("decode");
+        assert_eq!(image.rgba, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(command.reply(Ok(())), Some(b"\x1b_Gi=7;OK\x1b\\".to_vec()));
+        assert!(
+            command
+                .decode_image(b"AAAA")
+                .is_err_and(|err| err.starts_with("ENODATA"))
+        );
+    }
+
+    #[test]
+    fn placements_follow_the_aspect_ratio() {
+        let image = Arc::new(InlineImage::new(40, 64, vec![0; 40 * 64 * 4]).unwrap());
+        let natural = ImagePlacement::fit(image.clone(), None, None, (8, 16));
+        assert_eq!((natural.columns, natural.rows), (5, 4));
+        let narrow = ImagePlacement::fit(image, Some(10), None, (8, 16));
+        assert_eq!((narrow.columns, narrow.rows), (10, 8));
+    }
+}
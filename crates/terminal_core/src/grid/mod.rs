@@ -1,34 +1,720 @@
+use std::sync::Arc;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::graphics::ImagePlacement;
+use crate::style::{CellAttrs, ImageAnchor, StyledLine};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    /// Zero-width code points that complete the grapheme cluster started by
+    /// `ch`: combining marks, Hangul jamo, variation selectors and ZWJ
+    /// sequences.
+    pub combining: Option<Box<str>>,
+    pub attrs: CellAttrs,
+    /// Target of the OSC 8 hyperlink the cell was printed under.
+    pub link: Option<Arc<str>>,
+    /// Inline image whose top-left corner is this cell.
+    pub image: Option<Arc<ImagePlacement>>,
+    /// 1 for a regular cell, 2 for the leading half of a wide character and
+    /// 0 for the continuation cell that follows it.
+    pub width: u8,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            combining: None,
+            attrs: CellAttrs::default(),
+            link: None,
+            image: None,
+            width: 1,
+        }
+    }
+}
+
+impl Cell {
+    pub fn blank(attrs: CellAttrs) -> Self {
+        Self {
+            ch: ' ',
+            combining: None,
+            attrs,
+            link: None,
+            image: None,
+            width: 1,
+        }
+    }
+
+    pub fn is_wide(&self) -> bool {
+        self.width == 2
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.width == 0
+    }
+
+    pub fn push_combining(&mut self, ch: char) {
+        let mut text = self.combining.take().map(String::from).unwrap_or_default();
+        text.push(ch);
+        self.combining = Some(text.into_boxed_str());
+    }
+
+    /// Every code point of the cluster, base character first.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        std::iter::once(self.ch).chain(self.combining.iter().flat_map(|text| text.chars()))
+    }
+
+    pub fn grapheme(&self) -> String {
+        self.chars().collect()
+    }
+}
+
+pub fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+pub fn styled_line<'a>(cells: impl IntoIterator<Item = &'a Cell>) -> StyledLine {
+    let mut line = StyledLine::default();
+    for (column, cell) in cells.into_iter().enumerate() {
+        if let Some(placement) = &cell.image {
+            line.images.push(ImageAnchor {
+                column,
+                placement: placement.clone(),
+            });
+        }
+        if cell.is_continuation() {
+            continue;
+        }
+        let start = line.text.len();
+        for ch in cell.chars() {
+            line.push(ch, cell.attrs);
+        }
+        if let Some(uri) = &cell.link {
+            line.link_tail(line.text.len() - start, uri);
+        }
+    }
+    line.trim_end();
+    line
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Row {
+    cells: Vec<Cell>,
+    /// Set when the text on this row continues on the next row because of
+    /// autowrap rather than an explicit newline.
+    pub wrapped: bool,
+}
+
+impl Row {
+    pub fn new(width: usize) -> Self {
+        Self {
+            cells: vec![Cell::default(); width],
+            wrapped: false,
+        }
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [Cell] {
+        &mut self.cells
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn resize(&mut self, width: usize) {
+        self.cells.resize(width, Cell::default());
+        if let Some(last) = self.cells.last_mut()
+            && last.is_wide()
+        {
+            *last = Cell::default();
+        }
+        if let Some(first) = self.cells.first_mut()
+            && first.is_continuation()
+        {
+            *first = Cell::default();
+        }
+    }
+
+    pub fn clear(&mut self, attrs: CellAttrs) {
+        self.cells.fill(Cell::blank(attrs));
+        self.wrapped = false;
+    }
+
+    pub fn erase(&mut self, start: usize, end: usize, attrs: CellAttrs) {
+        let end = end.min(self.cells.len());
+        if start >= end {
+            return;
+        }
+        self.repair_wide_boundary(start);
+        self.repair_wide_boundary(end);
+        self.cells[start..end].fill(Cell::blank(attrs));
+    }
+
+    pub fn insert_blank(&mut self, at: usize, count: usize, attrs: CellAttrs) {
+        let width = self.cells.len();
+        if at >= width {
+            return;
+        }
+        self.repair_wide_boundary(at);
+        let count = count.min(width - at);
+        self.cells.truncate(width - count);
+        for _ in 0..count {
+            self.cells.insert(at, Cell::blank(attrs));
+        }
+        if let Some(last) = self.cells.last_mut()
+            && last.is_wide()
+        {
+            *last = Cell::blank(attrs);
+        }
+    }
+
+    pub fn delete(&mut self, at: usize, count: usize, attrs: CellAttrs) {
+        let width = self.cells.len();
+        if at >= width {
+            return;
+        }
+        let count = count.min(width - at);
+        self.repair_wide_boundary(at);
+        self.repair_wide_boundary(at + count);
+        self.cells.drain(at..at + count);
+        self.cells.resize(width, Cell::blank(attrs));
+    }
+
+    /// Writes `ch` at column `x`, clearing any wide character it partially
+    /// overwrites. Wide characters also claim the following cell.
+    pub fn put(&mut self, x: usize, ch: char, width: usize, attrs: CellAttrs) {
+        if x >= self.cells.len() {
+            return;
+        }
+        self.repair_wide_boundary(x);
+        if width == 2 {
+            if x + 1 >= self.cells.len() {
+                return;
+            }
+            self.repair_wide_boundary(x + 2);
+            self.cells[x] = Cell {
+                ch,
+                combining: None,
+                attrs,
+                link: None,
+                image: None,
+                width: 2,
+            };
+            self.cells[x + 1] = Cell {
+                ch: ' ',
+                combining: None,
+                attrs,
+                link: None,
+                image: None,
+                width: 0,
+            };
+        } else {
+            self.repair_wide_boundary(x + 1);
+            self.cells[x] = Cell {
+                ch,
+                combining: None,
+                attrs,
+                link: None,
+                image: None,
+                width: 1,
+            };
+        }
+    }
+
+    /// Points the cell at `x`, and the continuation of a wide cell, at an
+    /// OSC 8 target.
+    pub fn set_link(&mut self, x: usize, link: Option<Arc<str>>) {
+        let Some(cell) = self.cells.get_mut(x) else {
+            return;
+        };
+        let wide = cell.is_wide();
+        cell.link = link.clone();
+        if wide && let Some(next) = self.cells.get_mut(x + 1) {
+            next.link = link;
+        }
+    }
+
+    pub fn set_image(&mut self, x: usize, image: Option<Arc<ImagePlacement>>) {
+        if let Some(cell) = self.cells.get_mut(x) {
+            cell.image = image;
+        }
+    }
+
+    /// Appends a zero-width code point to the cluster in the cell at `x`,
+    /// stepping back from a continuation cell to its wide lead.
+    pub fn attach(&mut self, x: usize, ch: char) {
+        let x = if x > 0 && self.cells.get(x).is_some_and(Cell::is_continuation) {
+            x - 1
+        } else {
+            x
+        };
+        if let Some(cell) = self.cells.get_mut(x) {
+            cell.push_combining(ch);
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut out: String = self
+            .cells
+            .iter()
+            .filter(|cell| !cell.is_continuation())
+            .flat_map(Cell::chars)
+            .collect();
+        let trimmed = out.trim_end_matches(' ').len();
+        out.truncate(trimmed);
+        out
+    }
+
+    // Splitting a wide character in half leaves an orphaned lead or
+    // continuation cell; blank both halves the same way xterm does.
+    fn repair_wide_boundary(&mut self, x: usize) {
+        if x == 0 || x >= self.cells.len() {
+            return;
+        }
+        if self.cells[x].is_continuation() && self.cells[x - 1].is_wide() {
+            let attrs = self.cells[x - 1].attrs;
+            self.cells[x - 1] = Cell::blank(attrs);
+            self.cells[x] = Cell::blank(attrs);
+        }
+    }
+}
+
+/// Cursor position carried through [`TextGrid::reflow`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ReflowCursor {
+    pub x: usize,
+    pub y: usize,
+    pub pending_wrap: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct TextGrid {
     width: usize,
     height: usize,
-    lines: Vec<String>,
+    rows: Vec<Row>,
 }
 
 impl TextGrid {
     pub fn new(width: usize, height: usize) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
         Self {
             width,
             height,
-            lines: Vec::new(),
+            rows: (0..height).map(|_| Row::new(width)).collect(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn row(&self, y: usize) -> &Row {
+        &self.rows[y]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut Row {
+        &mut self.rows[y]
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.rows.get(y).and_then(|row| row.cells().get(x))
+    }
+
+    /// Drops the image placements `remove` selects.
+    pub fn remove_images(&mut self, mut remove: impl FnMut(&ImagePlacement) -> bool) {
+        for cell in self.rows.iter_mut().flat_map(|row| row.cells.iter_mut()) {
+            if cell.image.as_deref().is_some_and(&mut remove) {
+                cell.image = None;
+            }
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.rows.iter().map(Row::text).collect()
+    }
+
+    /// Text of the cells from `start` to `end` inclusive, in reading order.
+    /// Rows are joined with newlines unless they soft-wrapped.
+    pub fn selection_text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let ((start_x, start_y), (end_x, end_y)) = if (start.1, start.0) <= (end.1, end.0) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        let mut out = String::new();
+        for y in start_y..=end_y.min(self.rows.len().saturating_sub(1)) {
+            let row = &self.rows[y];
+            let from = if y == start_y { start_x } else { 0 };
+            let to = if y == end_y { end_x + 1 } else { row.len() };
+            let mut text: String = row
+                .cells
+                .get(from.min(row.len())..to.min(row.len()))
+                .unwrap_or_default()
+                .iter()
+                .filter(|cell| !cell.is_continuation())
+                .flat_map(Cell::chars)
+                .collect();
+            if row.wrapped && y != end_y {
+                out.push_str(&text);
+                continue;
+            }
+            text.truncate(text.trim_end_matches(' ').len());
+            out.push_str(&text);
+            if y != end_y {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let width = width.max(1);
+        let height = height.max(1);
+        for row in &mut self.rows {
+            row.resize(width);
+        }
+        if height < self.height {
+            self.rows.drain(..self.height - height);
+        } else {
+            self.rows
+                .extend((self.height..height).map(|_| Row::new(width)));
         }
+        self.width = width;
+        self.height = height;
     }
 
-    pub fn set_lines(&mut self, lines: &[String]) {
-        let start = lines.len().saturating_sub(self.height);
-        self.lines = lines[start..]
+    /// Resizes the grid and rewraps soft-wrapped rows to the new width,
+    /// keeping the cursor on the same character. `prefix` holds cells of a
+    /// wrapped line whose head already scrolled off the top; it is rewrapped
+    /// with that line and on return holds whatever no longer fits again.
+    pub fn reflow(
+        &mut self,
+        width: usize,
+        height: usize,
+        cursor: ReflowCursor,
+        prefix: &mut Vec<Cell>,
+    ) -> ReflowCursor {
+        let width = width.max(1);
+        let height = height.max(1);
+        let cursor_y = cursor.y.min(self.height - 1);
+        let last_row = self
+            .rows
             .iter()
-            .map(|line| line.chars().take(self.width).collect())
-            .collect();
+            .rposition(|row| row.cells.iter().any(|cell| *cell != Cell::default()))
+            .unwrap_or(0)
+            .max(cursor_y);
+
+        let mut rows = Vec::new();
+        let mut new_cursor = None;
+        let mut line = std::mem::take(prefix);
+        let mut cursor_offset = None;
+        for y in 0..=last_row {
+            let row = &self.rows[y];
+            let mut cells = row.cells.as_slice();
+            // A wide character that did not fit left a blank in the last
+            // column; it is padding, not content.
+            if row.wrapped
+                && self
+                    .rows
+                    .get(y + 1)
+                    .is_some_and(|next| next.cells[0].is_wide())
+                && cells.last() == Some(&Cell::default())
+            {
+                cells = &cells[..cells.len() - 1];
+            }
+            if y == cursor_y {
+                cursor_offset = Some(line.len() + cursor.x + usize::from(cursor.pending_wrap));
+            }
+            line.extend_from_slice(cells);
+            if row.wrapped && y < last_row {
+                continue;
+            }
+            if let Some((x, row_offset)) = rewrap(&mut rows, line, width, cursor_offset.take()) {
+                new_cursor = Some((x, row_offset));
+            }
+            line = Vec::new();
+        }
+
+        let (mut x, mut y) = new_cursor.unwrap_or((0, rows.len().saturating_sub(1)));
+        let pending_wrap = x >= width;
+        if pending_wrap {
+            x = width - 1;
+        }
+        // Drop rows from the top until the cursor is visible; a line that
+        // continues on screen keeps its hidden head in `prefix`.
+        let drop = (y + 1).saturating_sub(height);
+        let mut head = drop;
+        while head > 0 && rows[head - 1].wrapped {
+            head -= 1;
+        }
+        for row in &rows[head..drop] {
+            prefix.extend_from_slice(&row.cells);
+        }
+        rows.drain(..drop);
+        y -= drop;
+        rows.truncate(height);
+        rows.resize_with(height, || Row::new(width));
+
+        self.rows = rows;
+        self.width = width;
+        self.height = height;
+        ReflowCursor { x, y, pending_wrap }
+    }
+
+    pub fn clear(&mut self, attrs: CellAttrs) {
+        for row in &mut self.rows {
+            row.clear(attrs);
+        }
     }
 
-    pub fn lines(&self) -> &[String] {
-        &self.lines
+    /// Scrolls rows `top..=bottom` up by `count`, returning the rows that
+    /// left the region in top-to-bottom order.
+    pub fn scroll_up(
+        &mut self,
+        top: usize,
+        bottom: usize,
+        count: usize,
+        attrs: CellAttrs,
+    ) -> Vec<Row> {
+        if top > bottom || bottom >= self.height {
+            return Vec::new();
+        }
+        let count = count.min(bottom - top + 1);
+        let mut fresh = Row::new(self.width);
+        fresh.clear(attrs);
+        let removed: Vec<Row> = self.rows.drain(top..top + count).collect();
+        for offset in 0..count {
+            self.rows.insert(bottom + 1 - count + offset, fresh.clone());
+        }
+        removed
+    }
+
+    pub fn scroll_down(&mut self, top: usize, bottom: usize, count: usize, attrs: CellAttrs) {
+        if top > bottom || bottom >= self.height {
+            return;
+        }
+        let count = count.min(bottom - top + 1);
+        let mut fresh = Row::new(self.width);
+        fresh.clear(attrs);
+        self.rows.drain(bottom + 1 - count..=bottom);
+        for _ in 0..count {
+            self.rows.insert(top, fresh.clone());
+        }
     }
 }
 
+// Lays one logical line out over rows of `width` cells, returning the
+// `(x, row)` at which the cell `cursor_offset` landed.
+fn rewrap(
+    rows: &mut Vec<Row>,
+    mut cells: Vec<Cell>,
+    width: usize,
+    cursor_offset: Option<usize>,
+) -> Option<(usize, usize)> {
+    let content = cells
+        .iter()
+        .rposition(|cell| *cell != Cell::default())
+        .map_or(0, |last| last + 1);
+    cells.truncate(content.max(cursor_offset.unwrap_or(0)));
+
+    let mut row = Row::new(width);
+    let mut x = 0;
+    let mut cursor = None;
+    for (index, cell) in cells.into_iter().enumerate() {
+        if cell.is_continuation() {
+            if cursor_offset == Some(index) {
+                cursor = Some((x, rows.len()));
+            }
+            continue;
+        }
+        let cell_width = if cell.is_wide() { 2 } else { 1 };
+        if x + cell_width > width && cell_width <= width {
+            row.wrapped = true;
+            rows.push(std::mem::replace(&mut row, Row::new(width)));
+            x = 0;
+        }
+        if cursor_offset == Some(index) {
+            cursor = Some((x, rows.len()));
+        }
+        if cell_width > width {
+            continue;
+        }
+        if cell.is_wide() {
+            row.cells[x + 1] = Cell {
+                ch: ' ',
+                combining: None,
+                attrs: cell.attrs,
+                link: cell.link.clone(),
+                image: None,
+                width: 0,
+            };
+        }
+        row.cells[x] = cell;
+        x += cell_width;
+    }
+    if cursor.is_none() && cursor_offset.is_some() {
+        cursor = Some((x, rows.len()));
+    }
+    rows.push(row);
+    cursor
+}
+
 impl Default for TextGrid {
     fn default() -> Self {
         Self::new(140, 120)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CellAttrs, ReflowCursor, Row, TextGrid};
+
+    #[test]
+    fn selection_text_joins_wrapped_rows_and_trims_padding() {
+        let mut grid = TextGrid::new(4, 3);
+        for (x, ch) in "abcd".chars().enumerate() {
+            grid.row_mut(0).put(x, ch, 1, CellAttrs::default());
+        }
+        grid.row_mut(0).wrapped = true;
+        grid.row_mut(1).put(0, 'e', 1, CellAttrs::default());
+        grid.row_mut(2).put(0, '한', 2, CellAttrs::default());
+        assert_eq!(grid.selection_text((1, 0), (3, 2)), "bcde\n한");
+        assert_eq!(grid.selection_text((1, 2), (2, 0)), "cde\n한");
+        assert_eq!(grid.selection_text((2, 1), (2, 1)), "");
+    }
+
+    #[test]
+    fn wide_character_occupies_two_cells() {
+        let mut row = Row::new(6);
+        row.put(0, '한', 2, CellAttrs::default());
+        row.put(2, 'a', 1, CellAttrs::default());
+        assert!(row.cells()[0].is_wide());
+        assert!(row.cells()[1].is_continuation());
+        assert_eq!(row.text(), "한a");
+    }
+
+    #[test]
+    fn overwriting_half_of_wide_character_blanks_both_halves() {
+        let mut row = Row::new(4);
+        row.put(0, '글', 2, CellAttrs::default());
+        row.put(1, 'x', 1, CellAttrs::default());
+        assert_eq!(row.text(), " x");
+        assert!(!row.cells()[0].is_wide());
+    }
+
+    #[test]
+    fn combining_marks_stay_in_their_base_cell() {
+        let mut row = Row::new(4);
+        row.put(0, 'e', 1, CellAttrs::default());
+        row.attach(0, '\u{301}');
+        row.put(1, '한', 2, CellAttrs::default());
+        row.attach(2, '\u{302}');
+        assert_eq!(row.cells()[0].grapheme(), "e\u{301}");
+        assert_eq!(row.cells()[1].grapheme(), "한\u{302}");
+        assert_eq!(row.text(), "e\u{301}한\u{302}");
+
+        row.put(0, 'x', 1, CellAttrs::default());
+        assert_eq!(row.cells()[0].combining, None);
+    }
+
+    #[test]
+    fn scroll_up_returns_rows_leaving_region() {
+        let mut grid = TextGrid::new(4, 3);
+        grid.row_mut(0).put(0, 'a', 1, CellAttrs::default());
+        grid.row_mut(1).put(0, 'b', 1, CellAttrs::default());
+        grid.row_mut(2).put(0, 'c', 1, CellAttrs::default());
+
+        let removed = grid.scroll_up(0, 2, 1, CellAttrs::default());
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].text(), "a");
+        assert_eq!(grid.lines(), vec!["b", "c", ""]);
+    }
+
+    #[test]
+    fn scroll_down_inside_region_keeps_rows_outside() {
+        let mut grid = TextGrid::new(4, 4);
+        for (y, ch) in ['a', 'b', 'c', 'd'].into_iter().enumerate() {
+            grid.row_mut(y).put(0, ch, 1, CellAttrs::default());
+        }
+
+        grid.scroll_down(1, 2, 1, CellAttrs::default());
+        assert_eq!(grid.lines(), vec!["a", "", "b", "d"]);
+    }
+
+    fn write(grid: &mut TextGrid, y: usize, text: &str, wrapped: bool) {
+        for (x, ch) in text.chars().enumerate() {
+            grid.row_mut(y).put(x, ch, 1, CellAttrs::default());
+        }
+        grid.row_mut(y).wrapped = wrapped;
+    }
+
+    #[test]
+    fn reflow_rewraps_soft_wrapped_lines_and_keeps_the_cursor() {
+        let mut grid = TextGrid::new(4, 4);
+        write(&mut grid, 0, "abcd", true);
+        write(&mut grid, 1, "ef", false);
+        write(&mut grid, 2, "gh", false);
+        let mut prefix = Vec::new();
+        let cursor = ReflowCursor {
+            x: 2,
+            y: 2,
+            pending_wrap: false,
+        };
+
+        let cursor = grid.reflow(8, 4, cursor, &mut prefix);
+        assert_eq!(grid.lines(), vec!["abcdef", "gh", "", ""]);
+        assert_eq!((cursor.x, cursor.y), (2, 1));
+
+        let cursor = grid.reflow(3, 3, cursor, &mut prefix);
+        assert_eq!(grid.lines(), vec!["abc", "def", "gh"]);
+        assert!(grid.row(0).wrapped && !grid.row(1).wrapped);
+        assert_eq!((cursor.x, cursor.y), (2, 2));
+
+        // Narrowing further pushes the head of a wrapped line off the top.
+        let cursor = grid.reflow(2, 2, cursor, &mut prefix);
+        assert_eq!(grid.lines(), vec!["ef", "gh"]);
+        assert_eq!((cursor.x, cursor.y, cursor.pending_wrap), (1, 1, true));
+        assert_eq!(prefix.len(), 4);
+
+        let cursor = grid.reflow(6, 2, cursor, &mut prefix);
+        assert_eq!(grid.lines(), vec!["abcdef", "gh"]);
+        assert_eq!((cursor.x, cursor.y), (2, 1));
+        assert!(prefix.is_empty());
+    }
+
+    #[test]
+    fn reflow_moves_wide_characters_whole() {
+        let mut grid = TextGrid::new(5, 2);
+        grid.row_mut(0).put(0, 'a', 1, CellAttrs::default());
+        grid.row_mut(0).put(1, '한', 2, CellAttrs::default());
+        grid.row_mut(0).put(3, '글', 2, CellAttrs::default());
+        let mut prefix = Vec::new();
+
+        grid.reflow(4, 2, ReflowCursor::default(), &mut prefix);
+        assert_eq!(grid.lines(), vec!["a한", "글"]);
+        assert!(grid.row(0).wrapped);
+        assert!(grid.row(1).cells()[0].is_wide());
+
+        grid.reflow(5, 2, ReflowCursor::default(), &mut prefix);
+        assert_eq!(grid.lines(), vec!["a한글", ""]);
+    }
+}
@@ -0,0 +1,727 @@
+use crate::emulator::{MouseTracking, TerminalModes};
+
+/// Kitty keyboard protocol progressive enhancement flags.
+pub const KITTY_DISAMBIGUATE: u8 = 0b1;
+pub const KITTY_REPORT_EVENTS: u8 = 0b10;
+pub const KITTY_REPORT_ALL_KEYS: u8 = 0b1000;
+/// Flags the encoder implements; others are masked off when set.
+pub const KITTY_SUPPORTED_FLAGS: u8 =
+    KITTY_DISAMBIGUATE | KITTY_REPORT_EVENTS | KITTY_REPORT_ALL_KEYS;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KeyEventKind {
+    Press,
+    Repeat,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl Modifiers {
+    pub fn is_empty(&self) -> bool {
+        !self.shift && !self.alt && !self.ctrl
+    }
+
+    // xterm's modifier parameter: 1 + shift(1) + alt(2) + ctrl(4).
+    fn xterm_param(&self) -> u8 {
+        1 + u8::from(self.shift) + 2 * u8::from(self.alt) + 4 * u8::from(self.ctrl)
+    }
+}
+
+/// Encodes a key press for the given terminal modes.
+pub fn encode_key(key: Key, mods: Modifiers, modes: &TerminalModes) -> Option<Vec<u8>> {
+    encode_key_event(key, mods, KeyEventKind::Press, modes)
+}
+
+/// Encodes a key event. While the application has Kitty keyboard flags set
+/// it is reported the Kitty way; otherwise as xterm does, honouring
+/// modifyOtherKeys. Releases are only reported under Kitty flag 2.
+pub fn encode_key_event(
+    key: Key,
+    mods: Modifiers,
+    kind: KeyEventKind,
+    modes: &TerminalModes,
+) -> Option<Vec<u8>> {
+    if modes.keyboard_flags != 0 {
+        return kitty_key(key, mods, kind, modes);
+    }
+    if kind == KeyEventKind::Release {
+        return None;
+    }
+    if let Some(out) = modified_other_key(key, mods, modes.modify_other_keys) {
+        return Some(out);
+    }
+    legacy_key(key, mods, modes)
+}
+
+// `CSI 27 ; mods ; code ~` for modified keys whose plain xterm bytes are
+// ambiguous (level 1) or for every modified key (level 2). Shift alone only
+// changes the character typed.
+fn modified_other_key(key: Key, mods: Modifiers, level: u8) -> Option<Vec<u8>> {
+    if level == 0 || mods.is_empty() {
+        return None;
+    }
+    let code = match key {
+        Key::Char(ch) if mods.shift => ch.to_ascii_uppercase() as u32,
+        Key::Char(ch) => ch as u32,
+        Key::Enter => 13,
+        Key::Tab => 9,
+        Key::Backspace => 127,
+        Key::Escape => 27,
+        _ => return None,
+    };
+    let modify = match key {
+        Key::Char(_) if !mods.ctrl && !mods.alt => false,
+        _ if level >= 2 => true,
+        Key::Char(ch) => mods.ctrl && (mods.shift || ctrl_char(ch).is_none()),
+        _ => false,
+    };
+    modify.then(|| format!("\x1b[27;{};{code}~", mods.xterm_param()).into_bytes())
+}
+
+fn kitty_key(
+    key: Key,
+    mods: Modifiers,
+    kind: KeyEventKind,
+    modes: &TerminalModes,
+) -> Option<Vec<u8>> {
+    let flags = modes.keyboard_flags;
+    let report_all = flags & KITTY_REPORT_ALL_KEYS != 0;
+    let report_events = flags & KITTY_REPORT_EVENTS != 0;
+    if kind == KeyEventKind::Release && !report_events {
+        return None;
+    }
+
+    // Typed text and unmodified Enter, Tab and Backspace keep their plain
+    // bytes unless every key is reported, so a shell stays usable after a
+    // program exits without popping its flags.
+    let plain = match key {
+        Key::Char(_) => !mods.ctrl && !mods.alt,
+        Key::Enter | Key::Tab | Key::Backspace => mods.is_empty(),
+        _ => false,
+    };
+    if plain && !report_all {
+        return (kind != KeyEventKind::Release)
+            .then(|| legacy_key(key, mods, modes))
+            .flatten();
+    }
+
+    let (code, final_byte) = match key {
+        Key::Char(ch) => (ch.to_ascii_lowercase() as u32, b'u'),
+        Key::Enter => (13, b'u'),
+        Key::Tab => (9, b'u'),
+        Key::Backspace => (127, b'u'),
+        Key::Escape => (27, b'u'),
+        Key::Up => (1, b'A'),
+        Key::Down => (1, b'B'),
+        Key::Right => (1, b'C'),
+        Key::Left => (1, b'D'),
+        Key::Home => (1, b'H'),
+        Key::End => (1, b'F'),
+        Key::Insert => (2, b'~'),
+        Key::Delete => (3, b'~'),
+        Key::PageUp => (5, b'~'),
+        Key::PageDown => (6, b'~'),
+        Key::F(1) => (1, b'P'),
+        Key::F(2) => (1, b'Q'),
+        Key::F(3) => (13, b'~'),
+        Key::F(4) => (1, b'S'),
+        Key::F(5) => (15, b'~'),
+        Key::F(n @ 6..=10) => (u32::from(n) + 11, b'~'),
+        Key::F(11) => (23, b'~'),
+        Key::F(12) => (24, b'~'),
+        Key::F(_) => return None,
+    };
+    let event = match kind {
+        _ if !report_events => None,
+        KeyEventKind::Press => None,
+        KeyEventKind::Repeat => Some(2),
+        KeyEventKind::Release => Some(3),
+    };
+    if final_byte != b'u' && mods.is_empty() && event.is_none() && !report_all {
+        return legacy_key(key, mods, modes);
+    }
+
+    let mut out = b"\x1b[".to_vec();
+    let has_mods = !mods.is_empty() || event.is_some();
+    if final_byte == b'u' || final_byte == b'~' || has_mods {
+        out.extend_from_slice(code.to_string().as_bytes());
+    }
+    if has_mods {
+        out.extend_from_slice(format!(";{}", mods.xterm_param()).as_bytes());
+        if let Some(event) = event {
+            out.extend_from_slice(format!(":{event}").as_bytes());
+        }
+    }
+    out.push(final_byte);
+    Some(out)
+}
+
+fn legacy_key(key: Key, mods: Modifiers, modes: &TerminalModes) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    match key {
+        Key::Char(ch) => {
+            if mods.alt {
+                out.push(0x1b);
+            }
+            if mods.ctrl {
+                out.push(ctrl_char(ch)?);
+            } else {
+                let mut buf = [0_u8; 4];
+                out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        Key::Enter => {
+            if mods.alt {
+                out.push(0x1b);
+            }
+            out.extend_from_slice(if modes.linefeed_newline {
+                b"\r\n"
+            } else {
+                b"\r"
+            });
+        }
+        Key::Tab if mods.shift => out.extend_from_slice(b"\x1b[Z"),
+        Key::Tab => {
+            if mods.alt {
+                out.push(0x1b);
+            }
+            out.push(b'\t');
+        }
+        Key::Backspace => {
+            if mods.alt {
+                out.push(0x1b);
+            }
+            out.push(if mods.ctrl { 0x08 } else { 0x7f });
+        }
+        Key::Escape => {
+            if mods.alt {
+                out.push(0x1b);
+            }
+            out.push(0x1b);
+        }
+        Key::Up => cursor_key(&mut out, b'A', mods, modes),
+        Key::Down => cursor_key(&mut out, b'B', mods, modes),
+        Key::Right => cursor_key(&mut out, b'C', mods, modes),
+        Key::Left => cursor_key(&mut out, b'D', mods, modes),
+        Key::Home => cursor_key(&mut out, b'H', mods, modes),
+        Key::End => cursor_key(&mut out, b'F', mods, modes),
+        Key::Insert => tilde_key(&mut out, 2, mods),
+        Key::Delete => tilde_key(&mut out, 3, mods),
+        Key::PageUp => tilde_key(&mut out, 5, mods),
+        Key::PageDown => tilde_key(&mut out, 6, mods),
+        Key::F(n @ 1..=4) => {
+            let final_byte = b'P' + (n - 1);
+            if mods.is_empty() {
+                out.extend_from_slice(&[0x1b, b'O', final_byte]);
+            } else {
+                out.extend_from_slice(format!("\x1b[1;{}", mods.xterm_param()).as_bytes());
+                out.push(final_byte);
+            }
+        }
+        Key::F(n) => {
+            let code = match n {
+                5 => 15,
+                6 => 17,
+                7 => 18,
+                8 => 19,
+                9 => 20,
+                10 => 21,
+                11 => 23,
+                12 => 24,
+                _ => return None,
+            };
+            tilde_key(&mut out, code, mods);
+        }
+    }
+    Some(out)
+}
+
+fn ctrl_char(ch: char) -> Option<u8> {
+    match ch {
+        'a'..='z' | 'A'..='Z' => Some((ch.to_ascii_lowercase() as u8) & 0x1f),
+        '@' | ' ' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '7' | '/' => Some(0x1f),
+        '8' | '?' => Some(0x7f),
+        _ => None,
+    }
+}
+
+fn cursor_key(out: &mut Vec<u8>, final_byte: u8, mods: Modifiers, modes: &TerminalModes) {
+    if !mods.is_empty() {
+        out.extend_from_slice(format!("\x1b[1;{}", mods.xterm_param()).as_bytes());
+    } else if modes.application_cursor_keys {
+        out.extend_from_slice(b"\x1bO");
+    } else {
+        out.extend_from_slice(b"\x1b[");
+    }
+    out.push(final_byte);
+}
+
+fn tilde_key(out: &mut Vec<u8>, code: u8, mods: Modifiers) {
+    if mods.is_empty() {
+        out.extend_from_slice(format!("\x1b[{code}~").as_bytes());
+    } else {
+        out.extend_from_slice(format!("\x1b[{code};{}~", mods.xterm_param()).as_bytes());
+    }
+}
+
+/// Focus report for a pane gaining or losing focus, if the application
+/// asked for them.
+pub fn encode_focus(focused: bool, modes: &TerminalModes) -> Option<Vec<u8>> {
+    let report: &[u8] = if focused { b"\x1b[I" } else { b"\x1b[O" };
+    modes.focus_reporting.then(|| report.to_vec())
+}
+
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+
+/// Encodes pasted text. Newlines are sent as carriage returns, as if typed;
+/// under bracketed paste (DECSET 2004) the text is wrapped in the paste
+/// markers, and end markers inside it are removed so it cannot end early.
+pub fn encode_paste(text: &str, modes: &TerminalModes) -> Vec<u8> {
+    let mut text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if !modes.bracketed_paste {
+        return text.into_bytes();
+    }
+    while text.contains(PASTE_END) {
+        text = text.replace(PASTE_END, "");
+    }
+    format!("{PASTE_START}{text}{PASTE_END}").into_bytes()
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    /// Motion with no button held.
+    NoButton,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MouseAction {
+    Press,
+    Release,
+    /// The pointer moved to another cell; `button` is the one held.
+    Motion,
+}
+
+/// Encodes a mouse report for a zero-based cell position, or `None` when the
+/// application's tracking mode does not ask for it.
+pub fn encode_mouse(
+    button: MouseButton,
+    action: MouseAction,
+    col: usize,
+    row: usize,
+    mods: Modifiers,
+    modes: &TerminalModes,
+) -> Option<Vec<u8>> {
+    let reported = match (modes.mouse_tracking, action) {
+        (MouseTracking::Off, _) => false,
+        (MouseTracking::X10, action) => action == MouseAction::Press,
+        (MouseTracking::Normal, MouseAction::Motion) => false,
+        (MouseTracking::ButtonMotion, MouseAction::Motion) => button != MouseButton::NoButton,
+        _ => true,
+    };
+    let is_wheel = matches!(
+        button,
+        MouseButton::WheelUp
+            | MouseButton::WheelDown
+            | MouseButton::WheelLeft
+            | MouseButton::WheelRight
+    );
+    if !reported || (is_wheel && action != MouseAction::Press) {
+        return None;
+    }
+
+    let mut code: u32 = match button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+        MouseButton::NoButton => 3,
+        MouseButton::WheelUp => 64,
+        MouseButton::WheelDown => 65,
+        MouseButton::WheelLeft => 66,
+        MouseButton::WheelRight => 67,
+    };
+    if modes.mouse_tracking != MouseTracking::X10 {
+        if mods.shift {
+            code += 4;
+        }
+        if mods.alt {
+            code += 8;
+        }
+        if mods.ctrl {
+            code += 16;
+        }
+    }
+    if action == MouseAction::Motion {
+        code += 32;
+    }
+
+    let (col, row) = (col + 1, row + 1);
+    if modes.sgr_mouse {
+        let final_byte = if action == MouseAction::Release {
+            'm'
+        } else {
+            'M'
+        };
+        return Some(format!("\x1b[<{code};{col};{row}{final_byte}").into_bytes());
+    }
+
+    // The legacy encodings cannot express which button was released.
+    if action == MouseAction::Release {
+        code = (code & !0b11) | 3;
+    }
+    if modes.urxvt_mouse {
+        return Some(format!("\x1b[{};{col};{row}M", code + 32).into_bytes());
+    }
+    // Plain X10 bytes cannot carry coordinates past 223.
+    if col > 223 || row > 223 {
+        return None;
+    }
+    Some(vec![
+        0x1b,
+        b'[',
+        b'M',
+        32 + code as u8,
+        32 + col as u8,
+        32 + row as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        KITTY_DISAMBIGUATE, KITTY_REPORT_ALL_KEYS, KITTY_REPORT_EVENTS, Key, KeyEventKind,
+        Modifiers, MouseAction, MouseButton, encode_focus, encode_key, encode_key_event,
+        encode_mouse, encode_paste,
+    };
+    use crate::emulator::{MouseTracking, TerminalModes};
+
+    fn ctrl() -> Modifiers {
+        Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        }
+    }
+
+    #[test]
+    fn control_and_alt_characters() {
+        let modes = TerminalModes::default();
+        assert_eq!(encode_key(Key::Char('c'), ctrl(), &modes), Some(vec![0x03]));
+        assert_eq!(encode_key(Key::Char('['), ctrl(), &modes), Some(vec![0x1b]));
+        let alt = Modifiers {
+            alt: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(
+            encode_key(Key::Char('x'), alt, &modes),
+            Some(b"\x1bx".to_vec())
+        );
+    }
+
+    #[test]
+    fn cursor_keys_follow_application_mode_and_modifiers() {
+        let mut modes = TerminalModes::default();
+        let none = Modifiers::default();
+        assert_eq!(encode_key(Key::Up, none, &modes), Some(b"\x1b[A".to_vec()));
+        modes.application_cursor_keys = true;
+        assert_eq!(encode_key(Key::Up, none, &modes), Some(b"\x1bOA".to_vec()));
+        assert_eq!(
+            encode_key(Key::Right, ctrl(), &modes),
+            Some(b"\x1b[1;5C".to_vec())
+        );
+    }
+
+    #[test]
+    fn function_and_editing_keys() {
+        let modes = TerminalModes::default();
+        let none = Modifiers::default();
+        assert_eq!(
+            encode_key(Key::F(1), none, &modes),
+            Some(b"\x1bOP".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::F(5), none, &modes),
+            Some(b"\x1b[15~".to_vec())
+        );
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(
+            encode_key(Key::Delete, shift, &modes),
+            Some(b"\x1b[3;2~".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::Tab, shift, &modes),
+            Some(b"\x1b[Z".to_vec())
+        );
+    }
+
+    #[test]
+    fn modify_other_keys_disambiguates_modified_keys() {
+        let ctrl_shift = Modifiers {
+            shift: true,
+            ..ctrl()
+        };
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        let mut modes = TerminalModes {
+            modify_other_keys: 1,
+            ..TerminalModes::default()
+        };
+        assert_eq!(encode_key(Key::Char('i'), ctrl(), &modes), Some(vec![0x09]));
+        assert_eq!(
+            encode_key(Key::Char('a'), ctrl_shift, &modes),
+            Some(b"\x1b[27;6;65~".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::Char('.'), ctrl(), &modes),
+            Some(b"\x1b[27;5;46~".to_vec())
+        );
+
+        modes.modify_other_keys = 2;
+        assert_eq!(
+            encode_key(Key::Char('i'), ctrl(), &modes),
+            Some(b"\x1b[27;5;105~".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::Tab, ctrl(), &modes),
+            Some(b"\x1b[27;5;9~".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::Char('a'), shift, &modes),
+            Some(b"a".to_vec())
+        );
+        assert_eq!(
+            encode_key(Key::Up, ctrl(), &modes),
+            Some(b"\x1b[1;5A".to_vec())
+        );
+    }
+
+    #[test]
+    fn kitty_flags_select_the_reported_keys() {
+        let none = Modifiers::default();
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        let press = KeyEventKind::Press;
+        let mut modes = TerminalModes {
+            keyboard_flags: KITTY_DISAMBIGUATE,
+            ..TerminalModes::default()
+        };
+        assert_eq!(
+            encode_key_event(Key::Char('i'), ctrl(), press, &modes),
+            Some(b"\x1b[105;5u".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Tab, none, press, &modes),
+            Some(b"\t".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Tab, shift, press, &modes),
+            Some(b"\x1b[9;2u".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Escape, none, press, &modes),
+            Some(b"\x1b[27u".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Char('a'), shift, press, &modes),
+            Some(b"a".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Up, none, press, &modes),
+            Some(b"\x1b[A".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::F(3), none, press, &modes),
+            Some(b"\x1bOR".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Char('i'), ctrl(), KeyEventKind::Release, &modes),
+            None
+        );
+
+        modes.keyboard_flags = KITTY_DISAMBIGUATE | KITTY_REPORT_EVENTS | KITTY_REPORT_ALL_KEYS;
+        assert_eq!(
+            encode_key_event(Key::Char('a'), none, press, &modes),
+            Some(b"\x1b[97u".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Enter, none, KeyEventKind::Repeat, &modes),
+            Some(b"\x1b[13;1:2u".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::Up, ctrl(), KeyEventKind::Release, &modes),
+            Some(b"\x1b[1;5:3A".to_vec())
+        );
+        assert_eq!(
+            encode_key_event(Key::F(3), none, press, &modes),
+            Some(b"\x1b[13~".to_vec())
+        );
+    }
+
+    #[test]
+    fn focus_reports_require_the_mode() {
+        let mut modes = TerminalModes::default();
+        assert_eq!(encode_focus(true, &modes), None);
+        modes.focus_reporting = true;
+        assert_eq!(encode_focus(true, &modes), Some(b"\x1b[I".to_vec()));
+        assert_eq!(encode_focus(false, &modes), Some(b"\x1b[O".to_vec()));
+    }
+
+    #[test]
+    fn pastes_are_bracketed_only_when_requested() {
+        let mut modes = TerminalModes::default();
+        assert_eq!(encode_paste("ls\r\npwd\n", &modes), b"ls\rpwd\r".to_vec());
+
+        modes.bracketed_paste = true;
+        assert_eq!(
+            encode_paste("echo hi\n", &modes),
+            b"\x1b[200~echo hi\r\x1b[201~".to_vec()
+        );
+        assert_eq!(
+            encode_paste("a\x1b[20\x1b[201~1~b", &modes),
+            b"\x1b[200~ab\x1b[201~".to_vec()
+        );
+    }
+
+    #[test]
+    fn mouse_reports_require_tracking_and_honor_sgr() {
+        let mut modes = TerminalModes::default();
+        let none = Modifiers::default();
+        assert_eq!(
+            encode_mouse(MouseButton::Left, MouseAction::Press, 0, 0, none, &modes),
+            None
+        );
+
+        modes.mouse_tracking = MouseTracking::Normal;
+        assert_eq!(
+            encode_mouse(MouseButton::Left, MouseAction::Press, 4, 2, none, &modes),
+            Some(b"\x1b[M %#".to_vec())
+        );
+        modes.sgr_mouse = true;
+        assert_eq!(
+            encode_mouse(MouseButton::Right, MouseAction::Release, 4, 2, none, &modes),
+            Some(b"\x1b[<2;5;3m".to_vec())
+        );
+        assert_eq!(
+            encode_mouse(MouseButton::WheelUp, MouseAction::Press, 0, 0, none, &modes),
+            Some(b"\x1b[<64;1;1M".to_vec())
+        );
+    }
+
+    #[test]
+    fn mouse_motion_follows_the_tracking_mode() {
+        let mut modes = TerminalModes {
+            mouse_tracking: MouseTracking::Normal,
+            ..TerminalModes::default()
+        };
+        let none = Modifiers::default();
+        let motion = |button, modes: &TerminalModes| {
+            encode_mouse(button, MouseAction::Motion, 1, 1, none, modes)
+        };
+        assert_eq!(motion(MouseButton::Left, &modes), None);
+
+        modes.mouse_tracking = MouseTracking::ButtonMotion;
+        assert_eq!(
+            motion(MouseButton::Left, &modes),
+            Some(b"\x1b[M@\"\"".to_vec())
+        );
+        assert_eq!(motion(MouseButton::NoButton, &modes), None);
+
+        modes.mouse_tracking = MouseTracking::AnyMotion;
+        modes.sgr_mouse = true;
+        assert_eq!(
+            motion(MouseButton::NoButton, &modes),
+            Some(b"\x1b[<35;2;2M".to_vec())
+        );
+    }
+
+    #[test]
+    fn x10_and_urxvt_mouse_encodings() {
+        let mut modes = TerminalModes {
+            mouse_tracking: MouseTracking::X10,
+            ..TerminalModes::default()
+        };
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(
+            encode_mouse(MouseButton::Left, MouseAction::Press, 0, 0, ctrl, &modes),
+            Some(b"\x1b[M !!".to_vec())
+        );
+        assert_eq!(
+            encode_mouse(MouseButton::Left, MouseAction::Release, 0, 0, ctrl, &modes),
+            None
+        );
+
+        modes.mouse_tracking = MouseTracking::Normal;
+        modes.urxvt_mouse = true;
+        assert_eq!(
+            encode_mouse(
+                MouseButton::Right,
+                MouseAction::Release,
+                299,
+                9,
+                ctrl,
+                &modes
+            ),
+            Some(b"\x1b[51;300;10M".to_vec())
+        );
+        // SGR wins when both are enabled, as in xterm.
+        modes.sgr_mouse = true;
+        assert_eq!(
+            encode_mouse(
+                MouseButton::WheelLeft,
+                MouseAction::Press,
+                0,
+                0,
+                ctrl,
+                &modes
+            ),
+            Some(b"\x1b[<82;1;1M".to_vec())
+        );
+    }
+}
@@ -1,4 +1,14 @@
+pub mod asciicast;
+pub mod clipboard;
+pub mod emulator;
+pub mod graphics;
 pub mod grid;
+pub mod input;
+pub mod mux;
+mod private_dir;
 pub mod pty;
 pub mod scrollback;
+pub mod shell_integration;
+pub mod style;
+pub mod tmux;
 pub mod vt_parser;
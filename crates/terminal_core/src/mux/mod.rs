@@ -0,0 +1,1013 @@
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+
+#[cfg(unix)]
+use crate::private_dir;
+use crate::pty::{PtySession, ShellProfile};
+
+#[cfg(unix)]
+pub(crate) use socket::Connection;
+
+/// Command-line flag that runs the executable as the mux server; the socket
+/// path follows it.
+pub const SERVER_FLAG: &str = "--mux-server";
+
+// Output kept per session and replayed to the next client that attaches.
+const REPLAY_BYTES: usize = 2 * 1024 * 1024;
+// Input is split into frames of at most this size.
+const MAX_INPUT_FRAME: usize = 64 * 1024;
+// Larger frames are a protocol error; the replay is the largest message.
+const MAX_FRAME_BYTES: usize = REPLAY_BYTES + 64 * 1024;
+
+/// Where shells run: in this process, or in a mux server that keeps them
+/// alive while no window is attached.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PtyHost {
+    Local,
+    Server(PathBuf),
+}
+
+impl PtyHost {
+    /// Starts a shell. The mux server is launched first if none is
+    /// listening on the socket.
+    pub fn spawn(&self, profile: &ShellProfile, cols: u16, rows: u16) -> Result<PtySession> {
+        match self {
+            Self::Local => PtySession::spawn(profile, cols, rows),
+            Self::Server(socket) => socket::spawn(socket, profile, cols, rows),
+        }
+    }
+
+    /// Reattaches to a shell kept by the mux server. Its recent output is
+    /// returned with it, to be replayed into a fresh emulator.
+    pub fn attach(&self, id: u64, profile: &ShellProfile) -> Result<(PtySession, Vec<u8>)> {
+        match self {
+            Self::Local => bail!("local shells do not outlive their window"),
+            Self::Server(socket) => socket::attach(socket, id, profile),
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/ctyterm/mux.sock`, or a directory in the temp dir named
+/// after the uid when that is unset. The server and its clients refuse the
+/// directory unless this user owns it and nobody else can enter it.
+#[cfg(unix)]
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("ctyterm").join("mux.sock"),
+        None => std::env::temp_dir()
+            .join(format!("ctyterm-{}", private_dir::uid()))
+            .join("mux.sock"),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn default_socket_path() -> PathBuf {
+    std::env::temp_dir().join("ctyterm").join("mux.sock")
+}
+
+/// Runs the mux server on `socket` until the process is killed. Shells
+/// outlive the clients attached to them and end only when they exit or a
+/// client kills them.
+pub fn serve(socket: &Path) -> Result<()> {
+    socket::serve(socket)
+}
+
+const TAG_SPAWN: u8 = 1;
+const TAG_ATTACH: u8 = 2;
+const TAG_INPUT: u8 = 3;
+const TAG_RESIZE: u8 = 4;
+const TAG_KILL: u8 = 5;
+const TAG_READY: u8 = 0x81;
+const TAG_OUTPUT: u8 = 0x82;
+const TAG_ERROR: u8 = 0x83;
+
+// Frames are a little-endian u32 length, a tag byte and the fields. The
+// first request on a connection picks the session the rest applies to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Message {
+    Spawn {
+        profile: ShellProfile,
+        cols: u16,
+        rows: u16,
+    },
+    Attach {
+        id: u64,
+    },
+    Input(Vec<u8>),
+    Resize {
+        cols: u16,
+        rows: u16,
+    },
+    Kill,
+    Ready {
+        id: u64,
+        pid: Option<u32>,
+        replay: Vec<u8>,
+    },
+    Output(Vec<u8>),
+    Error(String),
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut frame = Encoder::default();
+        match self {
+            Self::Spawn {
+                profile,
+                cols,
+                rows,
+            } => {
+                frame.u8(TAG_SPAWN);
+                frame.profile(profile);
+                frame.u16(*cols);
+                frame.u16(*rows);
+            }
+            Self::Attach { id } => {
+                frame.u8(TAG_ATTACH);
+                frame.u64(*id);
+            }
+            Self::Input(bytes) => {
+                frame.u8(TAG_INPUT);
+                frame.bytes(bytes);
+            }
+            Self::Resize { cols, rows } => {
+                frame.u8(TAG_RESIZE);
+                frame.u16(*cols);
+                frame.u16(*rows);
+            }
+            Self::Kill => frame.u8(TAG_KILL),
+            Self::Ready { id, pid, replay } => {
+                frame.u8(TAG_READY);
+                frame.u64(*id);
+                frame.u32(pid.unwrap_or(0));
+                frame.bytes(replay);
+            }
+            Self::Output(bytes) => {
+                frame.u8(TAG_OUTPUT);
+                frame.bytes(bytes);
+            }
+            Self::Error(message) => {
+                frame.u8(TAG_ERROR);
+                frame.str(message);
+            }
+        }
+        frame.finish()
+    }
+
+    fn decode(frame: &[u8]) -> Option<Self> {
+        let mut fields = Decoder { rest: frame };
+        let message = match fields.u8()? {
+            TAG_SPAWN => Self::Spawn {
+                profile: fields.profile()?,
+                cols: fields.u16()?,
+                rows: fields.u16()?,
+            },
+            TAG_ATTACH => Self::Attach { id: fields.u64()? },
+            TAG_INPUT => Self::Input(fields.bytes()?.to_vec()),
+            TAG_RESIZE => Self::Resize {
+                cols: fields.u16()?,
+                rows: fields.u16()?,
+            },
+            TAG_KILL => Self::Kill,
+            TAG_READY => Self::Ready {
+                id: fields.u64()?,
+                pid: Some(fields.u32()?).filter(|pid| *pid != 0),
+                replay: fields.bytes()?.to_vec(),
+            },
+            TAG_OUTPUT => Self::Output(fields.bytes()?.to_vec()),
+            TAG_ERROR => Self::Error(fields.string()?),
+            _ => return None,
+        };
+        fields.rest.is_empty().then_some(message)
+    }
+}
+
+fn write_message(writer: &mut impl Write, message: &Message) -> io::Result<()> {
+    writer.write_all(&message.encode())
+}
+
+/// Reads the next message; `None` when the peer closed the connection
+/// between frames.
+fn read_message(reader: &mut impl Read) -> io::Result<Option<Message>> {
+    let mut len = [0_u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "mux frame too large",
+        ));
+    }
+    let mut frame = vec![0_u8; len];
+    reader.read_exact(&mut frame)?;
+    Message::decode(&frame)
+        .map(Some)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed mux frame"))
+}
+
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        // Room for the length, filled in by `finish`.
+        Self { buf: vec![0; 4] }
+    }
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    fn optional_str(&mut self, value: Option<&str>) {
+        self.u8(u8::from(value.is_some()));
+        if let Some(value) = value {
+            self.str(value);
+        }
+    }
+
+    fn profile(&mut self, profile: &ShellProfile) {
+        self.str(&profile.program);
+        self.u32(profile.args.len() as u32);
+        for arg in &profile.args {
+            self.str(arg);
+        }
+        self.u32(profile.env.len() as u32);
+        for (key, value) in &profile.env {
+            self.str(key);
+            self.str(value);
+        }
+        let cwd = profile.cwd.as_ref().map(|cwd| cwd.to_string_lossy());
+        self.optional_str(cwd.as_deref());
+        self.optional_str(profile.init_script.as_deref());
+        self.u8(u8::from(profile.shell_integration));
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = (self.buf.len() - 4) as u32;
+        self.buf[..4].copy_from_slice(&len.to_le_bytes());
+        self.buf
+    }
+}
+
+struct Decoder<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.rest.len() < len {
+            return None;
+        }
+        let (head, tail) = self.rest.split_at(len);
+        self.rest = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    fn optional_string(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            _ => Some(Some(self.string()?)),
+        }
+    }
+
+    fn profile(&mut self) -> Option<ShellProfile> {
+        let program = self.string()?;
+        // Counts are not trusted for preallocation; every item needs bytes.
+        let args = (0..self.u32()?)
+            .map(|_| self.string())
+            .collect::<Option<Vec<_>>>()?;
+        let env = (0..self.u32()?)
+            .map(|_| Some((self.string()?, self.string()?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(ShellProfile {
+            program,
+            args,
+            env,
+            cwd: self.optional_string()?.map(PathBuf::from),
+            init_script: self.optional_string()?,
+            shell_integration: self.u8()? != 0,
+        })
+    }
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::collections::{HashMap, VecDeque};
+    use std::fs::{self, TryLockError};
+    use std::io::{self, BufReader, Read, Write};
+    use std::net::Shutdown;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use anyhow::{Context, Result, anyhow, bail};
+    use crossbeam_channel::{Sender, bounded};
+
+    use super::{MAX_INPUT_FRAME, Message, REPLAY_BYTES, SERVER_FLAG, read_message, write_message};
+    use crate::private_dir;
+    use crate::pty::{Backend, PtySession, ShellProfile};
+
+    const SERVER_START_TIMEOUT: Duration = Duration::from_secs(3);
+    // Frames queued for a client; one that falls further behind is dropped
+    // rather than stalling its shell.
+    const CLIENT_BACKLOG: usize = 64;
+
+    pub fn serve(socket: &Path) -> Result<()> {
+        if let Some(dir) = socket.parent() {
+            private_dir::ensure(dir)?;
+        }
+        // Held until the server exits, so two servers starting together
+        // cannot unlink each other's socket.
+        let lock_path = socket.with_extension("lock");
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("failed to open {}", lock_path.display()))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                bail!("a mux server is already listening on {}", socket.display());
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("failed to lock {}", lock_path.display()));
+            }
+        }
+        // The file of a server that died refuses connections.
+        let _ = fs::remove_file(socket);
+        let listener = UnixListener::bind(socket)
+            .with_context(|| format!("failed to listen on {}", socket.display()))?;
+        // Whoever can connect can type into every shell.
+        fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+
+        let server = Arc::new(Server::new());
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let server = Arc::clone(&server);
+            let _ = thread::Builder::new()
+                .name("mux-client".to_owned())
+                .spawn(move || server.serve_client(stream));
+        }
+        Ok(())
+    }
+
+    struct Server {
+        sessions: Mutex<HashMap<u64, Arc<Session>>>,
+        next_id: AtomicU64,
+        next_client: AtomicU64,
+    }
+
+    struct Session {
+        id: u64,
+        pid: Option<u32>,
+        pty: PtySession,
+        shared: Mutex<Shared>,
+    }
+
+    // The output history and the attached client change together, so a
+    // client that attaches sees every byte exactly once.
+    #[derive(Default)]
+    struct Shared {
+        history: VecDeque<u8>,
+        truncated: bool,
+        client: Option<Client>,
+    }
+
+    // Frames reach a client through its own writer thread, so nothing
+    // blocks on a client while holding the session lock.
+    struct Client {
+        id: u64,
+        stream: UnixStream,
+        outbox: Sender<Message>,
+    }
+
+    impl Client {
+        fn new(id: u64, stream: UnixStream) -> io::Result<Self> {
+            let (outbox, frames) = bounded::<Message>(CLIENT_BACKLOG);
+            let mut writer = stream.try_clone()?;
+            thread::Builder::new()
+                .name("mux-writer".to_owned())
+                .spawn(move || {
+                    for message in frames {
+                        if write_message(&mut writer, &message).is_err() {
+                            break;
+                        }
+                    }
+                    let _ = writer.shutdown(Shutdown::Both);
+                })?;
+            Ok(Self { id, stream, outbox })
+        }
+
+        fn disconnect(self) {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    impl Server {
+        fn new() -> Self {
+            // Ids embed the server's pid, so a snapshot taken while an
+            // earlier server ran cannot attach to an unrelated shell.
+            let first_id = u64::from(std::process::id()) << 32 | 1;
+            Self {
+                sessions: Mutex::default(),
+                next_id: AtomicU64::new(first_id),
+                next_client: AtomicU64::new(1),
+            }
+        }
+
+        fn serve_client(self: Arc<Self>, stream: UnixStream) {
+            let Ok(read_half) = stream.try_clone() else {
+                return;
+            };
+            let mut reader = BufReader::new(read_half);
+            let session = match read_message(&mut reader) {
+                Ok(Some(Message::Spawn {
+                    profile,
+                    cols,
+                    rows,
+                })) => self.spawn(&profile, cols, rows),
+                Ok(Some(Message::Attach { id })) => self
+                    .sessions
+                    .lock()
+                    .ok()
+                    .and_then(|sessions| sessions.get(&id).cloned())
+                    .ok_or_else(|| anyhow!("no session {id}")),
+                _ => return,
+            };
+            let session = match session {
+                Ok(session) => session,
+                Err(err) => {
+                    let _ = write_message(&mut &stream, &Message::Error(format!("{err:#}")));
+                    return;
+                }
+            };
+
+            let client = self.next_client.fetch_add(1, Ordering::Relaxed);
+            if session.attach(client, stream).is_err() {
+                return;
+            }
+            loop {
+                match read_message(&mut reader) {
+                    Ok(Some(Message::Input(bytes))) => {
+                        let _ = session.pty.write_bytes(&bytes);
+                    }
+                    Ok(Some(Message::Resize { cols, rows })) => {
+                        let _ = session.pty.resize(cols, rows);
+                    }
+                    Ok(Some(Message::Kill)) => {
+                        session.pty.terminate();
+                        break;
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            session.detach(client);
+        }
+
+        fn spawn(
+            self: &Arc<Self>,
+            profile: &ShellProfile,
+            cols: u16,
+            rows: u16,
+        ) -> Result<Arc<Session>> {
+            let mut pty = PtySession::spawn(profile, cols, rows)?;
+            let session = Arc::new(Session {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                pid: pty.process_id(),
+                pty,
+                shared: Mutex::default(),
+            });
+            self.sessions
+                .lock()
+                .map_err(|_| anyhow!("failed to lock mux sessions"))?
+                .insert(session.id, Arc::clone(&session));
+            let server = Arc::clone(self);
+            let pumped = Arc::clone(&session);
+            thread::Builder::new()
+                .name("mux-session".to_owned())
+                .spawn(move || server.pump(&pumped))
+                .context("failed to spawn mux session thread")?;
+            Ok(session)
+        }
+
+        // Records and forwards a session's output until its shell exits.
+        fn pump(&self, session: &Session) {
+            while let Some(chunk) = session.pty.read_chunk() {
+                session.record(chunk);
+            }
+            if let Ok(mut sessions) = self.sessions.lock() {
+                sessions.remove(&session.id);
+            }
+            // Dropping the client lets its writer flush the last output
+            // before it closes the connection.
+            if let Ok(mut shared) = session.shared.lock() {
+                shared.client = None;
+            }
+        }
+    }
+
+    impl Session {
+        fn attach(&self, client: u64, stream: UnixStream) -> io::Result<()> {
+            let client = Client::new(client, stream)?;
+            let mut shared = self
+                .shared
+                .lock()
+                .map_err(|_| io::Error::other("failed to lock mux session"))?;
+            let ready = Message::Ready {
+                id: self.id,
+                pid: self.pid,
+                replay: shared.replay(),
+            };
+            client
+                .outbox
+                .try_send(ready)
+                .map_err(|_| io::Error::other("mux client writer stopped"))?;
+            // A window that attaches takes the session over.
+            if let Some(previous) = shared.client.replace(client) {
+                previous.disconnect();
+            }
+            Ok(())
+        }
+
+        fn detach(&self, client: u64) {
+            if let Ok(mut shared) = self.shared.lock()
+                && shared
+                    .client
+                    .as_ref()
+                    .is_some_and(|attached| attached.id == client)
+            {
+                shared.client = None;
+            }
+        }
+
+        fn record(&self, chunk: Vec<u8>) {
+            let Ok(mut shared) = self.shared.lock() else {
+                return;
+            };
+            shared.history.extend(&chunk);
+            let excess = shared.history.len().saturating_sub(REPLAY_BYTES);
+            if excess > 0 {
+                shared.history.drain(..excess);
+                shared.truncated = true;
+            }
+            let lost = shared
+                .client
+                .as_ref()
+                .is_some_and(|client| client.outbox.try_send(Message::Output(chunk)).is_err());
+            if lost && let Some(client) = shared.client.take() {
+                client.disconnect();
+            }
+        }
+    }
+
+    impl Shared {
+        // Once older output was dropped the replay starts at a line
+        // boundary rather than inside an escape sequence.
+        fn replay(&self) -> Vec<u8> {
+            let history: Vec<u8> = self.history.iter().copied().collect();
+            match history.iter().position(|byte| *byte == b'\n') {
+                Some(newline) if self.truncated => history[newline + 1..].to_vec(),
+                _ => history,
+            }
+        }
+    }
+
+    /// Connection of a [`PtySession`] to its session in the mux server.
+    pub(crate) struct Connection {
+        id: u64,
+        pid: Option<u32>,
+        stream: UnixStream,
+    }
+
+    impl Connection {
+        pub(crate) fn id(&self) -> u64 {
+            self.id
+        }
+
+        pub(crate) fn pid(&self) -> Option<u32> {
+            self.pid
+        }
+
+        pub(crate) fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+            write_message(&mut &self.stream, &Message::Resize { cols, rows })
+                .context("failed to resize mux session")
+        }
+
+        pub(crate) fn kill(&self) {
+            let _ = write_message(&mut &self.stream, &Message::Kill);
+        }
+    }
+
+    // Presents the Output frames of a connection as a byte stream.
+    struct OutputReader {
+        frames: BufReader<UnixStream>,
+        pending: Vec<u8>,
+    }
+
+    impl Read for OutputReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            while self.pending.is_empty() {
+                match read_message(&mut self.frames)? {
+                    Some(Message::Output(bytes)) => self.pending = bytes,
+                    Some(_) => {}
+                    None => return Ok(0),
+                }
+            }
+            let len = buf.len().min(self.pending.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    // Sends everything written to it as Input frames.
+    struct InputWriter(UnixStream);
+
+    impl Write for InputWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(MAX_INPUT_FRAME);
+            write_message(&mut self.0, &Message::Input(buf[..len].to_vec()))?;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    pub fn spawn(
+        socket: &Path,
+        profile: &ShellProfile,
+        cols: u16,
+        rows: u16,
+    ) -> Result<PtySession> {
+        let stream = connect_or_launch(socket)?;
+        let request = Message::Spawn {
+            profile: profile.clone(),
+            cols,
+            rows,
+        };
+        Ok(open(stream, &request, profile)?.0)
+    }
+
+    pub fn attach(socket: &Path, id: u64, profile: &ShellProfile) -> Result<(PtySession, Vec<u8>)> {
+        // A server started now would have no sessions to attach to.
+        let stream = UnixStream::connect(socket)
+            .with_context(|| format!("no mux server on {}", socket.display()))?;
+        open(stream, &Message::Attach { id }, profile)
+    }
+
+    fn open(
+        stream: UnixStream,
+        request: &Message,
+        profile: &ShellProfile,
+    ) -> Result<(PtySession, Vec<u8>)> {
+        write_message(&mut &stream, request).context("failed to reach the mux server")?;
+        let mut frames = BufReader::new(stream.try_clone()?);
+        let (id, pid, replay) = match read_message(&mut frames)? {
+            Some(Message::Ready { id, pid, replay }) => (id, pid, replay),
+            Some(Message::Error(message)) => bail!("mux server: {message}"),
+            _ => bail!("mux server closed the connection"),
+        };
+        let reader = OutputReader {
+            frames,
+            pending: Vec::new(),
+        };
+        let writer = InputWriter(stream.try_clone()?);
+        let backend = Backend::Mux(Connection { id, pid, stream });
+        let session =
+            PtySession::with_backend(backend, Box::new(reader), Box::new(writer), profile.kind())?;
+        Ok((session, replay))
+    }
+
+    fn connect_or_launch(socket: &Path) -> Result<UnixStream> {
+        // A socket in a directory others can write to may belong to anyone.
+        if let Some(dir) = socket.parent() {
+            private_dir::ensure(dir)?;
+        }
+        if let Ok(stream) = UnixStream::connect(socket) {
+            return Ok(stream);
+        }
+        let exe = std::env::current_exe().context("failed to locate the mux server executable")?;
+        Command::new(exe)
+            .arg(SERVER_FLAG)
+            .arg(socket)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // Keeps it out of signals sent to the window's process group.
+            .process_group(0)
+            .spawn()
+            .context("failed to start the mux server")?;
+        let deadline = Instant::now() + SERVER_START_TIMEOUT;
+        loop {
+            match UnixStream::connect(socket) {
+                Ok(stream) => return Ok(stream),
+                Err(err) if Instant::now() >= deadline => {
+                    return Err(err).with_context(|| {
+                        format!("mux server did not start on {}", socket.display())
+                    });
+                }
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod socket {
+    use std::path::Path;
+
+    use anyhow::{Result, bail};
+
+    use crate::pty::{PtySession, ShellProfile};
+
+    const UNSUPPORTED: &str = "the mux server needs Unix domain sockets";
+
+    pub fn serve(_socket: &Path) -> Result<()> {
+        bail!(UNSUPPORTED)
+    }
+
+    pub fn spawn(
+        _socket: &Path,
+        _profile: &ShellProfile,
+        _cols: u16,
+        _rows: u16,
+    ) -> Result<PtySession> {
+        bail!(UNSUPPORTED)
+    }
+
+    pub fn attach(
+        _socket: &Path,
+        _id: u64,
+        _profile: &ShellProfile,
+    ) -> Result<(PtySession, Vec<u8>)> {
+        bail!(UNSUPPORTED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Message, read_message};
+    use crate::pty::ShellProfile;
+
+    #[test]
+    fn messages_roundtrip_through_frames() {
+        let profile = ShellProfile {
+            args: vec!["-l".to_owned()],
+            env: vec![("LANG".to_owned(), "ko_KR.UTF-8".to_owned())],
+            cwd: Some("/home/user/프로젝트".into()),
+            init_script: None,
+            shell_integration: false,
+            ..ShellProfile::new("/bin/zsh")
+        };
+        let messages = [
+            Message::Spawn {
+                profile,
+                cols: 120,
+                rows: 40,
+            },
+            Message::Attach { id: 7 << 32 | 3 },
+            Message::Input(b"ls\r".to_vec()),
+            Message::Resize { cols: 80, rows: 24 },
+            Message::Kill,
+            Message::Ready {
+                id: 9,
+                pid: Some(4242),
+                replay: b"\x1b[1mhi\x1b[m\r\n".to_vec(),
+            },
+            Message::Ready {
+                id: 9,
+                pid: None,
+                replay: Vec::new(),
+            },
+            Message::Output(vec![0xff, 0x00]),
+            Message::Error("no session 3".to_owned()),
+        ];
+        let stream: Vec<u8> = messages.iter().flat_map(Message::encode).collect();
+        let mut reader = Cursor::new(stream);
+        for message in messages {
+            assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        }
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+
+        let mut truncated = Message::Input(b"abc".to_vec()).encode();
+        truncated.pop();
+        assert!(read_message(&mut Cursor::new(truncated)).is_err());
+        let oversized = u32::MAX.to_le_bytes().to_vec();
+        assert!(read_message(&mut Cursor::new(oversized)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shells_outlive_their_client_and_replay_output() {
+        use std::os::unix::net::UnixStream;
+        use std::time::{Duration, Instant};
+
+        use super::{PtyHost, serve};
+        use crate::pty::PtySession;
+
+        fn wait_for(session: &PtySession, expected: &str) -> String {
+            let deadline = Instant::now() + Duration::from_secs(8);
+            let mut output = Vec::new();
+            while Instant::now() < deadline {
+                match session.try_read_chunk() {
+                    Some(chunk) => output.extend(chunk),
+                    None => std::thread::sleep(Duration::from_millis(20)),
+                }
+                if String::from_utf8_lossy(&output).contains(expected) {
+                    break;
+                }
+            }
+            String::from_utf8_lossy(&output).into_owned()
+        }
+
+        let socket = std::env::temp_dir()
+            .join(format!("ctyterm-mux-test-{}", std::process::id()))
+            .join("mux.sock");
+        let server_socket = socket.clone();
+        std::thread::spawn(move || serve(&server_socket));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while UnixStream::connect(&socket).is_err() {
+            assert!(Instant::now() < deadline, "mux server did not start");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let host = PtyHost::Server(socket.clone());
+        let profile = ShellProfile::new("/bin/sh");
+        let session = host
+            .spawn(&profile, 80, 24)
+            .expect("mux spawn should succeed");
+        let id = session.mux_id().expect("mux sessions have an id");
+        session
+            .write_line("printf '%s%s\\n' __BEFORE _DETACH__")
+            .unwrap();
+        assert!(wait_for(&session, "__BEFORE_DETACH__").contains("__BEFORE_DETACH__"));
+        drop(session);
+
+        let (session, replay) = host.attach(id, &profile).expect("session should be alive");
+        assert!(String::from_utf8_lossy(&replay).contains("__BEFORE_DETACH__"));
+        session
+            .write_line("printf '%s%s\\n' __AFTER _ATTACH__")
+            .unwrap();
+        assert!(wait_for(&session, "__AFTER_ATTACH__").contains("__AFTER_ATTACH__"));
+
+        session.terminate();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while host.attach(id, &profile).is_ok() {
+            assert!(
+                Instant::now() < deadline,
+                "killed session is still attachable"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_client_that_stops_reading_does_not_stall_its_session() {
+        use std::os::unix::net::UnixStream;
+        use std::time::{Duration, Instant};
+
+        use super::{Message, PtyHost, serve, write_message};
+
+        let socket = std::env::temp_dir()
+            .join(format!("ctyterm-mux-stall-{}", std::process::id()))
+            .join("mux.sock");
+        let server_socket = socket.clone();
+        std::thread::spawn(move || serve(&server_socket));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while UnixStream::connect(&socket).is_err() {
+            assert!(Instant::now() < deadline, "mux server did not start");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let host = PtyHost::Server(socket.clone());
+        let profile = ShellProfile::new("/bin/sh");
+        let session = host.spawn(&profile, 80, 24).expect("mux spawn");
+        let id = session.mux_id().expect("mux sessions have an id");
+        drop(session);
+
+        // Attaches, floods the shell's output and never reads it.
+        let stalled = UnixStream::connect(&socket).unwrap();
+        write_message(&mut &stalled, &Message::Attach { id }).unwrap();
+        write_message(
+            &mut &stalled,
+            &Message::Input(b"head -c 50000000 /dev/zero | tr '\\0' x\n".to_vec()),
+        )
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+
+        let (session, _) = host.attach(id, &profile).expect("session should attach");
+        session.terminate();
+        drop(stalled);
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_second_server_leaves_the_running_one_alone() {
+        use std::os::unix::net::UnixStream;
+        use std::time::{Duration, Instant};
+
+        use super::serve;
+
+        let socket = std::env::temp_dir()
+            .join(format!("ctyterm-mux-twice-{}", std::process::id()))
+            .join("mux.sock");
+        let server_socket = socket.clone();
+        std::thread::spawn(move || serve(&server_socket));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while UnixStream::connect(&socket).is_err() {
+            assert!(Instant::now() < deadline, "mux server did not start");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        assert!(serve(&socket).is_err());
+        assert!(UnixStream::connect(&socket).is_ok());
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn server_refuses_a_socket_directory_others_can_write_to() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("ctyterm-mux-open-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+        let socket = dir.join("mux.sock");
+        assert!(super::serve(&socket).is_err());
+        assert!(!socket.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
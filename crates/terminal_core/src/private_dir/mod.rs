@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Creates `dir` so that only the current user can enter it, or checks that
+/// an existing one already is: a real directory owned by this user with no
+/// group or other access. Its parents are created with default permissions.
+#[cfg(unix)]
+pub(crate) fn ensure(dir: &Path) -> Result<()> {
+    use std::fs::{self, DirBuilder};
+    use std::io::ErrorKind;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    use anyhow::{Context, bail};
+
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to create {}", dir.display()));
+        }
+    }
+    // Not following links: a link planted by someone else must not be
+    // trusted even if it points at a directory of ours.
+    let meta = fs::symlink_metadata(dir)
+        .with_context(|| format!("failed to inspect {}", dir.display()))?;
+    if !meta.is_dir() {
+        bail!("{} is not a directory", dir.display());
+    }
+    if meta.uid() != uid() {
+        bail!("{} is owned by another user", dir.display());
+    }
+    if meta.mode() & 0o077 != 0 {
+        bail!(
+            "{} is accessible to other users (mode {:o})",
+            dir.display(),
+            meta.mode() & 0o777
+        );
+    }
+    Ok(())
+}
+
+/// Windows keeps per-user directories private through their ACLs.
+#[cfg(not(unix))]
+pub(crate) fn ensure(dir: &Path) -> Result<()> {
+    use anyhow::Context;
+
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))
+}
+
+#[cfg(unix)]
+pub(crate) fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    unsafe { libc::getuid() }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    use super::ensure;
+
+    #[test]
+    fn only_private_directories_of_this_user_are_accepted() {
+        let base = std::env::temp_dir().join(format!("ctyterm-private-dir-{}", std::process::id()));
+        let dir = base.join("fresh");
+        ensure(&dir).expect("new directory should be created");
+        let mode = fs::metadata(&dir).expect("metadata").permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        ensure(&dir).expect("existing private directory is accepted");
+
+        let open = base.join("open");
+        fs::create_dir(&open).expect("create open dir");
+        fs::set_permissions(&open, fs::Permissions::from_mode(0o777)).expect("chmod");
+        assert!(ensure(&open).is_err());
+
+        let link = base.join("link");
+        symlink(&dir, &link).expect("symlink");
+        assert!(ensure(&link).is_err());
+
+        let _ = fs::remove_dir_all(&base);
+    }
+}
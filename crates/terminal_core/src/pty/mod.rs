@@ -1,21 +1,154 @@
 use std::io::{Read, Write};
-use std::sync::{Arc, Mutex};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
 use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow};
-use crossbeam_channel::{Receiver, unbounded};
+use crossbeam_channel::{Receiver, Sender, bounded};
 use portable_pty::{Child, CommandBuilder, MasterPty, PtySize, native_pty_system};
 
+use crate::asciicast::Recorder;
+#[cfg(unix)]
+use crate::mux;
+use crate::shell_integration;
+use crate::tmux;
+
+const POWERSHELL_UTF8_PREAMBLE: &str = "$OutputEncoding=[System.Text.Encoding]::UTF8; \
+[Console]::InputEncoding=[System.Text.Encoding]::UTF8; \
+[Console]::OutputEncoding=[System.Text.Encoding]::UTF8; \
+chcp.com 65001 > $null";
+
+const READ_CHUNK_BYTES: usize = 16 * 1024;
+// Once this many reads are queued the reader blocks, the kernel buffer fills
+// and the child stalls on write instead of the UI buffering without bound.
+const CHANNEL_CAPACITY: usize = 16;
+/// Upper bound on the bytes returned by one [`PtySession::try_read_chunk`].
+pub const MAX_BATCH_BYTES: usize = 64 * 1024;
+
+/// Called from the reader thread whenever new output is queued.
+pub type Wakeup = Box<dyn Fn() + Send + Sync>;
+
+pub(crate) type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ShellKind {
+    PowerShell,
+    Bash,
+    Zsh,
+    Fish,
+    Other,
+}
+
+impl ShellKind {
+    pub fn detect(program: &str) -> Self {
+        let file_name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+        let name = file_name.to_ascii_lowercase();
+        let name = name.strip_suffix(".exe").unwrap_or(&name);
+        match name {
+            "powershell" | "pwsh" => Self::PowerShell,
+            "bash" => Self::Bash,
+            "zsh" => Self::Zsh,
+            "fish" => Self::Fish,
+            _ => Self::Other,
+        }
+    }
+
+    // PowerShell's PSReadLine expects CRLF; POSIX shells read a bare CR as
+    // Enter through the tty's ICRNL translation.
+    pub fn line_ending(self) -> &'static str {
+        match self {
+            Self::PowerShell => "\r\n",
+            _ => "\r",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ShellProfile {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub cwd: Option<PathBuf>,
+    pub init_script: Option<String>,
+    pub shell_integration: bool,
+}
+
+impl ShellProfile {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            env: Vec::new(),
+            cwd: None,
+            init_script: None,
+            shell_integration: true,
+        }
+    }
+
+    pub fn powershell() -> Self {
+        Self {
+            args: vec![
+                "-NoLogo".to_owned(),
+                "-NoExit".to_owned(),
+                "-Command".to_owned(),
+                POWERSHELL_UTF8_PREAMBLE.to_owned(),
+            ],
+            ..Self::new("powershell.exe")
+        }
+    }
+
+    #[cfg(windows)]
+    pub fn platform_default() -> Self {
+        Self::powershell()
+    }
+
+    #[cfg(not(windows))]
+    pub fn platform_default() -> Self {
+        let program = std::env::var("SHELL")
+            .ok()
+            .filter(|shell| !shell.trim().is_empty())
+            .unwrap_or_else(|| "/bin/sh".to_owned());
+        Self::new(program)
+    }
+
+    pub fn kind(&self) -> ShellKind {
+        ShellKind::detect(&self.program)
+    }
+}
+
+impl Default for ShellProfile {
+    fn default() -> Self {
+        Self::platform_default()
+    }
+}
+
 pub struct PtySession {
-    master: Box<dyn MasterPty + Send>,
-    child: Box<dyn Child + Send>,
-    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    backend: Backend,
+    writer: SharedWriter,
     receiver: Receiver<Vec<u8>>,
+    wakeup: Arc<OnceLock<Wakeup>>,
+    shell_kind: ShellKind,
+    recorder: Mutex<Option<Recorder>>,
+}
+
+pub(crate) enum Backend {
+    Local {
+        master: Mutex<Box<dyn MasterPty + Send>>,
+        child: Mutex<Box<dyn Child + Send>>,
+    },
+    // A shell owned by the mux server; dropping the session only detaches.
+    #[cfg(unix)]
+    Mux(mux::Connection),
+    // A pane of a `tmux -CC` client; commands go through the PTY running it.
+    Tmux {
+        pane: u32,
+        gateway: SharedWriter,
+    },
 }
 
 impl PtySession {
-    pub fn spawn_powershell(cols: u16, rows: u16) -> Result<Self> {
+    pub fn spawn(profile: &ShellProfile, cols: u16, rows: u16) -> Result<Self> {
         let pty_system = native_pty_system();
         let pair = pty_system
             .openpty(PtySize {
@@ -26,26 +159,28 @@ impl PtySession {
             })
             .context("failed to open PTY pair")?;
 
-        let mut cmd = CommandBuilder::new("powershell.exe");
-        cmd.arg("-NoLogo");
-        cmd.arg("-NoExit");
-        cmd.arg("-Command");
-        cmd.arg(
-            "$OutputEncoding=[System.Text.Encoding]::UTF8; \
-[Console]::InputEncoding=[System.Text.Encoding]::UTF8; \
-[Console]::OutputEncoding=[System.Text.Encoding]::UTF8; \
-chcp.com 65001 > $null",
-        );
-        if let Ok(cwd) = std::env::current_dir() {
-            cmd.cwd(cwd);
+        let mut cmd = CommandBuilder::new(&profile.program);
+        cmd.args(&profile.args);
+        cmd.env("TERM", "xterm-256color");
+        cmd.env("COLORTERM", "truecolor");
+        for (key, value) in &profile.env {
+            cmd.env(key, value);
+        }
+        match &profile.cwd {
+            Some(cwd) => cmd.cwd(cwd),
+            None => {
+                if let Ok(cwd) = std::env::current_dir() {
+                    cmd.cwd(cwd);
+                }
+            }
         }
 
         let child = pair
             .slave
             .spawn_command(cmd)
-            .context("failed to spawn powershell in PTY")?;
+            .with_context(|| format!("failed to spawn {} in PTY", profile.program))?;
 
-        let mut reader = pair
+        let reader = pair
             .master
             .try_clone_reader()
             .context("failed to clone PTY reader")?;
@@ -53,152 +188,489 @@ chcp.com 65001 > $null",
             .master
             .take_writer()
             .context("failed to take PTY writer")?;
-        let writer = Arc::new(Mutex::new(writer));
-        let reader_writer = Arc::clone(&writer);
+        let backend = Backend::Local {
+            master: Mutex::new(pair.master),
+            child: Mutex::new(child),
+        };
+        let session = Self::with_backend(backend, reader, writer, profile.kind())?;
+        // Integration is best effort; the shell still works without marks.
+        if profile.shell_integration
+            && let Ok(Some(source)) = shell_integration::install(session.shell_kind)
+        {
+            session.write_line(&source)?;
+        }
+        if let Some(script) = profile
+            .init_script
+            .as_deref()
+            .filter(|script| !script.trim().is_empty())
+        {
+            session.write_line(script)?;
+        }
+        Ok(session)
+    }
 
-        let (sender, receiver) = unbounded();
+    /// Wraps a shell's output and input streams, reading the output on a
+    /// thread of its own.
+    pub(crate) fn with_backend(
+        backend: Backend,
+        mut reader: Box<dyn Read + Send>,
+        writer: Box<dyn Write + Send>,
+        shell_kind: ShellKind,
+    ) -> Result<Self> {
+        let (sender, receiver) = bounded(CHANNEL_CAPACITY);
+        let wakeup = Arc::new(OnceLock::new());
+        let reader_wakeup = Arc::clone(&wakeup);
         thread::Builder::new()
             .name("pty-reader".to_owned())
-            .spawn(move || {
-                let mut buf = [0_u8; 4096];
-                loop {
-                    match reader.read(&mut buf) {
-                        Ok(0) => break,
-                        Ok(n) => {
-                            respond_to_terminal_queries(&buf[..n], &reader_writer);
-                            if sender.send(buf[..n].to_vec()).is_err() {
-                                break;
-                            }
-                        }
-                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
-                        Err(err)
-                            if matches!(
-                                err.kind(),
-                                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
-                            ) =>
-                        {
-                            thread::sleep(Duration::from_millis(8));
-                            continue;
-                        }
-                        Err(_) => break,
-                    }
-                }
-            })
+            .spawn(move || pump_output(&mut reader, &sender, &reader_wakeup))
             .context("failed to spawn PTY reader thread")?;
-
         Ok(Self {
-            master: pair.master,
-            child,
-            writer,
+            backend,
+            writer: Arc::new(Mutex::new(writer)),
             receiver,
+            wakeup,
+            shell_kind,
+            recorder: Mutex::new(None),
         })
     }
 
+    /// Returns everything queued so far, coalesced up to roughly
+    /// [`MAX_BATCH_BYTES`].
     pub fn try_read_chunk(&self) -> Option<Vec<u8>> {
-        self.receiver.try_recv().ok()
+        let batch = drain_batch(&self.receiver, MAX_BATCH_BYTES)?;
+        self.record(|recorder| recorder.output(&batch));
+        Some(batch)
+    }
+
+    /// Like [`Self::try_read_chunk`] but waits for output. `None` once the
+    /// shell has closed the PTY and everything queued was read.
+    pub fn read_chunk(&self) -> Option<Vec<u8>> {
+        let mut batch = self.receiver.recv().ok()?;
+        while batch.len() < MAX_BATCH_BYTES
+            && let Ok(chunk) = self.receiver.try_recv()
+        {
+            batch.extend_from_slice(&chunk);
+        }
+        self.record(|recorder| recorder.output(&batch));
+        Some(batch)
+    }
+
+    /// Installs the callback used to wake the UI when output arrives. Only
+    /// the first call has an effect.
+    pub fn set_wakeup(&self, wakeup: Wakeup) {
+        let _ = self.wakeup.set(wakeup);
+    }
+
+    pub fn has_wakeup(&self) -> bool {
+        self.wakeup.get().is_some()
     }
 
     pub fn write_input(&self, input: &str) -> Result<()> {
+        self.write_bytes(input.as_bytes())
+    }
+
+    pub fn write_bytes(&self, bytes: &[u8]) -> Result<()> {
         let mut writer = self
             .writer
             .lock()
             .map_err(|_| anyhow!("failed to lock PTY writer"))?;
-        writer
-            .write_all(input.as_bytes())
-            .context("failed writing to PTY")?;
+        writer.write_all(bytes).context("failed writing to PTY")?;
         writer.flush().context("failed flushing PTY writer")?;
+        drop(writer);
+        self.record(|recorder| recorder.input(bytes));
         Ok(())
     }
 
+    pub fn write_line(&self, line: &str) -> Result<()> {
+        self.write_input(&format!("{line}{}", self.shell_kind.line_ending()))
+    }
+
+    pub fn shell_kind(&self) -> ShellKind {
+        self.shell_kind
+    }
+
     pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
-        self.master
-            .resize(PtySize {
-                rows,
-                cols,
-                pixel_width: 0,
-                pixel_height: 0,
-            })
-            .context("failed to resize PTY")?;
+        match &self.backend {
+            Backend::Local { master, .. } => master
+                .lock()
+                .map_err(|_| anyhow!("failed to lock PTY master"))?
+                .resize(PtySize {
+                    rows,
+                    cols,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                })
+                .context("failed to resize PTY")?,
+            #[cfg(unix)]
+            Backend::Mux(connection) => {
+                // Held so the request is not interleaved with input frames.
+                let _writer = self
+                    .writer
+                    .lock()
+                    .map_err(|_| anyhow!("failed to lock PTY writer"))?;
+                connection.resize(cols, rows)?;
+            }
+            // tmux sizes its panes from the client size and reports them in
+            // layout changes.
+            Backend::Tmux { .. } => return Ok(()),
+        }
+        self.record(|recorder| recorder.resize(cols, rows));
         Ok(())
     }
 
+    /// Starts writing everything this session reads, and its input if the
+    /// recorder asks for it, replacing any recording in progress.
+    pub fn start_recording(&self, recorder: Recorder) {
+        if let Ok(mut slot) = self.recorder.lock() {
+            *slot = Some(recorder);
+        }
+    }
+
+    /// Returns whether a recording was in progress.
+    pub fn stop_recording(&self) -> bool {
+        let Some(recorder) = self.recorder.lock().ok().and_then(|mut slot| slot.take()) else {
+            return false;
+        };
+        let _ = recorder.finish();
+        true
+    }
+
+    /// False again once a write to the recording failed.
+    pub fn is_recording(&self) -> bool {
+        self.recorder.lock().is_ok_and(|slot| slot.is_some())
+    }
+
+    fn record(&self, write: impl FnOnce(&mut Recorder) -> Result<()>) {
+        let Ok(mut slot) = self.recorder.lock() else {
+            return;
+        };
+        if let Some(recorder) = slot.as_mut()
+            && write(recorder).is_err()
+        {
+            *slot = None;
+        }
+    }
+
     pub fn process_id(&mut self) -> Option<u32> {
-        self.child.process_id()
+        match &mut self.backend {
+            Backend::Local { child, .. } => child.get_mut().ok()?.process_id(),
+            #[cfg(unix)]
+            Backend::Mux(connection) => connection.pid(),
+            Backend::Tmux { .. } => None,
+        }
+    }
+
+    /// Id of the session in the mux server, when the shell runs there.
+    pub fn mux_id(&self) -> Option<u64> {
+        match &self.backend {
+            #[cfg(unix)]
+            Backend::Mux(connection) => Some(connection.id()),
+            _ => None,
+        }
+    }
+
+    /// Number of the tmux pane, when this session mirrors one.
+    pub fn tmux_pane(&self) -> Option<u32> {
+        match &self.backend {
+            Backend::Tmux { pane, .. } => Some(*pane),
+            _ => None,
+        }
     }
-}
 
-fn respond_to_terminal_queries(chunk: &[u8], writer: &Arc<Mutex<Box<dyn Write + Send>>>) {
-    let has_dsr =
-        chunk.windows(4).any(|w| w == b"\x1b[6n") || chunk.windows(5).any(|w| w == b"\x1b[?6n");
+    /// Runs a tmux command through the control client this pane belongs to.
+    pub fn tmux_command(&self, command: &str) -> Result<()> {
+        let Backend::Tmux { gateway, .. } = &self.backend else {
+            return Err(anyhow!("not a tmux pane"));
+        };
+        let mut gateway = gateway
+            .lock()
+            .map_err(|_| anyhow!("failed to lock tmux client"))?;
+        writeln!(gateway, "{command}").context("failed writing tmux command")?;
+        gateway.flush().context("failed flushing tmux command")?;
+        Ok(())
+    }
+
+    pub(crate) fn shared_writer(&self) -> SharedWriter {
+        Arc::clone(&self.writer)
+    }
+
+    /// Ends the shell. Unlike dropping the session this also stops shells
+    /// kept by the mux server.
+    pub fn terminate(&self) {
+        match &self.backend {
+            Backend::Local { child, .. } => {
+                if let Ok(mut child) = child.lock() {
+                    let _ = child.kill();
+                }
+            }
+            #[cfg(unix)]
+            Backend::Mux(connection) => {
+                if let Ok(_writer) = self.writer.lock() {
+                    connection.kill();
+                }
+            }
+            Backend::Tmux { pane, .. } => {
+                let _ = self.tmux_command(&tmux::kill_pane(*pane));
+            }
+        }
+    }
+}
 
-    if !has_dsr {
-        return;
+fn pump_output(reader: &mut dyn Read, sender: &Sender<Vec<u8>>, wakeup: &OnceLock<Wakeup>) {
+    let mut buf = vec![0_u8; READ_CHUNK_BYTES];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                // Blocks while the UI is behind; fails once the session is gone.
+                if sender.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+                if let Some(wakeup) = wakeup.get() {
+                    wakeup();
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                thread::sleep(Duration::from_millis(8));
+                continue;
+            }
+            Err(_) => break,
+        }
     }
+}
 
-    if let Ok(mut w) = writer.lock() {
-        let _ = w.write_all(b"\x1b[1;1R");
-        let _ = w.flush();
+fn drain_batch(receiver: &Receiver<Vec<u8>>, max_bytes: usize) -> Option<Vec<u8>> {
+    let mut batch = receiver.try_recv().ok()?;
+    while batch.len() < max_bytes {
+        match receiver.try_recv() {
+            Ok(chunk) => batch.extend_from_slice(&chunk),
+            Err(_) => break,
+        }
     }
+    Some(batch)
 }
 
 impl Drop for PtySession {
     fn drop(&mut self) {
-        let _ = self.child.kill();
+        if let Backend::Local { child, .. } = &mut self.backend
+            && let Ok(child) = child.get_mut()
+        {
+            let _ = child.kill();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::PtySession;
+    use super::{
+        CHANNEL_CAPACITY, MAX_BATCH_BYTES, PtySession, READ_CHUNK_BYTES, ShellKind, ShellProfile,
+        Wakeup, drain_batch, pump_output,
+    };
+    use crate::asciicast::{Cast, EventKind, Header, Recorder};
+    use crate::emulator::{TerminalEmulator, TerminalEvent};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, OnceLock};
     use std::time::{Duration, Instant};
 
     #[cfg(windows)]
-    #[test]
-    fn powershell_pty_roundtrip_emits_output() {
-        let session = PtySession::spawn_powershell(120, 40).expect("pty spawn should succeed");
-        session
-            .write_input("Write-Output '__PTY_OK__'\r\n")
-            .expect("pty write should succeed");
+    fn test_profile() -> ShellProfile {
+        ShellProfile::powershell()
+    }
+
+    #[cfg(not(windows))]
+    fn test_profile() -> ShellProfile {
+        ShellProfile::new("/bin/sh")
+    }
+
+    // The marker is assembled by the shell so the echoed input line never
+    // matches on its own.
+    #[cfg(windows)]
+    fn print_joined(left: &str, right: &str) -> String {
+        format!("Write-Output ('{left}' + '{right}')")
+    }
+
+    #[cfg(not(windows))]
+    fn print_joined(left: &str, right: &str) -> String {
+        format!("printf '%s%s\\n' '{left}' '{right}'")
+    }
 
+    #[cfg(windows)]
+    fn print_env(name: &str, suffix: &str) -> String {
+        format!("Write-Output ($env:{name} + '{suffix}')")
+    }
+
+    #[cfg(not(windows))]
+    fn print_env(name: &str, suffix: &str) -> String {
+        format!("printf '%s%s\\n' \"${name}\" '{suffix}'")
+    }
+
+    // Queries are answered like the UI does, since ConPTY waits for a cursor
+    // position report before starting the shell.
+    fn wait_for_output(session: &PtySession, expected: &str) -> Result<(), String> {
         let deadline = Instant::now() + Duration::from_secs(8);
+        let mut emulator = TerminalEmulator::new(120, 40);
         let mut combined = Vec::new();
         while Instant::now() < deadline {
             if let Some(chunk) = session.try_read_chunk() {
+                for event in emulator.feed(&chunk) {
+                    if let TerminalEvent::Reply(reply) = event {
+                        let _ = session.write_bytes(&reply);
+                    }
+                }
                 combined.extend(chunk);
-                if String::from_utf8_lossy(&combined).contains("__PTY_OK__") {
-                    return;
+                if String::from_utf8_lossy(&combined).contains(expected) {
+                    return Ok(());
                 }
             } else {
                 std::thread::sleep(Duration::from_millis(20));
             }
         }
+        Err(String::from_utf8_lossy(&combined).to_string())
+    }
+
+    #[test]
+    fn reader_applies_backpressure_and_batches_output() {
+        let (sender, receiver) = crossbeam_channel::bounded(CHANNEL_CAPACITY);
+        let wakeups = Arc::new(AtomicUsize::new(0));
+        let wakeup = Arc::new(OnceLock::<Wakeup>::new());
+        let counter = Arc::clone(&wakeups);
+        let _ = wakeup.set(Box::new(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+
+        let total = READ_CHUNK_BYTES * CHANNEL_CAPACITY * 4;
+        let reader = std::thread::spawn(move || {
+            let mut input = std::io::Cursor::new(vec![b'x'; total]);
+            pump_output(&mut input, &sender, &wakeup);
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(receiver.len() <= CHANNEL_CAPACITY);
+        assert!(!reader.is_finished());
+
+        let mut received = 0;
+        while received < total {
+            match drain_batch(&receiver, MAX_BATCH_BYTES) {
+                Some(batch) => {
+                    assert!(batch.len() < MAX_BATCH_BYTES + READ_CHUNK_BYTES);
+                    received += batch.len();
+                }
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        reader.join().unwrap();
+        assert_eq!(received, total);
+        assert!(wakeups.load(Ordering::Relaxed) > 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bash_integration_reports_exit_status() {
+        if !std::path::Path::new("/bin/bash").exists() {
+            return;
+        }
+        let profile = ShellProfile {
+            args: vec!["--norc".to_owned(), "--noprofile".to_owned()],
+            ..ShellProfile::new("/bin/bash")
+        };
+        let session = PtySession::spawn(&profile, 120, 40).expect("pty spawn should succeed");
+        session
+            .write_line("(exit 3)")
+            .expect("pty write should succeed");
+
+        if let Err(output) = wait_for_output(&session, "\x1b]133;D;3\x07") {
+            panic!("did not receive command finished mark; output={output:?}");
+        }
+    }
 
-        let output = String::from_utf8_lossy(&combined).to_string();
-        panic!("did not receive expected marker from powershell PTY; output={output}");
+    #[test]
+    fn shell_kind_is_detected_from_program_path() {
+        assert_eq!(ShellKind::detect("/usr/bin/zsh"), ShellKind::Zsh);
+        assert_eq!(ShellKind::detect("/bin/bash"), ShellKind::Bash);
+        assert_eq!(
+            ShellKind::detect("C:\\Program Files\\PowerShell\\7\\pwsh.exe"),
+            ShellKind::PowerShell
+        );
+        assert_eq!(ShellKind::detect("powershell.exe"), ShellKind::PowerShell);
+        assert_eq!(ShellKind::detect("/bin/sh"), ShellKind::Other);
     }
 
-    #[cfg(windows)]
     #[test]
-    fn powershell_pty_roundtrip_emits_korean_output() {
-        let session = PtySession::spawn_powershell(120, 40).expect("pty spawn should succeed");
+    fn pty_roundtrip_emits_output() {
+        let session =
+            PtySession::spawn(&test_profile(), 120, 40).expect("pty spawn should succeed");
         session
-            .write_input("$text='한글테스트'; Write-Output $text\r\n")
+            .write_line(&print_joined("__PTY_", "OK__"))
             .expect("pty write should succeed");
 
-        let deadline = Instant::now() + Duration::from_secs(8);
-        let mut combined = Vec::new();
-        while Instant::now() < deadline {
-            if let Some(chunk) = session.try_read_chunk() {
-                combined.extend(chunk);
-                if String::from_utf8_lossy(&combined).contains("한글테스트") {
-                    return;
-                }
-            } else {
-                std::thread::sleep(Duration::from_millis(20));
-            }
+        if let Err(output) = wait_for_output(&session, "__PTY_OK__") {
+            panic!("did not receive expected marker from shell PTY; output={output}");
+        }
+    }
+
+    #[test]
+    fn pty_roundtrip_emits_korean_output() {
+        let session =
+            PtySession::spawn(&test_profile(), 120, 40).expect("pty spawn should succeed");
+        session
+            .write_line(&print_joined("한글", "테스트"))
+            .expect("pty write should succeed");
+
+        if let Err(output) = wait_for_output(&session, "한글테스트") {
+            panic!("did not receive expected korean text from shell PTY; output={output}");
+        }
+    }
+
+    #[test]
+    fn init_script_and_env_are_applied() {
+        let mut profile = test_profile();
+        profile
+            .env
+            .push(("CTYTERM_PROFILE_TEST".to_owned(), "__FROM_ENV".to_owned()));
+        profile.init_script = Some(print_env("CTYTERM_PROFILE_TEST", "_OK__"));
+        let session = PtySession::spawn(&profile, 120, 40).expect("pty spawn should succeed");
+
+        if let Err(output) = wait_for_output(&session, "__FROM_ENV_OK__") {
+            panic!("init script did not run; output={output}");
+        }
+    }
+
+    #[test]
+    fn recording_captures_output_input_and_resizes() {
+        let path =
+            std::env::temp_dir().join(format!("ctyterm-pty-recording-{}.cast", std::process::id()));
+        let session =
+            PtySession::spawn(&test_profile(), 120, 40).expect("pty spawn should succeed");
+        let recorder = Recorder::create(&path, &Header::new(120, 40), true)
+            .expect("recorder should be created");
+        session.start_recording(recorder);
+        session
+            .write_line(&print_joined("__REC_", "OK__"))
+            .expect("pty write should succeed");
+        if let Err(output) = wait_for_output(&session, "__REC_OK__") {
+            panic!("did not receive expected marker from shell PTY; output={output}");
         }
+        session.resize(100, 30).expect("resize should succeed");
+        assert!(session.stop_recording());
+        assert!(!session.is_recording());
 
-        let output = String::from_utf8_lossy(&combined).to_string();
-        panic!("did not receive expected korean text from powershell PTY; output={output}");
+        let cast = Cast::load(&path).expect("recording should load");
+        let _ = std::fs::remove_file(&path);
+        let data = |kind| {
+            cast.events
+                .iter()
+                .filter(|event| event.kind == kind)
+                .map(|event| event.data.as_str())
+                .collect::<String>()
+        };
+        assert!(data(EventKind::Output).contains("__REC_OK__"));
+        assert!(data(EventKind::Input).contains("__REC_"));
+        assert_eq!(data(EventKind::Resize), "100x30");
     }
 }
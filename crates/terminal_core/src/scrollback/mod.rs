@@ -1,40 +1,919 @@
+use std::borrow::Cow;
 use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-#[derive(Debug, Clone)]
+use anyhow::{Context, Result, anyhow};
+
+use crate::graphics::{ImagePlacement, InlineImage};
+use crate::private_dir;
+use crate::style::{CellAttrs, Color, ImageAnchor, LinkRange, StyledLine, StyledRange};
+
+const UNAVAILABLE_LINE: &str = "<scrollback page unavailable>";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ScrollbackConfig {
+    /// Bytes of line data held in memory. Compressed pages count at their
+    /// compressed size; spilled pages do not count.
+    pub memory_budget: usize,
+    /// Bytes of spilled pages kept on disk. Past it the oldest spilled pages
+    /// are dropped and the spill file is rewritten without them.
+    pub disk_budget: u64,
+    pub page_lines: usize,
+    /// Where pages that no longer fit the budget are written. Without one,
+    /// the oldest lines are dropped instead.
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        Self {
+            memory_budget: 32 * 1024 * 1024,
+            disk_budget: 256 * 1024 * 1024,
+            page_lines: 256,
+            spill_dir: Some(default_spill_dir()),
+        }
+    }
+}
+
+/// A per-user cache directory: `$XDG_CACHE_HOME/ctyterm/scrollback` or
+/// `~/.cache/ctyterm/scrollback`, falling back to a directory in the temp
+/// dir named after the uid. Spilled pages hold terminal output, so the
+/// directory is created private and refused when someone else owns it.
+#[cfg(unix)]
+pub fn default_spill_dir() -> PathBuf {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(|home| PathBuf::from(home).join(".cache"))
+        });
+    match cache {
+        Some(cache) => cache.join("ctyterm").join("scrollback"),
+        None => std::env::temp_dir().join(format!("ctyterm-scrollback-{}", private_dir::uid())),
+    }
+}
+
+/// The temp dir is already per user on Windows.
+#[cfg(not(unix))]
+pub fn default_spill_dir() -> PathBuf {
+    std::env::temp_dir().join("ctyterm-scrollback")
+}
+
+#[derive(Debug)]
+enum PageData {
+    Hot(Arc<Vec<StyledLine>>),
+    Compressed(Vec<u8>),
+    Spilled { offset: u64, len: usize },
+}
+
+impl PageData {
+    fn spilled_bytes(&self) -> u64 {
+        match self {
+            PageData::Spilled { len, .. } => *len as u64,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Page {
+    id: u64,
+    // Index of the page's first line, counting lines dropped from the front.
+    start: usize,
+    lines: usize,
+    hot_bytes: usize,
+    data: PageData,
+}
+
+impl Page {
+    fn memory_cost(&self) -> usize {
+        match &self.data {
+            PageData::Hot(_) => self.hot_bytes,
+            PageData::Compressed(bytes) => bytes.len(),
+            PageData::Spilled { .. } => 0,
+        }
+    }
+}
+
+/// Append-only line store. Lines are grouped into fixed-size pages; once the
+/// memory budget is exceeded the oldest pages are compressed, then spilled to
+/// a temporary file.
+///
+/// Each entry is a logical line ended by a hard newline; soft wraps are never
+/// stored, so the text rewraps to whatever width it is shown at.
+#[derive(Debug, Clone, Default)]
 pub struct ScrollbackBuffer {
-    capacity: usize,
-    lines: VecDeque<String>,
+    config: ScrollbackConfig,
+    pages: VecDeque<Arc<Page>>,
+    tail: Vec<StyledLine>,
+    tail_bytes: usize,
+    sealed_bytes: usize,
+    sealed_lines: usize,
+    // Bytes of this buffer's pages in the spill file; the file itself also
+    // holds pages dropped since it was last compacted.
+    spilled_bytes: u64,
+    dropped: usize,
+    next_page_id: u64,
+    spill: Option<Arc<SpillFile>>,
+    cache: PageCache,
 }
 
 impl ScrollbackBuffer {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(config: ScrollbackConfig) -> Self {
         Self {
-            capacity,
-            lines: VecDeque::with_capacity(capacity),
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn config(&self) -> &ScrollbackConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ScrollbackConfig) {
+        if self.config == config {
+            return;
+        }
+        if self.config.spill_dir != config.spill_dir {
+            self.spill = None;
+        }
+        self.config = config;
+        self.enforce_budget();
+    }
+
+    /// Number of retained lines.
+    pub fn len(&self) -> usize {
+        self.sealed_lines + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty() && self.tail.is_empty()
+    }
+
+    /// Lines evicted from the front because they could not be spilled or
+    /// did not fit the disk budget.
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped
+    }
+
+    /// Counts `lines` as already evicted, for output restored without its
+    /// oldest lines. Has no effect once lines were pushed.
+    pub fn with_dropped_lines(mut self, lines: usize) -> Self {
+        if self.is_empty() {
+            self.dropped = lines;
         }
+        self
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.sealed_bytes + self.tail_bytes
     }
 
-    pub fn push_line(&mut self, line: String) {
-        if self.lines.len() >= self.capacity {
-            self.lines.pop_front();
+    pub fn disk_usage(&self) -> u64 {
+        self.spilled_bytes
+    }
+
+    /// Size of the spill file, including space not yet reclaimed.
+    pub fn spill_file_len(&self) -> u64 {
+        self.spill.as_ref().map_or(0, |spill| spill.len())
+    }
+
+    pub fn push(&mut self, line: StyledLine) {
+        self.tail_bytes += line_cost(&line);
+        self.tail.push(line);
+        if self.tail.len() >= self.config.page_lines.max(1) {
+            self.seal_tail();
         }
-        self.lines.push_back(line);
     }
 
-    pub fn extend(&mut self, lines: impl IntoIterator<Item = String>) {
+    pub fn extend(&mut self, lines: impl IntoIterator<Item = StyledLine>) {
         for line in lines {
-            self.push_line(line);
+            self.push(line);
+        }
+    }
+
+    pub fn line(&self, idx: usize) -> Option<StyledLine> {
+        self.lines(idx..idx.saturating_add(1)).pop()
+    }
+
+    pub fn lines(&self, range: Range<usize>) -> Vec<StyledLine> {
+        let len = self.len();
+        let range = range.start.min(len)..range.end.min(len);
+        let mut out = Vec::with_capacity(range.len());
+        if range.is_empty() {
+            return out;
+        }
+
+        let first = self.dropped + range.start;
+        let last = self.dropped + range.end;
+        let first_page = self
+            .pages
+            .partition_point(|page| page.start + page.lines <= first);
+        for page in self.pages.iter().skip(first_page) {
+            if page.start >= last {
+                break;
+            }
+            let contents = self.load(page);
+            let from = first.saturating_sub(page.start);
+            let to = (last - page.start).min(page.lines);
+            out.extend(contents[from..to].iter().cloned());
+        }
+
+        let tail_start = len - self.tail.len();
+        if range.end > tail_start {
+            let from = range.start.saturating_sub(tail_start);
+            out.extend(self.tail[from..range.end - tail_start].iter().cloned());
+        }
+        out
+    }
+
+    pub fn tail(&self, max_lines: usize) -> Vec<StyledLine> {
+        let len = self.len();
+        self.lines(len.saturating_sub(max_lines)..len)
+    }
+
+    /// Visits every retained line in order, decompressing one page at a time.
+    pub fn iter(&self) -> impl Iterator<Item = StyledLine> + '_ {
+        self.pages
+            .iter()
+            .flat_map(|page| {
+                let contents = self.load(page);
+                (0..contents.len()).map(move |idx| contents[idx].clone())
+            })
+            .chain(self.tail.iter().cloned())
+    }
+
+    /// Whether any line's text satisfies `pred`, without cloning lines.
+    pub fn any_text(&self, mut pred: impl FnMut(&str) -> bool) -> bool {
+        for page in &self.pages {
+            if self.load(page).iter().any(|line| pred(&line.text)) {
+                return true;
+            }
+        }
+        self.tail.iter().any(|line| pred(&line.text))
+    }
+
+    fn seal_tail(&mut self) {
+        let lines = std::mem::take(&mut self.tail);
+        let page = Page {
+            id: self.next_page_id,
+            start: self.dropped + self.sealed_lines,
+            lines: lines.len(),
+            hot_bytes: self.tail_bytes,
+            data: PageData::Hot(Arc::new(lines)),
+        };
+        self.next_page_id += 1;
+        self.sealed_bytes += page.hot_bytes;
+        self.sealed_lines += page.lines;
+        self.tail_bytes = 0;
+        self.pages.push_back(Arc::new(page));
+        self.enforce_budget();
+    }
+
+    fn enforce_budget(&mut self) {
+        self.shrink_memory_to(self.config.memory_budget);
+    }
+
+    /// Compresses, spills, then drops the oldest pages until at most
+    /// `budget` bytes stay in memory or only the open tail is left. Used to
+    /// hold several buffers to one shared budget.
+    pub fn shrink_memory_to(&mut self, budget: usize) {
+        while self.memory_usage() > budget {
+            let hot = self
+                .pages
+                .iter()
+                .position(|page| matches!(page.data, PageData::Hot(_)));
+            if let Some(idx) = hot {
+                self.compress_page(idx);
+                continue;
+            }
+            let compressed = self
+                .pages
+                .iter()
+                .position(|page| matches!(page.data, PageData::Compressed(_)));
+            if let Some(idx) = compressed
+                && self.spill_page(idx).is_ok()
+            {
+                continue;
+            }
+            if !self.drop_front_page() {
+                break;
+            }
+        }
+        self.shrink_disk_to(self.config.disk_budget);
+    }
+
+    /// Drops the oldest pages until at most `budget` spilled bytes are left,
+    /// then rewrites the spill file so the space is given back. Drops go a
+    /// quarter below the budget so the file is not rewritten on every page.
+    pub fn shrink_disk_to(&mut self, budget: u64) {
+        if self.spilled_bytes <= budget {
+            return;
+        }
+        let target = budget - budget / 4;
+        while self.spilled_bytes > target && self.drop_front_page() {}
+        self.compact_spill();
+    }
+
+    fn drop_front_page(&mut self) -> bool {
+        let Some(page) = self.pages.pop_front() else {
+            return false;
+        };
+        self.sealed_bytes -= page.memory_cost();
+        self.sealed_lines -= page.lines;
+        self.spilled_bytes -= page.data.spilled_bytes();
+        self.dropped += page.lines;
+        true
+    }
+
+    /// Moves the remaining spilled pages into a fresh file. Clones keep the
+    /// old file alive until they are dropped. Failing to copy leaves the
+    /// pages where they were.
+    fn compact_spill(&mut self) {
+        let Some(old) = self.spill.clone() else {
+            return;
+        };
+        if self.spilled_bytes == 0 {
+            self.spill = None;
+            return;
+        }
+        let Some(dir) = self.config.spill_dir.clone() else {
+            return;
+        };
+        let copied = SpillFile::create(dir).and_then(|spill| {
+            let mut moved = Vec::new();
+            for (idx, page) in self.pages.iter().enumerate() {
+                if let PageData::Spilled { offset, len } = page.data {
+                    let offset = spill.append(&old.read(offset, len)?)?;
+                    moved.push((idx, offset, len));
+                }
+            }
+            Ok((spill, moved))
+        });
+        let Ok((spill, moved)) = copied else {
+            return;
+        };
+        for (idx, offset, len) in moved {
+            self.replace_page_data(idx, PageData::Spilled { offset, len });
+        }
+        self.spill = Some(Arc::new(spill));
+    }
+
+    fn replace_page_data(&mut self, idx: usize, data: PageData) {
+        let page = &self.pages[idx];
+        self.sealed_bytes -= page.memory_cost();
+        self.spilled_bytes -= page.data.spilled_bytes();
+        self.spilled_bytes += data.spilled_bytes();
+        let page = Page {
+            id: page.id,
+            start: page.start,
+            lines: page.lines,
+            hot_bytes: page.hot_bytes,
+            data,
+        };
+        self.sealed_bytes += page.memory_cost();
+        self.pages[idx] = Arc::new(page);
+    }
+
+    fn compress_page(&mut self, idx: usize) {
+        let PageData::Hot(lines) = &self.pages[idx].data else {
+            return;
+        };
+        let compressed = lz4_flex::compress_prepend_size(&encode_page(lines));
+        self.replace_page_data(idx, PageData::Compressed(compressed));
+    }
+
+    fn spill_page(&mut self, idx: usize) -> Result<()> {
+        let page = self.pages[idx].clone();
+        let PageData::Compressed(bytes) = &page.data else {
+            return Ok(());
+        };
+        let spill = match &self.spill {
+            Some(spill) => spill.clone(),
+            None => {
+                let dir = self
+                    .config
+                    .spill_dir
+                    .clone()
+                    .ok_or_else(|| anyhow!("scrollback spilling is disabled"))?;
+                let spill = Arc::new(SpillFile::create(dir)?);
+                self.spill = Some(spill.clone());
+                spill
+            }
+        };
+        let offset = spill.append(bytes)?;
+        self.replace_page_data(
+            idx,
+            PageData::Spilled {
+                offset,
+                len: bytes.len(),
+            },
+        );
+        Ok(())
+    }
+
+    fn load(&self, page: &Page) -> Arc<Vec<StyledLine>> {
+        let compressed = match &page.data {
+            PageData::Hot(lines) => return lines.clone(),
+            _ if let Some(lines) = self.cache.get(page.id) => return lines,
+            PageData::Compressed(bytes) => Cow::Borrowed(bytes.as_slice()),
+            PageData::Spilled { offset, len } => {
+                match self.spill.as_ref().map(|spill| spill.read(*offset, *len)) {
+                    Some(Ok(bytes)) => Cow::Owned(bytes),
+                    _ => return unavailable_page(page.lines),
+                }
+            }
+        };
+        let decoded = lz4_flex::decompress_size_prepended(&compressed)
+            .map_err(|err| anyhow!("{err}"))
+            .and_then(|bytes| decode_page(&bytes));
+        let lines = match decoded {
+            Ok(lines) if lines.len() == page.lines => Arc::new(lines),
+            _ => return unavailable_page(page.lines),
+        };
+        self.cache.put(page.id, lines.clone());
+        lines
+    }
+}
+
+fn unavailable_page(lines: usize) -> Arc<Vec<StyledLine>> {
+    Arc::new(vec![StyledLine::plain(UNAVAILABLE_LINE); lines])
+}
+
+fn line_cost(line: &StyledLine) -> usize {
+    std::mem::size_of::<StyledLine>()
+        + line.text.len()
+        + line.ranges.len() * std::mem::size_of::<StyledRange>()
+        + line
+            .links
+            .iter()
+            .map(|link| std::mem::size_of::<LinkRange>() + link.uri.len())
+            .sum::<usize>()
+        + line
+            .images
+            .iter()
+            .map(|anchor| std::mem::size_of::<ImagePlacement>() + anchor.placement.image.rgba.len())
+            .sum::<usize>()
+}
+
+// The most recently decompressed page, so scrolling through a compressed or
+// spilled page does not decode it once per line. Clones start cold.
+#[derive(Debug, Default)]
+struct PageCache(Mutex<Option<(u64, Arc<Vec<StyledLine>>)>>);
+
+impl PageCache {
+    fn get(&self, page_id: u64) -> Option<Arc<Vec<StyledLine>>> {
+        let cached = self.0.lock().ok()?;
+        cached
+            .as_ref()
+            .filter(|(id, _)| *id == page_id)
+            .map(|(_, lines)| lines.clone())
+    }
+
+    fn put(&self, page_id: u64, lines: Arc<Vec<StyledLine>>) {
+        if let Ok(mut cached) = self.0.lock() {
+            *cached = Some((page_id, lines));
         }
     }
+}
 
-    pub fn tail(&self, max_lines: usize) -> Vec<String> {
-        let start = self.lines.len().saturating_sub(max_lines);
-        self.lines.iter().skip(start).cloned().collect()
+impl Clone for PageCache {
+    fn clone(&self) -> Self {
+        Self::default()
     }
 }
 
-impl Default for ScrollbackBuffer {
-    fn default() -> Self {
-        Self::new(20_000)
+/// Temporary file holding spilled pages; removed when the last buffer
+/// sharing it is dropped.
+#[derive(Debug)]
+struct SpillFile {
+    path: PathBuf,
+    file: Mutex<(File, u64)>,
+}
+
+impl SpillFile {
+    fn create(dir: PathBuf) -> Result<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        private_dir::ensure(&dir)?;
+        // A file left by an earlier process with the same pid is skipped
+        // rather than reused.
+        let mut attempts = 0;
+        loop {
+            let path = dir.join(format!(
+                "scrollback-{}-{}.bin",
+                std::process::id(),
+                NEXT_ID.fetch_add(1, Ordering::Relaxed)
+            ));
+            let mut options = OpenOptions::new();
+            options.read(true).write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            match options.open(&path) {
+                Ok(file) => {
+                    return Ok(Self {
+                        path,
+                        file: Mutex::new((file, 0)),
+                    });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists && attempts < 16 => {
+                    attempts += 1;
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("failed to create {}", path.display()));
+                }
+            }
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.file.lock().map_or(0, |guard| guard.1)
+    }
+
+    fn append(&self, bytes: &[u8]) -> Result<u64> {
+        let mut guard = self
+            .file
+            .lock()
+            .map_err(|_| anyhow!("scrollback spill file lock poisoned"))?;
+        let (file, end) = &mut *guard;
+        let offset = *end;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(bytes)?;
+        *end += bytes.len() as u64;
+        Ok(offset)
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut guard = self
+            .file
+            .lock()
+            .map_err(|_| anyhow!("scrollback spill file lock poisoned"))?;
+        let file = &mut guard.0;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0; len];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn encode_page(lines: &[StyledLine]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for line in lines {
+        put_u32(&mut out, line.text.len());
+        out.extend_from_slice(line.text.as_bytes());
+        put_u32(&mut out, line.ranges.len());
+        for range in &line.ranges {
+            put_u32(&mut out, range.start);
+            put_u32(&mut out, range.end);
+            put_color(&mut out, range.attrs.fg);
+            put_color(&mut out, range.attrs.bg);
+            let flags = [
+                range.attrs.bold,
+                range.attrs.dim,
+                range.attrs.italic,
+                range.attrs.underline,
+                range.attrs.blink,
+                range.attrs.inverse,
+                range.attrs.hidden,
+                range.attrs.strikethrough,
+            ];
+            out.push(
+                flags
+                    .iter()
+                    .enumerate()
+                    .fold(0_u8, |acc, (bit, set)| acc | (u8::from(*set) << bit)),
+            );
+        }
+        put_u32(&mut out, line.links.len());
+        for link in &line.links {
+            put_u32(&mut out, link.start);
+            put_u32(&mut out, link.end);
+            put_u32(&mut out, link.uri.len());
+            out.extend_from_slice(link.uri.as_bytes());
+        }
+        put_u32(&mut out, line.images.len());
+        for anchor in &line.images {
+            let placement = &anchor.placement;
+            put_u32(&mut out, anchor.column);
+            put_u32(&mut out, placement.columns);
+            put_u32(&mut out, placement.rows);
+            out.extend_from_slice(&placement.image.id.to_le_bytes());
+            put_u32(&mut out, placement.image.width as usize);
+            put_u32(&mut out, placement.image.height as usize);
+            out.extend_from_slice(&placement.image.rgba);
+        }
+    }
+    out
+}
+
+fn put_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn put_color(out: &mut Vec<u8>, color: Color) {
+    match color {
+        Color::Default => out.push(0),
+        Color::Indexed(index) => out.extend_from_slice(&[1, index]),
+        Color::Rgb(r, g, b) => out.extend_from_slice(&[2, r, g, b]),
+    }
+}
+
+fn decode_page(bytes: &[u8]) -> Result<Vec<StyledLine>> {
+    let mut reader = PageReader { bytes, pos: 0 };
+    let mut lines = Vec::new();
+    while reader.pos < bytes.len() {
+        let text_len = reader.u32()?;
+        let text = String::from_utf8(reader.take(text_len)?.to_vec())?;
+        let range_count = reader.u32()?;
+        let mut ranges = Vec::with_capacity(range_count.min(reader.remaining()));
+        for _ in 0..range_count {
+            let start = reader.u32()?;
+            let end = reader.u32()?;
+            let fg = reader.color()?;
+            let bg = reader.color()?;
+            let flags = reader.u8()?;
+            let bit = |n: u8| flags & (1 << n) != 0;
+            ranges.push(StyledRange {
+                start,
+                end,
+                attrs: CellAttrs {
+                    fg,
+                    bg,
+                    bold: bit(0),
+                    dim: bit(1),
+                    italic: bit(2),
+                    underline: bit(3),
+                    blink: bit(4),
+                    inverse: bit(5),
+                    hidden: bit(6),
+                    strikethrough: bit(7),
+                },
+            });
+        }
+        let link_count = reader.u32()?;
+        let mut links = Vec::with_capacity(link_count.min(reader.remaining()));
+        for _ in 0..link_count {
+            let start = reader.u32()?;
+            let end = reader.u32()?;
+            let uri_len = reader.u32()?;
+            let uri = String::from_utf8(reader.take(uri_len)?.to_vec())?;
+            links.push(LinkRange { start, end, uri });
+        }
+        let image_count = reader.u32()?;
+        let mut images = Vec::with_capacity(image_count.min(reader.remaining()));
+        for _ in 0..image_count {
+            let column = reader.u32()?;
+            let columns = reader.u32()?;
+            let rows = reader.u32()?;
+            let id = reader.take(8)?;
+            let id = u64::from_le_bytes(id.try_into()?);
+            let width = reader.u32()?;
+            let height = reader.u32()?;
+            let rgba_len = width
+                .checked_mul(height)
+                .and_then(|pixels| pixels.checked_mul(4))
+                .ok_or_else(|| anyhow!("oversized image in scrollback page"))?;
+            let rgba = reader.take(rgba_len)?.to_vec();
+            // Keeping the id lets renderers reuse the texture they already
+            // uploaded for this image.
+            let image = InlineImage {
+                id,
+                width: width as u32,
+                height: height as u32,
+                rgba,
+            };
+            images.push(ImageAnchor {
+                column,
+                placement: Arc::new(ImagePlacement {
+                    image: Arc::new(image),
+                    columns,
+                    rows,
+                }),
+            });
+        }
+        lines.push(StyledLine {
+            text,
+            ranges,
+            links,
+            images,
+        });
+    }
+    Ok(lines)
+}
+
+struct PageReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PageReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("truncated scrollback page"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    // Counts read from a page are only trusted as far as there are bytes
+    // left to back them.
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn u32(&mut self) -> Result<usize> {
+        let raw = self.take(4)?;
+        Ok(u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as usize)
+    }
+
+    fn color(&mut self) -> Result<Color> {
+        match self.u8()? {
+            0 => Ok(Color::Default),
+            1 => Ok(Color::Indexed(self.u8()?)),
+            2 => {
+                let rgb = self.take(3)?;
+                Ok(Color::Rgb(rgb[0], rgb[1], rgb[2]))
+            }
+            tag => Err(anyhow!("unknown colour tag {tag} in scrollback page")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{ScrollbackBuffer, ScrollbackConfig, decode_page};
+    use crate::graphics::{ImagePlacement, InlineImage};
+    use crate::style::{CellAttrs, Color, ImageAnchor, StyledLine};
+
+    fn numbered(idx: usize) -> StyledLine {
+        let mut line = StyledLine::plain(format!("line {idx} "));
+        line.push(
+            'E',
+            CellAttrs {
+                fg: Color::Rgb(1, 2, 3),
+                bold: true,
+                ..CellAttrs::default()
+            },
+        );
+        line.link_tail(1, &format!("https://example.com/{idx}"));
+        if idx.is_multiple_of(100) {
+            let image = InlineImage {
+                id: idx as u64,
+                width: 1,
+                height: 1,
+                rgba: vec![idx as u8, 2, 3, 255],
+            };
+            line.images.push(ImageAnchor {
+                column: 4,
+                placement: Arc::new(ImagePlacement {
+                    image: Arc::new(image),
+                    columns: 2,
+                    rows: 1,
+                }),
+            });
+        }
+        line
+    }
+
+    #[test]
+    fn pages_compress_and_spill_past_the_budget() {
+        let dir =
+            std::env::temp_dir().join(format!("ctyterm-scrollback-test-{}", std::process::id()));
+        let mut buffer = ScrollbackBuffer::new(ScrollbackConfig {
+            memory_budget: 4 * 1024,
+            disk_budget: u64::MAX,
+            page_lines: 16,
+            spill_dir: Some(dir.clone()),
+        });
+        buffer.extend((0..2_000).map(numbered));
+
+        assert_eq!(buffer.len(), 2_000);
+        assert_eq!(buffer.dropped_lines(), 0);
+        assert!(buffer.memory_usage() <= 4 * 1024);
+        assert!(buffer.disk_usage() > 0);
+        assert_eq!(buffer.line(0), Some(numbered(0)));
+        assert_eq!(buffer.line(1_234), Some(numbered(1_234)));
+        assert_eq!(buffer.line(1_500), Some(numbered(1_500)));
+        assert_eq!(
+            buffer.lines(14..18),
+            (14..18).map(numbered).collect::<Vec<_>>()
+        );
+        assert_eq!(buffer.tail(2), vec![numbered(1_998), numbered(1_999)]);
+        assert_eq!(buffer.iter().count(), 2_000);
+        assert!(buffer.any_text(|text| text.starts_with("line 7 ")));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &std::path::Path| {
+                std::fs::metadata(path)
+                    .expect("metadata")
+                    .permissions()
+                    .mode()
+                    & 0o777
+            };
+            assert_eq!(mode(&dir), 0o700);
+            let spill = std::fs::read_dir(&dir)
+                .expect("spill dir lists")
+                .next()
+                .expect("spill file exists")
+                .expect("entry")
+                .path();
+            assert_eq!(mode(&spill), 0o600);
+        }
+
+        drop(buffer);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn oldest_lines_are_dropped_without_a_spill_dir() {
+        let mut buffer = ScrollbackBuffer::new(ScrollbackConfig {
+            memory_budget: 2 * 1024,
+            disk_budget: u64::MAX,
+            page_lines: 8,
+            spill_dir: None,
+        });
+        buffer.extend((0..1_000).map(numbered));
+
+        assert!(buffer.dropped_lines() > 0);
+        assert_eq!(buffer.len() + buffer.dropped_lines(), 1_000);
+        assert!(buffer.memory_usage() <= 2 * 1024);
+        assert_eq!(buffer.line(buffer.len() - 1), Some(numbered(999)));
+        assert_eq!(buffer.line(0), Some(numbered(buffer.dropped_lines())));
+    }
+
+    #[test]
+    fn spilled_pages_past_the_disk_budget_are_dropped_and_reclaimed() {
+        let dir = std::env::temp_dir().join(format!(
+            "ctyterm-scrollback-disk-test-{}",
+            std::process::id()
+        ));
+        let mut buffer = ScrollbackBuffer::new(ScrollbackConfig {
+            memory_budget: 2 * 1024,
+            disk_budget: 8 * 1024,
+            page_lines: 16,
+            spill_dir: Some(dir.clone()),
+        });
+        buffer.extend((0..4_000).map(numbered));
+
+        assert!(buffer.dropped_lines() > 0);
+        assert_eq!(buffer.len() + buffer.dropped_lines(), 4_000);
+        assert!(buffer.disk_usage() > 0);
+        assert!(buffer.disk_usage() <= 8 * 1024);
+        // Compaction leaves the file no bigger than the budget plus the
+        // pages spilled since.
+        assert!(buffer.spill_file_len() <= 2 * 8 * 1024);
+        assert_eq!(buffer.line(0), Some(numbered(buffer.dropped_lines())));
+        assert_eq!(buffer.tail(1), vec![numbered(3_999)]);
+
+        buffer.shrink_disk_to(0);
+        assert_eq!(buffer.disk_usage(), 0);
+        assert_eq!(buffer.spill_file_len(), 0);
+        assert_eq!(buffer.tail(1), vec![numbered(3_999)]);
+
+        drop(buffer);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn corrupt_page_sizes_are_errors() {
+        let u32s = |values: &[u32]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect()
+        };
+        // One empty line with an image whose pixel size overflows.
+        let mut page = u32s(&[0, 0, 0, 1, 0, 1, 1]);
+        page.extend(7u64.to_le_bytes());
+        page.extend(u32s(&[u32::MAX, u32::MAX]));
+        assert!(decode_page(&page).is_err());
+
+        // Counts far beyond the bytes that follow them.
+        assert!(decode_page(&u32s(&[0, u32::MAX])).is_err());
+        assert!(decode_page(&u32s(&[0, 0, 0, u32::MAX])).is_err());
     }
 }
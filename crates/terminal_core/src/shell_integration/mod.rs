@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::private_dir;
+use crate::pty::ShellKind;
+
+const BASH_SCRIPT: &str = include_str!("scripts/ctyterm.bash");
+const ZSH_SCRIPT: &str = include_str!("scripts/ctyterm.zsh");
+const FISH_SCRIPT: &str = include_str!("scripts/ctyterm.fish");
+const POWERSHELL_SCRIPT: &str = include_str!("scripts/ctyterm.ps1");
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ShellMark {
+    PromptStart,
+    CommandStart,
+    CommandExecuted,
+    CommandFinished { exit_code: Option<i32> },
+}
+
+pub fn parse_osc133(params: &[&[u8]]) -> Option<ShellMark> {
+    let kind = params.get(1)?;
+    match *kind {
+        b"A" => Some(ShellMark::PromptStart),
+        b"B" => Some(ShellMark::CommandStart),
+        b"C" => Some(ShellMark::CommandExecuted),
+        b"D" => {
+            let exit_code = params
+                .get(2)
+                .and_then(|raw| std::str::from_utf8(raw).ok())
+                .and_then(|raw| raw.trim().parse().ok());
+            Some(ShellMark::CommandFinished { exit_code })
+        }
+        _ => None,
+    }
+}
+
+/// Extracts the path from an OSC 7 `file://host/path` report.
+pub fn parse_osc7(params: &[&[u8]]) -> Option<String> {
+    file_uri_to_path(std::str::from_utf8(params.get(1)?).ok()?)
+}
+
+/// Converts a `file://host/path` URI to a local path, percent-decoded.
+pub fn file_uri_to_path(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let path = percent_decode(path);
+
+    // Windows reports look like `/C:/Users/me`.
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return Some(path[1..].replace('/', "\\"));
+    }
+    Some(path)
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%'
+            && idx + 2 < bytes.len()
+            && bytes[idx + 1].is_ascii_hexdigit()
+            && bytes[idx + 2].is_ascii_hexdigit()
+            && let Ok(value) = u8::from_str_radix(&input[idx + 1..idx + 3], 16)
+        {
+            out.push(value);
+            idx += 3;
+            continue;
+        }
+        out.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub fn integration_script(kind: ShellKind) -> Option<(&'static str, &'static str)> {
+    match kind {
+        ShellKind::Bash => Some(("ctyterm.bash", BASH_SCRIPT)),
+        ShellKind::Zsh => Some(("ctyterm.zsh", ZSH_SCRIPT)),
+        ShellKind::Fish => Some(("ctyterm.fish", FISH_SCRIPT)),
+        ShellKind::PowerShell => Some(("ctyterm.ps1", POWERSHELL_SCRIPT)),
+        ShellKind::Other => None,
+    }
+}
+
+/// Writes the integration script for `kind` to a private per-user directory
+/// and returns the command line that sources it, or `None` for unsupported
+/// shells.
+pub fn install(kind: ShellKind) -> Result<Option<String>> {
+    let Some((file_name, script)) = integration_script(kind) else {
+        return Ok(None);
+    };
+    // Every new shell runs this file, so nobody else may be able to plant
+    // or swap it.
+    let dir = script_dir();
+    private_dir::ensure(&dir)?;
+    let path = dir.join(file_name);
+    fs::write(&path, script).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(Some(source_command(kind, &path)))
+}
+
+#[cfg(unix)]
+fn script_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("ctyterm-shell-integration-{}", private_dir::uid()))
+}
+
+/// The temp dir is already per user on Windows.
+#[cfg(not(unix))]
+fn script_dir() -> PathBuf {
+    std::env::temp_dir().join("ctyterm-shell-integration")
+}
+
+fn source_command(kind: ShellKind, path: &Path) -> String {
+    let path = path.display().to_string();
+    match kind {
+        ShellKind::PowerShell => format!(". '{}'", path.replace('\'', "''")),
+        ShellKind::Fish => format!("source '{}'", path.replace('\'', "\\'")),
+        // The leading space keeps the line out of history with ignorespace.
+        _ => format!(" . '{}'", path.replace('\'', "'\\''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BASH_SCRIPT, ShellMark, install, parse_osc7, parse_osc133, script_dir};
+    use crate::pty::ShellKind;
+
+    #[test]
+    fn osc133_marks_are_parsed() {
+        assert_eq!(parse_osc133(&[b"133", b"A"]), Some(ShellMark::PromptStart));
+        assert_eq!(
+            parse_osc133(&[b"133", b"C"]),
+            Some(ShellMark::CommandExecuted)
+        );
+        assert_eq!(
+            parse_osc133(&[b"133", b"D", b"127"]),
+            Some(ShellMark::CommandFinished {
+                exit_code: Some(127)
+            })
+        );
+        assert_eq!(
+            parse_osc133(&[b"133", b"D"]),
+            Some(ShellMark::CommandFinished { exit_code: None })
+        );
+        assert_eq!(parse_osc133(&[b"133", b"Z"]), None);
+    }
+
+    #[test]
+    fn osc7_extracts_decoded_path() {
+        assert_eq!(
+            parse_osc7(&[b"7", b"file://devbox/home/me/my%20repo"]),
+            Some("/home/me/my repo".to_owned())
+        );
+        assert_eq!(
+            parse_osc7(&[b"7", b"file://PC/C:/Users/me"]),
+            Some("C:\\Users\\me".to_owned())
+        );
+        assert_eq!(parse_osc7(&[b"7", b"http://x/y"]), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scripts_are_installed_in_a_private_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let command = install(ShellKind::Bash)
+            .expect("install should succeed")
+            .expect("bash is supported");
+        let path = script_dir().join("ctyterm.bash");
+        assert!(command.contains(&path.display().to_string()));
+        assert_eq!(std::fs::read_to_string(&path).expect("script"), BASH_SCRIPT);
+        let mode = std::fs::metadata(script_dir())
+            .expect("metadata")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
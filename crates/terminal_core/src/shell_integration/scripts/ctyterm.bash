@@ -0,0 +1,22 @@
+# CtyTerm shell integration for bash: OSC 133 prompt/command marks and OSC 7 cwd.
+# Requires bash 4.4+ for PS0.
+if [ -z "${__CTYTERM_INTEGRATION:-}" ]; then
+    __CTYTERM_INTEGRATION=1
+
+    __ctyterm_prompt_command() {
+        local status=$?
+        # PS0 sets __ctyterm_running when a command line is executed, so an
+        # empty Enter does not report a stale exit status.
+        if [ -n "${__ctyterm_running+x}" ]; then
+            printf '\033]133;D;%s\007' "$status"
+            unset __ctyterm_running
+        fi
+        printf '\033]7;file://%s%s\007' "${HOSTNAME:-localhost}" "$PWD"
+        printf '\033]133;A\007'
+        return $status
+    }
+
+    PROMPT_COMMAND="__ctyterm_prompt_command${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+    PS0="${PS0:-}\${__ctyterm_running=}\[\033]133;C\007\]"
+    PS1="${PS1}\[\033]133;B\007\]"
+fi
@@ -0,0 +1,17 @@
+# CtyTerm shell integration for fish: OSC 133 prompt/command marks and OSC 7 cwd.
+if not set -q __CTYTERM_INTEGRATION
+    set -g __CTYTERM_INTEGRATION 1
+
+    function __ctyterm_prompt --on-event fish_prompt
+        printf '\e]7;file://%s%s\a' (hostname) "$PWD"
+        printf '\e]133;A\a'
+    end
+
+    function __ctyterm_preexec --on-event fish_preexec
+        printf '\e]133;C\a'
+    end
+
+    function __ctyterm_postexec --on-event fish_postexec
+        printf '\e]133;D;%s\a' $status
+    end
+end
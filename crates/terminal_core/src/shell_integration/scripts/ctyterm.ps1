@@ -0,0 +1,37 @@
+# CtyTerm shell integration for PowerShell: OSC 133 prompt/command marks and OSC 7 cwd.
+if (-not $global:__CtyTermIntegration) {
+    $global:__CtyTermIntegration = $true
+    $global:__CtyTermOriginalPrompt = $function:prompt
+    $global:__CtyTermLastHistoryId = (Get-History -Count 1).Id
+
+    function global:prompt {
+        $success = $?
+        $exitCode = $global:LASTEXITCODE
+        $esc = [char]27
+        $bel = [char]7
+        $out = ""
+
+        $last = Get-History -Count 1
+        if ($last -and $last.Id -ne $global:__CtyTermLastHistoryId) {
+            $global:__CtyTermLastHistoryId = $last.Id
+            $code = if ($success) { 0 } elseif ($exitCode) { $exitCode } else { 1 }
+            $out += "$esc]133;D;$code$bel"
+        }
+
+        $cwd = (Get-Location).ProviderPath -replace '\\', '/'
+        $out += "$esc]7;file://$env:COMPUTERNAME/$cwd$bel"
+        $out += "$esc]133;A$bel"
+        $out += & $global:__CtyTermOriginalPrompt
+        $out += "$esc]133;B$bel"
+
+        $global:LASTEXITCODE = $exitCode
+        return $out
+    }
+
+    if (Get-Module PSReadLine) {
+        Set-PSReadLineKeyHandler -Chord Enter -ScriptBlock {
+            [Microsoft.PowerShell.PSConsoleReadLine]::AcceptLine()
+            [Console]::Write("$([char]27)]133;C$([char]7)")
+        }
+    }
+}
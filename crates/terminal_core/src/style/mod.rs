@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::ImagePlacement;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CellAttrs {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+impl CellAttrs {
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Byte range of a line's text drawn with non-default attributes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StyledRange {
+    pub start: usize,
+    pub end: usize,
+    pub attrs: CellAttrs,
+}
+
+/// Byte range of a line's text that an OSC 8 hyperlink points at `uri`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LinkRange {
+    pub start: usize,
+    pub end: usize,
+    pub uri: String,
+}
+
+/// An inline image whose top-left corner sits in cell `column` of the line.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ImageAnchor {
+    pub column: usize,
+    pub placement: Arc<ImagePlacement>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StyledLine {
+    pub text: String,
+    pub ranges: Vec<StyledRange>,
+    pub links: Vec<LinkRange>,
+    /// Images are anchored by cell, so truncating the text keeps them.
+    pub images: Vec<ImageAnchor>,
+}
+
+impl StyledLine {
+    pub fn plain(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ranges: Vec::new(),
+            links: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, ch: char, attrs: CellAttrs) {
+        let start = self.text.len();
+        self.text.push(ch);
+        let end = self.text.len();
+        if attrs.is_plain() {
+            return;
+        }
+        match self.ranges.last_mut() {
+            Some(last) if last.end == start && last.attrs == attrs => last.end = end,
+            _ => self.ranges.push(StyledRange { start, end, attrs }),
+        }
+    }
+
+    /// Marks the last `len` bytes pushed as linking to `uri`, merging with a
+    /// directly preceding range for the same target.
+    pub fn link_tail(&mut self, len: usize, uri: &str) {
+        let end = self.text.len();
+        let start = end.saturating_sub(len);
+        match self.links.last_mut() {
+            Some(last) if last.end == start && last.uri == uri => last.end = end,
+            _ => self.links.push(LinkRange {
+                start,
+                end,
+                uri: uri.to_owned(),
+            }),
+        }
+    }
+
+    /// Shortens the text to `len` bytes, clipping ranges that extend past it.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.text.len() {
+            return;
+        }
+        self.text.truncate(len);
+        self.ranges.retain_mut(|range| {
+            range.end = range.end.min(len);
+            range.start < range.end
+        });
+        self.links.retain_mut(|link| {
+            link.end = link.end.min(len);
+            link.start < link.end
+        });
+    }
+
+    pub fn trim_end(&mut self) {
+        let len = self.text.trim_end_matches(' ').len();
+        self.truncate(len);
+    }
+}
+
+/// Resolves a 256-colour palette index to RGB. Indices below 16 use the xterm
+/// defaults; callers that theme the base colours should handle those first.
+pub fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+
+    match index {
+        0..=15 => BASE[usize::from(index)],
+        16..=231 => {
+            let idx = index - 16;
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            (level(idx / 36), level((idx / 6) % 6), level(idx % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CellAttrs, Color, StyledLine, indexed_rgb};
+
+    #[test]
+    fn adjacent_chars_with_same_attrs_share_a_range() {
+        let red = CellAttrs {
+            fg: Color::Indexed(1),
+            ..CellAttrs::default()
+        };
+        let mut line = StyledLine::default();
+        line.push('o', CellAttrs::default());
+        line.push('k', red);
+        line.push('!', red);
+        assert_eq!(line.text, "ok!");
+        assert_eq!(line.ranges.len(), 1);
+        assert_eq!((line.ranges[0].start, line.ranges[0].end), (1, 3));
+
+        line.truncate(2);
+        assert_eq!((line.ranges[0].start, line.ranges[0].end), (1, 2));
+    }
+
+    #[test]
+    fn palette_covers_cube_and_grayscale() {
+        assert_eq!(indexed_rgb(196), (255, 0, 0));
+        assert_eq!(indexed_rgb(16), (0, 0, 0));
+        assert_eq!(indexed_rgb(244), (128, 128, 128));
+    }
+}
@@ -0,0 +1,997 @@
+//! Client side of tmux control mode (`tmux -CC`).
+//!
+//! tmux announces control mode with `DCS 1000 p` and then speaks a line
+//! protocol until the closing ST: replies to commands framed by `%begin` and
+//! `%end`/`%error`, interleaved with `%`-prefixed notifications such as pane
+//! output and layout changes. [`ControlParser`] turns the DCS payload into
+//! [`Notification`]s; [`ControlClient`] follows them and asks tmux for what it
+//! does not announce on its own.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender, unbounded};
+
+use crate::pty::{Backend, PtySession, SharedWriter, ShellKind};
+
+/// DCS parameter tmux uses to announce control mode.
+pub const CONTROL_MODE_PARAM: u16 = 1000;
+
+// Longer lines are truncated; `%output` lines carry at most a read's worth.
+const MAX_LINE_BYTES: usize = 1024 * 1024;
+// Printed ahead of a query so its reply can be told apart from the empty
+// replies to input and other commands.
+const REPLY_MARKER: &str = "ctyterm-reply";
+const HISTORY_LINES: usize = 2000;
+// Keys per `send-keys` command, so a paste does not build one huge line.
+const KEYS_PER_COMMAND: usize = 256;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Notification {
+    /// Output of a command, or of the one tmux runs on attach.
+    Reply {
+        lines: Vec<String>,
+        success: bool,
+    },
+    Output {
+        pane: u32,
+        data: Vec<u8>,
+    },
+    /// The visible layout of a window changed; zoomed windows show one pane.
+    LayoutChange {
+        window: u32,
+        layout: Layout,
+    },
+    WindowAdd {
+        window: u32,
+    },
+    WindowClose {
+        window: u32,
+    },
+    WindowRenamed {
+        window: u32,
+        name: String,
+    },
+    /// The active pane of a window changed.
+    WindowPaneChanged {
+        window: u32,
+        pane: u32,
+    },
+    /// The client was attached to another session, as it is on startup.
+    SessionChanged {
+        session: u32,
+        name: String,
+    },
+    Exit {
+        reason: Option<String>,
+    },
+}
+
+/// Splits the payload of the control-mode DCS into notifications.
+#[derive(Debug, Default)]
+pub struct ControlParser {
+    line: Vec<u8>,
+    // Arguments of the open `%begin`, repeated by the `%end` closing it, and
+    // the reply lines so far.
+    reply: Option<(String, Vec<String>)>,
+    exited: bool,
+}
+
+impl ControlParser {
+    pub fn put(&mut self, byte: u8) -> Option<Notification> {
+        if byte != b'\n' {
+            if self.line.len() < MAX_LINE_BYTES {
+                self.line.push(byte);
+            }
+            return None;
+        }
+        let mut line = std::mem::take(&mut self.line);
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        self.parse_line(&line)
+    }
+
+    /// Called when the DCS ends, reporting an exit tmux did not announce.
+    pub fn finish(&mut self) -> Option<Notification> {
+        if self.exited {
+            return None;
+        }
+        self.exited = true;
+        Some(Notification::Exit { reason: None })
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> Option<Notification> {
+        if let Some((args, lines)) = &mut self.reply {
+            let text = String::from_utf8_lossy(line);
+            let success = match text.split_once(' ') {
+                Some(("%end", rest)) if rest == args => true,
+                Some(("%error", rest)) if rest == args => false,
+                _ => {
+                    lines.push(text.into_owned());
+                    return None;
+                }
+            };
+            let (_, lines) = self.reply.take()?;
+            return Some(Notification::Reply { lines, success });
+        }
+
+        if let Some(rest) = line.strip_prefix(b"%output ") {
+            let split = rest.iter().position(|byte| *byte == b' ')?;
+            let pane = parse_id(&String::from_utf8_lossy(&rest[..split]), '%')?;
+            let data = unescape_output(&rest[split + 1..]);
+            return Some(Notification::Output { pane, data });
+        }
+        if let Some(rest) = line.strip_prefix(b"%extended-output ") {
+            let split = rest.iter().position(|byte| *byte == b' ')?;
+            let pane = parse_id(&String::from_utf8_lossy(&rest[..split]), '%')?;
+            let data_at = rest.windows(3).position(|window| window == b" : ")? + 3;
+            let data = unescape_output(&rest[data_at..]);
+            return Some(Notification::Output { pane, data });
+        }
+
+        let text = String::from_utf8_lossy(line);
+        let (keyword, rest) = text.split_once(' ').unwrap_or((&text, ""));
+        match keyword {
+            "%begin" => {
+                self.reply = Some((rest.to_owned(), Vec::new()));
+                None
+            }
+            "%layout-change" => {
+                let mut fields = rest.split(' ');
+                let window = parse_id(fields.next()?, '@')?;
+                let layout = fields.next()?;
+                // tmux before 2.2 sends no visible layout.
+                let visible = fields.next().unwrap_or(layout);
+                let layout = Layout::parse(visible)?;
+                Some(Notification::LayoutChange { window, layout })
+            }
+            "%window-add" => Some(Notification::WindowAdd {
+                window: parse_id(rest, '@')?,
+            }),
+            "%window-close" => Some(Notification::WindowClose {
+                window: parse_id(rest, '@')?,
+            }),
+            "%window-renamed" => {
+                let (window, name) = rest.split_once(' ')?;
+                Some(Notification::WindowRenamed {
+                    window: parse_id(window, '@')?,
+                    name: name.to_owned(),
+                })
+            }
+            "%window-pane-changed" => {
+                let (window, pane) = rest.split_once(' ')?;
+                Some(Notification::WindowPaneChanged {
+                    window: parse_id(window, '@')?,
+                    pane: parse_id(pane, '%')?,
+                })
+            }
+            "%session-changed" => {
+                let (session, name) = rest.split_once(' ')?;
+                Some(Notification::SessionChanged {
+                    session: parse_id(session, '$')?,
+                    name: name.to_owned(),
+                })
+            }
+            "%exit" => {
+                self.exited = true;
+                let reason = (!rest.is_empty()).then(|| rest.to_owned());
+                Some(Notification::Exit { reason })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn parse_id(text: &str, sigil: char) -> Option<u32> {
+    text.trim().strip_prefix(sigil)?.parse().ok()
+}
+
+// `%output` escapes bytes below 0x20 and the backslash as `\ooo`.
+fn unescape_output(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut idx = 0;
+    while idx < data.len() {
+        let octal = data.get(idx + 1..idx + 4).filter(|digits| {
+            data[idx] == b'\\' && digits.iter().all(|digit| (b'0'..=b'7').contains(digit))
+        });
+        match octal {
+            Some(digits) => {
+                let value = digits
+                    .iter()
+                    .fold(0_u32, |value, digit| value * 8 + u32::from(digit - b'0'));
+                out.push(value as u8);
+                idx += 4;
+            }
+            None => {
+                out.push(data[idx]);
+                idx += 1;
+            }
+        }
+    }
+    out
+}
+
+/// A cell of a tmux window layout, as in `b25d,80x24,0,0{40x24,0,0,0,...}`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Layout {
+    pub cols: u16,
+    pub rows: u16,
+    pub x: u16,
+    pub y: u16,
+    pub cells: LayoutCells,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LayoutCells {
+    Pane(u32),
+    /// Children side by side, `{...}` in the layout string.
+    Columns(Vec<Layout>),
+    /// Children stacked top to bottom, `[...]` in the layout string.
+    Rows(Vec<Layout>),
+}
+
+impl Layout {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (_checksum, cells) = text.split_once(',')?;
+        let mut cursor = LayoutCursor {
+            bytes: cells.as_bytes(),
+            pos: 0,
+        };
+        let layout = cursor.cell()?;
+        (cursor.pos == cursor.bytes.len()).then_some(layout)
+    }
+
+    /// Panes in layout order with their size in cells.
+    pub fn panes(&self) -> Vec<(u32, u16, u16)> {
+        match &self.cells {
+            LayoutCells::Pane(pane) => vec![(*pane, self.cols, self.rows)],
+            LayoutCells::Columns(children) | LayoutCells::Rows(children) => {
+                children.iter().flat_map(Layout::panes).collect()
+            }
+        }
+    }
+
+    /// Window size at which every pane would get the size `pane_size`
+    /// gives for it; panes it has no size for keep theirs. Neighbouring
+    /// cells are one separator apart.
+    pub fn fit(&self, pane_size: &impl Fn(u32) -> Option<(u16, u16)>) -> (u16, u16) {
+        match &self.cells {
+            LayoutCells::Pane(pane) => pane_size(*pane).unwrap_or((self.cols, self.rows)),
+            LayoutCells::Columns(children) => {
+                let sizes = children.iter().map(|child| child.fit(pane_size));
+                sizes.fold((0, 0), |(cols, rows), (child_cols, child_rows)| {
+                    let separator = u16::from(cols > 0);
+                    (cols + separator + child_cols, rows.max(child_rows))
+                })
+            }
+            LayoutCells::Rows(children) => {
+                let sizes = children.iter().map(|child| child.fit(pane_size));
+                sizes.fold((0, 0), |(cols, rows), (child_cols, child_rows)| {
+                    let separator = u16::from(rows > 0);
+                    (cols.max(child_cols), rows + separator + child_rows)
+                })
+            }
+        }
+    }
+}
+
+struct LayoutCursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl LayoutCursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        (self.peek()? == byte).then(|| self.pos += 1)
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Option<T> {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn cell(&mut self) -> Option<Layout> {
+        let cols = self.number()?;
+        self.expect(b'x')?;
+        let rows = self.number()?;
+        self.expect(b',')?;
+        let x = self.number()?;
+        self.expect(b',')?;
+        let y = self.number()?;
+        let cells = match self.peek()? {
+            b',' => {
+                self.pos += 1;
+                LayoutCells::Pane(self.number()?)
+            }
+            open @ (b'{' | b'[') => {
+                self.pos += 1;
+                let mut children = vec![self.cell()?];
+                while self.peek() == Some(b',') {
+                    self.pos += 1;
+                    children.push(self.cell()?);
+                }
+                if open == b'{' {
+                    self.expect(b'}')?;
+                    LayoutCells::Columns(children)
+                } else {
+                    self.expect(b']')?;
+                    LayoutCells::Rows(children)
+                }
+            }
+            _ => return None,
+        };
+        Some(Layout {
+            cols,
+            rows,
+            x,
+            y,
+            cells,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub name: String,
+    pub active: bool,
+    pub active_pane: u32,
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ClientEvent {
+    /// A line to write to the PTY running `tmux -CC`.
+    Command(String),
+    /// Every window of the attached session; windows not listed are gone.
+    Windows(Vec<WindowInfo>),
+    Layout {
+        window: u32,
+        layout: Layout,
+    },
+    WindowClosed(u32),
+    WindowRenamed {
+        window: u32,
+        name: String,
+    },
+    PaneFocused {
+        window: u32,
+        pane: u32,
+    },
+    /// Bytes for the emulator of a pane. A pane that appears first gets its
+    /// history and screen as tmux has them, then live output.
+    Output {
+        pane: u32,
+        data: Vec<u8>,
+    },
+    Exit(Option<String>),
+}
+
+#[derive(Debug)]
+enum Expected {
+    Windows,
+    History(u32),
+    Screen(u32),
+}
+
+#[derive(Debug, Default)]
+struct Restore {
+    history_size: usize,
+    history: Vec<String>,
+    cursor: (u16, u16),
+    alternate: bool,
+}
+
+/// Mirrors the windows and panes of the attached tmux session.
+#[derive(Debug, Default)]
+pub struct ControlClient {
+    windows: HashMap<u32, Vec<u32>>,
+    // Replies announced by a marker, in the order they will arrive.
+    expected: VecDeque<Expected>,
+    // Panes whose contents were requested. Output tmux sends before the
+    // captures is already part of them and is dropped.
+    restoring: HashMap<u32, Restore>,
+    client_size: Option<(u16, u16)>,
+    // tmux answers the command it runs on attach before anything else.
+    // Until that reply, the notifications may have been printed by any
+    // program and nothing is sent back in response.
+    attached: bool,
+    // A session or window was announced before the attach reply.
+    windows_pending: bool,
+}
+
+impl ControlClient {
+    pub fn handle(&mut self, notification: Notification) -> Vec<ClientEvent> {
+        let mut events = Vec::new();
+        if !self.attached {
+            match notification {
+                Notification::Reply { .. } => {
+                    self.attached = true;
+                    if std::mem::take(&mut self.windows_pending) {
+                        events.push(list_windows());
+                    }
+                }
+                Notification::SessionChanged { .. } | Notification::WindowAdd { .. } => {
+                    self.windows_pending = true;
+                }
+                Notification::Exit { reason } => events.push(ClientEvent::Exit(reason)),
+                _ => {}
+            }
+            return events;
+        }
+        match notification {
+            Notification::Reply { lines, success } => self.reply(lines, success, &mut events),
+            Notification::Output { pane, data } => {
+                if !self.restoring.contains_key(&pane) {
+                    events.push(ClientEvent::Output { pane, data });
+                }
+            }
+            Notification::LayoutChange { window, layout } => {
+                let panes = layout.panes().into_iter().map(|(pane, ..)| pane).collect();
+                events.push(ClientEvent::Layout { window, layout });
+                self.set_window_panes(window, panes, &mut events);
+            }
+            Notification::SessionChanged { .. } | Notification::WindowAdd { .. } => {
+                events.push(list_windows());
+            }
+            Notification::WindowClose { window } => {
+                self.set_window_panes(window, Vec::new(), &mut events);
+                self.windows.remove(&window);
+                events.push(ClientEvent::WindowClosed(window));
+            }
+            Notification::WindowRenamed { window, name } => {
+                events.push(ClientEvent::WindowRenamed { window, name });
+            }
+            Notification::WindowPaneChanged { window, pane } => {
+                events.push(ClientEvent::PaneFocused { window, pane });
+            }
+            Notification::Exit { reason } => events.push(ClientEvent::Exit(reason)),
+        }
+        events
+    }
+
+    /// Command that sizes the tmux client, unless it already has the size.
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Option<String> {
+        if cols == 0 || rows == 0 || self.client_size == Some((cols, rows)) {
+            return None;
+        }
+        self.client_size = Some((cols, rows));
+        Some(format!("refresh-client -C {cols}x{rows}"))
+    }
+
+    fn reply(&mut self, lines: Vec<String>, success: bool, events: &mut Vec<ClientEvent>) {
+        if let Some(marker) = lines
+            .first()
+            .and_then(|line| line.strip_prefix(REPLY_MARKER))
+        {
+            self.marker(marker.trim());
+            return;
+        }
+        let Some(expected) = self.expected.pop_front() else {
+            return;
+        };
+        if !success {
+            if let Expected::History(pane) | Expected::Screen(pane) = expected {
+                self.restoring.remove(&pane);
+            }
+            return;
+        }
+        match expected {
+            Expected::Windows => {
+                let windows = lines
+                    .iter()
+                    .filter_map(|line| parse_window(line))
+                    .collect::<Vec<_>>();
+                let listed = windows.iter().map(|window| window.id).collect::<Vec<_>>();
+                let gone = self
+                    .windows
+                    .keys()
+                    .copied()
+                    .filter(|window| !listed.contains(window))
+                    .collect::<Vec<_>>();
+                events.push(ClientEvent::Windows(windows.clone()));
+                for window in gone {
+                    self.set_window_panes(window, Vec::new(), events);
+                    self.windows.remove(&window);
+                }
+                for window in windows {
+                    let panes = window.layout.panes().into_iter();
+                    let panes = panes.map(|(pane, ..)| pane).collect();
+                    self.set_window_panes(window.id, panes, events);
+                }
+            }
+            Expected::History(pane) => {
+                if let Some(restore) = self.restoring.get_mut(&pane)
+                    && restore.history_size > 0
+                {
+                    restore.history = lines;
+                }
+            }
+            Expected::Screen(pane) => {
+                if let Some(restore) = self.restoring.remove(&pane) {
+                    let data =
+                        restore_screen(&restore.history, &lines, restore.cursor, restore.alternate);
+                    events.push(ClientEvent::Output { pane, data });
+                }
+            }
+        }
+    }
+
+    fn marker(&mut self, marker: &str) {
+        let fields = marker.split(' ').collect::<Vec<_>>();
+        match fields.as_slice() {
+            ["windows"] => self.expected.push_back(Expected::Windows),
+            ["pane", pane, cursor_x, cursor_y, history_size, alternate] => {
+                let Ok(pane) = pane.parse() else {
+                    return;
+                };
+                let restore = self.restoring.entry(pane).or_default();
+                restore.cursor = (
+                    cursor_x.parse().unwrap_or_default(),
+                    cursor_y.parse().unwrap_or_default(),
+                );
+                restore.history_size = history_size.parse().unwrap_or_default();
+                restore.alternate = *alternate == "1";
+                self.expected.push_back(Expected::History(pane));
+                self.expected.push_back(Expected::Screen(pane));
+            }
+            _ => {}
+        }
+    }
+
+    // Records the panes of a window, asking for the contents of those that
+    // were not shown before.
+    fn set_window_panes(&mut self, window: u32, panes: Vec<u32>, events: &mut Vec<ClientEvent>) {
+        let previous = self
+            .windows
+            .insert(window, panes.clone())
+            .unwrap_or_default();
+        for pane in previous.iter().filter(|pane| !panes.contains(pane)) {
+            self.restoring.remove(pane);
+        }
+        for pane in panes.into_iter().filter(|pane| !previous.contains(pane)) {
+            self.restoring.insert(pane, Restore::default());
+            events.push(ClientEvent::Command(format!(
+                "display-message -p -t %{pane} \"{REPLY_MARKER} pane {pane} #{{cursor_x}} \
+                 #{{cursor_y}} #{{history_size}} #{{alternate_on}}\" ; \
+                 capture-pane -p -e -J -t %{pane} -S -{HISTORY_LINES} -E -1 ; \
+                 capture-pane -p -e -t %{pane}"
+            )));
+        }
+    }
+}
+
+fn list_windows() -> ClientEvent {
+    ClientEvent::Command(format!(
+        "display-message -p \"{REPLY_MARKER} windows\" ; list-windows -F \
+         \"#{{window_id}} #{{window_active}} #{{pane_id}} \
+         #{{window_visible_layout}} #{{window_name}}\""
+    ))
+}
+
+// `@1 1 %3 b25d,80x24,0,0,3 name with spaces`
+fn parse_window(line: &str) -> Option<WindowInfo> {
+    let mut fields = line.splitn(5, ' ');
+    let id = parse_id(fields.next()?, '@')?;
+    let active = fields.next()? == "1";
+    let active_pane = parse_id(fields.next()?, '%')?;
+    let layout = Layout::parse(fields.next()?)?;
+    let name = fields.next().unwrap_or_default().to_owned();
+    Some(WindowInfo {
+        id,
+        name,
+        active,
+        active_pane,
+        layout,
+    })
+}
+
+// History lines become committed output; a primary screen is replayed up to
+// the cursor line like a shell would have printed it, an alternate screen is
+// painted row by row.
+fn restore_screen(
+    history: &[String],
+    screen: &[String],
+    cursor: (u16, u16),
+    alternate: bool,
+) -> Vec<u8> {
+    let (cursor_x, cursor_y) = (usize::from(cursor.0), usize::from(cursor.1));
+    let mut out = String::new();
+    for line in history {
+        out.push_str(line.trim_end());
+        out.push_str("\r\n");
+    }
+    if alternate {
+        out.push_str("\x1b[?1049h");
+        for (row, line) in screen.iter().enumerate() {
+            let _ = write!(out, "\x1b[{};1H\x1b[2K{line}", row + 1);
+        }
+        let _ = write!(out, "\x1b[0m\x1b[{};{}H", cursor_y + 1, cursor_x + 1);
+    } else {
+        let rows = screen.len().min(cursor_y + 1);
+        out.push_str(&screen[..rows].join("\r\n"));
+        let _ = write!(out, "\x1b[0m\x1b[{}G", cursor_x + 1);
+    }
+    out.into_bytes()
+}
+
+/// Input for a tmux pane as `send-keys` commands, one per line.
+pub fn send_keys(pane: u32, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(KEYS_PER_COMMAND) {
+        let _ = write!(out, "send-keys -H -t %{pane}");
+        for byte in chunk {
+            let _ = write!(out, " {byte:02x}");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Splits a pane; `side_by_side` puts the new pane to its right.
+pub fn split_window(pane: u32, side_by_side: bool) -> String {
+    let flag = if side_by_side { "-h" } else { "-v" };
+    format!("split-window {flag} -t %{pane}")
+}
+
+/// Makes a pane and its window the current ones.
+pub fn select_pane(pane: u32) -> String {
+    format!("select-window -t %{pane} ; select-pane -t %{pane}")
+}
+
+/// Zooms a pane or, when zoomed, unzooms it.
+pub fn zoom_pane(pane: u32) -> String {
+    format!("resize-pane -Z -t %{pane}")
+}
+
+pub fn kill_pane(pane: u32) -> String {
+    format!("kill-pane -t %{pane}")
+}
+
+/// Opens a session for tmux pane `pane` of the client running in `gateway`.
+/// Its output is whatever is sent through the returned sender.
+pub fn pane_session(gateway: &PtySession, pane: u32) -> Result<(PtySession, Sender<Vec<u8>>)> {
+    let (sender, receiver) = unbounded();
+    let gateway = gateway.shared_writer();
+    let reader = FeedReader {
+        receiver,
+        chunk: Vec::new(),
+        offset: 0,
+    };
+    let writer = KeysWriter {
+        pane,
+        gateway: gateway.clone(),
+    };
+    let backend = Backend::Tmux { pane, gateway };
+    let session = PtySession::with_backend(
+        backend,
+        Box::new(reader),
+        Box::new(writer),
+        ShellKind::Other,
+    )?;
+    Ok((session, sender))
+}
+
+struct FeedReader {
+    receiver: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    offset: usize,
+}
+
+impl Read for FeedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset == self.chunk.len() {
+            // The pane is gone once the client drops its sender.
+            let Ok(chunk) = self.receiver.recv() else {
+                return Ok(0);
+            };
+            self.chunk = chunk;
+            self.offset = 0;
+        }
+        let len = buf.len().min(self.chunk.len() - self.offset);
+        buf[..len].copy_from_slice(&self.chunk[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+struct KeysWriter {
+    pane: u32,
+    gateway: SharedWriter,
+}
+
+impl Write for KeysWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut gateway = self
+            .gateway
+            .lock()
+            .map_err(|_| io::Error::other("tmux client writer poisoned"))?;
+        gateway.write_all(send_keys(self.pane, buf).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.gateway
+            .lock()
+            .map_err(|_| io::Error::other("tmux client writer poisoned"))?
+            .flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ClientEvent, ControlClient, ControlParser, Layout, LayoutCells, Notification, REPLY_MARKER,
+        send_keys,
+    };
+
+    fn parse(parser: &mut ControlParser, text: &[u8]) -> Vec<Notification> {
+        text.iter().filter_map(|byte| parser.put(*byte)).collect()
+    }
+
+    fn reply(lines: &[&str]) -> Notification {
+        Notification::Reply {
+            lines: lines.iter().map(|line| (*line).to_owned()).collect(),
+            success: true,
+        }
+    }
+
+    #[test]
+    fn control_lines_become_notifications() {
+        let mut parser = ControlParser::default();
+        let stream = b"%begin 17 263 0\r\n%end 1 2 0\r\n%end 17 263 0\r\n\
+%output %3 ls\\015\\012\\134\r\n\
+%layout-change @1 8205,80x24,0,0{40x24,0,0,0,39x24,41,0,1} a87d,80x24,0,0,0 *Z\r\n\
+%window-renamed @1 my window\r\n%unknown thing\r\n%exit\r\n";
+        let notifications = parse(&mut parser, stream);
+        assert_eq!(notifications.len(), 5);
+        assert_eq!(notifications[0], reply(&["%end 1 2 0"]));
+        assert_eq!(
+            notifications[1],
+            Notification::Output {
+                pane: 3,
+                data: b"ls\r\n\\".to_vec()
+            }
+        );
+        let Notification::LayoutChange { window: 1, layout } = &notifications[2] else {
+            panic!("expected a layout change, got {:?}", notifications[2]);
+        };
+        assert_eq!(layout.cells, LayoutCells::Pane(0));
+        assert_eq!(
+            notifications[3],
+            Notification::WindowRenamed {
+                window: 1,
+                name: "my window".to_owned()
+            }
+        );
+        assert_eq!(notifications[4], Notification::Exit { reason: None });
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn layouts_nest_and_fit_pane_sizes() {
+        let layout =
+            Layout::parse("5e2a,80x24,0,0{40x24,0,0,0,39x24,41,0[39x12,41,0,1,39x11,41,13,2]}")
+                .expect("layout should parse");
+        assert_eq!(layout.panes(), vec![(0, 40, 24), (1, 39, 12), (2, 39, 11)]);
+        assert_eq!(layout.fit(&|_| None), (80, 24));
+        assert_eq!(
+            layout.fit(&|pane| (pane == 0).then_some((50, 30))),
+            (90, 30)
+        );
+        assert!(Layout::parse("5e2a,80x24,0,0{40x24,0,0,0").is_none());
+    }
+
+    #[test]
+    fn new_panes_are_restored_before_live_output() {
+        let mut client = ControlClient::default();
+        let events = client.handle(Notification::SessionChanged {
+            session: 0,
+            name: "main".to_owned(),
+        });
+        assert!(events.is_empty());
+        let events = client.handle(reply(&[]));
+        assert!(
+            matches!(&events[..], [ClientEvent::Command(command)] if command.contains("list-windows"))
+        );
+
+        assert!(
+            client
+                .handle(reply(&[&format!("{REPLY_MARKER} windows")]))
+                .is_empty()
+        );
+        let events = client.handle(reply(&["@1 1 %4 b25d,80x24,0,0,4 shell"]));
+        let [ClientEvent::Windows(windows), ClientEvent::Command(capture)] = &events[..] else {
+            panic!("unexpected events {events:?}");
+        };
+        assert_eq!(windows[0].name, "shell");
+        assert_eq!(windows[0].active_pane, 4);
+        assert!(capture.contains("capture-pane -p -e -t %4"));
+
+        // Output already covered by the capture is dropped.
+        let output = Notification::Output {
+            pane: 4,
+            data: b"old".to_vec(),
+        };
+        assert!(client.handle(output.clone()).is_empty());
+        client.handle(reply(&[&format!("{REPLY_MARKER} pane 4 2 1 1 0")]));
+        client.handle(reply(&["earlier   "]));
+        let events = client.handle(reply(&["$ ls", "$ ", "", ""]));
+        assert_eq!(
+            events,
+            vec![ClientEvent::Output {
+                pane: 4,
+                data: b"earlier\r\n$ ls\r\n$ \x1b[0m\x1b[3G".to_vec()
+            }]
+        );
+        assert_eq!(client.handle(output).len(), 1);
+
+        let events = client.handle(Notification::WindowClose { window: 1 });
+        assert_eq!(events, vec![ClientEvent::WindowClosed(1)]);
+        assert_eq!(
+            client.resize(100, 30).as_deref(),
+            Some("refresh-client -C 100x30")
+        );
+        assert_eq!(client.resize(100, 30), None);
+    }
+
+    #[test]
+    fn nothing_is_sent_before_tmux_answers_the_attach() {
+        let mut client = ControlClient::default();
+        for notification in [
+            Notification::WindowAdd { window: 1 },
+            Notification::LayoutChange {
+                window: 1,
+                layout: Layout::parse("b25d,80x24,0,0,4").expect("layout should parse"),
+            },
+            Notification::Output {
+                pane: 4,
+                data: b"rm -rf ~".to_vec(),
+            },
+        ] {
+            assert!(client.handle(notification).is_empty());
+        }
+        assert_eq!(
+            client.handle(Notification::Exit { reason: None }),
+            vec![ClientEvent::Exit(None)]
+        );
+    }
+
+    #[test]
+    fn input_is_sent_as_hex_keys() {
+        assert_eq!(send_keys(2, b"l\r"), "send-keys -H -t %2 6c 0d\n");
+        let paste = vec![b'a'; 300];
+        assert_eq!(send_keys(2, &paste).lines().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mirrors_a_local_tmux_session() {
+        use crate::emulator::{TerminalEmulator, TerminalEvent};
+        use crate::pty::{PtySession, ShellProfile};
+        use crossbeam_channel::Sender;
+        use std::collections::HashMap;
+        use std::time::{Duration, Instant};
+
+        if std::process::Command::new("tmux")
+            .arg("-V")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let socket = std::env::temp_dir().join(format!("ctyterm-tmux-test-{}", std::process::id()));
+        let tmux = |args: &[&str]| {
+            std::process::Command::new("tmux")
+                .args(["-f", "/dev/null", "-S"])
+                .arg(&socket)
+                .args(args)
+                .status()
+        };
+        let started = tmux(&[
+            "new-session",
+            "-d",
+            "-x",
+            "80",
+            "-y",
+            "24",
+            "sh",
+            "-c",
+            "printf 'before %s\\n' attach; exec sh",
+        ]);
+        if !started.is_ok_and(|status| status.success()) {
+            return;
+        }
+
+        let profile = ShellProfile {
+            args: vec![
+                "-f".to_owned(),
+                "/dev/null".to_owned(),
+                "-S".to_owned(),
+                socket.display().to_string(),
+                "-CC".to_owned(),
+                "attach".to_owned(),
+            ],
+            shell_integration: false,
+            ..ShellProfile::new("tmux")
+        };
+        let gateway = PtySession::spawn(&profile, 80, 24).expect("tmux should start");
+        let mut emulator = TerminalEmulator::new(80, 24);
+        let mut client = ControlClient::default();
+        let mut panes: HashMap<u32, (PtySession, Sender<Vec<u8>>)> = HashMap::new();
+        let mut screens: HashMap<u32, TerminalEmulator> = HashMap::new();
+        let mut typed = false;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let found = loop {
+            if Instant::now() > deadline {
+                break false;
+            }
+            let Some(chunk) = gateway.try_read_chunk() else {
+                for (pane, (session, _)) in &panes {
+                    let screen = screens.get_mut(pane).unwrap();
+                    while let Some(chunk) = session.try_read_chunk() {
+                        screen.feed(&chunk);
+                    }
+                }
+                let text = screens
+                    .values()
+                    .flat_map(|screen| screen.grid().rows().iter().map(|row| row.text()))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if text.contains("before attach") && text.contains("after input") {
+                    break true;
+                }
+                if text.contains("before attach") && !typed {
+                    typed = true;
+                    let (session, _) = panes.values().next().unwrap();
+                    session.write_line("echo after' 'input").unwrap();
+                }
+                std::thread::sleep(Duration::from_millis(20));
+                continue;
+            };
+            for event in emulator.feed(&chunk) {
+                let TerminalEvent::Tmux(notification) = event else {
+                    continue;
+                };
+                for event in client.handle(notification) {
+                    match event {
+                        ClientEvent::Command(command) => {
+                            gateway.write_input(&format!("{command}\n")).unwrap()
+                        }
+                        ClientEvent::Windows(windows) => {
+                            for (pane, cols, rows) in windows[0].layout.panes() {
+                                let (session, sender) =
+                                    super::pane_session(&gateway, pane).unwrap();
+                                panes.insert(pane, (session, sender));
+                                screens
+                                    .insert(pane, TerminalEmulator::new(cols.into(), rows.into()));
+                            }
+                        }
+                        ClientEvent::Output { pane, data } => {
+                            if let Some((_, sender)) = panes.get(&pane) {
+                                sender.send(data).unwrap();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        };
+        let _ = tmux(&["kill-server"]);
+        let _ = std::fs::remove_file(&socket);
+        assert!(found, "tmux pane contents were not mirrored");
+    }
+}
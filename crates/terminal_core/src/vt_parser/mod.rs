@@ -1,127 +1,388 @@
+const MAX_PARAMS: usize = 32;
+const MAX_INTERMEDIATES: usize = 2;
+// Inline images (iTerm2 OSC 1337, unchunked Kitty APC) arrive as one string.
+// Longer OSC and APC strings are dropped whole rather than cut short.
+const MAX_STRING_LEN: usize = 32 * 1024 * 1024;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum ParseState {
     Ground,
     Escape,
-    Csi,
-    Osc,
-    OscEscape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    OscString,
+    DcsEntry,
+    DcsParam,
+    DcsIntermediate,
+    DcsPassthrough,
+    DcsIgnore,
+    ApcString,
+    SosPmString,
+}
+
+/// Numeric CSI/DCS parameters. Each entry holds the colon-separated
+/// sub-parameters of one `;`-separated parameter; omitted values are 0.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Params {
+    groups: Vec<Vec<u16>>,
 }
 
-pub struct MinimalVtParser {
+impl Params {
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u16]> {
+        self.groups.iter().map(Vec::as_slice)
+    }
+
+    pub fn get(&self, idx: usize) -> Option<u16> {
+        self.groups
+            .get(idx)
+            .and_then(|group| group.first())
+            .copied()
+    }
+
+    /// Returns parameter `idx`, substituting `default` when it is missing or 0.
+    pub fn get_or(&self, idx: usize, default: u16) -> u16 {
+        match self.get(idx) {
+            Some(0) | None => default,
+            Some(value) => value,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.groups.clear();
+    }
+
+    fn push_digit(&mut self, digit: u8) {
+        if self.groups.is_empty() {
+            self.groups.push(vec![0]);
+        }
+        if let Some(value) = self.groups.last_mut().and_then(|group| group.last_mut()) {
+            *value = value.saturating_mul(10).saturating_add(u16::from(digit));
+        }
+    }
+
+    fn next_param(&mut self) {
+        if self.groups.is_empty() {
+            self.groups.push(vec![0]);
+        }
+        if self.groups.len() < MAX_PARAMS {
+            self.groups.push(vec![0]);
+        }
+    }
+
+    fn next_subparam(&mut self) {
+        if self.groups.is_empty() {
+            self.groups.push(vec![0]);
+        }
+        if let Some(group) = self.groups.last_mut() {
+            group.push(0);
+        }
+    }
+}
+
+/// Receives the decoded actions of a [`VtParser`].
+pub trait Perform {
+    fn print(&mut self, ch: char);
+
+    fn execute(&mut self, byte: u8);
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: u8);
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8);
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool);
+
+    fn dcs_hook(&mut self, _params: &Params, _intermediates: &[u8], _action: u8) {}
+
+    fn dcs_put(&mut self, _byte: u8) {}
+
+    fn dcs_unhook(&mut self) {}
+
+    fn apc_dispatch(&mut self, _data: &[u8]) {}
+}
+
+/// Byte-level VT500 state machine (after Paul Williams' DEC parser) with
+/// UTF-8 decoding in the ground state.
+pub struct VtParser {
     state: ParseState,
-    current_line: String,
+    params: Params,
+    intermediates: Vec<u8>,
+    osc: Vec<u8>,
+    apc: Vec<u8>,
     pending_utf8: Vec<u8>,
-    saw_cr: bool,
+    // The last byte was ESC inside a string state; `\` completes ST.
+    string_escape: bool,
+    // The OSC or APC string being collected outgrew MAX_STRING_LEN.
+    string_overflow: bool,
 }
 
-impl Default for MinimalVtParser {
+impl Default for VtParser {
     fn default() -> Self {
         Self {
             state: ParseState::Ground,
-            current_line: String::new(),
+            params: Params::default(),
+            intermediates: Vec::new(),
+            osc: Vec::new(),
+            apc: Vec::new(),
             pending_utf8: Vec::new(),
-            saw_cr: false,
+            string_escape: false,
+            string_overflow: false,
         }
     }
 }
 
-impl MinimalVtParser {
-    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
-        let mut completed = Vec::new();
-
+impl VtParser {
+    pub fn advance<P: Perform>(&mut self, performer: &mut P, chunk: &[u8]) {
         for &byte in chunk {
-            match self.state {
-                ParseState::Ground => match byte {
-                    0x1B => {
-                        self.flush_pending_utf8();
-                        self.state = ParseState::Escape;
-                    }
-                    b'\r' => {
-                        self.flush_pending_utf8();
-                        self.saw_cr = true;
-                    }
-                    b'\n' => {
-                        self.flush_pending_utf8();
-                        self.saw_cr = false;
-                        completed.push(std::mem::take(&mut self.current_line));
-                    }
-                    0x08 => {
-                        self.flush_pending_utf8();
-                        self.saw_cr = false;
-                        self.current_line.pop();
+            self.advance_byte(performer, byte);
+        }
+    }
+
+    fn advance_byte<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if self.string_escape {
+            self.string_escape = false;
+            if byte == b'\\' {
+                self.finish_string(performer, false);
+                self.state = ParseState::Ground;
+                return;
+            }
+            self.finish_string(performer, false);
+            self.enter_escape();
+        }
+
+        match byte {
+            0x18 | 0x1A => {
+                self.abort_string(performer);
+                self.flush_invalid_utf8(performer);
+                self.state = ParseState::Ground;
+                return;
+            }
+            0x1B => {
+                self.flush_invalid_utf8(performer);
+                if self.in_string_state() {
+                    self.string_escape = true;
+                } else {
+                    self.enter_escape();
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        match self.state {
+            ParseState::Ground => self.ground(performer, byte),
+            ParseState::Escape => match byte {
+                0x00..=0x17 | 0x19 | 0x1C..=0x1F => performer.execute(byte),
+                0x20..=0x2F => {
+                    self.collect(byte);
+                    self.state = ParseState::EscapeIntermediate;
+                }
+                b'[' => {
+                    self.clear_sequence();
+                    self.state = ParseState::CsiEntry;
+                }
+                b']' => {
+                    self.osc.clear();
+                    self.state = ParseState::OscString;
+                }
+                b'P' => {
+                    self.clear_sequence();
+                    self.state = ParseState::DcsEntry;
+                }
+                b'_' => {
+                    self.apc.clear();
+                    self.state = ParseState::ApcString;
+                }
+                b'X' | b'^' => self.state = ParseState::SosPmString,
+                0x30..=0x7E => {
+                    performer.esc_dispatch(&self.intermediates, byte);
+                    self.state = ParseState::Ground;
+                }
+                _ => {}
+            },
+            ParseState::EscapeIntermediate => match byte {
+                0x00..=0x17 | 0x19 | 0x1C..=0x1F => performer.execute(byte),
+                0x20..=0x2F => self.collect(byte),
+                0x30..=0x7E => {
+                    performer.esc_dispatch(&self.intermediates, byte);
+                    self.state = ParseState::Ground;
+                }
+                _ => {}
+            },
+            ParseState::CsiEntry | ParseState::CsiParam => match byte {
+                0x00..=0x17 | 0x19 | 0x1C..=0x1F => performer.execute(byte),
+                b'0'..=b'9' => {
+                    self.params.push_digit(byte - b'0');
+                    self.state = ParseState::CsiParam;
+                }
+                b';' => {
+                    self.params.next_param();
+                    self.state = ParseState::CsiParam;
+                }
+                b':' => {
+                    self.params.next_subparam();
+                    self.state = ParseState::CsiParam;
+                }
+                b'<'..=b'?' => {
+                    if self.state == ParseState::CsiEntry {
+                        self.collect(byte);
+                    } else {
+                        self.state = ParseState::CsiIgnore;
                     }
-                    b'\t' => {
-                        self.flush_pending_utf8();
-                        if self.saw_cr {
-                            self.current_line.clear();
-                            self.saw_cr = false;
-                        }
-                        self.saw_cr = false;
-                        self.current_line.push_str("    ");
+                }
+                0x20..=0x2F => {
+                    self.collect(byte);
+                    self.state = ParseState::CsiIntermediate;
+                }
+                0x40..=0x7E => {
+                    performer.csi_dispatch(&self.params, &self.intermediates, byte);
+                    self.state = ParseState::Ground;
+                }
+                _ => {}
+            },
+            ParseState::CsiIntermediate => match byte {
+                0x00..=0x17 | 0x19 | 0x1C..=0x1F => performer.execute(byte),
+                0x20..=0x2F => self.collect(byte),
+                0x30..=0x3F => self.state = ParseState::CsiIgnore,
+                0x40..=0x7E => {
+                    performer.csi_dispatch(&self.params, &self.intermediates, byte);
+                    self.state = ParseState::Ground;
+                }
+                _ => {}
+            },
+            ParseState::CsiIgnore => match byte {
+                0x00..=0x17 | 0x19 | 0x1C..=0x1F => performer.execute(byte),
+                0x40..=0x7E => self.state = ParseState::Ground,
+                _ => {}
+            },
+            ParseState::OscString => match byte {
+                0x07 => {
+                    self.finish_string(performer, true);
+                    self.state = ParseState::Ground;
+                }
+                0x00..=0x1F => {}
+                _ => {
+                    if self.osc.len() < MAX_STRING_LEN {
+                        self.osc.push(byte);
+                    } else {
+                        self.string_overflow = true;
                     }
-                    b if !b.is_ascii_control() => {
-                        if self.saw_cr {
-                            // CR without LF means cursor returned to column 0.
-                            // Approximate this by replacing the current line content.
-                            self.current_line.clear();
-                        }
-                        self.saw_cr = false;
-                        self.pending_utf8.push(b);
-                        self.flush_pending_utf8();
+                }
+            },
+            ParseState::DcsEntry | ParseState::DcsParam => match byte {
+                b'0'..=b'9' => {
+                    self.params.push_digit(byte - b'0');
+                    self.state = ParseState::DcsParam;
+                }
+                b';' => {
+                    self.params.next_param();
+                    self.state = ParseState::DcsParam;
+                }
+                b':' => {
+                    self.params.next_subparam();
+                    self.state = ParseState::DcsParam;
+                }
+                b'<'..=b'?' => {
+                    if self.state == ParseState::DcsEntry {
+                        self.collect(byte);
+                    } else {
+                        self.state = ParseState::DcsIgnore;
                     }
-                    _ => {}
-                },
-                ParseState::Escape => match byte {
-                    b'[' => self.state = ParseState::Csi,
-                    b']' => self.state = ParseState::Osc,
-                    _ => self.state = ParseState::Ground,
-                },
-                ParseState::Csi => {
-                    if (0x40..=0x7E).contains(&byte) {
-                        self.state = ParseState::Ground;
+                }
+                0x20..=0x2F => {
+                    self.collect(byte);
+                    self.state = ParseState::DcsIntermediate;
+                }
+                0x40..=0x7E => {
+                    performer.dcs_hook(&self.params, &self.intermediates, byte);
+                    self.state = ParseState::DcsPassthrough;
+                }
+                _ => {}
+            },
+            ParseState::DcsIntermediate => match byte {
+                0x20..=0x2F => self.collect(byte),
+                0x30..=0x3F => self.state = ParseState::DcsIgnore,
+                0x40..=0x7E => {
+                    performer.dcs_hook(&self.params, &self.intermediates, byte);
+                    self.state = ParseState::DcsPassthrough;
+                }
+                _ => {}
+            },
+            ParseState::DcsPassthrough => match byte {
+                0x7F => {}
+                _ => performer.dcs_put(byte),
+            },
+            ParseState::ApcString => match byte {
+                0x07 => {
+                    self.finish_string(performer, true);
+                    self.state = ParseState::Ground;
+                }
+                0x00..=0x1F => {}
+                _ => {
+                    if self.apc.len() < MAX_STRING_LEN {
+                        self.apc.push(byte);
+                    } else {
+                        self.string_overflow = true;
                     }
                 }
-                ParseState::Osc => match byte {
-                    0x07 => self.state = ParseState::Ground,
-                    0x1B => self.state = ParseState::OscEscape,
-                    _ => {}
-                },
-                ParseState::OscEscape => match byte {
-                    b'\\' => self.state = ParseState::Ground,
-                    _ => self.state = ParseState::Osc,
-                },
+            },
+            ParseState::DcsIgnore | ParseState::SosPmString => {
+                if byte == 0x07 {
+                    self.state = ParseState::Ground;
+                }
             }
         }
-
-        self.flush_pending_utf8();
-        completed
     }
 
-    pub fn current_line(&self) -> &str {
-        &self.current_line
+    fn ground<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match byte {
+            0x00..=0x1F => {
+                self.flush_invalid_utf8(performer);
+                performer.execute(byte);
+            }
+            0x20..=0x7E if self.pending_utf8.is_empty() => performer.print(byte as char),
+            0x7F if self.pending_utf8.is_empty() => {}
+            _ => {
+                self.pending_utf8.push(byte);
+                self.decode_utf8(performer);
+            }
+        }
     }
 
-    fn flush_pending_utf8(&mut self) {
+    fn decode_utf8<P: Perform>(&mut self, performer: &mut P) {
         while !self.pending_utf8.is_empty() {
             match std::str::from_utf8(&self.pending_utf8) {
                 Ok(text) => {
-                    self.current_line.push_str(text);
+                    for ch in text.chars() {
+                        performer.print(ch);
+                    }
                     self.pending_utf8.clear();
-                    break;
                 }
                 Err(err) => {
                     let valid_up_to = err.valid_up_to();
                     if valid_up_to > 0 {
                         let valid = std::str::from_utf8(&self.pending_utf8[..valid_up_to])
                             .expect("valid prefix must decode");
-                        self.current_line.push_str(valid);
+                        for ch in valid.chars() {
+                            performer.print(ch);
+                        }
                         self.pending_utf8.drain(..valid_up_to);
                         continue;
                     }
 
                     match err.error_len() {
                         Some(error_len) => {
-                            self.current_line.push('\u{FFFD}');
+                            performer.print('\u{FFFD}');
                             self.pending_utf8.drain(..error_len);
                         }
                         None => break,
@@ -130,47 +391,196 @@ impl MinimalVtParser {
             }
         }
     }
+
+    fn flush_invalid_utf8<P: Perform>(&mut self, performer: &mut P) {
+        if !self.pending_utf8.is_empty() {
+            self.pending_utf8.clear();
+            performer.print('\u{FFFD}');
+        }
+    }
+
+    fn enter_escape(&mut self) {
+        self.intermediates.clear();
+        self.state = ParseState::Escape;
+    }
+
+    fn clear_sequence(&mut self) {
+        self.params.clear();
+        self.intermediates.clear();
+    }
+
+    fn collect(&mut self, byte: u8) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(byte);
+        }
+    }
+
+    fn in_string_state(&self) -> bool {
+        matches!(
+            self.state,
+            ParseState::OscString
+                | ParseState::DcsPassthrough
+                | ParseState::DcsIgnore
+                | ParseState::ApcString
+                | ParseState::SosPmString
+        )
+    }
+
+    fn finish_string<P: Perform>(&mut self, performer: &mut P, bell_terminated: bool) {
+        if std::mem::take(&mut self.string_overflow) {
+            self.osc.clear();
+            self.apc.clear();
+            return;
+        }
+        match self.state {
+            ParseState::OscString => {
+                let params: Vec<&[u8]> = self.osc.split(|b| *b == b';').collect();
+                performer.osc_dispatch(&params, bell_terminated);
+                self.osc.clear();
+            }
+            ParseState::DcsPassthrough => performer.dcs_unhook(),
+            ParseState::ApcString => {
+                performer.apc_dispatch(&self.apc);
+                self.apc.clear();
+            }
+            _ => {}
+        }
+    }
+
+    fn abort_string<P: Perform>(&mut self, performer: &mut P) {
+        if self.state == ParseState::DcsPassthrough {
+            performer.dcs_unhook();
+        }
+        self.osc.clear();
+        self.apc.clear();
+        self.string_escape = false;
+        self.string_overflow = false;
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::MinimalVtParser;
+    use super::{Params, Perform, VtParser};
+
+    #[derive(Default)]
+    struct Recorder {
+        printed: String,
+        executed: Vec<u8>,
+        csi: Vec<(Vec<Vec<u16>>, Vec<u8>, char)>,
+        osc: Vec<Vec<String>>,
+        apc: Vec<String>,
+    }
+
+    impl Perform for Recorder {
+        fn print(&mut self, ch: char) {
+            self.printed.push(ch);
+        }
+
+        fn execute(&mut self, byte: u8) {
+            self.executed.push(byte);
+        }
+
+        fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: u8) {
+            self.csi.push((
+                params.iter().map(<[u16]>::to_vec).collect(),
+                intermediates.to_vec(),
+                action as char,
+            ));
+        }
+
+        fn esc_dispatch(&mut self, _intermediates: &[u8], _byte: u8) {}
+
+        fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+            self.osc.push(
+                params
+                    .iter()
+                    .map(|p| String::from_utf8_lossy(p).to_string())
+                    .collect(),
+            );
+        }
+
+        fn apc_dispatch(&mut self, data: &[u8]) {
+            self.apc.push(String::from_utf8_lossy(data).to_string());
+        }
+    }
+
+    #[test]
+    fn parses_csi_params_and_private_marker() {
+        let mut parser = VtParser::default();
+        let mut recorder = Recorder::default();
+        parser.advance(&mut recorder, b"\x1b[?1049h\x1b[12;40H");
+        assert_eq!(recorder.csi[0], (vec![vec![1049]], b"?".to_vec(), 'h'));
+        assert_eq!(recorder.csi[1], (vec![vec![12], vec![40]], Vec::new(), 'H'));
+    }
 
     #[test]
-    fn strips_ansi_sequences() {
-        let mut parser = MinimalVtParser::default();
-        let lines = parser.feed(b"\x1b[31mhello\x1b[0m\n");
-        assert_eq!(lines, vec!["hello"]);
+    fn parses_colon_subparameters() {
+        let mut parser = VtParser::default();
+        let mut recorder = Recorder::default();
+        parser.advance(&mut recorder, b"\x1b[38:2::10:20:30m");
+        assert_eq!(recorder.csi[0].0, vec![vec![38, 2, 0, 10, 20, 30]]);
     }
 
     #[test]
-    fn handles_crlf_without_dropping_line_content() {
-        let mut parser = MinimalVtParser::default();
-        let lines = parser.feed(b"alpha\r\nbeta\r\n");
-        assert_eq!(lines, vec!["alpha", "beta"]);
+    fn parses_osc_with_bel_and_st_terminators() {
+        let mut parser = VtParser::default();
+        let mut recorder = Recorder::default();
+        parser.advance(
+            &mut recorder,
+            b"\x1b]0;title\x07\x1b]7;file://host/tmp\x1b\\",
+        );
+        assert_eq!(recorder.osc[0], vec!["0", "title"]);
+        assert_eq!(recorder.osc[1], vec!["7", "file://host/tmp"]);
+        assert!(recorder.printed.is_empty());
     }
 
     #[test]
-    fn carriage_return_rewrites_the_same_line() {
-        let mut parser = MinimalVtParser::default();
-        let lines = parser.feed(b"first\rsecond\n");
-        assert_eq!(lines, vec!["second"]);
+    fn apc_strings_are_dispatched_and_pm_is_dropped() {
+        let mut parser = VtParser::default();
+        let mut recorder = Recorder::default();
+        parser.advance(
+            &mut recorder,
+            b"\x1b_Ga=q,i=1;AAAA\x1b\\\x1b^private\x1b\\ok",
+        );
+        assert_eq!(recorder.apc, vec!["Ga=q,i=1;AAAA"]);
+        assert_eq!(recorder.printed, "ok");
     }
 
     #[test]
-    fn parses_utf8_text() {
-        let mut parser = MinimalVtParser::default();
-        let lines = parser.feed("한글 출력\n".as_bytes());
-        assert_eq!(lines, vec!["한글 출력"]);
+    fn overlong_strings_are_dropped_not_truncated() {
+        let mut parser = VtParser::default();
+        let mut recorder = Recorder::default();
+        let mut osc = b"\x1b]0;".to_vec();
+        osc.resize(super::MAX_STRING_LEN + 16, b'x');
+        osc.extend_from_slice(b"\x07\x1b]0;short\x07");
+        parser.advance(&mut recorder, &osc);
+        assert_eq!(recorder.osc, vec![vec!["0", "short"]]);
+
+        let mut apc = b"\x1b_G".to_vec();
+        apc.resize(super::MAX_STRING_LEN + 16, b'A');
+        apc.extend_from_slice(b"\x1b\\ok");
+        parser.advance(&mut recorder, &apc);
+        assert!(recorder.apc.is_empty());
+        assert_eq!(recorder.printed, "ok");
     }
 
     #[test]
     fn keeps_utf8_sequences_split_across_chunks() {
-        let mut parser = MinimalVtParser::default();
-        let text = "가\n".as_bytes();
-        let lines = parser.feed(&text[..1]);
-        assert!(lines.is_empty());
-        let lines = parser.feed(&text[1..]);
-        assert_eq!(lines, vec!["가"]);
+        let mut parser = VtParser::default();
+        let mut recorder = Recorder::default();
+        let text = "가".as_bytes();
+        parser.advance(&mut recorder, &text[..1]);
+        assert!(recorder.printed.is_empty());
+        parser.advance(&mut recorder, &text[1..]);
+        assert_eq!(recorder.printed, "가");
+    }
+
+    #[test]
+    fn invalid_utf8_becomes_replacement_character() {
+        let mut parser = VtParser::default();
+        let mut recorder = Recorder::default();
+        parser.advance(&mut recorder, b"a\xffb\r");
+        assert_eq!(recorder.printed, "a\u{FFFD}b");
+        assert_eq!(recorder.executed, vec![b'\r']);
     }
 }
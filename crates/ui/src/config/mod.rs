@@ -1,21 +1,29 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use terminal_core::asciicast;
+use terminal_core::clipboard::ClipboardRequest;
+use terminal_core::mux::{self, PtyHost};
+use terminal_core::pty::ShellProfile;
+use terminal_core::scrollback;
 use ux_model::ai::AiTool;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct AppConfig {
     pub ai: AiConfig,
     pub session: SessionConfig,
-}
-
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            ai: AiConfig::default(),
-            session: SessionConfig::default(),
-        }
-    }
+    pub shell: ShellConfig,
+    pub scrollback: ScrollbackConfig,
+    pub clipboard: ClipboardConfig,
+    pub bell: BellConfig,
+    pub notifications: NotificationConfig,
+    // Left out of written configs where the mux server cannot run.
+    #[cfg_attr(not(unix), serde(skip_serializing))]
+    pub mux: MuxConfig,
+    pub recording: RecordingConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -99,20 +107,247 @@ impl Default for SessionConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
-pub struct AiCommandTemplate {
+pub struct ShellConfig {
+    // Empty program means the platform default ($SHELL, or PowerShell on Windows).
     pub program: String,
     pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub cwd: String,
+    pub init_script: String,
+    pub shell_integration: bool,
 }
 
-impl Default for AiCommandTemplate {
+impl Default for ShellConfig {
     fn default() -> Self {
         Self {
             program: String::new(),
             args: Vec::new(),
+            env: BTreeMap::new(),
+            cwd: String::new(),
+            init_script: String::new(),
+            shell_integration: true,
+        }
+    }
+}
+
+impl ShellConfig {
+    pub fn to_profile(&self) -> ShellProfile {
+        let mut profile = if self.program.trim().is_empty() {
+            ShellProfile::platform_default()
+        } else {
+            ShellProfile::new(self.program.trim())
+        };
+        if !self.program.trim().is_empty() || !self.args.is_empty() {
+            profile.args = self.args.clone();
+        }
+        profile.env.extend(
+            self.env
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        if !self.cwd.trim().is_empty() {
+            profile.cwd = Some(PathBuf::from(self.cwd.trim()));
+        }
+        if !self.init_script.trim().is_empty() {
+            profile.init_script = Some(self.init_script.clone());
+        }
+        profile.shell_integration = self.shell_integration;
+        profile
+    }
+}
+
+/// Output storage limits, shared by all command blocks of a pane.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScrollbackConfig {
+    pub memory_budget_mb: u64,
+    pub disk_budget_mb: u64,
+    pub page_lines: usize,
+    pub spill_to_disk: bool,
+    // Empty means the private per-user cache directory,
+    // `$XDG_CACHE_HOME/ctyterm/scrollback` or `~/.cache/ctyterm/scrollback`.
+    pub spill_dir: String,
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        Self {
+            memory_budget_mb: 32,
+            disk_budget_mb: 256,
+            page_lines: 256,
+            spill_to_disk: true,
+            spill_dir: String::new(),
+        }
+    }
+}
+
+impl ScrollbackConfig {
+    pub fn to_buffer_config(&self) -> scrollback::ScrollbackConfig {
+        let defaults = scrollback::ScrollbackConfig::default();
+        let spill_dir = if !self.spill_to_disk {
+            None
+        } else if self.spill_dir.trim().is_empty() {
+            defaults.spill_dir
+        } else {
+            Some(PathBuf::from(self.spill_dir.trim()))
+        };
+        scrollback::ScrollbackConfig {
+            memory_budget: usize::try_from(self.memory_budget_mb.saturating_mul(1024 * 1024))
+                .unwrap_or(usize::MAX),
+            disk_budget: self.disk_budget_mb.saturating_mul(1024 * 1024),
+            page_lines: self.page_lines.max(1),
+            spill_dir,
+        }
+    }
+}
+
+/// What to do when a program sets the clipboard through OSC 52.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipboardPolicy {
+    Deny,
+    #[default]
+    AllowWrite,
+    Prompt,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ClipboardDecision {
+    Allow,
+    Deny,
+    Ask,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    pub osc52: ClipboardPolicy,
+    // Reads expose whatever the user copied last, so they stay off unless
+    // asked for; `prompt` then asks for reads too.
+    pub allow_read: bool,
+}
+
+impl ClipboardConfig {
+    pub fn decide(&self, request: &ClipboardRequest) -> ClipboardDecision {
+        let is_read = matches!(request, ClipboardRequest::Read { .. });
+        match self.osc52 {
+            ClipboardPolicy::Deny => ClipboardDecision::Deny,
+            _ if is_read && !self.allow_read => ClipboardDecision::Deny,
+            ClipboardPolicy::AllowWrite => ClipboardDecision::Allow,
+            ClipboardPolicy::Prompt => ClipboardDecision::Ask,
         }
     }
 }
 
+/// How BEL from a pane is signalled.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BellStyle {
+    None,
+    /// Flash the pane that rang.
+    #[default]
+    Visual,
+    Sound,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BellConfig {
+    pub style: BellStyle,
+    // Marks background tabs whose panes rang until they are selected.
+    pub tab_badge: bool,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        Self {
+            style: BellStyle::Visual,
+            tab_badge: true,
+        }
+    }
+}
+
+/// OSC 9 / OSC 777 desktop notifications.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Running shells in the mux server so they outlive the window.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MuxConfig {
+    pub enabled: bool,
+    // Empty means the default per-user socket.
+    pub socket: String,
+}
+
+impl MuxConfig {
+    /// The server listens on a Unix domain socket; elsewhere `enabled` is
+    /// ignored and shells stay local.
+    pub const SUPPORTED: bool = cfg!(unix);
+
+    pub fn to_host(&self) -> PtyHost {
+        let socket = self.socket.trim();
+        match (self.enabled && Self::SUPPORTED, socket.is_empty()) {
+            (false, _) => PtyHost::Local,
+            (true, true) => PtyHost::Server(mux::default_socket_path()),
+            (true, false) => PtyHost::Server(PathBuf::from(socket)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub directory: String,
+    // Off by default: typed input includes passwords entered at prompts.
+    pub record_input: bool,
+    // Pauses longer than this play back at this length; 0 keeps them.
+    pub idle_time_limit_sec: f64,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            directory: "recordings".to_owned(),
+            record_input: false,
+            idle_time_limit_sec: 0.0,
+        }
+    }
+}
+
+impl RecordingConfig {
+    pub fn directory(&self) -> PathBuf {
+        match self.directory.trim() {
+            "" => PathBuf::from("recordings"),
+            directory => PathBuf::from(directory),
+        }
+    }
+
+    pub fn header(&self, cols: u16, rows: u16, title: &str) -> asciicast::Header {
+        let mut header = asciicast::Header::new(cols, rows);
+        header.title = Some(title.to_owned());
+        header.idle_time_limit =
+            Some(self.idle_time_limit_sec).filter(|limit| limit.is_finite() && *limit > 0.0);
+        header
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AiCommandTemplate {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
 impl AiCommandTemplate {
     pub fn resolve(&self, prompt: &str) -> ResolvedAiCommand {
         let args = self
@@ -144,7 +379,14 @@ pub fn deserialize(content: &str) -> Result<AppConfig> {
 
 #[cfg(test)]
 mod tests {
-    use super::{AiCommandTemplate, AiConfig, AppConfig, deserialize, serialize_pretty};
+    use super::{
+        AiCommandTemplate, AiConfig, AppConfig, BellStyle, ClipboardDecision, ClipboardPolicy,
+        ScrollbackConfig, ShellConfig, deserialize, serialize_pretty,
+    };
+    use std::path::PathBuf;
+    use terminal_core::clipboard::ClipboardRequest;
+    use terminal_core::mux::{self, PtyHost};
+    use terminal_core::pty::ShellProfile;
     use ux_model::ai::AiTool;
 
     #[test]
@@ -171,6 +413,164 @@ mod tests {
         assert_eq!(parsed.ai.codex.program, "codex");
     }
 
+    #[test]
+    fn scrollback_config_converts_to_buffer_limits() {
+        let config = ScrollbackConfig {
+            memory_budget_mb: 2,
+            disk_budget_mb: 3,
+            page_lines: 0,
+            spill_to_disk: false,
+            spill_dir: "ignored".to_owned(),
+        };
+        let buffer = config.to_buffer_config();
+        assert_eq!(buffer.memory_budget, 2 * 1024 * 1024);
+        assert_eq!(buffer.disk_budget, 3 * 1024 * 1024);
+        assert_eq!(buffer.page_lines, 1);
+        assert_eq!(buffer.spill_dir, None);
+
+        let spilled = ScrollbackConfig {
+            spill_dir: " /var/tmp/cty ".to_owned(),
+            ..ScrollbackConfig::default()
+        };
+        assert_eq!(
+            spilled.to_buffer_config().spill_dir,
+            Some("/var/tmp/cty".into())
+        );
+    }
+
+    #[test]
+    fn empty_shell_config_uses_platform_default() {
+        let profile = ShellConfig::default().to_profile();
+        assert_eq!(profile, ShellProfile::platform_default());
+    }
+
+    #[test]
+    fn shell_config_overrides_program_and_env() {
+        let parsed = deserialize(
+            r#"
+[shell]
+program = "/usr/bin/fish"
+args = ["--login"]
+cwd = "/tmp"
+init_script = "set -g fish_greeting"
+
+[shell.env]
+EDITOR = "vim"
+"#,
+        )
+        .expect("deserialize should succeed");
+
+        let profile = parsed.shell.to_profile();
+        assert_eq!(profile.program, "/usr/bin/fish");
+        assert_eq!(profile.args, vec!["--login"]);
+        assert_eq!(profile.env, vec![("EDITOR".to_owned(), "vim".to_owned())]);
+        assert_eq!(profile.cwd.as_deref(), Some(std::path::Path::new("/tmp")));
+        assert_eq!(profile.init_script.as_deref(), Some("set -g fish_greeting"));
+    }
+
+    #[test]
+    fn clipboard_policy_gates_osc52_writes_and_reads() {
+        let write = ClipboardRequest::Write {
+            selection: "c".to_owned(),
+            text: "x".to_owned(),
+        };
+        let read = ClipboardRequest::Read {
+            selection: "c".to_owned(),
+            bell_terminated: true,
+        };
+
+        let defaults = AppConfig::default().clipboard;
+        assert_eq!(defaults.decide(&write), ClipboardDecision::Allow);
+        assert_eq!(defaults.decide(&read), ClipboardDecision::Deny);
+
+        let parsed = deserialize(
+            r#"
+[clipboard]
+osc52 = "prompt"
+allow_read = true
+"#,
+        )
+        .expect("deserialize should succeed");
+        assert_eq!(parsed.clipboard.osc52, ClipboardPolicy::Prompt);
+        assert_eq!(parsed.clipboard.decide(&write), ClipboardDecision::Ask);
+        assert_eq!(parsed.clipboard.decide(&read), ClipboardDecision::Ask);
+
+        let denied = deserialize("[clipboard]\nosc52 = \"deny\"\nallow_read = true\n")
+            .expect("deserialize should succeed");
+        assert_eq!(denied.clipboard.decide(&read), ClipboardDecision::Deny);
+    }
+
+    #[test]
+    fn bell_and_notification_settings_default_and_parse() {
+        let defaults = AppConfig::default();
+        assert_eq!(defaults.bell.style, BellStyle::Visual);
+        assert!(defaults.bell.tab_badge);
+        assert!(defaults.notifications.enabled);
+
+        let parsed = deserialize(
+            r#"
+[bell]
+style = "sound"
+tab_badge = false
+
+[notifications]
+enabled = false
+"#,
+        )
+        .expect("deserialize should succeed");
+        assert_eq!(parsed.bell.style, BellStyle::Sound);
+        assert!(!parsed.bell.tab_badge);
+        assert!(!parsed.notifications.enabled);
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn mux_stays_local_without_unix_sockets() {
+        let parsed = deserialize("[mux]\nenabled = true\n").expect("deserialize should succeed");
+        assert_eq!(parsed.mux.to_host(), PtyHost::Local);
+        assert!(!serialize_pretty(&parsed).unwrap().contains("[mux]"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mux_is_opt_in_and_honours_the_socket_path() {
+        assert_eq!(AppConfig::default().mux.to_host(), PtyHost::Local);
+
+        let parsed = deserialize("[mux]\nenabled = true\nsocket = \"/run/cty/mux.sock\"\n")
+            .expect("deserialize should succeed");
+        assert_eq!(
+            parsed.mux.to_host(),
+            PtyHost::Server(PathBuf::from("/run/cty/mux.sock"))
+        );
+        let default_socket =
+            deserialize("[mux]\nenabled = true\n").expect("deserialize should succeed");
+        assert_eq!(
+            default_socket.mux.to_host(),
+            PtyHost::Server(mux::default_socket_path())
+        );
+    }
+
+    #[test]
+    fn recording_defaults_skip_input_and_idle_limit() {
+        let config = AppConfig::default().recording;
+        assert!(!config.record_input);
+        assert_eq!(config.directory(), PathBuf::from("recordings"));
+        assert_eq!(config.header(80, 24, "tab").idle_time_limit, None);
+
+        let parsed = deserialize(
+            "[recording]\ndirectory = \"state/casts\"\nrecord_input = true\nidle_time_limit_sec = 2.5\n",
+        )
+        .expect("deserialize should succeed");
+        assert!(parsed.recording.record_input);
+        assert_eq!(parsed.recording.directory(), PathBuf::from("state/casts"));
+        let header = parsed.recording.header(100, 30, "build");
+        assert_eq!(
+            (header.width, header.height, header.idle_time_limit),
+            (100, 30, Some(2.5))
+        );
+        assert_eq!(header.title.as_deref(), Some("build"));
+    }
+
     #[test]
     fn claude_resolve_injects_continue_by_default() {
         let config = AiConfig::default();
@@ -216,4 +616,32 @@ mod tests {
             vec!["--no-session-persistence".to_owned(), "hello".to_owned()]
         );
     }
+
+    #[test]
+    fn shipped_sample_config_parses_and_its_commented_defaults_are_current() {
+        let sample = include_str!("../../../../config/config.toml");
+        let config = deserialize(sample).expect("sample config should parse");
+        assert_eq!(config.clipboard.osc52, ClipboardPolicy::Prompt);
+
+        let uncommented: String = sample
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(rest) if rest.starts_with('[') || rest.contains(" = ") => rest,
+                _ => line,
+            })
+            .flat_map(|line| [line, "\n"])
+            .collect();
+        let uncommented = deserialize(&uncommented).expect("uncommented sample should parse");
+        let expected = AppConfig {
+            ai: config.ai,
+            session: config.session,
+            shell: config.shell,
+            clipboard: config.clipboard,
+            ..AppConfig::default()
+        };
+        assert_eq!(
+            serialize_pretty(&uncommented).unwrap(),
+            serialize_pretty(&expected).unwrap()
+        );
+    }
 }
@@ -1,12 +1,13 @@
 pub mod ai_panel;
 pub mod config;
+pub mod notify;
 pub mod palette;
 pub mod panes;
 pub mod sidebar;
 pub mod tabs;
 pub mod theme;
 
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs;
 use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
@@ -19,50 +20,96 @@ use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use anyhow::{Result, anyhow};
 use crossbeam_channel::{Receiver, Sender, unbounded};
 use eframe::egui;
+use render::grid_view::{self, GridSelection, GridView};
+use render::images;
+use render::links::{LinkLabel, LinkTarget, detect_links};
 use serde::{Deserialize, Serialize};
-use terminal_core::pty::PtySession;
-use terminal_core::vt_parser::MinimalVtParser;
+use terminal_core::asciicast::{self, Player, Recorder};
+use terminal_core::clipboard::{ClipboardRequest, osc52_reply};
+use terminal_core::emulator::{
+    MouseTracking, Progress, TerminalEmulator, TerminalEvent, TerminalModes,
+};
+use terminal_core::input::{self, MouseAction, MouseButton};
+use terminal_core::mux::{self, PtyHost};
+use terminal_core::pty::{PtySession, ShellKind, ShellProfile};
+use terminal_core::scrollback::ScrollbackConfig;
+use terminal_core::shell_integration::{ShellMark, file_uri_to_path};
+use terminal_core::style::{ImageAnchor, LinkRange, StyledLine, StyledRange};
+use terminal_core::tmux::{self, ClientEvent, ControlClient, Layout, LayoutCells};
 use ux_model::ai::{AiBlock, AiBlockStatus, AiTool};
 use ux_model::blocks::CommandBlock;
+use ux_model::search::TimelineMatches;
 use ux_model::session::{SessionSnapshot, SessionState, TimelineItem};
 
 use crate::ai_panel::{AiPanelAction, AiPanelState};
 use crate::config::{
-    AppConfig, ResolvedAiCommand, deserialize as config_deserialize,
-    serialize_pretty as config_serialize_pretty,
+    AppConfig, BellStyle, ClipboardDecision, MuxConfig, ResolvedAiCommand,
+    deserialize as config_deserialize, serialize_pretty as config_serialize_pretty,
 };
+use crate::notify::{BELL_FLASH, NotificationBackend, PaneAlerts};
 use crate::palette::PaletteAction;
 use crate::palette::PaletteState;
-use crate::panes::PaneGridState;
-use crate::panes::PaneLayout;
+use crate::panes::{FocusDirection, PaneId, PaneNode, PaneTree, SplitDirection};
 use crate::sidebar::{SidebarAction, SidebarState};
 use crate::tabs::TabAction;
 use crate::tabs::TabState;
 
+const PTY_RESIZE_DEBOUNCE: Duration = Duration::from_millis(120);
+// Grid size for shells started before any pane was laid out; their first
+// layout resizes them at once.
+const DEFAULT_PANE_SIZE: (u16, u16) = (180, 48);
+// eframe sends no paste event for an empty clipboard, so an OSC 52 read
+// still waiting after this is answered with empty contents.
+const CLIPBOARD_READ_TIMEOUT: Duration = Duration::from_millis(500);
+// Output beyond this is left queued for the next frame, which keeps input
+// and painting responsive while a command floods the terminal.
+const PTY_PARSE_BUDGET: Duration = Duration::from_millis(8);
+// Horizontal space taken by a command block card's margin and border.
+const BLOCK_CARD_CHROME_WIDTH: f32 = 20.0;
+const PANE_DIVIDER_WIDTH: f32 = 6.0;
+// tmux notifications kept while the user decides whether to mirror a pane;
+// later ones are dropped.
+const MAX_HELD_TMUX_NOTIFICATIONS: usize = 4096;
+const REPLAY_SPEEDS: [f64; 5] = [0.5, 1.0, 2.0, 4.0, 8.0];
+const REPLAY_SEEK_STEP_SEC: f64 = 5.0;
+const REPLAY_MAX_TICK: Duration = Duration::from_millis(250);
+
 enum AiRunEvent {
     OutputChunk {
         tab_id: u64,
+        pane_id: PaneId,
         ai_block_id: u64,
         lines: Vec<String>,
     },
     Completed {
         tab_id: u64,
+        pane_id: PaneId,
         ai_block_id: u64,
         exit_code: i32,
         duration_ms: u64,
     },
     Failed {
         tab_id: u64,
+        pane_id: PaneId,
         ai_block_id: u64,
         message: String,
         duration_ms: u64,
     },
     CodexSessionUpdated {
         tab_id: u64,
+        pane_id: PaneId,
         session_id: String,
     },
 }
 
+// The AI block a runner thread reports its events against.
+#[derive(Debug, Clone, Copy)]
+struct AiRunTarget {
+    tab_id: u64,
+    pane_id: PaneId,
+    ai_block_id: u64,
+}
+
 #[derive(Debug, Clone)]
 struct FileLineRef {
     path: PathBuf,
@@ -70,20 +117,78 @@ struct FileLineRef {
     column: Option<u32>,
 }
 
-struct TabRuntime {
+struct PaneRuntime {
     pty: PtySession,
-    parser: MinimalVtParser,
+    emulator: TerminalEmulator,
     session: SessionState,
-    pane_grid: PaneGridState,
     input_buffer: String,
     input_history_cursor: Option<usize>,
     block_search_query: String,
+    block_search: Option<BlockSearch>,
     bookmarks_only: bool,
     selected_context_block_ids: BTreeSet<u64>,
     export_message: String,
     ai_status_line: String,
     running_ai_jobs: usize,
     codex_last_session_id: Option<String>,
+    shell_cwd: Option<String>,
+    // Set between an OSC 133 prompt mark and the next command start, while
+    // the committed lines are prompt text rather than command output.
+    at_shell_prompt: bool,
+    shell_reports_command_start: bool,
+    command_started_at: Option<Instant>,
+    fullscreen_started_at: Option<Instant>,
+    expanded_fullscreen_blocks: BTreeSet<u64>,
+    // Keystrokes go straight to the PTY instead of through the line editor.
+    interactive_input: bool,
+    pty_size: (u16, u16),
+    pending_resize: Option<((u16, u16), Instant)>,
+    // Whether the pane has been given its rendered size yet.
+    laid_out: bool,
+    // Output the mux server kept for a reattached shell, fed once the pane
+    // has its rendered size so it is laid out at that width.
+    pending_replay: Vec<u8>,
+    // Local selection on the full-screen grid; `selecting` while its drag is
+    // in progress.
+    grid_selection: Option<GridSelection>,
+    selecting: bool,
+    // Last cell reported to a mouse-tracking application, so motion is only
+    // sent when the pointer crosses into another cell.
+    mouse_cell: Option<(usize, usize)>,
+    alerts: PaneAlerts,
+    // Whether the pane had focus the last time it was checked; changes are
+    // reported to applications that enabled DECSET 1004.
+    focused: bool,
+}
+
+/// Last block search of a pane, valid while the query and the session
+/// generation stay the same.
+struct BlockSearch {
+    query: String,
+    generation: u64,
+    matches: TimelineMatches,
+}
+
+/// A terminal tab: a split tree of panes, each running its own shell.
+struct TabRuntime {
+    tree: PaneTree,
+    panes: HashMap<PaneId, PaneRuntime>,
+}
+
+/// A pane running `tmux -CC`. Each tmux window is shown as a tab whose
+/// splits are the window's panes; pane ids are tmux's pane numbers.
+#[derive(Default)]
+struct TmuxGateway {
+    client: ControlClient,
+    // Tab and visible layout of each window.
+    windows: HashMap<u32, (u64, Layout)>,
+    // Output channels of the mirrored panes.
+    feeds: HashMap<u32, Sender<Vec<u8>>>,
+    // Any program can print the control-mode DCS, so nothing is mirrored
+    // or sent to tmux until the user allows it. Notifications wait in
+    // `held` meanwhile.
+    allowed: Option<bool>,
+    held: Vec<tmux::Notification>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,7 +202,51 @@ struct WorkspaceSnapshot {
 struct SavedTabSnapshot {
     tab_id: u64,
     tab_label: String,
+    #[serde(default)]
+    label_pinned: bool,
+    // Session of the focused pane; the other panes are in `pane_sessions`.
+    session: SessionSnapshot,
+    #[serde(default)]
+    pane_tree: Option<PaneTree>,
+    #[serde(default)]
+    pane_sessions: Vec<SavedPaneSession>,
+    // Mux server session of the focused pane, reattached on restore.
+    #[serde(default)]
+    mux_session: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedPaneSession {
+    pane_id: PaneId,
     session: SessionSnapshot,
+    #[serde(default)]
+    mux_session: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct ClipboardPrompt {
+    tab_id: u64,
+    pane_id: PaneId,
+    request: ClipboardRequest,
+}
+
+/// A paste held back for confirmation; its text can be edited before sending.
+#[derive(Debug, Clone)]
+struct PendingPaste {
+    tab_id: u64,
+    pane_id: PaneId,
+    text: String,
+    // Goes into the line editor instead of straight to the PTY.
+    to_input_bar: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PendingClipboardRead {
+    tab_id: u64,
+    pane_id: PaneId,
+    selection: String,
+    bell_terminated: bool,
+    requested_at: Instant,
 }
 
 #[derive(Debug, Clone)]
@@ -110,6 +259,7 @@ struct SavedSessionEntry {
 enum AppTabKind {
     Terminal,
     Editor,
+    Replay,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -148,33 +298,373 @@ struct EditorTabState {
     dirty: bool,
 }
 
-impl TabRuntime {
-    fn with_session(session: SessionState) -> Result<(Self, u32)> {
-        let mut pty = PtySession::spawn_powershell(180, 48)?;
+/// A recording played back in a read-only tab.
+struct ReplayTabState {
+    path: PathBuf,
+    player: Player,
+    last_tick: Instant,
+}
+
+impl ReplayTabState {
+    fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            player: Player::new(asciicast::Cast::load(path)?),
+            last_tick: Instant::now(),
+        })
+    }
+
+    fn label(&self) -> String {
+        let name = self
+            .path
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.display().to_string());
+        format!("replay: {name}")
+    }
+
+    // Frames stop while the tab is in the background; a long gap would
+    // otherwise skip ahead when it is shown again.
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let elapsed = (now - self.last_tick).min(REPLAY_MAX_TICK);
+        self.last_tick = now;
+        self.player.advance(elapsed);
+    }
+}
+
+impl PaneRuntime {
+    /// Starts a shell for `session`, or reattaches to the mux server session
+    /// `mux_session` while it is still running.
+    fn with_session(
+        session: SessionState,
+        profile: &ShellProfile,
+        scrollback: &ScrollbackConfig,
+        host: &PtyHost,
+        mux_session: Option<u64>,
+        (cols, rows): (u16, u16),
+    ) -> Result<(Self, u32)> {
+        let attached = mux_session.and_then(|id| host.attach(id, profile).ok());
+        let (mut pty, replay) = match attached {
+            Some(attached) => attached,
+            None => (host.spawn(profile, cols, rows)?, Vec::new()),
+        };
         let pid = pty.process_id().unwrap_or_default();
+        let mut pane = Self::from_pty(pty, session, scrollback, (cols, rows));
+        pane.pending_replay = replay;
+        Ok((pane, pid))
+    }
+
+    fn from_pty(
+        pty: PtySession,
+        mut session: SessionState,
+        scrollback: &ScrollbackConfig,
+        (cols, rows): (u16, u16),
+    ) -> Self {
+        session.set_scrollback_config(scrollback.clone());
+        let mut emulator = TerminalEmulator::new(usize::from(cols), usize::from(rows));
+        emulator.set_reported_colors(theme::TERMINAL_PALETTE.reported_colors());
+        Self {
+            pty,
+            emulator,
+            session,
+            input_buffer: String::new(),
+            input_history_cursor: None,
+            block_search_query: String::new(),
+            block_search: None,
+            bookmarks_only: false,
+            selected_context_block_ids: BTreeSet::new(),
+            export_message: String::new(),
+            ai_status_line: "idle".to_owned(),
+            running_ai_jobs: 0,
+            codex_last_session_id: None,
+            shell_cwd: None,
+            at_shell_prompt: false,
+            shell_reports_command_start: false,
+            command_started_at: None,
+            fullscreen_started_at: None,
+            expanded_fullscreen_blocks: BTreeSet::new(),
+            interactive_input: false,
+            pty_size: (cols, rows),
+            pending_resize: None,
+            laid_out: false,
+            pending_replay: Vec::new(),
+            grid_selection: None,
+            selecting: false,
+            mouse_cell: None,
+            alerts: PaneAlerts::default(),
+            focused: false,
+        }
+    }
+
+    fn new(
+        cwd: String,
+        profile: &ShellProfile,
+        scrollback: &ScrollbackConfig,
+        host: &PtyHost,
+        size: (u16, u16),
+    ) -> Result<(Self, u32)> {
+        Self::with_session(
+            SessionState::new(cwd),
+            profile,
+            scrollback,
+            host,
+            None,
+            size,
+        )
+    }
+
+    fn schedule_resize(&mut self, cols: u16, rows: u16) {
+        let size = (cols, rows);
+        match self.pending_resize {
+            Some((pending, _)) if pending == size => {}
+            None if self.laid_out && self.pty_size == size => {}
+            _ => self.pending_resize = Some((size, Instant::now())),
+        }
+    }
+
+    /// Applies a scheduled resize once the pane size has settled. The first
+    /// one is applied at once, before the shell draws much at its starting
+    /// size.
+    fn apply_pending_resize(&mut self) -> Result<()> {
+        let Some(((cols, rows), requested_at)) = self.pending_resize else {
+            return Ok(());
+        };
+        if self.laid_out && requested_at.elapsed() < PTY_RESIZE_DEBOUNCE {
+            return Ok(());
+        }
+        self.pending_resize = None;
+        self.laid_out = true;
+        if self.pty_size != (cols, rows) {
+            self.pty.resize(cols, rows)?;
+            // tmux panes keep the size of their tmux layout; this one only
+            // decides the size asked of tmux.
+            if self.pty.tmux_pane().is_none() {
+                self.emulator.resize(usize::from(cols), usize::from(rows));
+            }
+            self.pty_size = (cols, rows);
+        }
+        // Queries and bells in the replayed output were answered while it
+        // was first shown; only the screen state is wanted.
+        let replay = std::mem::take(&mut self.pending_replay);
+        if !replay.is_empty() {
+            let _ = self.emulator.feed(&replay);
+        }
+        Ok(())
+    }
+
+    fn working_directory(&self) -> String {
+        self.shell_cwd.clone().unwrap_or_else(|| {
+            std::env::current_dir()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|_| ".".to_owned())
+        })
+    }
+
+    fn filtered_timeline_items(&mut self) -> Vec<TimelineItem> {
+        let query = self.block_search_query.trim().to_lowercase();
+        if !query.is_empty() {
+            self.refresh_block_search(&query);
+        }
+        let matches = self.block_search.as_ref().map(|search| &search.matches);
+        let mut items = self.session.timeline_items();
+
+        items.retain(|item| match item {
+            TimelineItem::Command(block) => {
+                if self.bookmarks_only && !block.bookmarked {
+                    return false;
+                }
+
+                query.is_empty() || matches.is_some_and(|m| m.command_ids.contains(&block.id))
+            }
+            TimelineItem::Ai(block) => {
+                if self.bookmarks_only {
+                    return false;
+                }
+
+                query.is_empty() || matches.is_some_and(|m| m.ai_ids.contains(&block.id))
+            }
+        });
+
+        items
+    }
+
+    /// Re-runs the block search only when the query or the session changed
+    /// since the last frame.
+    fn refresh_block_search(&mut self, query: &str) {
+        let generation = self.session.generation();
+        if self
+            .block_search
+            .as_ref()
+            .is_some_and(|search| search.query == query && search.generation == generation)
+        {
+            return;
+        }
+        self.block_search = Some(BlockSearch {
+            query: query.to_owned(),
+            generation,
+            matches: self.session.search_timeline(query),
+        });
+    }
+
+    fn apply_shell_mark(&mut self, mark: ShellMark) {
+        match mark {
+            ShellMark::PromptStart => self.at_shell_prompt = true,
+            ShellMark::CommandStart => {}
+            ShellMark::CommandExecuted => {
+                // Commands typed in interactive mode never went through
+                // `submit_input`, so give their output a block of its own.
+                let needs_block =
+                    self.session.blocks().last().is_none_or(|block| {
+                        block.exit_code.is_some() || block.duration_ms.is_some()
+                    });
+                if self.interactive_input && needs_block {
+                    let cwd = self.working_directory();
+                    self.session
+                        .start_command_block("(interactive)".to_owned(), cwd);
+                }
+                self.shell_reports_command_start = true;
+                self.at_shell_prompt = false;
+                self.command_started_at = Some(Instant::now());
+            }
+            ShellMark::CommandFinished { exit_code } => {
+                if let Some(started_at) = self.command_started_at.take() {
+                    let duration_ms = started_at.elapsed().as_millis().min(u64::MAX as u128) as u64;
+                    self.session
+                        .complete_last_command_block(exit_code, duration_ms);
+                }
+                self.at_shell_prompt = true;
+            }
+        }
+    }
+}
 
-        Ok((
-            Self {
-                pty,
-                parser: MinimalVtParser::default(),
+impl TabRuntime {
+    fn with_session(
+        session: SessionState,
+        profile: &ShellProfile,
+        scrollback: &ScrollbackConfig,
+        host: &PtyHost,
+        size: (u16, u16),
+    ) -> Result<(Self, u32)> {
+        let (pane, pid) =
+            PaneRuntime::with_session(session, profile, scrollback, host, None, size)?;
+        let tree = PaneTree::default();
+        let panes = HashMap::from([(tree.active(), pane)]);
+        Ok((Self { tree, panes }, pid))
+    }
+
+    fn new(
+        cwd: String,
+        profile: &ShellProfile,
+        scrollback: &ScrollbackConfig,
+        host: &PtyHost,
+        size: (u16, u16),
+    ) -> Result<(Self, u32)> {
+        Self::with_session(SessionState::new(cwd), profile, scrollback, host, size)
+    }
+
+    /// Rebuilds a saved tab, reattaching panes whose shells the mux server
+    /// still runs and spawning a shell for the others.
+    fn restore(
+        tab: SavedTabSnapshot,
+        cwd: &str,
+        profile: &ShellProfile,
+        scrollback: &ScrollbackConfig,
+        host: &PtyHost,
+        size: (u16, u16),
+    ) -> Result<Self> {
+        let Some(mut tree) = tab.pane_tree else {
+            let session = SessionState::from_snapshot(tab.session);
+            let (pane, _pid) = PaneRuntime::with_session(
                 session,
-                pane_grid: PaneGridState::default(),
-                input_buffer: String::new(),
-                input_history_cursor: None,
-                block_search_query: String::new(),
-                bookmarks_only: false,
-                selected_context_block_ids: BTreeSet::new(),
-                export_message: String::new(),
-                ai_status_line: "idle".to_owned(),
-                running_ai_jobs: 0,
-                codex_last_session_id: None,
-            },
-            pid,
-        ))
+                profile,
+                scrollback,
+                host,
+                tab.mux_session,
+                size,
+            )?;
+            let tree = PaneTree::default();
+            let panes = HashMap::from([(tree.active(), pane)]);
+            return Ok(Self { tree, panes });
+        };
+        tree.normalize();
+        let mut sessions = tab
+            .pane_sessions
+            .into_iter()
+            .map(|saved| (saved.pane_id, (saved.session, saved.mux_session)))
+            .collect::<HashMap<_, _>>();
+        sessions.insert(tree.active(), (tab.session, tab.mux_session));
+
+        let mut panes = HashMap::new();
+        for pane_id in tree.pane_ids() {
+            let (session, mux_session) = match sessions.remove(&pane_id) {
+                Some((snapshot, mux_session)) => {
+                    (SessionState::from_snapshot(snapshot), mux_session)
+                }
+                None => (SessionState::new(cwd.to_owned()), None),
+            };
+            let (pane, _pid) =
+                PaneRuntime::with_session(session, profile, scrollback, host, mux_session, size)?;
+            panes.insert(pane_id, pane);
+        }
+        Ok(Self { tree, panes })
+    }
+
+    fn active_pane(&self) -> Option<&PaneRuntime> {
+        self.panes.get(&self.tree.active())
     }
 
-    fn new(cwd: String) -> Result<(Self, u32)> {
-        Self::with_session(SessionState::new(cwd))
+    fn active_pane_mut(&mut self) -> Option<&mut PaneRuntime> {
+        self.panes.get_mut(&self.tree.active())
+    }
+
+    /// Splits the active pane and starts a shell in the new half, in the
+    /// directory the active shell last reported.
+    fn split(
+        &mut self,
+        direction: SplitDirection,
+        profile: &ShellProfile,
+        scrollback: &ScrollbackConfig,
+        host: &PtyHost,
+    ) -> Result<u32> {
+        let mut profile = profile.clone();
+        if let Some(cwd) = self.active_pane().and_then(|pane| pane.shell_cwd.clone()) {
+            profile.cwd = Some(PathBuf::from(cwd));
+        }
+        let cwd = self
+            .active_pane()
+            .map(PaneRuntime::working_directory)
+            .unwrap_or_else(|| ".".to_owned());
+        // The new half starts at its share of the active pane.
+        let (cols, rows) = self
+            .active_pane()
+            .map_or(DEFAULT_PANE_SIZE, |pane| pane.pty_size);
+        let size = match direction {
+            SplitDirection::Vertical => ((cols / 2).max(1), rows),
+            SplitDirection::Horizontal => (cols, (rows / 2).max(1)),
+        };
+        let (pane, pid) = PaneRuntime::new(cwd, &profile, scrollback, host, size)?;
+        let pane_id = self.tree.split_active(direction);
+        self.panes.insert(pane_id, pane);
+        Ok(pid)
+    }
+
+    fn close_pane(&mut self, pane_id: PaneId) -> bool {
+        if !self.tree.close(pane_id) {
+            return false;
+        }
+        if let Some(pane) = self.panes.remove(&pane_id) {
+            pane.pty.terminate();
+        }
+        true
+    }
+
+    /// Ends every shell of the tab, including those the mux server keeps.
+    fn terminate(&self) {
+        for pane in self.panes.values() {
+            pane.pty.terminate();
+        }
     }
 }
 
@@ -456,6 +946,15 @@ enum EditorCommand {
     CloseRequested,
 }
 
+pub use terminal_core::mux::SERVER_FLAG as MUX_SERVER_FLAG;
+
+/// Runs the mux server that keeps shells alive while no window is attached,
+/// on the default socket unless one is given.
+pub fn run_mux_server(socket: Option<PathBuf>) -> Result<()> {
+    let socket = socket.unwrap_or_else(mux::default_socket_path);
+    mux::serve(&socket)
+}
+
 pub fn run() -> Result<()> {
     let native_options = eframe::NativeOptions {
         renderer: eframe::Renderer::Wgpu,
@@ -480,6 +979,9 @@ pub fn run() -> Result<()> {
 
 pub struct TerminalApp {
     config: AppConfig,
+    // Chosen at startup; changing `[mux]` takes effect on the next launch.
+    pty_host: PtyHost,
+    notifier: Box<dyn NotificationBackend>,
     config_path: PathBuf,
     config_mtime: Option<SystemTime>,
     last_config_poll: Instant,
@@ -487,6 +989,7 @@ pub struct TerminalApp {
     tab_runtimes: HashMap<u64, TabRuntime>,
     tab_kinds: HashMap<u64, AppTabKind>,
     editor_tabs: HashMap<u64, EditorTabState>,
+    replay_tabs: HashMap<u64, ReplayTabState>,
     tabs: TabState,
     sidebar: SidebarState,
     palette: PaletteState,
@@ -503,8 +1006,18 @@ pub struct TerminalApp {
     session_save_path: PathBuf,
     restore_picker_open: bool,
     saved_session_entries: Vec<SavedSessionEntry>,
+    replay_picker_open: bool,
+    recording_entries: Vec<PathBuf>,
     last_session_save: Instant,
     theme_applied: bool,
+    clipboard_prompts: VecDeque<ClipboardPrompt>,
+    // Held pastes, confirmed one at a time in arrival order.
+    pending_pastes: VecDeque<PendingPaste>,
+    pending_clipboard_read: Option<PendingClipboardRead>,
+    // Keyed by the tab and pane running `tmux -CC`.
+    tmux_gateways: HashMap<(u64, PaneId), TmuxGateway>,
+    // Grid size of the last pane laid out; new tabs start their shells at it.
+    pane_size: (u16, u16),
 }
 
 impl TerminalApp {
@@ -518,9 +1031,21 @@ impl TerminalApp {
         let session_save_path = PathBuf::from(&config.session.session_file);
         let session = SessionState::new(cwd);
 
+        let pty_host = config.mux.to_host();
+        let mux_note = if config.mux.enabled && !MuxConfig::SUPPORTED {
+            " | mux.enabled ignored: the mux server needs Unix domain sockets"
+        } else {
+            ""
+        };
         let tabs = TabState::default();
         let initial_tab_id = tabs.active_id();
-        let (runtime, pid) = TabRuntime::with_session(session)?;
+        let (runtime, pid) = TabRuntime::with_session(
+            session,
+            &config.shell.to_profile(),
+            &config.scrollback.to_buffer_config(),
+            &pty_host,
+            DEFAULT_PANE_SIZE,
+        )?;
         let mut tab_runtimes = HashMap::new();
         tab_runtimes.insert(initial_tab_id, runtime);
         let mut tab_kinds = HashMap::new();
@@ -528,6 +1053,8 @@ impl TerminalApp {
 
         let mut app = Self {
             config,
+            pty_host,
+            notifier: notify::system_backend(),
             config_path,
             config_mtime,
             last_config_poll: Instant::now(),
@@ -535,6 +1062,7 @@ impl TerminalApp {
             tab_runtimes,
             tab_kinds,
             editor_tabs: HashMap::new(),
+            replay_tabs: HashMap::new(),
             tabs,
             sidebar: SidebarState::default(),
             palette: PaletteState::default(),
@@ -546,29 +1074,63 @@ impl TerminalApp {
             prompt_mascot_load_attempted: false,
             focus_terminal_input_next_frame: false,
             status_text: format!(
-                "PTY attached (pid={pid}) | {config_note} | auto-restore off (use restore session)"
+                "PTY attached (pid={pid}) | {config_note} | auto-restore off (use restore session){mux_note}"
             ),
             ai_event_tx,
             ai_event_rx,
             session_save_path,
             restore_picker_open: false,
             saved_session_entries: Vec::new(),
+            replay_picker_open: false,
+            recording_entries: Vec::new(),
             last_session_save: Instant::now(),
             theme_applied: false,
+            clipboard_prompts: VecDeque::new(),
+            pending_pastes: VecDeque::new(),
+            pending_clipboard_read: None,
+            tmux_gateways: HashMap::new(),
+            pane_size: DEFAULT_PANE_SIZE,
         };
         app.refresh_saved_session_entries();
+        // The mux server kept the last window's shells running; pick them up
+        // where they were left.
+        if app.pty_host != PtyHost::Local && app.session_save_path.exists() {
+            let path = app.session_save_path.clone();
+            app.restore_workspace_from_path(&path);
+        }
         Ok(app)
     }
 
-    fn active_runtime(&self) -> Option<&TabRuntime> {
+    fn active_tab_runtime(&self) -> Option<&TabRuntime> {
         self.tab_runtimes.get(&self.tabs.active_id())
     }
 
-    fn active_runtime_mut(&mut self) -> Option<&mut TabRuntime> {
+    fn active_tab_runtime_mut(&mut self) -> Option<&mut TabRuntime> {
         let tab_id = self.tabs.active_id();
         self.tab_runtimes.get_mut(&tab_id)
     }
 
+    /// Runtime of the focused pane in the active tab.
+    fn active_runtime(&self) -> Option<&PaneRuntime> {
+        self.active_tab_runtime()?.active_pane()
+    }
+
+    fn active_runtime_mut(&mut self) -> Option<&mut PaneRuntime> {
+        self.active_tab_runtime_mut()?.active_pane_mut()
+    }
+
+    fn pane_runtime(&self, pane_id: PaneId) -> Option<&PaneRuntime> {
+        self.active_tab_runtime()?.panes.get(&pane_id)
+    }
+
+    fn pane_runtime_mut(&mut self, pane_id: PaneId) -> Option<&mut PaneRuntime> {
+        self.active_tab_runtime_mut()?.panes.get_mut(&pane_id)
+    }
+
+    fn tab_pane_runtime_mut(&mut self, tab_id: u64, pane_id: PaneId) -> Option<&mut PaneRuntime> {
+        self.tab_runtimes.get_mut(&tab_id)?.panes.get_mut(&pane_id)
+    }
+
     fn active_tab_kind(&self) -> AppTabKind {
         self.tab_kinds
             .get(&self.tabs.active_id())
@@ -587,7 +1149,13 @@ impl TerminalApp {
         }
 
         let cwd = self.workspace_root.display().to_string();
-        match TabRuntime::new(cwd) {
+        match TabRuntime::new(
+            cwd,
+            &self.config.shell.to_profile(),
+            &self.config.scrollback.to_buffer_config(),
+            &self.pty_host,
+            self.pane_size,
+        ) {
             Ok((runtime, pid)) => {
                 self.tab_runtimes.insert(tab_id, runtime);
                 self.tab_kinds.insert(tab_id, AppTabKind::Terminal);
@@ -815,6 +1383,115 @@ impl TerminalApp {
         self.status_text = format!("opened editor: {}", display_path(&normalized));
     }
 
+    fn open_replay_tab(&mut self, path: PathBuf) {
+        let normalized = normalize_opened_path(path.canonicalize().unwrap_or(path));
+        if let Some((tab_id, _)) = self
+            .replay_tabs
+            .iter()
+            .find(|(_, replay)| replay.path == normalized)
+        {
+            let _ = self.tabs.set_active_by_id(*tab_id);
+            self.status_text = format!("focused replay: {}", display_path(&normalized));
+            return;
+        }
+
+        let replay = match ReplayTabState::open(&normalized) {
+            Ok(replay) => replay,
+            Err(err) => {
+                self.status_text = format!("failed to open recording: {err:#}");
+                return;
+            }
+        };
+        let tab_id = self.tabs.add_tab_with_label(replay.label());
+        self.replay_tabs.insert(tab_id, replay);
+        self.tab_kinds.insert(tab_id, AppTabKind::Replay);
+        self.status_text = format!("replaying: {}", display_path(&normalized));
+    }
+
+    fn tab_is_recording(&self, tab_id: u64) -> bool {
+        self.tab_runtimes
+            .get(&tab_id)
+            .is_some_and(|tab| tab.panes.values().any(|pane| pane.pty.is_recording()))
+    }
+
+    /// Starts recording every pane of the active tab to a `.cast` file of its
+    /// own, or stops a recording in progress.
+    fn toggle_tab_recording(&mut self) {
+        let tab_id = self.tabs.active_id();
+        let label = self.tabs.active_label();
+        let Some(tab) = self.tab_runtimes.get(&tab_id) else {
+            self.status_text = "active tab runtime not available".to_owned();
+            return;
+        };
+        if self.tab_is_recording(tab_id) {
+            for pane in tab.panes.values() {
+                pane.pty.stop_recording();
+            }
+            self.status_text = format!("stopped recording {label}");
+            self.refresh_recording_entries();
+            return;
+        }
+
+        let recording = &self.config.recording;
+        let directory = recording.directory();
+        let unix_sec = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let pane_ids = tab.tree.pane_ids();
+        let mut started = Vec::new();
+        for pane_id in &pane_ids {
+            let Some(pane) = tab.panes.get(pane_id) else {
+                continue;
+            };
+            let name = if pane_ids.len() > 1 {
+                format!("tab{tab_id}-{unix_sec}-pane{pane_id}")
+            } else {
+                format!("tab{tab_id}-{unix_sec}")
+            };
+            let path = directory.join(format!("{name}.{}", asciicast::FILE_EXTENSION));
+            // The emulator has the size the shell draws for, which for tmux
+            // panes is not the size asked of the PTY.
+            let (cols, rows) = (pane.emulator.cols(), pane.emulator.rows());
+            let header = recording.header(
+                u16::try_from(cols).unwrap_or(u16::MAX),
+                u16::try_from(rows).unwrap_or(u16::MAX),
+                label,
+            );
+            match Recorder::create(&path, &header, recording.record_input) {
+                Ok(recorder) => {
+                    pane.pty.start_recording(recorder);
+                    started.push(path);
+                }
+                Err(err) => {
+                    for pane in tab.panes.values() {
+                        pane.pty.stop_recording();
+                    }
+                    self.status_text = format!("recording failed: {err:#}");
+                    return;
+                }
+            }
+        }
+        self.status_text = match started.as_slice() {
+            [path] => format!("recording {label} to {}", display_path(path)),
+            paths => format!(
+                "recording {} panes of {label} to {}",
+                paths.len(),
+                display_path(&directory)
+            ),
+        };
+    }
+
+    fn refresh_recording_entries(&mut self) {
+        self.recording_entries =
+            list_recordings(&self.config.recording.directory()).unwrap_or_default();
+    }
+
+    fn open_replay_picker(&mut self) {
+        self.replay_picker_open = true;
+        self.refresh_recording_entries();
+    }
+
     fn close_tab_if_possible(&mut self, tab_id: u64) {
         let entries = self.tabs.entries();
         if entries.len() <= 1 {
@@ -827,12 +1504,23 @@ impl TerminalApp {
         };
         let closed_label = closed_label.clone();
 
-        self.tab_runtimes.remove(&tab_id);
+        if let Some(runtime) = self.remove_tab(tab_id) {
+            runtime.terminate();
+        }
+        self.status_text = format!("closed tab: {closed_label}");
+    }
+
+    // Takes a tab off the strip, handing back its terminal runtime.
+    fn remove_tab(&mut self, tab_id: u64) -> Option<TabRuntime> {
+        let runtime = self.tab_runtimes.remove(&tab_id);
         self.editor_tabs.remove(&tab_id);
+        self.replay_tabs.remove(&tab_id);
         self.tab_kinds.remove(&tab_id);
 
         let active_before = self.tabs.active_id();
-        let remaining = entries
+        let remaining = self
+            .tabs
+            .entries()
             .into_iter()
             .filter(|(id, _)| *id != tab_id)
             .collect::<Vec<_>>();
@@ -846,7 +1534,7 @@ impl TerminalApp {
         if self.active_tab_kind() == AppTabKind::Terminal {
             self.ensure_tab_runtime(self.tabs.active_id());
         }
-        self.status_text = format!("closed tab: {closed_label}");
+        runtime
     }
 
     fn close_active_tab_if_possible(&mut self) {
@@ -857,6 +1545,12 @@ impl TerminalApp {
     fn apply_sidebar_actions(&mut self, actions: Vec<SidebarAction>) {
         for action in actions {
             match action {
+                SidebarAction::OpenFile(path)
+                    if path.extension().and_then(|ext| ext.to_str())
+                        == Some(asciicast::FILE_EXTENSION) =>
+                {
+                    self.open_replay_tab(path)
+                }
                 SidebarAction::OpenFile(path) => self.open_file_in_editor_tab(path),
             }
         }
@@ -894,10 +1588,10 @@ impl TerminalApp {
                         .strong()
                         .color(mode_color),
                 );
-                if ui.small_button("save").clicked() {
-                    if let Err(err) = editor.write_to_disk() {
-                        editor.status_line = format!("write failed: {err}");
-                    }
+                if ui.small_button("save").clicked()
+                    && let Err(err) = editor.write_to_disk()
+                {
+                    editor.status_line = format!("write failed: {err}");
                 }
             });
 
@@ -980,6 +1674,105 @@ impl TerminalApp {
         }
     }
 
+    fn render_replay_view(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let tab_id = self.tabs.active_id();
+        let Some(replay) = self.replay_tabs.get_mut(&tab_id) else {
+            ui.label(
+                egui::RichText::new("replay tab state missing")
+                    .monospace()
+                    .color(theme::ERROR),
+            );
+            return;
+        };
+
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Space)) {
+            replay.player.toggle_pause();
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowLeft)) {
+            replay
+                .player
+                .seek(replay.player.position() - REPLAY_SEEK_STEP_SEC);
+        }
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowRight)) {
+            replay
+                .player
+                .seek(replay.player.position() + REPLAY_SEEK_STEP_SEC);
+        }
+        replay.tick();
+        if let Some(wait) = replay.player.time_to_next_event() {
+            ctx.request_repaint_after(wait.min(REPLAY_MAX_TICK));
+        }
+
+        theme::toolbar_frame().show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(
+                    egui::RichText::new("REPLAY")
+                        .monospace()
+                        .strong()
+                        .color(theme::TEXT_MUTED),
+                );
+                ui.separator();
+                let player = &mut replay.player;
+                let toggle_label = if player.is_paused() { "play" } else { "pause" };
+                if ui
+                    .small_button(egui::RichText::new(toggle_label).color(theme::ACCENT_BLUE))
+                    .clicked()
+                {
+                    player.toggle_pause();
+                }
+                if ui.small_button("restart").clicked() {
+                    player.seek(0.0);
+                    player.set_paused(false);
+                }
+                ui.separator();
+                for speed in REPLAY_SPEEDS {
+                    if ui
+                        .selectable_label(player.speed() == speed, format!("{speed}x"))
+                        .clicked()
+                    {
+                        player.set_speed(speed);
+                    }
+                }
+                ui.separator();
+                let duration = player.duration();
+                let mut position = player.position();
+                ui.spacing_mut().slider_width = 260.0;
+                if ui
+                    .add(egui::Slider::new(&mut position, 0.0..=duration).show_value(false))
+                    .changed()
+                {
+                    player.seek(position);
+                }
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{} / {}",
+                        format_replay_time(player.position()),
+                        format_replay_time(duration)
+                    ))
+                    .monospace()
+                    .color(theme::TEXT_PRIMARY),
+                );
+                ui.separator();
+                ui.label(
+                    egui::RichText::new(display_path(&replay.path))
+                        .monospace()
+                        .color(theme::TEXT_MUTED),
+                );
+            });
+        });
+        ui.add_space(6.0);
+
+        let (grid, cursor) = replay.player.emulator().presented_screen();
+        let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                GridView::new(grid, &theme::TERMINAL_PALETTE, font_id)
+                    .cursor(cursor)
+                    .show(ui)
+            });
+    }
+
     fn build_workspace_snapshot(&self) -> WorkspaceSnapshot {
         let tabs = self
             .tabs
@@ -987,10 +1780,37 @@ impl TerminalApp {
             .into_iter()
             .filter_map(|(tab_id, tab_label)| {
                 let runtime = self.tab_runtimes.get(&tab_id)?;
+                // tmux keeps its windows; they are mirrored again on attach.
+                if runtime
+                    .panes
+                    .values()
+                    .any(|pane| pane.pty.tmux_pane().is_some())
+                {
+                    return None;
+                }
+                let active_pane = runtime.tree.active();
+                let pane_sessions = runtime
+                    .tree
+                    .pane_ids()
+                    .into_iter()
+                    .filter(|pane_id| *pane_id != active_pane)
+                    .filter_map(|pane_id| {
+                        let pane = runtime.panes.get(&pane_id)?;
+                        Some(SavedPaneSession {
+                            pane_id,
+                            session: pane.session.to_snapshot(),
+                            mux_session: pane.pty.mux_id(),
+                        })
+                    })
+                    .collect();
                 Some(SavedTabSnapshot {
                     tab_id,
+                    label_pinned: self.tabs.is_label_pinned(tab_id),
                     tab_label,
-                    session: runtime.session.to_snapshot(),
+                    session: runtime.active_pane()?.session.to_snapshot(),
+                    pane_tree: Some(runtime.tree.clone()),
+                    pane_sessions,
+                    mux_session: runtime.active_pane()?.pty.mux_id(),
                 })
             })
             .collect();
@@ -1002,7 +1822,7 @@ impl TerminalApp {
         };
 
         WorkspaceSnapshot {
-            format_version: 1,
+            format_version: 2,
             active_tab_id,
             tabs,
         }
@@ -1060,16 +1880,28 @@ impl TerminalApp {
         };
 
         let mut new_entries = Vec::new();
+        let mut pinned_tabs = Vec::new();
         let mut new_runtimes = HashMap::new();
         let mut restored_tabs = 0usize;
         let mut failed_tabs = 0usize;
 
+        let cwd = self.workspace_root.display().to_string();
         for tab in loaded.tabs {
-            let session = SessionState::from_snapshot(tab.session);
-            match TabRuntime::with_session(session) {
-                Ok((runtime, _pid)) => {
-                    new_entries.push((tab.tab_id, tab.tab_label));
-                    new_runtimes.insert(tab.tab_id, runtime);
+            let (tab_id, tab_label) = (tab.tab_id, tab.tab_label.clone());
+            if tab.label_pinned {
+                pinned_tabs.push(tab_id);
+            }
+            match TabRuntime::restore(
+                tab,
+                &cwd,
+                &self.config.shell.to_profile(),
+                &self.config.scrollback.to_buffer_config(),
+                &self.pty_host,
+                self.pane_size,
+            ) {
+                Ok(runtime) => {
+                    new_entries.push((tab_id, tab_label));
+                    new_runtimes.insert(tab_id, runtime);
                     restored_tabs += 1;
                 }
                 Err(_) => {
@@ -1079,8 +1911,13 @@ impl TerminalApp {
         }
 
         if new_entries.is_empty() {
-            let cwd = self.workspace_root.display().to_string();
-            match TabRuntime::new(cwd) {
+            match TabRuntime::new(
+                cwd,
+                &self.config.shell.to_profile(),
+                &self.config.scrollback.to_buffer_config(),
+                &self.pty_host,
+                self.pane_size,
+            ) {
                 Ok((runtime, _pid)) => {
                     new_entries.push((0, "main".to_owned()));
                     new_runtimes.insert(0, runtime);
@@ -1093,15 +1930,36 @@ impl TerminalApp {
         }
 
         let active_id = loaded.active_tab_id;
+        let labels: HashMap<u64, String> = new_entries.iter().cloned().collect();
         self.tabs.replace_tabs(new_entries, active_id);
-        self.tab_runtimes = new_runtimes;
-        self.tab_kinds = self
+        for tab_id in pinned_tabs {
+            if let Some(label) = labels.get(&tab_id) {
+                self.tabs.pin_tab_label(tab_id, label.clone());
+            }
+        }
+        // Replaced panes end their shells, except those reattached above.
+        let reattached: HashSet<u64> = new_runtimes
+            .values()
+            .flat_map(|tab| tab.panes.values())
+            .filter_map(|pane| pane.pty.mux_id())
+            .collect();
+        let replaced = std::mem::replace(&mut self.tab_runtimes, new_runtimes);
+        for pane in replaced.values().flat_map(|tab| tab.panes.values()) {
+            let kept = pane.pty.tmux_pane().is_some()
+                || pane.pty.mux_id().is_some_and(|id| reattached.contains(&id));
+            if !kept {
+                pane.pty.terminate();
+            }
+        }
+        self.tmux_gateways.clear();
+        self.tab_kinds = self
             .tabs
             .entries()
             .into_iter()
             .map(|(id, _)| (id, AppTabKind::Terminal))
             .collect();
         self.editor_tabs.clear();
+        self.replay_tabs.clear();
         self.last_session_save = Instant::now();
         self.refresh_saved_session_entries();
 
@@ -1117,50 +1975,865 @@ impl TerminalApp {
         }
     }
 
-    fn poll_pty_output(&mut self) {
-        for runtime in self.tab_runtimes.values_mut() {
-            while let Some(chunk) = runtime.pty.try_read_chunk() {
-                let lines = runtime.parser.feed(&chunk);
-                let command = runtime
-                    .session
-                    .blocks()
-                    .last()
-                    .map(|block| block.command.as_str())
+    fn poll_pty_output(&mut self, ctx: &egui::Context) {
+        let deadline = Instant::now() + PTY_PARSE_BUDGET;
+        let mut clipboard_requests = Vec::new();
+        let mut bells = Vec::new();
+        let mut notifications = Vec::new();
+        let mut tmux_notifications = Vec::new();
+        let panes = self.tab_runtimes.iter_mut().flat_map(|(tab_id, tab)| {
+            tab.panes
+                .iter_mut()
+                .map(move |(pane_id, runtime)| (*tab_id, *pane_id, runtime))
+        });
+        for (tab_id, pane_id, runtime) in panes {
+            if !runtime.pty.has_wakeup() {
+                let ctx = ctx.clone();
+                runtime
+                    .pty
+                    .set_wakeup(Box::new(move || ctx.request_repaint()));
+            }
+            // Every pane gets at least one batch per frame so a flood in one
+            // pane cannot starve the others.
+            let mut parsed_any = false;
+            while !(parsed_any && Instant::now() >= deadline) {
+                let Some(chunk) = runtime.pty.try_read_chunk() else {
+                    break;
+                };
+                parsed_any = true;
+                for event in runtime.emulator.feed(&chunk) {
+                    match event {
+                        TerminalEvent::Line(line) => {
+                            if runtime.at_shell_prompt {
+                                continue;
+                            }
+                            let command = runtime
+                                .session
+                                .blocks()
+                                .last()
+                                .map(|block| block.command.as_str())
+                                .unwrap_or_default();
+                            let lines = sanitize_shell_output_lines(vec![line], command);
+                            runtime.session.push_styled_output_lines(lines);
+                        }
+                        TerminalEvent::ShellMark(mark) => runtime.apply_shell_mark(mark),
+                        TerminalEvent::WorkingDirectory(cwd) => runtime.shell_cwd = Some(cwd),
+                        TerminalEvent::Reply(bytes) => {
+                            // tmux already answered its panes' queries.
+                            if runtime.pty.tmux_pane().is_none() {
+                                let _ = runtime.pty.write_bytes(&bytes);
+                            }
+                        }
+                        TerminalEvent::Tmux(notification) => {
+                            tmux_notifications.push((tab_id, pane_id, notification));
+                        }
+                        TerminalEvent::Clipboard(request) => {
+                            clipboard_requests.push((tab_id, pane_id, request));
+                        }
+                        TerminalEvent::Bell => bells.push((tab_id, pane_id)),
+                        TerminalEvent::Notification { title, body } => {
+                            notifications.push((tab_id, pane_id, title, body));
+                        }
+                        TerminalEvent::AlternateScreenEntered => {
+                            runtime.fullscreen_started_at = Some(Instant::now());
+                            runtime.grid_selection = None;
+                        }
+                        TerminalEvent::AlternateScreenExited { last_screen } => {
+                            let duration_ms = runtime
+                                .fullscreen_started_at
+                                .take()
+                                .map(|started_at| {
+                                    started_at.elapsed().as_millis().min(u64::MAX as u128) as u64
+                                })
+                                .unwrap_or_default();
+                            let cwd = runtime.working_directory();
+                            runtime.session.record_fullscreen_session(
+                                cwd,
+                                duration_ms,
+                                last_screen,
+                            );
+                        }
+                    }
+                }
+            }
+            if !parsed_any {
+                continue;
+            }
+            if Instant::now() >= deadline {
+                ctx.request_repaint();
+            }
+
+            let pending = runtime.emulator.current_line();
+            let hide_prompt = runtime.at_shell_prompt && !runtime.interactive_input;
+            if hide_prompt || should_hide_pending_line(&pending) {
+                runtime.session.set_pending_line(String::new());
+            } else if let Some(pending) = sanitize_pending_shell_line(&pending) {
+                runtime.session.set_pending_line(pending);
+            } else {
+                runtime.session.set_pending_line(String::new());
+            }
+        }
+
+        for (tab_id, pane_id, request) in clipboard_requests {
+            self.handle_clipboard_request(ctx, tab_id, pane_id, request);
+        }
+        for (tab_id, pane_id) in bells {
+            self.ring_bell(tab_id, pane_id);
+        }
+        for (tab_id, pane_id, title, body) in notifications {
+            self.show_notification(tab_id, pane_id, title, body);
+        }
+        for (tab_id, pane_id, notification) in tmux_notifications {
+            self.handle_tmux_notification((tab_id, pane_id), notification);
+        }
+    }
+
+    fn handle_tmux_notification(
+        &mut self,
+        gateway: (u64, PaneId),
+        notification: tmux::Notification,
+    ) {
+        let state = self.tmux_gateways.entry(gateway).or_insert_with(|| {
+            self.status_text = format!(
+                "tmux control mode requested by pane-{}: confirm to mirror it",
+                gateway.1 + 1
+            );
+            TmuxGateway::default()
+        });
+        let exited = matches!(notification, tmux::Notification::Exit { .. });
+        match state.allowed {
+            Some(true) => {}
+            Some(false) | None if exited => {
+                self.tmux_gateways.remove(&gateway);
+                return;
+            }
+            Some(false) => return,
+            None => {
+                if state.held.len() < MAX_HELD_TMUX_NOTIFICATIONS {
+                    state.held.push(notification);
+                }
+                return;
+            }
+        }
+        for event in state.client.handle(notification) {
+            self.apply_tmux_event(gateway, event);
+        }
+    }
+
+    fn apply_tmux_event(&mut self, gateway: (u64, PaneId), event: ClientEvent) {
+        match event {
+            ClientEvent::Command(command) => {
+                if let Some(runtime) = self.tab_pane_runtime_mut(gateway.0, gateway.1) {
+                    let _ = runtime.pty.write_input(&format!("{command}\n"));
+                }
+            }
+            ClientEvent::Windows(windows) => {
+                let listed = windows
+                    .iter()
+                    .map(|window| window.id)
+                    .collect::<HashSet<_>>();
+                let gone = self
+                    .tmux_gateways
+                    .get(&gateway)
+                    .map(|state| {
+                        state
+                            .windows
+                            .keys()
+                            .copied()
+                            .filter(|window| !listed.contains(window))
+                            .collect::<Vec<_>>()
+                    })
                     .unwrap_or_default();
-                let lines = sanitize_shell_output_lines(lines, command);
-                runtime.session.push_output_lines(lines);
-
-                let pending = runtime.parser.current_line();
-                if should_hide_pending_line(pending) {
-                    runtime.session.set_pending_line(String::new());
-                } else if let Some(pending) = sanitize_pending_shell_line(pending) {
-                    runtime.session.set_pending_line(pending);
+                for window in gone {
+                    self.remove_tmux_window(gateway, window);
+                }
+                for window in windows {
+                    let Some(tab_id) =
+                        self.show_tmux_window(gateway, window.id, &window.layout, &window.name)
+                    else {
+                        continue;
+                    };
+                    if let Some(tab) = self.tab_runtimes.get_mut(&tab_id) {
+                        tab.tree.set_active(PaneId::from(window.active_pane));
+                    }
+                    if window.active {
+                        self.tabs.set_active_by_id(tab_id);
+                    }
+                }
+            }
+            ClientEvent::Layout { window, layout } => {
+                self.show_tmux_window(gateway, window, &layout, "tmux");
+            }
+            ClientEvent::WindowClosed(window) => self.remove_tmux_window(gateway, window),
+            ClientEvent::WindowRenamed { window, name } => {
+                if let Some((tab_id, _)) = self
+                    .tmux_gateways
+                    .get(&gateway)
+                    .and_then(|state| state.windows.get(&window))
+                {
+                    self.tabs.set_tab_label(*tab_id, name);
+                }
+            }
+            ClientEvent::PaneFocused { window, pane } => {
+                let tab_id = self
+                    .tmux_gateways
+                    .get(&gateway)
+                    .and_then(|state| state.windows.get(&window))
+                    .map(|(tab_id, _)| *tab_id);
+                if let Some(tab) = tab_id.and_then(|tab_id| self.tab_runtimes.get_mut(&tab_id)) {
+                    tab.tree.set_active(PaneId::from(pane));
+                }
+            }
+            ClientEvent::Output { pane, data } => {
+                if let Some(feed) = self
+                    .tmux_gateways
+                    .get(&gateway)
+                    .and_then(|state| state.feeds.get(&pane))
+                {
+                    let _ = feed.send(data);
+                }
+            }
+            ClientEvent::Exit(reason) => {
+                self.end_tmux_gateway(gateway);
+                self.status_text = match reason {
+                    Some(reason) => format!("tmux detached: {reason}"),
+                    None => "tmux detached".to_owned(),
+                };
+            }
+        }
+    }
+
+    /// Shows a tmux window as a tab split like its layout, opening a tab
+    /// labelled `name` the first time. Returns the tab.
+    fn show_tmux_window(
+        &mut self,
+        gateway: (u64, PaneId),
+        window: u32,
+        layout: &Layout,
+        name: &str,
+    ) -> Option<u64> {
+        let known_tab = self
+            .tmux_gateways
+            .get(&gateway)?
+            .windows
+            .get(&window)
+            .map(|(tab_id, _)| *tab_id);
+        // Closed here; tmux has yet to confirm.
+        if known_tab.is_some_and(|tab_id| !self.tab_runtimes.contains_key(&tab_id)) {
+            return None;
+        }
+        let previous = known_tab.and_then(|tab_id| self.tab_runtimes.remove(&tab_id));
+        let previous_active = previous.as_ref().map(|tab| tab.tree.active());
+        let mut panes = previous.map(|tab| tab.panes).unwrap_or_default();
+
+        let scrollback = self.config.scrollback.to_buffer_config();
+        let cwd = self.workspace_root.display().to_string();
+        let gateway_pty = self
+            .tab_runtimes
+            .get(&gateway.0)
+            .and_then(|tab| tab.panes.get(&gateway.1))
+            .map(|runtime| &runtime.pty);
+        let mut feeds = Vec::new();
+        let mut failure = None;
+        for (pane, cols, rows) in layout.panes() {
+            let pane_id = PaneId::from(pane);
+            if let Some(runtime) = panes.get_mut(&pane_id) {
+                let grid = runtime.emulator.grid();
+                if (grid.width(), grid.height()) != (usize::from(cols), usize::from(rows)) {
+                    runtime
+                        .emulator
+                        .resize(usize::from(cols), usize::from(rows));
+                }
+                continue;
+            }
+            let Some(gateway_pty) = gateway_pty else {
+                break;
+            };
+            match tmux::pane_session(gateway_pty, pane) {
+                Ok((pty, feed)) => {
+                    let session = SessionState::new(cwd.clone());
+                    let runtime = PaneRuntime::from_pty(pty, session, &scrollback, (cols, rows));
+                    panes.insert(pane_id, runtime);
+                    feeds.push((pane, feed));
+                }
+                Err(err) => failure = Some(err),
+            }
+        }
+        let shown = layout
+            .panes()
+            .into_iter()
+            .map(|(pane, ..)| PaneId::from(pane))
+            .filter(|pane_id| panes.contains_key(pane_id))
+            .collect::<Vec<_>>();
+        panes.retain(|pane_id, _| shown.contains(pane_id));
+        let Some(&first) = shown.first() else {
+            self.status_text = match failure {
+                Some(err) => format!("failed to mirror tmux window: {err}"),
+                None => "failed to mirror tmux window".to_owned(),
+            };
+            return None;
+        };
+        let active = previous_active
+            .filter(|pane_id| shown.contains(pane_id))
+            .unwrap_or(first);
+        let tree = PaneTree::with_root(pane_node_from_layout(layout), active);
+
+        let tab_id = match known_tab {
+            Some(tab_id) => tab_id,
+            None => {
+                let tab_id = self.tabs.add_tab_with_label(name);
+                self.tab_kinds.insert(tab_id, AppTabKind::Terminal);
+                tab_id
+            }
+        };
+        self.tab_runtimes.insert(tab_id, TabRuntime { tree, panes });
+        let state = self.tmux_gateways.get_mut(&gateway)?;
+        if let Some((_, old_layout)) = state.windows.insert(window, (tab_id, layout.clone())) {
+            for (pane, ..) in old_layout.panes() {
+                if !shown.contains(&PaneId::from(pane)) {
+                    state.feeds.remove(&pane);
+                }
+            }
+        }
+        state.feeds.extend(feeds);
+        if let Some(err) = failure {
+            self.status_text = format!("failed to mirror tmux pane: {err}");
+        }
+        Some(tab_id)
+    }
+
+    fn remove_tmux_window(&mut self, gateway: (u64, PaneId), window: u32) {
+        let Some(state) = self.tmux_gateways.get_mut(&gateway) else {
+            return;
+        };
+        let Some((tab_id, layout)) = state.windows.remove(&window) else {
+            return;
+        };
+        for (pane, ..) in layout.panes() {
+            state.feeds.remove(&pane);
+        }
+        // The panes are gone in tmux already; only the mirror is dropped.
+        if self.tab_runtimes.contains_key(&tab_id) {
+            self.remove_tab(tab_id);
+        }
+    }
+
+    fn end_tmux_gateway(&mut self, gateway: (u64, PaneId)) {
+        let windows = self
+            .tmux_gateways
+            .get(&gateway)
+            .map(|state| state.windows.keys().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        for window in windows {
+            self.remove_tmux_window(gateway, window);
+        }
+        self.tmux_gateways.remove(&gateway);
+    }
+
+    // Drops mirrors whose `tmux -CC` pane was closed, and sizes each tmux
+    // client to fit the panes of its window on screen.
+    fn sync_tmux_gateways(&mut self) {
+        let closed = self
+            .tmux_gateways
+            .keys()
+            .copied()
+            .filter(|(tab_id, pane_id)| {
+                self.tab_runtimes
+                    .get(tab_id)
+                    .is_none_or(|tab| !tab.panes.contains_key(pane_id))
+            })
+            .collect::<Vec<_>>();
+        for gateway in closed {
+            self.end_tmux_gateway(gateway);
+        }
+
+        let active_tab = self.tabs.active_id();
+        let Some(tab) = self.tab_runtimes.get(&active_tab) else {
+            return;
+        };
+        for (gateway, state) in &mut self.tmux_gateways {
+            let Some((_, layout)) = state
+                .windows
+                .values()
+                .find(|(tab_id, _)| *tab_id == active_tab)
+            else {
+                continue;
+            };
+            let (cols, rows) = layout.fit(&|pane| {
+                tab.panes
+                    .get(&PaneId::from(pane))
+                    .map(|runtime| runtime.pty_size)
+            });
+            if let Some(command) = state.client.resize(cols, rows)
+                && let Some(runtime) = self
+                    .tab_runtimes
+                    .get(&gateway.0)
+                    .and_then(|tab| tab.panes.get(&gateway.1))
+            {
+                let _ = runtime.pty.write_input(&format!("{command}\n"));
+            }
+        }
+    }
+
+    fn ring_bell(&mut self, tab_id: u64, pane_id: PaneId) {
+        let Some(runtime) = self
+            .tab_runtimes
+            .get_mut(&tab_id)
+            .and_then(|tab| tab.panes.get_mut(&pane_id))
+        else {
+            return;
+        };
+        if runtime
+            .alerts
+            .ring(Instant::now(), &self.config.bell, self.notifier.as_ref())
+        {
+            self.tabs.mark_attention(tab_id);
+        }
+    }
+
+    fn show_notification(
+        &mut self,
+        tab_id: u64,
+        pane_id: PaneId,
+        title: Option<String>,
+        body: String,
+    ) {
+        if !self.config.notifications.enabled {
+            return;
+        }
+        let Some(runtime) = self
+            .tab_runtimes
+            .get_mut(&tab_id)
+            .and_then(|tab| tab.panes.get_mut(&pane_id))
+        else {
+            return;
+        };
+        let title = title.unwrap_or_else(|| format!("pane-{}", pane_id + 1));
+        if let Some((title, body)) =
+            runtime
+                .alerts
+                .notify(Instant::now(), &title, &body, self.notifier.as_ref())
+        {
+            self.status_text = format!("{title}: {body}");
+        }
+    }
+
+    fn handle_clipboard_request(
+        &mut self,
+        ctx: &egui::Context,
+        tab_id: u64,
+        pane_id: PaneId,
+        request: ClipboardRequest,
+    ) {
+        let access = match &request {
+            ClipboardRequest::Write { .. } => "write",
+            ClipboardRequest::Read { .. } => "read",
+        };
+        match self.config.clipboard.decide(&request) {
+            ClipboardDecision::Allow => self.apply_clipboard_request(ctx, tab_id, pane_id, request),
+            ClipboardDecision::Deny => {
+                self.status_text = format!(
+                    "OSC 52: blocked clipboard {access} from pane-{}",
+                    pane_id + 1
+                );
+            }
+            ClipboardDecision::Ask => {
+                self.status_text = format!(
+                    "OSC 52: pane-{} asks for clipboard {access} access",
+                    pane_id + 1
+                );
+                self.clipboard_prompts.push_back(ClipboardPrompt {
+                    tab_id,
+                    pane_id,
+                    request,
+                });
+            }
+        }
+    }
+
+    fn apply_clipboard_request(
+        &mut self,
+        ctx: &egui::Context,
+        tab_id: u64,
+        pane_id: PaneId,
+        request: ClipboardRequest,
+    ) {
+        match request {
+            ClipboardRequest::Write { text, .. } => {
+                self.status_text = format!(
+                    "OSC 52: pane-{} copied {} chars to the clipboard",
+                    pane_id + 1,
+                    text.chars().count()
+                );
+                ctx.copy_text(text);
+            }
+            ClipboardRequest::Read {
+                selection,
+                bell_terminated,
+            } => {
+                self.pending_clipboard_read = Some(PendingClipboardRead {
+                    tab_id,
+                    pane_id,
+                    selection,
+                    bell_terminated,
+                    requested_at: Instant::now(),
+                });
+                ctx.send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+                ctx.request_repaint_after(CLIPBOARD_READ_TIMEOUT);
+            }
+        }
+    }
+
+    // Takes the paste event produced for an OSC 52 read before it can reach
+    // the focused input.
+    fn answer_clipboard_read(&mut self, ctx: &egui::Context) {
+        let Some(pending) = &self.pending_clipboard_read else {
+            return;
+        };
+        let pasted = ctx.input_mut(|input| {
+            let idx = input
+                .events
+                .iter()
+                .position(|event| matches!(event, egui::Event::Paste(_)))?;
+            match input.events.remove(idx) {
+                egui::Event::Paste(text) => Some(text),
+                _ => None,
+            }
+        });
+        if pasted.is_none() && pending.requested_at.elapsed() < CLIPBOARD_READ_TIMEOUT {
+            return;
+        }
+        let Some(pending) = self.pending_clipboard_read.take() else {
+            return;
+        };
+        let text = pasted.unwrap_or_default();
+        let reply = osc52_reply(&pending.selection, &text, pending.bell_terminated);
+        if let Some(runtime) = self.tab_pane_runtime_mut(pending.tab_id, pending.pane_id) {
+            let _ = runtime.pty.write_bytes(&reply);
+            self.status_text = format!(
+                "OSC 52: pane-{} read {} chars from the clipboard",
+                pending.pane_id + 1,
+                text.chars().count()
+            );
+        }
+    }
+
+    fn show_clipboard_prompt(&mut self, ctx: &egui::Context) {
+        let Some(prompt) = self.clipboard_prompts.front() else {
+            return;
+        };
+        let tab_label = self
+            .tabs
+            .entries()
+            .into_iter()
+            .find(|(id, _)| *id == prompt.tab_id)
+            .map(|(_, label)| label)
+            .unwrap_or_default();
+        let mut allowed = None;
+        egui::Window::new("Clipboard Access")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .frame(theme::panel_frame())
+            .show(ctx, |ui| {
+                let source = format!("pane-{} in {tab_label}", prompt.pane_id + 1);
+                match &prompt.request {
+                    ClipboardRequest::Write { text, .. } => {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{source} wants to copy {} chars to the clipboard:",
+                                text.chars().count()
+                            ))
+                            .color(theme::TEXT_PRIMARY),
+                        );
+                        let preview: String = text.chars().take(200).collect();
+                        ui.label(
+                            egui::RichText::new(preview)
+                                .monospace()
+                                .color(theme::TEXT_MUTED),
+                        );
+                    }
+                    ClipboardRequest::Read { .. } => {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{source} wants to read the clipboard contents."
+                            ))
+                            .color(theme::WARNING),
+                        );
+                    }
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Allow").clicked() {
+                        allowed = Some(true);
+                    }
+                    if ui.button("Deny").clicked() {
+                        allowed = Some(false);
+                    }
+                });
+            });
+
+        let Some(allowed) = allowed else {
+            return;
+        };
+        let Some(prompt) = self.clipboard_prompts.pop_front() else {
+            return;
+        };
+        if allowed {
+            self.apply_clipboard_request(ctx, prompt.tab_id, prompt.pane_id, prompt.request);
+        } else {
+            self.status_text = format!(
+                "OSC 52: denied clipboard access for pane-{}",
+                prompt.pane_id + 1
+            );
+        }
+    }
+
+    fn show_tmux_prompt(&mut self, ctx: &egui::Context) {
+        let Some(gateway) = self
+            .tmux_gateways
+            .iter()
+            .find(|(_, state)| state.allowed.is_none())
+            .map(|(gateway, _)| *gateway)
+        else {
+            return;
+        };
+        let tab_label = self
+            .tabs
+            .entries()
+            .into_iter()
+            .find(|(id, _)| *id == gateway.0)
+            .map(|(_, label)| label)
+            .unwrap_or_default();
+        let mut allowed = None;
+        egui::Window::new("tmux Control Mode")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .frame(theme::panel_frame())
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(format!(
+                        "pane-{} in {tab_label} started tmux control mode (tmux -CC).",
+                        gateway.1 + 1
+                    ))
+                    .color(theme::TEXT_PRIMARY),
+                );
+                ui.label(
+                    egui::RichText::new(
+                        "Mirroring opens its windows as tabs and sends tmux commands to this \
+                         pane. Only allow it if you started tmux -CC yourself.",
+                    )
+                    .color(theme::WARNING),
+                );
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Allow").clicked() {
+                        allowed = Some(true);
+                    }
+                    if ui.button("Deny").clicked() {
+                        allowed = Some(false);
+                    }
+                });
+            });
+
+        let Some(allowed) = allowed else {
+            return;
+        };
+        let Some(state) = self.tmux_gateways.get_mut(&gateway) else {
+            return;
+        };
+        state.allowed = Some(allowed);
+        let held = std::mem::take(&mut state.held);
+        if allowed {
+            self.status_text = "tmux control mode: windows open as tabs".to_owned();
+            for notification in held {
+                self.handle_tmux_notification(gateway, notification);
+            }
+        } else {
+            self.status_text = format!(
+                "tmux control mode denied for pane-{}; its output stays hidden until tmux exits",
+                gateway.1 + 1
+            );
+        }
+    }
+
+    fn hold_paste(&mut self, text: String, to_input_bar: bool) {
+        let Some(pane_id) = self.active_tab_runtime().map(|tab| tab.tree.active()) else {
+            return;
+        };
+        let tab_id = self.tabs.active_id();
+        self.pending_pastes.push_back(PendingPaste {
+            tab_id,
+            pane_id,
+            text,
+            to_input_bar,
+        });
+        if self.pending_pastes.len() > 1 {
+            self.status_text = format!(
+                "paste queued behind {} waiting for confirmation",
+                self.pending_pastes.len() - 1
+            );
+        }
+    }
+
+    fn show_paste_confirmation(&mut self, ctx: &egui::Context) {
+        let queued = self.pending_pastes.len().saturating_sub(1);
+        let Some(pending) = self.pending_pastes.front_mut() else {
+            return;
+        };
+        let mut confirmed = None;
+        egui::Window::new("Confirm Paste")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .frame(theme::panel_frame())
+            .show(ctx, |ui| {
+                let line_count = pending.text.lines().count().max(1);
+                let target = if pending.to_input_bar {
+                    "the input bar"
                 } else {
-                    runtime.session.set_pending_line(String::new());
+                    "the running program"
+                };
+                ui.label(
+                    egui::RichText::new(format!(
+                        "Paste {line_count} line(s) into {target} of pane-{}?",
+                        pending.pane_id + 1
+                    ))
+                    .color(theme::TEXT_PRIMARY),
+                );
+                if queued > 0 {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "{queued} more paste(s) waiting after this one"
+                        ))
+                        .color(theme::TEXT_MUTED),
+                    );
+                }
+                if pending.text.lines().any(is_dangerous_command) {
+                    ui.label(
+                        egui::RichText::new("The text runs sudo or rm -rf.").color(theme::WARNING),
+                    );
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(260.0)
+                    .show(ui, |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut pending.text)
+                                .code_editor()
+                                .desired_width(520.0)
+                                .desired_rows(6),
+                        );
+                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Paste").clicked() {
+                        confirmed = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        confirmed = Some(false);
+                    }
+                });
+            });
+
+        let Some(confirmed) = confirmed else {
+            return;
+        };
+        let Some(pending) = self.pending_pastes.pop_front() else {
+            return;
+        };
+        if !confirmed {
+            self.status_text = "paste cancelled".to_owned();
+            return;
+        }
+        let Some(runtime) = self.tab_pane_runtime_mut(pending.tab_id, pending.pane_id) else {
+            return;
+        };
+        let chars = pending.text.chars().count();
+        if pending.to_input_bar {
+            runtime.input_buffer.push_str(&pending.text);
+            self.focus_terminal_input_next_frame = true;
+            self.status_text = format!("pasted {chars} characters into the input bar");
+            return;
+        }
+        let bytes = input::encode_paste(&pending.text, runtime.emulator.modes());
+        self.status_text = match runtime.pty.write_bytes(&bytes) {
+            Ok(()) => format!("pasted {chars} characters"),
+            Err(err) => format!("write failed: {err}"),
+        };
+    }
+
+    // The active pane of the active tab has focus while the window does.
+    fn report_pane_focus(&mut self, ctx: &egui::Context) {
+        let window_focused = ctx.input(|i| i.focused);
+        let active_tab = self.tabs.active_id();
+        for (tab_id, tab) in &mut self.tab_runtimes {
+            let active_pane = tab.tree.active();
+            for (pane_id, runtime) in &mut tab.panes {
+                let focused = window_focused && *tab_id == active_tab && *pane_id == active_pane;
+                if focused == runtime.focused {
+                    continue;
+                }
+                runtime.focused = focused;
+                if focused && let Some(pane) = runtime.pty.tmux_pane() {
+                    let _ = runtime.pty.tmux_command(&tmux::select_pane(pane));
+                }
+                if let Some(report) = input::encode_focus(focused, runtime.emulator.modes()) {
+                    let _ = runtime.pty.write_bytes(&report);
                 }
             }
         }
     }
 
+    // Tab labels and progress follow the focused pane of each tab.
+    fn sync_tab_titles(&mut self) {
+        for (tab_id, runtime) in &self.tab_runtimes {
+            let Some(pane) = runtime.active_pane() else {
+                continue;
+            };
+            self.tabs.set_shell_title(*tab_id, pane.emulator.title());
+            self.tabs
+                .set_tab_progress(*tab_id, pane.emulator.progress());
+        }
+    }
+
+    fn apply_pending_resizes(&mut self) {
+        let mut failure = None;
+        let panes = self
+            .tab_runtimes
+            .values_mut()
+            .flat_map(|tab| tab.panes.values_mut());
+        for runtime in panes {
+            if let Err(err) = runtime.apply_pending_resize() {
+                failure = Some(err);
+            }
+        }
+        if let Some(err) = failure {
+            self.status_text = format!("resize failed: {err}");
+        }
+    }
+
     fn poll_ai_events(&mut self) {
         while let Ok(event) = self.ai_event_rx.try_recv() {
             match event {
                 AiRunEvent::OutputChunk {
                     tab_id,
+                    pane_id,
                     ai_block_id,
                     lines,
                 } => {
-                    if let Some(runtime) = self.tab_runtimes.get_mut(&tab_id) {
+                    if let Some(runtime) = self.tab_pane_runtime_mut(tab_id, pane_id) {
                         let _ = runtime.session.append_ai_output_lines(ai_block_id, &lines);
                     }
                 }
                 AiRunEvent::Completed {
                     tab_id,
+                    pane_id,
                     ai_block_id,
                     exit_code,
                     duration_ms,
                 } => {
-                    if let Some(runtime) = self.tab_runtimes.get_mut(&tab_id) {
+                    if let Some(runtime) = self.tab_pane_runtime_mut(tab_id, pane_id) {
                         let _ =
                             runtime
                                 .session
@@ -1174,11 +2847,12 @@ impl TerminalApp {
                 }
                 AiRunEvent::Failed {
                     tab_id,
+                    pane_id,
                     ai_block_id,
                     message,
                     duration_ms,
                 } => {
-                    if let Some(runtime) = self.tab_runtimes.get_mut(&tab_id) {
+                    if let Some(runtime) = self.tab_pane_runtime_mut(tab_id, pane_id) {
                         let _ = runtime.session.fail_ai_block(
                             ai_block_id,
                             message.clone(),
@@ -1190,8 +2864,12 @@ impl TerminalApp {
                         self.status_text = runtime.ai_status_line.clone();
                     }
                 }
-                AiRunEvent::CodexSessionUpdated { tab_id, session_id } => {
-                    if let Some(runtime) = self.tab_runtimes.get_mut(&tab_id) {
+                AiRunEvent::CodexSessionUpdated {
+                    tab_id,
+                    pane_id,
+                    session_id,
+                } => {
+                    if let Some(runtime) = self.tab_pane_runtime_mut(tab_id, pane_id) {
                         let changed = runtime.codex_last_session_id.as_deref() != Some(&session_id);
                         runtime.codex_last_session_id = Some(session_id.clone());
                         if changed {
@@ -1240,6 +2918,14 @@ impl TerminalApp {
                 let old_session_path = self.session_save_path.clone();
                 let new_session_path = PathBuf::from(&config.session.session_file);
 
+                let scrollback = config.scrollback.to_buffer_config();
+                let panes = self
+                    .tab_runtimes
+                    .values_mut()
+                    .flat_map(|tab| tab.panes.values_mut());
+                for runtime in panes {
+                    runtime.session.set_scrollback_config(scrollback.clone());
+                }
                 self.config = config;
                 self.config_mtime = Some(modified);
                 self.status_text = format!("config reloaded: {}", self.config_path.display());
@@ -1294,15 +2980,34 @@ impl TerminalApp {
             }
 
             let command = std::mem::take(&mut runtime.input_buffer);
-            let command = normalize_multiline_powershell_command(command.trim_end());
-            let command_for_shell = normalize_windows_shell_newlines(&command);
-            let cwd = std::env::current_dir()
-                .map(|p| p.display().to_string())
-                .unwrap_or_else(|_| ".".to_owned());
+            let shell_kind = runtime.pty.shell_kind();
+            let (command, command_for_shell) = if shell_kind == ShellKind::PowerShell {
+                let command = normalize_multiline_powershell_command(command.trim_end());
+                let command_for_shell = normalize_windows_shell_newlines(&command);
+                (command, command_for_shell)
+            } else {
+                let command = command.trim_end().replace("\r\n", "\n");
+                let command_for_shell = command.replace('\n', shell_kind.line_ending());
+                (command, command_for_shell)
+            };
+            let cwd = runtime.working_directory();
 
             runtime.session.start_command_block(command.clone(), cwd);
             runtime.input_history_cursor = None;
-            runtime.pty.write_input(&format!("{command_for_shell}\r\n"))
+            runtime.command_started_at = Some(Instant::now());
+            if !runtime.shell_reports_command_start {
+                runtime.at_shell_prompt = false;
+            }
+            // A shell with bracketed paste on takes multi-line input as one
+            // edit and runs it on the final line ending.
+            let modes = runtime.emulator.modes();
+            if modes.bracketed_paste && command.contains('\n') {
+                let mut bytes = input::encode_paste(&command, modes);
+                bytes.extend_from_slice(shell_kind.line_ending().as_bytes());
+                runtime.pty.write_bytes(&bytes)
+            } else {
+                runtime.pty.write_line(&command_for_shell)
+            }
         };
 
         if let Err(err) = write_result {
@@ -1351,28 +3056,170 @@ impl TerminalApp {
         }
     }
 
-    fn set_pane_layout(&mut self, layout: PaneLayout) {
-        let changed = if let Some(runtime) = self.active_runtime_mut() {
-            match layout {
-                PaneLayout::Single => runtime.pane_grid.set_single(),
-                PaneLayout::VerticalSplit => runtime.pane_grid.split_vertical(),
-                PaneLayout::HorizontalSplit => runtime.pane_grid.split_horizontal(),
+    fn split_active_pane(&mut self, direction: SplitDirection) {
+        if let Some(runtime) = self.active_runtime()
+            && let Some(pane) = runtime.pty.tmux_pane()
+        {
+            let command = tmux::split_window(pane, direction == SplitDirection::Vertical);
+            let result = runtime.pty.tmux_command(&command);
+            self.status_text = match result {
+                Ok(()) => "split requested from tmux".to_owned(),
+                Err(err) => format!("failed to split pane: {err}"),
+            };
+            return;
+        }
+        let profile = self.config.shell.to_profile();
+        let scrollback = self.config.scrollback.to_buffer_config();
+        let host = self.pty_host.clone();
+        let Some(tab) = self.active_tab_runtime_mut() else {
+            return;
+        };
+        self.status_text = match tab.split(direction, &profile, &scrollback, &host) {
+            Ok(pid) => {
+                let direction_label = match direction {
+                    SplitDirection::Vertical => "side by side",
+                    SplitDirection::Horizontal => "stacked",
+                };
+                format!(
+                    "split pane {direction_label} (pid={pid}, {})",
+                    tab.tree.label()
+                )
             }
-            true
+            Err(err) => format!("failed to split pane: {err}"),
+        };
+        self.focus_terminal_input_next_frame = true;
+    }
+
+    /// Closes the focused pane, or the whole tab when it is the last pane.
+    fn close_active_pane(&mut self) {
+        let Some(tab) = self.active_tab_runtime_mut() else {
+            self.close_active_tab_if_possible();
+            return;
+        };
+        if tab.tree.pane_count() <= 1 {
+            self.close_active_tab_if_possible();
+            return;
+        }
+        let pane_id = tab.tree.active();
+        if tab.close_pane(pane_id) {
+            self.status_text = format!("closed pane ({})", tab.tree.label());
+            self.focus_terminal_input_next_frame = true;
+        }
+    }
+
+    fn toggle_pane_zoom(&mut self) {
+        if let Some(runtime) = self.active_runtime()
+            && let Some(pane) = runtime.pty.tmux_pane()
+        {
+            if let Err(err) = runtime.pty.tmux_command(&tmux::zoom_pane(pane)) {
+                self.status_text = format!("failed to zoom pane: {err}");
+            }
+            return;
+        }
+        let Some(tab) = self.active_tab_runtime_mut() else {
+            return;
+        };
+        if tab.tree.pane_count() <= 1 {
+            self.status_text = "nothing to zoom: tab has a single pane".to_owned();
+            return;
+        }
+        self.status_text = if tab.tree.toggle_zoom() {
+            "pane zoomed".to_owned()
         } else {
-            false
+            "pane zoom off".to_owned()
         };
+    }
+
+    fn focus_pane(&mut self, direction: FocusDirection) {
+        if let Some(tab) = self.active_tab_runtime_mut()
+            && tab.tree.focus(direction)
+        {
+            self.focus_terminal_input_next_frame = true;
+        }
+    }
+
+    fn set_interactive_input(&mut self, enabled: bool) {
+        let Some(runtime) = self.active_runtime_mut() else {
+            return;
+        };
+        runtime.interactive_input = enabled;
+        self.focus_terminal_input_next_frame = true;
+        self.status_text = if enabled {
+            "interactive input: keystrokes go straight to the shell".to_owned()
+        } else {
+            "line input".to_owned()
+        };
+    }
 
-        if changed {
-            let layout_label = match layout {
-                PaneLayout::Single => "single",
-                PaneLayout::VerticalSplit => "vertical",
-                PaneLayout::HorizontalSplit => "horizontal",
+    fn forward_interactive_keys(&mut self, ui: &mut egui::Ui) {
+        let request_focus = self.focus_terminal_input_next_frame;
+        let held_paste;
+        let write_result = {
+            let Some(runtime) = self.active_runtime() else {
+                return;
             };
-            self.status_text = format!(
-                "pane layout: {layout_label} (tab: {})",
-                self.tabs.active_label()
+            let size = egui::vec2((ui.available_width() - 92.0).max(120.0), 30.0);
+            let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+            if request_focus || response.clicked() || ui.memory(|m| m.focused().is_none()) {
+                response.request_focus();
+            }
+            let focused = response.has_focus();
+            ui.painter().rect(
+                rect,
+                egui::CornerRadius::same(4),
+                theme::BG_SURFACE_1,
+                egui::Stroke::new(
+                    1.0,
+                    if focused {
+                        theme::ACCENT_BLUE
+                    } else {
+                        theme::BORDER
+                    },
+                ),
+                egui::StrokeKind::Inside,
+            );
+            let hint = if focused {
+                "keys go to the shell | Ctrl+Shift+I: line input"
+            } else {
+                "click to send keys to the shell"
+            };
+            ui.painter().text(
+                rect.left_center() + egui::vec2(8.0, 0.0),
+                egui::Align2::LEFT_CENTER,
+                hint,
+                egui::TextStyle::Monospace.resolve(ui.style()),
+                theme::TEXT_MUTED,
             );
+            if !focused {
+                return;
+            }
+
+            ui.memory_mut(|m| {
+                m.set_focus_lock_filter(
+                    response.id,
+                    egui::EventFilter {
+                        tab: true,
+                        horizontal_arrows: true,
+                        vertical_arrows: true,
+                        escape: true,
+                    },
+                )
+            });
+            let mut events = ui.input(|i| i.events.clone());
+            held_paste = take_confirmable_paste(&mut events);
+            let bytes = encode_key_events(&events, runtime.emulator.modes());
+            if bytes.is_empty() {
+                Ok(())
+            } else {
+                runtime.pty.write_bytes(&bytes)
+            }
+        };
+        for text in held_paste {
+            self.hold_paste(text, false);
+        }
+
+        if let Err(err) = write_result {
+            self.status_text = format!("write failed: {err}");
         }
     }
 
@@ -1408,8 +3255,8 @@ impl TerminalApp {
         }
     }
 
-    fn prune_context_block_selection(&mut self) {
-        if let Some(runtime) = self.active_runtime_mut() {
+    fn prune_context_block_selection(&mut self, pane_id: PaneId) {
+        if let Some(runtime) = self.pane_runtime_mut(pane_id) {
             runtime
                 .selected_context_block_ids
                 .retain(|id| runtime.session.block_by_id(*id).is_some());
@@ -1418,8 +3265,16 @@ impl TerminalApp {
 
     fn start_ai_request(&mut self, tool: AiTool, prompt: String) {
         let tab_id = self.tabs.active_id();
+        let Some(pane_id) = self.active_tab_runtime().map(|tab| tab.tree.active()) else {
+            self.status_text = "active tab runtime is unavailable".to_owned();
+            return;
+        };
         let (resolved, combined_prompt, ai_block_id, timeout_sec, codex_resume_session_id) = {
-            let Some(runtime) = self.tab_runtimes.get_mut(&tab_id) else {
+            let Some(runtime) = self
+                .tab_runtimes
+                .get_mut(&tab_id)
+                .and_then(|tab| tab.panes.get_mut(&pane_id))
+            else {
                 self.status_text = "active tab runtime is unavailable".to_owned();
                 return;
             };
@@ -1462,8 +3317,11 @@ impl TerminalApp {
                 run_ai_command(
                     resolved,
                     Some(combined_prompt),
-                    tab_id,
-                    ai_block_id,
+                    AiRunTarget {
+                        tab_id,
+                        pane_id,
+                        ai_block_id,
+                    },
                     timeout_sec,
                     workspace_root,
                     codex_resume_session_id,
@@ -1471,63 +3329,19 @@ impl TerminalApp {
                 );
             });
 
-        if let Err(err) = spawn_result {
-            if let Some(runtime) = self.tab_runtimes.get_mut(&tab_id) {
-                runtime.running_ai_jobs = runtime.running_ai_jobs.saturating_sub(1);
-                let _ = runtime.session.fail_ai_block(
-                    ai_block_id,
-                    format!("failed to spawn AI runner thread: {err}"),
-                    0,
-                );
-                runtime.ai_status_line = format!("AI block #{ai_block_id} failed to start");
-            }
+        if let Err(err) = spawn_result
+            && let Some(runtime) = self.tab_pane_runtime_mut(tab_id, pane_id)
+        {
+            runtime.running_ai_jobs = runtime.running_ai_jobs.saturating_sub(1);
+            let _ = runtime.session.fail_ai_block(
+                ai_block_id,
+                format!("failed to spawn AI runner thread: {err}"),
+                0,
+            );
+            runtime.ai_status_line = format!("AI block #{ai_block_id} failed to start");
         }
     }
 
-    fn filtered_timeline_items(&self) -> Vec<TimelineItem> {
-        let Some(runtime) = self.active_runtime() else {
-            return Vec::new();
-        };
-
-        let query = runtime.block_search_query.trim().to_lowercase();
-        let mut items = runtime.session.timeline_items();
-
-        items.retain(|item| match item {
-            TimelineItem::Command(block) => {
-                if runtime.bookmarks_only && !block.bookmarked {
-                    return false;
-                }
-
-                if query.is_empty() {
-                    return true;
-                }
-
-                block.command.to_lowercase().contains(&query)
-                    || block
-                        .output_lines
-                        .iter()
-                        .any(|line| line.to_lowercase().contains(&query))
-            }
-            TimelineItem::Ai(block) => {
-                if runtime.bookmarks_only {
-                    return false;
-                }
-
-                if query.is_empty() {
-                    return true;
-                }
-
-                block.prompt.to_lowercase().contains(&query)
-                    || block
-                        .output_lines
-                        .iter()
-                        .any(|line| line.to_lowercase().contains(&query))
-            }
-        });
-
-        items
-    }
-
     fn export_session_markdown(&mut self, bookmarks_only: bool) {
         let markdown = match self.active_runtime() {
             Some(runtime) => runtime.session.export_markdown(bookmarks_only),
@@ -1576,20 +3390,29 @@ impl TerminalApp {
         let can_close_tab = self.tabs.entries().len() > 1;
         let has_terminal_runtime = self.active_runtime().is_some();
         let is_maximized = ctx.input(|i| i.viewport().maximized).unwrap_or(false);
-        let current_layout = self
-            .active_runtime()
-            .map(|runtime| runtime.pane_grid.layout());
+        let (pane_count, pane_zoomed) = self
+            .active_tab_runtime()
+            .map(|tab| (tab.tree.pane_count(), tab.tree.zoomed().is_some()))
+            .unwrap_or((0, false));
         let recent_commands = self
             .active_runtime()
             .map(|runtime| runtime.session.history_search("", 8))
             .unwrap_or_default();
         let recent_sessions: Vec<SavedSessionEntry> =
             self.saved_session_entries.iter().take(8).cloned().collect();
+        let recording_label = if self.tab_is_recording(self.tabs.active_id()) {
+            "Stop Recording"
+        } else {
+            "Start Recording"
+        };
         let density = self.ui_density;
-        let pane_label = self
+        let interactive_input = self
             .active_runtime()
-            .map(|runtime| runtime.pane_grid.label())
-            .unwrap_or_else(|| "panes: 0 (single)".to_owned());
+            .is_some_and(|runtime| runtime.interactive_input);
+        let pane_label = self
+            .active_tab_runtime()
+            .map(|tab| tab.tree.label())
+            .unwrap_or_else(|| "panes: 0".to_owned());
         let sidebar_toggle_label = if self.sidebar_visible {
             "Hide Sidebar  Ctrl+B"
         } else {
@@ -1662,6 +3485,18 @@ impl TerminalApp {
                             ui.close_menu();
                         }
                         ui.separator();
+                        if ui
+                            .add_enabled(has_terminal_runtime, egui::Button::new(recording_label))
+                            .clicked()
+                        {
+                            self.toggle_tab_recording();
+                            ui.close_menu();
+                        }
+                        if ui.button("Replay Recording").clicked() {
+                            self.open_replay_picker();
+                            ui.close_menu();
+                        }
+                        ui.separator();
                         if ui.button("Save Session Snapshot").clicked() {
                             self.save_manual_workspace_snapshot();
                             ui.close_menu();
@@ -1714,43 +3549,61 @@ impl TerminalApp {
                     });
 
                     ui.menu_button("Pane", |ui| {
-                        let single_label = if current_layout == Some(PaneLayout::Single) {
-                            "[x] Single  Ctrl+1"
-                        } else {
-                            "[ ] Single  Ctrl+1"
-                        };
                         if ui
-                            .add_enabled(has_terminal_runtime, egui::Button::new(single_label))
+                            .add_enabled(
+                                has_terminal_runtime,
+                                egui::Button::new("Split Side by Side  Ctrl+2"),
+                            )
+                            .clicked()
+                        {
+                            self.split_active_pane(SplitDirection::Vertical);
+                            ui.close_menu();
+                        }
+                        if ui
+                            .add_enabled(
+                                has_terminal_runtime,
+                                egui::Button::new("Split Stacked  Ctrl+3"),
+                            )
                             .clicked()
                         {
-                            self.set_pane_layout(PaneLayout::Single);
+                            self.split_active_pane(SplitDirection::Horizontal);
                             ui.close_menu();
                         }
 
-                        let vertical_label = if current_layout == Some(PaneLayout::VerticalSplit) {
-                            "[x] Vertical  Ctrl+2"
+                        let zoom_label = if pane_zoomed {
+                            "[x] Zoom Pane  Ctrl+1"
                         } else {
-                            "[ ] Vertical  Ctrl+2"
+                            "[ ] Zoom Pane  Ctrl+1"
                         };
                         if ui
-                            .add_enabled(has_terminal_runtime, egui::Button::new(vertical_label))
+                            .add_enabled(pane_count > 1, egui::Button::new(zoom_label))
+                            .clicked()
+                        {
+                            self.toggle_pane_zoom();
+                            ui.close_menu();
+                        }
+                        if ui
+                            .add_enabled(
+                                has_terminal_runtime,
+                                egui::Button::new("Close Pane  Ctrl+Shift+W"),
+                            )
                             .clicked()
                         {
-                            self.set_pane_layout(PaneLayout::VerticalSplit);
+                            self.close_active_pane();
                             ui.close_menu();
                         }
 
-                        let horizontal_label =
-                            if current_layout == Some(PaneLayout::HorizontalSplit) {
-                                "[x] Horizontal  Ctrl+3"
-                            } else {
-                                "[ ] Horizontal  Ctrl+3"
-                            };
+                        ui.separator();
+                        let interactive_label = if interactive_input {
+                            "[x] Interactive Input  Ctrl+Shift+I"
+                        } else {
+                            "[ ] Interactive Input  Ctrl+Shift+I"
+                        };
                         if ui
-                            .add_enabled(has_terminal_runtime, egui::Button::new(horizontal_label))
+                            .add_enabled(has_terminal_runtime, egui::Button::new(interactive_label))
                             .clicked()
                         {
-                            self.set_pane_layout(PaneLayout::HorizontalSplit);
+                            self.set_interactive_input(!interactive_input);
                             ui.close_menu();
                         }
 
@@ -1922,44 +3775,96 @@ impl TerminalApp {
     }
 
     fn render_panes(&mut self, ui: &mut egui::Ui) {
-        let Some(layout) = self
-            .active_runtime()
-            .map(|runtime| runtime.pane_grid.layout())
-        else {
+        let Some(tab) = self.active_tab_runtime() else {
             return;
         };
-
-        match layout {
-            PaneLayout::Single => {
-                self.render_terminal_pane(ui, 0);
+        let rect = ui.available_rect_before_wrap();
+        let layout = tab.tree.layout(rect, PANE_DIVIDER_WIDTH);
+        let active_pane = tab.tree.active();
+
+        for divider in &layout.dividers {
+            let response = ui.interact(
+                divider.rect,
+                ui.id().with(("pane-divider", divider.split_index)),
+                egui::Sense::drag(),
+            );
+            let (cursor, line) = match divider.direction {
+                SplitDirection::Vertical => (
+                    egui::CursorIcon::ResizeHorizontal,
+                    egui::Rect::from_center_size(
+                        divider.rect.center(),
+                        egui::vec2(1.0, divider.rect.height()),
+                    ),
+                ),
+                SplitDirection::Horizontal => (
+                    egui::CursorIcon::ResizeVertical,
+                    egui::Rect::from_center_size(
+                        divider.rect.center(),
+                        egui::vec2(divider.rect.width(), 1.0),
+                    ),
+                ),
+            };
+            let highlighted = response.hovered() || response.dragged();
+            if highlighted {
+                ui.ctx().set_cursor_icon(cursor);
             }
-            PaneLayout::VerticalSplit => {
-                ui.columns(2, |cols| {
-                    self.render_terminal_pane(&mut cols[0], 0);
-                    self.render_terminal_pane(&mut cols[1], 1);
-                });
+            ui.painter().rect_filled(
+                line,
+                0.0,
+                if highlighted {
+                    theme::ACCENT_BLUE
+                } else {
+                    theme::BORDER
+                },
+            );
+            if response.dragged()
+                && let Some(pos) = response.interact_pointer_pos()
+                && let Some(tab) = self.active_tab_runtime_mut()
+            {
+                tab.tree
+                    .set_ratio(divider.split_index, divider.ratio_at(pos));
             }
-            PaneLayout::HorizontalSplit => {
-                let available_width = ui.available_width();
-                let available_height = ui.available_height();
-                let half_height = ((available_height - 8.0) / 2.0).max(140.0);
+        }
 
-                ui.allocate_ui(egui::vec2(available_width, half_height), |ui| {
-                    self.render_terminal_pane(ui, 0);
-                });
-                ui.separator();
-                ui.allocate_ui(egui::vec2(available_width, half_height), |ui| {
-                    self.render_terminal_pane(ui, 1);
-                });
+        let pressed_at = ui.input(|i| {
+            i.pointer
+                .primary_pressed()
+                .then(|| i.pointer.interact_pos())
+                .flatten()
+        });
+        for (pane_id, pane_rect) in layout.panes {
+            if pane_id != active_pane
+                && pressed_at.is_some_and(|pos| pane_rect.contains(pos))
+                && let Some(tab) = self.active_tab_runtime_mut()
+            {
+                tab.tree.set_active(pane_id);
+            }
+            ui.scope_builder(
+                egui::UiBuilder::new()
+                    .max_rect(pane_rect)
+                    .id_salt(("terminal-pane", pane_id)),
+                |ui| self.render_terminal_pane(ui, pane_id, pane_id == active_pane),
+            );
+            let flash = self
+                .pane_runtime(pane_id)
+                .and_then(|runtime| runtime.alerts.bell_at)
+                .map(|rang_at| rang_at.elapsed().as_secs_f32() / BELL_FLASH.as_secs_f32())
+                .filter(|progress| *progress < 1.0);
+            if let Some(progress) = flash
+                && self.config.bell.style == BellStyle::Visual
+            {
+                ui.painter().rect_filled(
+                    pane_rect,
+                    egui::CornerRadius::same(8),
+                    theme::WARNING.gamma_multiply(0.25 * (1.0 - progress)),
+                );
+                ui.ctx().request_repaint();
             }
         }
+        ui.advance_cursor_after_rect(rect);
     }
 
-    fn render_terminal_pane(&mut self, ui: &mut egui::Ui, pane_idx: usize) {
-        let is_active = self
-            .active_runtime()
-            .map(|runtime| runtime.pane_grid.active_pane() == pane_idx)
-            .unwrap_or(false);
+    fn render_terminal_pane(&mut self, ui: &mut egui::Ui, pane_id: PaneId, is_active: bool) {
         let border_color = if is_active {
             theme::ACCENT_BLUE
         } else {
@@ -1982,9 +3887,9 @@ impl TerminalApp {
             .show(ui, |ui| {
                 ui.horizontal(|ui| {
                     let pane_label = if is_active {
-                        format!("pane-{} *", pane_idx + 1)
+                        format!("pane-{} *", pane_id + 1)
                     } else {
-                        format!("pane-{}", pane_idx + 1)
+                        format!("pane-{}", pane_id + 1)
                     };
                     if ui
                         .selectable_label(
@@ -1998,24 +3903,23 @@ impl TerminalApp {
                                 }),
                         )
                         .clicked()
+                        && let Some(tab) = self.active_tab_runtime_mut()
                     {
-                        if let Some(runtime) = self.active_runtime_mut() {
-                            runtime.pane_grid.set_active_pane(pane_idx);
-                        }
+                        tab.tree.set_active(pane_id);
                     }
                     if ui
                         .small_button(egui::RichText::new("clear").color(theme::ERROR))
-                        .on_hover_text("clear tab timeline")
+                        .on_hover_text("clear pane timeline")
                         .clicked()
                     {
-                        if let Some(runtime) = self.active_runtime_mut() {
+                        if let Some(runtime) = self.pane_runtime_mut(pane_id) {
                             runtime.session.clear_timeline();
                             runtime.selected_context_block_ids.clear();
                             runtime.block_search_query.clear();
                         }
                         self.status_text = format!(
                             "cleared timeline from pane-{} ({})",
-                            pane_idx + 1,
+                            pane_id + 1,
                             self.tabs.active_label()
                         );
                     }
@@ -2027,12 +3931,244 @@ impl TerminalApp {
                     );
                 });
                 ui.separator();
-                self.render_block_cards(ui);
+                let in_fullscreen = self
+                    .pane_runtime(pane_id)
+                    .is_some_and(|runtime| runtime.emulator.is_alternate_screen());
+                let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+                let cell = grid_view::cell_size(ui, &font_id);
+                let mut available = ui.available_size();
+                available.x -= ui.spacing().scroll.bar_width;
+                if !in_fullscreen {
+                    available.x -= BLOCK_CARD_CHROME_WIDTH;
+                }
+                let (cols, rows) = grid_view::fit_grid(cell, available);
+                let pixels_per_point = ui.ctx().pixels_per_point();
+                if let Some(runtime) = self.pane_runtime_mut(pane_id) {
+                    runtime.emulator.set_cell_pixel_size(
+                        (cell.x * pixels_per_point).round() as u32,
+                        (cell.y * pixels_per_point).round() as u32,
+                    );
+                    runtime.schedule_resize(cols, rows);
+                }
+                self.pane_size = (cols, rows);
+                if in_fullscreen {
+                    self.render_fullscreen_grid(ui, pane_id, is_active);
+                } else {
+                    self.render_block_cards(ui, pane_id);
+                }
+            });
+    }
+
+    fn render_fullscreen_grid(&mut self, ui: &mut egui::Ui, pane_id: PaneId, is_active: bool) {
+        let mut clicked_link = None;
+        let mut copied = None;
+        let mut held_paste = Vec::new();
+        let write_result = {
+            let Some(runtime) = self.pane_runtime_mut(pane_id) else {
+                return;
+            };
+            let emulator = &runtime.emulator;
+            let modes = *emulator.modes();
+            let mouse_reporting = modes.mouse_tracking != MouseTracking::Off;
+            // Held at its pre-update state while a synchronized update is
+            // being drawn.
+            let (grid, cursor) = emulator.presented_screen();
+            let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+
+            let view = egui::ScrollArea::both()
+                .auto_shrink([false, false])
+                .enable_scrolling(!mouse_reporting)
+                .show(ui, |ui| {
+                    GridView::new(grid, &theme::TERMINAL_PALETTE, font_id)
+                        .cursor(cursor)
+                        .selection(runtime.grid_selection)
+                        .show(ui)
+                })
+                .inner;
+            if !is_active {
+                return;
+            }
+
+            let response = &view.response;
+            if response.clicked() || ui.memory(|m| m.focused().is_none()) {
+                response.request_focus();
+            }
+            if response.clicked()
+                && ui.input(|i| i.modifiers.command)
+                && let Some(pos) = response.interact_pointer_pos()
+            {
+                let (col, row) = view.cell_at(pos, grid);
+                clicked_link = grid
+                    .cell(col, row)
+                    .and_then(|cell| cell.link.as_deref())
+                    .map(|uri| (uri.to_owned(), runtime.working_directory()));
+            }
+            let mut events = ui.input(|i| i.events.clone());
+            let mut bytes = Vec::new();
+            if response.has_focus() {
+                held_paste = take_confirmable_paste(&mut events);
+                ui.memory_mut(|m| {
+                    m.set_focus_lock_filter(
+                        response.id,
+                        egui::EventFilter {
+                            tab: true,
+                            horizontal_arrows: true,
+                            vertical_arrows: true,
+                            escape: true,
+                        },
+                    )
+                });
+                bytes.extend(encode_key_events(&events, &modes));
+            }
+
+            let (hover_pos, held_button, current_modifiers) = ui.input(|i| {
+                let held = [
+                    (egui::PointerButton::Primary, MouseButton::Left),
+                    (egui::PointerButton::Middle, MouseButton::Middle),
+                    (egui::PointerButton::Secondary, MouseButton::Right),
+                ]
+                .into_iter()
+                .find(|(button, _)| i.pointer.button_down(*button))
+                .map_or(MouseButton::NoButton, |(_, button)| button);
+                (i.pointer.hover_pos(), held, i.modifiers)
             });
+            for event in &events {
+                // Primary drags select locally unless the application tracks
+                // the mouse; Shift-drag selects regardless.
+                match event {
+                    egui::Event::PointerButton {
+                        pos,
+                        button: egui::PointerButton::Primary,
+                        pressed: true,
+                        modifiers,
+                    } if response.rect.contains(*pos) => {
+                        runtime.grid_selection = None;
+                        if !mouse_reporting || modifiers.shift {
+                            let cell = view.cell_at(*pos, grid);
+                            runtime.grid_selection = Some(GridSelection::new(cell));
+                            runtime.selecting = true;
+                            continue;
+                        }
+                    }
+                    egui::Event::PointerMoved(pos) if runtime.selecting => {
+                        if let Some(selection) = &mut runtime.grid_selection {
+                            selection.head = view.cell_at(*pos, grid);
+                        }
+                        continue;
+                    }
+                    egui::Event::PointerButton {
+                        button: egui::PointerButton::Primary,
+                        pressed: false,
+                        ..
+                    } if runtime.selecting => {
+                        runtime.selecting = false;
+                        copied = runtime
+                            .grid_selection
+                            .map(|selection| selection.text(grid))
+                            .filter(|text| !text.is_empty());
+                        continue;
+                    }
+                    _ => {}
+                }
+                if !mouse_reporting {
+                    continue;
+                }
+
+                let (pos, button, action, modifiers) = match event {
+                    egui::Event::PointerButton {
+                        pos,
+                        button,
+                        pressed,
+                        modifiers,
+                    } => {
+                        let button = match button {
+                            egui::PointerButton::Primary => MouseButton::Left,
+                            egui::PointerButton::Middle => MouseButton::Middle,
+                            egui::PointerButton::Secondary => MouseButton::Right,
+                            _ => continue,
+                        };
+                        let action = if *pressed {
+                            MouseAction::Press
+                        } else {
+                            MouseAction::Release
+                        };
+                        (*pos, button, action, *modifiers)
+                    }
+                    egui::Event::PointerMoved(pos) => {
+                        (*pos, held_button, MouseAction::Motion, current_modifiers)
+                    }
+                    egui::Event::MouseWheel {
+                        delta, modifiers, ..
+                    } => {
+                        let Some(pos) = hover_pos else {
+                            continue;
+                        };
+                        let button = if delta.y > 0.0 {
+                            MouseButton::WheelUp
+                        } else if delta.y < 0.0 {
+                            MouseButton::WheelDown
+                        } else if delta.x > 0.0 {
+                            MouseButton::WheelLeft
+                        } else if delta.x < 0.0 {
+                            MouseButton::WheelRight
+                        } else {
+                            continue;
+                        };
+                        (pos, button, MouseAction::Press, *modifiers)
+                    }
+                    _ => continue,
+                };
+                // Drags keep reporting (clamped) once the pointer leaves the
+                // grid, like xterm.
+                let dragging =
+                    action == MouseAction::Motion && held_button != MouseButton::NoButton;
+                if !response.rect.contains(pos) && !dragging {
+                    continue;
+                }
+                let (col, row) = view.cell_at(pos, grid);
+                if action == MouseAction::Motion && runtime.mouse_cell == Some((col, row)) {
+                    continue;
+                }
+                runtime.mouse_cell = Some((col, row));
+                if let Some(report) = input::encode_mouse(
+                    button,
+                    action,
+                    col,
+                    row,
+                    terminal_modifiers(modifiers),
+                    &modes,
+                ) {
+                    bytes.extend(report);
+                }
+            }
+
+            if bytes.is_empty() {
+                Ok(())
+            } else {
+                runtime.pty.write_bytes(&bytes)
+            }
+        };
+
+        if let Err(err) = write_result {
+            self.status_text = format!("write failed: {err}");
+        }
+        if let Some(text) = copied {
+            self.status_text = format!("copied {} characters", text.chars().count());
+            ui.ctx().copy_text(text);
+        }
+        for text in held_paste {
+            self.hold_paste(text, false);
+        }
+        if let Some((uri, cwd)) = clicked_link {
+            self.open_link(ui.ctx(), &LinkTarget::Url(uri), Path::new(&cwd));
+        }
     }
 
-    fn render_block_cards(&mut self, ui: &mut egui::Ui) {
-        let items = self.filtered_timeline_items();
+    fn render_block_cards(&mut self, ui: &mut egui::Ui, pane_id: PaneId) {
+        let items = self
+            .pane_runtime_mut(pane_id)
+            .map(PaneRuntime::filtered_timeline_items)
+            .unwrap_or_default();
         let start = items.len().saturating_sub(220);
         let mut delete_command_ids = Vec::new();
         let mut delete_ai_ids = Vec::new();
@@ -2053,7 +4189,7 @@ impl TerminalApp {
                 for item in &items[start..] {
                     match item {
                         TimelineItem::Command(block) => {
-                            if self.render_command_block_card(ui, block) {
+                            if self.render_command_block_card(ui, pane_id, block) {
                                 delete_command_ids.push(block.id);
                             }
                         }
@@ -2084,26 +4220,31 @@ impl TerminalApp {
             });
 
         for block_id in delete_command_ids {
-            if let Some(runtime) = self.active_runtime_mut() {
-                if runtime.session.remove_command_block(block_id) {
-                    runtime.selected_context_block_ids.remove(&block_id);
-                    self.status_text = format!("deleted command block #{block_id}");
-                }
+            if let Some(runtime) = self.pane_runtime_mut(pane_id)
+                && runtime.session.remove_command_block(block_id)
+            {
+                runtime.selected_context_block_ids.remove(&block_id);
+                self.status_text = format!("deleted command block #{block_id}");
             }
         }
 
         for ai_block_id in delete_ai_ids {
-            if let Some(runtime) = self.active_runtime_mut() {
-                if runtime.session.remove_ai_block(ai_block_id) {
-                    self.status_text = format!("deleted ai block #{ai_block_id}");
-                }
+            if let Some(runtime) = self.pane_runtime_mut(pane_id)
+                && runtime.session.remove_ai_block(ai_block_id)
+            {
+                self.status_text = format!("deleted ai block #{ai_block_id}");
             }
         }
 
-        self.prune_context_block_selection();
+        self.prune_context_block_selection(pane_id);
     }
 
-    fn render_command_block_card(&mut self, ui: &mut egui::Ui, block: &CommandBlock) -> bool {
+    fn render_command_block_card(
+        &mut self,
+        ui: &mut egui::Ui,
+        pane_id: PaneId,
+        block: &CommandBlock,
+    ) -> bool {
         let is_ctx = self
             .active_runtime()
             .map(|runtime| runtime.selected_context_block_ids.contains(&block.id))
@@ -2129,10 +4270,10 @@ impl TerminalApp {
                     } else {
                         egui::RichText::new("bookmark:off").color(theme::TEXT_MUTED)
                     };
-                    if ui.small_button(bookmark_label).clicked() {
-                        if let Some(runtime) = self.active_runtime_mut() {
-                            let _ = runtime.session.toggle_bookmark(block.id);
-                        }
+                    if ui.small_button(bookmark_label).clicked()
+                        && let Some(runtime) = self.pane_runtime_mut(pane_id)
+                    {
+                        let _ = runtime.session.toggle_bookmark(block.id);
                     }
 
                     let ctx_label = if is_ctx {
@@ -2140,13 +4281,13 @@ impl TerminalApp {
                     } else {
                         egui::RichText::new("context:off").color(theme::TEXT_MUTED)
                     };
-                    if ui.small_button(ctx_label).clicked() {
-                        if let Some(runtime) = self.active_runtime_mut() {
-                            if is_ctx {
-                                runtime.selected_context_block_ids.remove(&block.id);
-                            } else {
-                                runtime.selected_context_block_ids.insert(block.id);
-                            }
+                    if ui.small_button(ctx_label).clicked()
+                        && let Some(runtime) = self.pane_runtime_mut(pane_id)
+                    {
+                        if is_ctx {
+                            runtime.selected_context_block_ids.remove(&block.id);
+                        } else {
+                            runtime.selected_context_block_ids.insert(block.id);
                         }
                     }
 
@@ -2190,22 +4331,86 @@ impl TerminalApp {
                             .monospace()
                             .color(theme::TEXT_MUTED),
                     );
+                    if let Some(exit_code) = block.exit_code {
+                        let exit_color = if exit_code == 0 {
+                            theme::SUCCESS
+                        } else {
+                            theme::ERROR
+                        };
+                        ui.separator();
+                        ui.label(
+                            egui::RichText::new(format!("exit: {exit_code}"))
+                                .monospace()
+                                .color(exit_color),
+                        );
+                    }
+                    if let Some(duration_ms) = block.duration_ms {
+                        ui.separator();
+                        ui.label(
+                            egui::RichText::new(format!("{duration_ms}ms"))
+                                .monospace()
+                                .color(theme::TEXT_MUTED),
+                        );
+                    }
                 });
 
                 ui.separator();
 
-                let total_lines = block.output_lines.len();
+                if let Some(fullscreen) = &block.fullscreen {
+                    let expanded = self.pane_runtime(pane_id).is_some_and(|runtime| {
+                        runtime.expanded_fullscreen_blocks.contains(&block.id)
+                    });
+                    ui.horizontal_wrapped(|ui| {
+                        let toggle_label = if expanded { "collapse" } else { "expand" };
+                        if ui
+                            .small_button(
+                                egui::RichText::new(toggle_label).color(theme::ACCENT_BLUE),
+                            )
+                            .clicked()
+                            && let Some(runtime) = self.pane_runtime_mut(pane_id)
+                            && !runtime.expanded_fullscreen_blocks.remove(&block.id)
+                        {
+                            runtime.expanded_fullscreen_blocks.insert(block.id);
+                        }
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "full-screen session ({}ms, {} lines on last screen)",
+                                fullscreen.duration_ms,
+                                fullscreen.last_screen.len()
+                            ))
+                            .monospace()
+                            .italics()
+                            .color(theme::TEXT_MUTED),
+                        );
+                    });
+                    if expanded {
+                        for line in &fullscreen.last_screen {
+                            self.render_output_line(
+                                ui,
+                                line,
+                                &[],
+                                &[],
+                                Path::new(&block.working_directory),
+                            );
+                        }
+                    }
+                }
+
+                let total_lines = block.output.len();
                 let visible_limit = 220usize;
                 let output_start = total_lines.saturating_sub(visible_limit);
+                let omitted = output_start + block.output.dropped_lines();
 
-                if output_start > 0 {
+                if omitted > 0 {
                     ui.label(
-                        egui::RichText::new(format!("... {} lines omitted", output_start))
+                        egui::RichText::new(format!("... {} lines omitted", omitted))
                             .color(theme::TEXT_MUTED),
                     );
                 }
 
-                if total_lines == 0 {
+                if total_lines == 0 && block.fullscreen.is_some() {
+                    // The collapsed session summary stands in for output.
+                } else if total_lines == 0 {
                     ui.label(
                         egui::RichText::new("(no output yet)")
                             .italics()
@@ -2213,8 +4418,12 @@ impl TerminalApp {
                             .color(theme::TEXT_MUTED),
                     );
                 } else {
-                    for line in &block.output_lines[output_start..] {
-                        self.render_output_line(ui, line);
+                    let cwd = Path::new(&block.working_directory);
+                    for line in block.output.lines(output_start..total_lines) {
+                        self.render_output_line(ui, &line.text, &line.ranges, &line.links, cwd);
+                        if !line.images.is_empty() {
+                            render_output_images(ui, &line.images);
+                        }
                     }
                 }
             });
@@ -2309,8 +4518,9 @@ impl TerminalApp {
                                 .color(theme::TEXT_MUTED),
                         );
                     }
+                    let root = self.workspace_root.clone();
                     for line in &block.output_lines[start..] {
-                        self.render_output_line(ui, line);
+                        self.render_output_line(ui, line, &[], &[], &root);
                     }
                 }
             });
@@ -2318,14 +4528,25 @@ impl TerminalApp {
         delete_requested
     }
 
-    fn render_output_line(&mut self, ui: &mut egui::Ui, line: &str) {
+    fn render_output_line(
+        &mut self,
+        ui: &mut egui::Ui,
+        line: &str,
+        styles: &[StyledRange],
+        links: &[LinkRange],
+        base_dir: &Path,
+    ) {
         let line_color = if line.starts_with("[stderr]") {
             theme::ERROR
         } else {
             theme::TEXT_PRIMARY
         };
+        let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+        let job = theme::TERMINAL_PALETTE.layout_line(line, styles, font_id, line_color);
+        let links = detect_links(line, links);
 
-        if let Some(reference) = parse_first_file_line_ref(line, &self.workspace_root) {
+        let clicked = if let Some(reference) = parse_first_file_line_ref(line, &self.workspace_root)
+        {
             ui.horizontal_wrapped(|ui| {
                 let mut label = format!("open {}:{}", reference.path.display(), reference.line);
                 if let Some(col) = reference.column {
@@ -2346,12 +4567,53 @@ impl TerminalApp {
                         reference.line
                     );
                 }
-                ui.label(egui::RichText::new(line).monospace().color(line_color));
-            });
-            return;
+                LinkLabel::new(job, &links).show(ui).cloned()
+            })
+            .inner
+        } else {
+            LinkLabel::new(job, &links).show(ui).cloned()
+        };
+
+        if let Some(link) = clicked {
+            self.open_link(ui.ctx(), &link.target, base_dir);
         }
+    }
 
-        ui.label(egui::RichText::new(line).monospace().color(line_color));
+    /// Opens a Ctrl+clicked link: URLs go to the system browser, files to an
+    /// editor tab at the referenced line.
+    fn open_link(&mut self, ctx: &egui::Context, target: &LinkTarget, base_dir: &Path) {
+        let (path, line, column) = match target {
+            LinkTarget::Url(uri) => match file_uri_to_path(uri) {
+                Some(path) => (path, None, None),
+                None => {
+                    ctx.open_url(egui::OpenUrl::new_tab(uri));
+                    self.status_text = format!("opened {uri}");
+                    return;
+                }
+            },
+            LinkTarget::Path { path, line, column } => (path.clone(), *line, *column),
+        };
+
+        let resolved = resolve_link_path(&path, base_dir);
+        if !resolved.is_file() {
+            self.status_text = format!("no such file: {}", display_path(&resolved));
+            return;
+        }
+        self.open_file_in_editor_tab(resolved);
+        if let Some(line) = line
+            && let Some(editor) = self.editor_tabs.get_mut(&self.tabs.active_id())
+        {
+            editor.cursor_line = (line as usize)
+                .saturating_sub(1)
+                .min(editor.lines.len().saturating_sub(1));
+            let line_len = editor
+                .lines
+                .get(editor.cursor_line)
+                .map_or(0, |text| text.chars().count());
+            editor.cursor_col = column
+                .map_or(0, |col| (col as usize).saturating_sub(1))
+                .min(line_len);
+        }
     }
 }
 
@@ -2364,28 +4626,70 @@ impl eframe::App for TerminalApp {
         self.apply_ui_density(ctx);
         self.ensure_prompt_mascot_loaded(ctx);
 
-        self.poll_pty_output();
+        self.answer_clipboard_read(ctx);
+        self.poll_pty_output(ctx);
+        self.sync_tmux_gateways();
+        self.sync_tab_titles();
+        self.report_pane_focus(ctx);
+        self.apply_pending_resizes();
         self.poll_ai_events();
         self.poll_config_reload();
         self.persist_session_if_needed();
+        images::release_idle_textures(ctx);
 
-        if ctx.input(|i| i.modifiers.ctrl && i.modifiers.shift && i.key_pressed(egui::Key::P)) {
+        // Consumed so they are not forwarded as Ctrl-keys in interactive or
+        // full-screen mode.
+        let ctrl_shift = egui::Modifiers::CTRL | egui::Modifiers::SHIFT;
+        if ctx.input_mut(|i| i.consume_key(ctrl_shift, egui::Key::P)) {
             self.palette.open();
         }
-        if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::B)) {
-            self.toggle_sidebar_visible();
-        }
-        if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Num1)) {
-            self.set_pane_layout(PaneLayout::Single);
-        }
-        if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Num2)) {
-            self.set_pane_layout(PaneLayout::VerticalSplit);
+        if ctx.input_mut(|i| i.consume_key(ctrl_shift, egui::Key::I)) {
+            let enabled = self
+                .active_runtime()
+                .is_some_and(|runtime| runtime.interactive_input);
+            self.set_interactive_input(!enabled);
         }
-        if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Num3)) {
-            self.set_pane_layout(PaneLayout::HorizontalSplit);
+        if self.active_tab_kind() == AppTabKind::Terminal
+            && ctx.input_mut(|i| i.consume_key(ctrl_shift, egui::Key::W))
+        {
+            self.close_active_pane();
         }
-        if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::W)) {
-            self.close_active_tab_if_possible();
+        // Full-screen and interactive programs get Ctrl-keys themselves.
+        let fullscreen_active = self.active_tab_kind() == AppTabKind::Terminal
+            && self
+                .active_runtime()
+                .is_some_and(|runtime| runtime.emulator.is_alternate_screen());
+        let interactive_active = self.active_tab_kind() == AppTabKind::Terminal
+            && self
+                .active_runtime()
+                .is_some_and(|runtime| runtime.interactive_input);
+        if !fullscreen_active && !interactive_active {
+            if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::B)) {
+                self.toggle_sidebar_visible();
+            }
+            if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Num1)) {
+                self.toggle_pane_zoom();
+            }
+            if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Num2)) {
+                self.split_active_pane(SplitDirection::Vertical);
+            }
+            if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Num3)) {
+                self.split_active_pane(SplitDirection::Horizontal);
+            }
+            let focus_keys = [
+                (egui::Key::ArrowLeft, FocusDirection::Left),
+                (egui::Key::ArrowRight, FocusDirection::Right),
+                (egui::Key::ArrowUp, FocusDirection::Up),
+                (egui::Key::ArrowDown, FocusDirection::Down),
+            ];
+            for (key, direction) in focus_keys {
+                if ctx.input_mut(|i| i.consume_key(egui::Modifiers::ALT, key)) {
+                    self.focus_pane(direction);
+                }
+            }
+            if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::W)) {
+                self.close_active_tab_if_possible();
+            }
         }
 
         self.render_main_menu(ctx);
@@ -2419,24 +4723,38 @@ impl eframe::App for TerminalApp {
                     .inner_margin(egui::Margin::symmetric(10, 4)),
             )
             .show(ctx, |ui| {
-                let (cmd_count, ai_count, bookmark_count, pane_label) =
-                    if let Some(runtime) = self.active_runtime() {
-                        (
-                            runtime.session.block_count(),
-                            runtime.session.ai_block_count(),
-                            runtime.session.bookmarked_count(),
-                            runtime.pane_grid.label(),
-                        )
-                    } else if let Some(editor) = self.active_editor() {
-                        (
-                            0,
-                            0,
-                            0,
-                            format!("editor: {}", editor.mode_label().to_lowercase()),
-                        )
+                let (cmd_count, ai_count, bookmark_count, pane_label) = if let Some(tab) =
+                    self.active_tab_runtime()
+                    && let Some(runtime) = tab.active_pane()
+                {
+                    (
+                        runtime.session.block_count(),
+                        runtime.session.ai_block_count(),
+                        runtime.session.bookmarked_count(),
+                        tab.tree.label(),
+                    )
+                } else if let Some(editor) = self.active_editor() {
+                    (
+                        0,
+                        0,
+                        0,
+                        format!("editor: {}", editor.mode_label().to_lowercase()),
+                    )
+                } else if let Some(replay) = self.replay_tabs.get(&self.tabs.active_id()) {
+                    let state = if replay.player.is_paused() {
+                        "paused"
                     } else {
-                        (0, 0, 0, "panes: 0 (single)".to_owned())
+                        "playing"
                     };
+                    (
+                        0,
+                        0,
+                        0,
+                        format!("replay: {state} {}x", replay.player.speed()),
+                    )
+                } else {
+                    (0, 0, 0, "panes: 0".to_owned())
+                };
 
                 ui.horizontal(|ui| {
                     ui.label(
@@ -2474,6 +4792,28 @@ impl eframe::App for TerminalApp {
                             .monospace()
                             .color(theme::TEXT_MUTED),
                     );
+                    if self.tab_is_recording(self.tabs.active_id()) {
+                        ui.separator();
+                        ui.label(
+                            egui::RichText::new("REC")
+                                .monospace()
+                                .strong()
+                                .color(theme::ERROR),
+                        );
+                    }
+                    if let Some(progress) = self.tabs.active_progress() {
+                        ui.separator();
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::vec2(60.0, ui.spacing().interact_size.y * 0.5),
+                            egui::Sense::hover(),
+                        );
+                        tabs::paint_progress(ui, rect, progress);
+                        ui.label(
+                            egui::RichText::new(progress_label(progress))
+                                .monospace()
+                                .color(theme::TEXT_MUTED),
+                        );
+                    }
                     ui.separator();
                     ui.label(
                         egui::RichText::new(format!(
@@ -2486,12 +4826,12 @@ impl eframe::App for TerminalApp {
                 });
             });
 
-        let input_bar_height = if self.active_tab_kind() == AppTabKind::Editor {
+        let input_bar_height = if self.active_tab_kind() != AppTabKind::Terminal {
             48.0
         } else {
             let input_rows = self
                 .active_runtime()
-                .map(|runtime| runtime.input_buffer.lines().count().max(1).min(4))
+                .map(|runtime| runtime.input_buffer.lines().count().clamp(1, 4))
                 .unwrap_or(1);
             48.0 + (input_rows.saturating_sub(1) as f32) * 18.0
         };
@@ -2505,7 +4845,22 @@ impl eframe::App for TerminalApp {
                     .inner_margin(egui::Margin::symmetric(10, 8)),
             )
             .show(ctx, |ui| {
-                if self.active_tab_kind() == AppTabKind::Editor {
+                if fullscreen_active {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(
+                            egui::RichText::new("FULL-SCREEN")
+                                .monospace()
+                                .strong()
+                                .color(theme::ACCENT_BLUE),
+                        );
+                        ui.separator();
+                        ui.label(
+                            egui::RichText::new("keys and mouse go to the running program")
+                                .monospace()
+                                .color(theme::TEXT_MUTED),
+                        );
+                    });
+                } else if self.active_tab_kind() == AppTabKind::Editor {
                     ui.horizontal_wrapped(|ui| {
                         ui.label(
                             egui::RichText::new("VIM")
@@ -2520,6 +4875,21 @@ impl eframe::App for TerminalApp {
                                 .color(theme::TEXT_MUTED),
                         );
                     });
+                } else if self.active_tab_kind() == AppTabKind::Replay {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(
+                            egui::RichText::new("READ-ONLY")
+                                .monospace()
+                                .strong()
+                                .color(theme::ACCENT_BLUE),
+                        );
+                        ui.separator();
+                        ui.label(
+                            egui::RichText::new("Space play/pause, Left/Right seek 5s")
+                                .monospace()
+                                .color(theme::TEXT_MUTED),
+                        );
+                    });
                 } else {
                     ui.horizontal(|ui| {
                         let time_sec = ui.input(|i| i.time);
@@ -2542,12 +4912,33 @@ impl eframe::App for TerminalApp {
                                 ui.add(egui::Image::new((texture.id(), size)));
                             }
                         }
-                        ui.label(
-                            egui::RichText::new(">")
-                                .monospace()
-                                .strong()
-                                .color(theme::TEXT_MUTED),
-                        );
+                        ui.label(
+                            egui::RichText::new(">")
+                                .monospace()
+                                .strong()
+                                .color(theme::TEXT_MUTED),
+                        );
+
+                        if interactive_active {
+                            self.forward_interactive_keys(ui);
+                            self.focus_terminal_input_next_frame = false;
+                            if ui
+                                .add_sized(
+                                    [84.0, 30.0],
+                                    egui::Button::new(
+                                        egui::RichText::new("line")
+                                            .monospace()
+                                            .strong()
+                                            .color(theme::ACCENT_BLUE),
+                                    ),
+                                )
+                                .on_hover_text("back to line input (Ctrl+Shift+I)")
+                                .clicked()
+                            {
+                                self.set_interactive_input(false);
+                            }
+                            return;
+                        }
 
                         let mut request_history_up = false;
                         let mut request_history_down = false;
@@ -2555,16 +4946,23 @@ impl eframe::App for TerminalApp {
                         let active_tab_id = self.tabs.active_id();
                         let request_focus_input = self.focus_terminal_input_next_frame;
                         let mut consumed_focus_request = false;
+                        let input_id = egui::Id::new("terminal-input").with(active_tab_id);
+                        // Taken before the editor inserts them.
+                        let held_paste = if ui.memory(|m| m.has_focus(input_id)) {
+                            ui.input_mut(|i| take_confirmable_paste(&mut i.events))
+                        } else {
+                            Vec::new()
+                        };
 
                         if let Some(runtime) = self.active_runtime_mut() {
-                            let input_rows = runtime.input_buffer.lines().count().max(1).min(4);
+                            let input_rows = runtime.input_buffer.lines().count().clamp(1, 4);
                             let input_width = (ui.available_width() - 92.0).max(120.0);
                             let input_height = 30.0 + (input_rows.saturating_sub(1) as f32) * 18.0;
                             let response = ui.add_sized(
                                 [input_width, input_height],
                                 egui::TextEdit::multiline(&mut runtime.input_buffer)
                                     .desired_rows(1)
-                                    .id_salt(egui::Id::new("terminal-input").with(active_tab_id))
+                                    .id(input_id)
                                     .hint_text("Enter: run | Shift+Enter: newline"),
                             );
                             if request_focus_input {
@@ -2618,6 +5016,9 @@ impl eframe::App for TerminalApp {
                         if consumed_focus_request {
                             self.focus_terminal_input_next_frame = false;
                         }
+                        for text in held_paste {
+                            self.hold_paste(text, true);
+                        }
 
                         if request_history_up {
                             self.navigate_history_up();
@@ -2661,6 +5062,10 @@ impl eframe::App for TerminalApp {
                     self.render_editor_view(ui, ctx);
                     return;
                 }
+                if self.active_tab_kind() == AppTabKind::Replay {
+                    self.render_replay_view(ui, ctx);
+                    return;
+                }
 
                 ui.vertical(|ui| {
                     theme::toolbar_frame().show(ui, |ui| {
@@ -2709,7 +5114,9 @@ impl eframe::App for TerminalApp {
                                 }
                             }
 
-                            let match_count = self.filtered_timeline_items().len();
+                            let match_count = self
+                                .active_runtime_mut()
+                                .map_or(0, |runtime| runtime.filtered_timeline_items().len());
                             ui.separator();
                             ui.label(
                                 egui::RichText::new(format!("matches: {match_count}"))
@@ -2719,14 +5126,14 @@ impl eframe::App for TerminalApp {
                         });
                     });
 
-                    if let Some(runtime) = self.active_runtime() {
-                        if !runtime.export_message.is_empty() {
-                            ui.label(
-                                egui::RichText::new(&runtime.export_message)
-                                    .monospace()
-                                    .color(theme::ACCENT_BLUE),
-                            );
-                        }
+                    if let Some(runtime) = self.active_runtime()
+                        && !runtime.export_message.is_empty()
+                    {
+                        ui.label(
+                            egui::RichText::new(&runtime.export_message)
+                                .monospace()
+                                .color(theme::ACCENT_BLUE),
+                        );
                     }
 
                     let (selected_context_count, running_jobs, ai_status_line) =
@@ -2765,6 +5172,10 @@ impl eframe::App for TerminalApp {
             self.apply_palette_action(action);
         }
 
+        self.show_clipboard_prompt(ctx);
+        self.show_tmux_prompt(ctx);
+        self.show_paste_confirmation(ctx);
+
         if self.restore_picker_open {
             let mut open = self.restore_picker_open;
             let mut selected_restore: Option<PathBuf> = None;
@@ -2835,6 +5246,72 @@ impl eframe::App for TerminalApp {
             self.restore_picker_open = open;
         }
 
+        if self.replay_picker_open {
+            let mut open = self.replay_picker_open;
+            let mut selected_replay: Option<PathBuf> = None;
+            egui::Window::new("Replay Recording")
+                .collapsible(false)
+                .resizable(true)
+                .default_size([640.0, 360.0])
+                .frame(theme::panel_frame())
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("refresh").clicked() {
+                            self.refresh_recording_entries();
+                        }
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{}: {}",
+                                display_path(&self.config.recording.directory()),
+                                self.recording_entries.len()
+                            ))
+                            .monospace()
+                            .color(theme::TEXT_MUTED),
+                        );
+                    });
+                    ui.separator();
+
+                    if self.recording_entries.is_empty() {
+                        ui.label(
+                            egui::RichText::new("(no recordings yet)")
+                                .monospace()
+                                .italics()
+                                .color(theme::TEXT_MUTED),
+                        );
+                    } else {
+                        egui::ScrollArea::vertical()
+                            .auto_shrink([false, false])
+                            .show(ui, |ui| {
+                                for path in &self.recording_entries {
+                                    ui.horizontal_wrapped(|ui| {
+                                        if ui
+                                            .small_button(
+                                                egui::RichText::new("play")
+                                                    .color(theme::ACCENT_BLUE),
+                                            )
+                                            .clicked()
+                                        {
+                                            selected_replay = Some(path.clone());
+                                        }
+                                        ui.label(
+                                            egui::RichText::new(display_path(path))
+                                                .monospace()
+                                                .color(theme::TEXT_PRIMARY),
+                                        );
+                                    });
+                                }
+                            });
+                    }
+                });
+
+            if let Some(path) = selected_replay {
+                self.open_replay_tab(path);
+                open = false;
+            }
+            self.replay_picker_open = open;
+        }
+
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
             self.palette.close();
         }
@@ -3391,13 +5868,17 @@ fn fnv1a64(bytes: &[u8]) -> u64 {
 fn run_ai_command(
     command: ResolvedAiCommand,
     prompt: Option<String>,
-    tab_id: u64,
-    ai_block_id: u64,
+    target: AiRunTarget,
     timeout_sec: u64,
     workspace_root: PathBuf,
     codex_resume_session_id: Option<String>,
     tx: Sender<AiRunEvent>,
 ) {
+    let AiRunTarget {
+        tab_id,
+        pane_id,
+        ai_block_id,
+    } = target;
     let started = Instant::now();
     let program = command.program;
     let args = command.args;
@@ -3405,6 +5886,7 @@ fn run_ai_command(
     if program.trim().is_empty() {
         let _ = tx.send(AiRunEvent::Failed {
             tab_id,
+            pane_id,
             ai_block_id,
             message: "AI command program is empty in config".to_owned(),
             duration_ms: 0,
@@ -3486,6 +5968,7 @@ fn run_ai_command(
             };
             let _ = tx.send(AiRunEvent::Failed {
                 tab_id,
+                pane_id,
                 ai_block_id,
                 message,
                 duration_ms: started.elapsed().as_millis().min(u64::MAX as u128) as u64,
@@ -3500,6 +5983,7 @@ fn run_ai_command(
                 if let Err(err) = stdin.write_all(payload.as_bytes()) {
                     let _ = tx.send(AiRunEvent::Failed {
                         tab_id,
+                        pane_id,
                         ai_block_id,
                         message: format!("failed writing prompt to AI stdin: {err}"),
                         duration_ms: started.elapsed().as_millis().min(u64::MAX as u128) as u64,
@@ -3513,6 +5997,7 @@ fn run_ai_command(
             None => {
                 let _ = tx.send(AiRunEvent::Failed {
                     tab_id,
+                    pane_id,
                     ai_block_id,
                     message: "failed launching AI process with stdin prompt".to_owned(),
                     duration_ms: started.elapsed().as_millis().min(u64::MAX as u128) as u64,
@@ -3531,8 +6016,7 @@ fn run_ai_command(
     let stdout_handle = child.stdout.take().map(|stdout| {
         spawn_stream_reader(
             stdout,
-            tab_id,
-            ai_block_id,
+            target,
             tx.clone(),
             had_output.clone(),
             program.clone(),
@@ -3542,8 +6026,7 @@ fn run_ai_command(
     let stderr_handle = child.stderr.take().map(|stderr| {
         spawn_stream_reader(
             stderr,
-            tab_id,
-            ai_block_id,
+            target,
             tx.clone(),
             had_output.clone(),
             program.clone(),
@@ -3574,6 +6057,7 @@ fn run_ai_command(
             Err(err) => {
                 let _ = tx.send(AiRunEvent::Failed {
                     tab_id,
+                    pane_id,
                     ai_block_id,
                     message: format!("failed to poll AI process status: {err}"),
                     duration_ms: started.elapsed().as_millis().min(u64::MAX as u128) as u64,
@@ -3593,6 +6077,7 @@ fn run_ai_command(
     if !had_output.load(Ordering::Relaxed) {
         let _ = tx.send(AiRunEvent::OutputChunk {
             tab_id,
+            pane_id,
             ai_block_id,
             lines: vec!["(no output from AI CLI)".to_owned()],
         });
@@ -3602,6 +6087,7 @@ fn run_ai_command(
     if timed_out {
         let _ = tx.send(AiRunEvent::Failed {
             tab_id,
+            pane_id,
             ai_block_id,
             message: format!("AI command timed out after {}s", timeout.as_secs()),
             duration_ms: elapsed,
@@ -3612,6 +6098,7 @@ fn run_ai_command(
     if exit_code == 0 {
         let _ = tx.send(AiRunEvent::Completed {
             tab_id,
+            pane_id,
             ai_block_id,
             exit_code,
             duration_ms: elapsed,
@@ -3619,6 +6106,7 @@ fn run_ai_command(
     } else {
         let _ = tx.send(AiRunEvent::Failed {
             tab_id,
+            pane_id,
             ai_block_id,
             message: format!("{launched_program} exited with code {exit_code}"),
             duration_ms: elapsed,
@@ -3628,13 +6116,17 @@ fn run_ai_command(
 
 fn spawn_stream_reader<R: Read + Send + 'static>(
     pipe: R,
-    tab_id: u64,
-    ai_block_id: u64,
+    target: AiRunTarget,
     tx: Sender<AiRunEvent>,
     had_output: Arc<AtomicBool>,
     program: String,
     is_stderr: bool,
 ) -> thread::JoinHandle<()> {
+    let AiRunTarget {
+        tab_id,
+        pane_id,
+        ai_block_id,
+    } = target;
     thread::spawn(move || {
         let mut reader = BufReader::new(pipe);
         let mut line = String::new();
@@ -3656,7 +6148,11 @@ fn spawn_stream_reader<R: Read + Send + 'static>(
             }
 
             if let Some(session_id) = extract_codex_session_id(&program, &text) {
-                let _ = tx.send(AiRunEvent::CodexSessionUpdated { tab_id, session_id });
+                let _ = tx.send(AiRunEvent::CodexSessionUpdated {
+                    tab_id,
+                    pane_id,
+                    session_id,
+                });
             }
 
             if is_stderr && !should_emit_ai_stderr_line(&program, &text) {
@@ -3672,6 +6168,7 @@ fn spawn_stream_reader<R: Read + Send + 'static>(
             if tx
                 .send(AiRunEvent::OutputChunk {
                     tab_id,
+                    pane_id,
                     ai_block_id,
                     lines: vec![payload],
                 })
@@ -3781,7 +6278,11 @@ fn load_workspace_snapshot_from_disk(path: &PathBuf) -> Result<Option<WorkspaceS
             tabs: vec![SavedTabSnapshot {
                 tab_id: 0,
                 tab_label: "main".to_owned(),
+                label_pinned: false,
                 session: legacy,
+                pane_tree: None,
+                pane_sessions: Vec::new(),
+                mux_session: None,
             }],
         }));
     }
@@ -3802,6 +6303,32 @@ fn save_workspace_snapshot_to_disk(path: &PathBuf, snapshot: &WorkspaceSnapshot)
     Ok(())
 }
 
+/// `.cast` files in `dir`, newest first.
+fn list_recordings(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut recordings = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file()
+            && path.extension().and_then(|ext| ext.to_str()) == Some(asciicast::FILE_EXTENSION)
+        {
+            let modified = fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .unwrap_or(UNIX_EPOCH);
+            recordings.push((modified, path));
+        }
+    }
+    recordings.sort_by(|a, b| b.cmp(a));
+    Ok(recordings.into_iter().map(|(_, path)| path).collect())
+}
+
+fn format_replay_time(secs: f64) -> String {
+    let tenths = (secs.max(0.0) * 10.0).round() as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
 fn list_saved_workspace_entries(
     autosave_path: &PathBuf,
     snapshot_dir: &PathBuf,
@@ -3873,7 +6400,7 @@ fn list_saved_workspace_entries(
     Ok(entries)
 }
 
-fn parse_first_file_line_ref(line: &str, workspace_root: &PathBuf) -> Option<FileLineRef> {
+fn parse_first_file_line_ref(line: &str, workspace_root: &Path) -> Option<FileLineRef> {
     for token in line.split_whitespace() {
         let trimmed = token.trim_matches(|c: char| {
             matches!(
@@ -3929,6 +6456,21 @@ fn parse_first_file_line_ref(line: &str, workspace_root: &PathBuf) -> Option<Fil
     None
 }
 
+/// Resolves a path token from terminal output against the directory the
+/// output was produced in, expanding a leading `~`.
+fn resolve_link_path(path: &str, base_dir: &Path) -> PathBuf {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    let expanded = match (path.strip_prefix("~/").or(path.strip_prefix("~\\")), home) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    };
+    if expanded.is_absolute() {
+        expanded
+    } else {
+        base_dir.join(expanded)
+    }
+}
+
 fn build_editor_open_command(reference: &FileLineRef) -> String {
     match reference.column {
         Some(col) => format!(
@@ -3953,8 +6495,8 @@ fn build_command_block_copy_text(block: &CommandBlock) -> String {
     out.push_str(&format!("Timestamp(ms): {}\n", block.timestamp_unix_ms));
     out.push_str(&format!("Bookmarked: {}\n", block.bookmarked));
     out.push_str("Output:\n");
-    for line in &block.output_lines {
-        out.push_str(line);
+    for line in block.output_text() {
+        out.push_str(&line);
         out.push('\n');
     }
     out
@@ -3976,10 +6518,233 @@ fn build_ai_block_copy_text(block: &AiBlock) -> String {
     out
 }
 
-fn sanitize_shell_output_lines(lines: Vec<String>, command: &str) -> Vec<String> {
+fn terminal_modifiers(modifiers: egui::Modifiers) -> input::Modifiers {
+    input::Modifiers {
+        shift: modifiers.shift,
+        alt: modifiers.alt,
+        ctrl: modifiers.ctrl,
+    }
+}
+
+fn terminal_key(key: egui::Key) -> Option<input::Key> {
+    use egui::Key as K;
+
+    let mapped = match key {
+        K::ArrowUp => input::Key::Up,
+        K::ArrowDown => input::Key::Down,
+        K::ArrowLeft => input::Key::Left,
+        K::ArrowRight => input::Key::Right,
+        K::Enter => input::Key::Enter,
+        K::Tab => input::Key::Tab,
+        K::Backspace => input::Key::Backspace,
+        K::Escape => input::Key::Escape,
+        K::Home => input::Key::Home,
+        K::End => input::Key::End,
+        K::PageUp => input::Key::PageUp,
+        K::PageDown => input::Key::PageDown,
+        K::Insert => input::Key::Insert,
+        K::Delete => input::Key::Delete,
+        K::Space => input::Key::Char(' '),
+        K::F1 => input::Key::F(1),
+        K::F2 => input::Key::F(2),
+        K::F3 => input::Key::F(3),
+        K::F4 => input::Key::F(4),
+        K::F5 => input::Key::F(5),
+        K::F6 => input::Key::F(6),
+        K::F7 => input::Key::F(7),
+        K::F8 => input::Key::F(8),
+        K::F9 => input::Key::F(9),
+        K::F10 => input::Key::F(10),
+        K::F11 => input::Key::F(11),
+        K::F12 => input::Key::F(12),
+        _ => {
+            let mut chars = key.symbol_or_name().chars();
+            let ch = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            input::Key::Char(ch.to_ascii_lowercase())
+        }
+    };
+    Some(mapped)
+}
+
+/// Removes pastes that need confirmation from `events`, returning them in
+/// order.
+fn take_confirmable_paste(events: &mut Vec<egui::Event>) -> Vec<String> {
+    let mut held = Vec::new();
+    events.retain(|event| match event {
+        egui::Event::Paste(text) if paste_needs_confirmation(text) => {
+            held.push(text.clone());
+            false
+        }
+        _ => true,
+    });
+    held
+}
+
+// Several lines would run one after another; a single trailing newline is
+// ignored since copying a whole line usually includes it.
+fn paste_needs_confirmation(text: &str) -> bool {
+    let body = text
+        .strip_suffix('\n')
+        .map(|rest| rest.strip_suffix('\r').unwrap_or(rest))
+        .unwrap_or(text);
+    body.contains(['\n', '\r']) || is_dangerous_command(body)
+}
+
+/// Whether a command line runs `sudo` or a recursive forced `rm`.
+fn is_dangerous_command(line: &str) -> bool {
+    line.split([';', '&', '|']).any(|command| {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        if words.contains(&"sudo") {
+            return true;
+        }
+        let Some(rm) = words
+            .iter()
+            .position(|word| *word == "rm" || word.ends_with("/rm"))
+        else {
+            return false;
+        };
+        let (mut recursive, mut force) = (false, false);
+        for flag in words[rm + 1..].iter().filter(|word| word.starts_with('-')) {
+            match *flag {
+                "--recursive" => recursive = true,
+                "--force" => force = true,
+                flag if !flag.starts_with("--") => {
+                    recursive |= flag.contains(['r', 'R']);
+                    force |= flag.contains('f');
+                }
+                _ => {}
+            }
+        }
+        recursive && force
+    })
+}
+
+/// Turns egui keyboard events into xterm input bytes. Printable keys are taken from `Text` events unless Ctrl or Alt is held.
+fn encode_key_events(events: &[egui::Event], modes: &TerminalModes) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut skip_next_text = false;
+    for event in events {
+        match event {
+            egui::Event::Text(text) if !skip_next_text => out.extend_from_slice(text.as_bytes()),
+            egui::Event::Text(_) => skip_next_text = false,
+            egui::Event::Paste(text) => out.extend(input::encode_paste(text, modes)),
+            // egui turns Ctrl+C and Ctrl+X into clipboard events.
+            egui::Event::Copy | egui::Event::Cut => {
+                let ch = if matches!(event, egui::Event::Copy) {
+                    'c'
+                } else {
+                    'x'
+                };
+                let ctrl = input::Modifiers {
+                    ctrl: true,
+                    ..input::Modifiers::default()
+                };
+                out.extend(
+                    input::encode_key(input::Key::Char(ch), ctrl, modes).unwrap_or_default(),
+                );
+            }
+            egui::Event::Key {
+                key,
+                pressed,
+                repeat,
+                modifiers,
+                ..
+            } => {
+                if *pressed {
+                    skip_next_text = false;
+                }
+                let Some(key) = terminal_key(*key) else {
+                    continue;
+                };
+                let kind = match (*pressed, *repeat) {
+                    (false, _) => input::KeyEventKind::Release,
+                    (true, true) => input::KeyEventKind::Repeat,
+                    (true, false) => input::KeyEventKind::Press,
+                };
+                // Plain characters arrive as text unless the application asked
+                // for every key as an escape code.
+                let is_char = matches!(key, input::Key::Char(_));
+                let report_all = modes.keyboard_flags & input::KITTY_REPORT_ALL_KEYS != 0;
+                if is_char && !modifiers.ctrl && !modifiers.alt && !report_all {
+                    continue;
+                }
+                let mods = terminal_modifiers(*modifiers);
+                if let Some(bytes) = input::encode_key_event(key, mods, kind, modes) {
+                    out.extend(bytes);
+                    skip_next_text |= is_char && *pressed;
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Reserves the cells covered by a line's inline images below its text and
+/// paints them there.
+fn render_output_images(ui: &mut egui::Ui, anchors: &[ImageAnchor]) {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let cell = grid_view::cell_size(ui, &font_id);
+    let size = anchors.iter().fold(egui::Vec2::ZERO, |size, anchor| {
+        let area = images::placement_size(&anchor.placement, cell);
+        size.max(egui::vec2(anchor.column as f32 * cell.x + area.x, area.y))
+    });
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    if !ui.is_rect_visible(rect) {
+        return;
+    }
+    for anchor in anchors {
+        let origin = rect.min + egui::vec2(anchor.column as f32 * cell.x, 0.0);
+        images::paint_placement(ui.painter(), &anchor.placement, origin, cell);
+    }
+}
+
+// tmux puts any number of cells side by side or stacked; nested halves keep
+// each cell's share of the space.
+fn pane_node_from_layout(layout: &Layout) -> PaneNode {
+    match &layout.cells {
+        LayoutCells::Pane(pane) => PaneNode::Leaf(PaneId::from(*pane)),
+        LayoutCells::Columns(cells) => split_layout_cells(cells, SplitDirection::Vertical),
+        LayoutCells::Rows(cells) => split_layout_cells(cells, SplitDirection::Horizontal),
+    }
+}
+
+fn split_layout_cells(cells: &[Layout], direction: SplitDirection) -> PaneNode {
+    let extent = |cell: &Layout| match direction {
+        SplitDirection::Vertical => f32::from(cell.cols),
+        SplitDirection::Horizontal => f32::from(cell.rows),
+    };
+    match cells {
+        [] => PaneNode::Leaf(0),
+        [cell] => pane_node_from_layout(cell),
+        [first, rest @ ..] => {
+            let total = cells.iter().map(extent).sum::<f32>().max(1.0);
+            PaneNode::Split {
+                direction,
+                ratio: extent(first) / total,
+                first: Box::new(pane_node_from_layout(first)),
+                second: Box::new(split_layout_cells(rest, direction)),
+            }
+        }
+    }
+}
+
+fn sanitize_shell_output_lines(lines: Vec<StyledLine>, command: &str) -> Vec<StyledLine> {
     lines
         .into_iter()
-        .filter_map(|line| sanitize_shell_output_line(&line, command))
+        .filter_map(|mut line| {
+            // Lines carrying inline images are kept even without text.
+            let kept = match sanitize_shell_output_line(&line.text, command) {
+                Some(kept) => kept.len(),
+                None if !line.images.is_empty() => 0,
+                None => return None,
+            };
+            line.truncate(kept);
+            Some(line)
+        })
         .collect()
 }
 
@@ -4006,17 +6771,17 @@ fn normalize_multiline_powershell_command(command: &str) -> String {
         }
 
         let leading_trimmed = line.trim_start();
-        if let Some(rest) = leading_trimmed.strip_prefix('|') {
-            if let Some(previous) = normalized.last_mut() {
-                if !previous.trim_end().ends_with('|') {
-                    previous.push_str(" |");
-                }
-                let tail = rest.trim_start();
-                if !tail.is_empty() {
-                    normalized.push(tail.to_owned());
-                }
-                continue;
+        if let Some(rest) = leading_trimmed.strip_prefix('|')
+            && let Some(previous) = normalized.last_mut()
+        {
+            if !previous.trim_end().ends_with('|') {
+                previous.push_str(" |");
+            }
+            let tail = rest.trim_start();
+            if !tail.is_empty() {
+                normalized.push(tail.to_owned());
             }
+            continue;
         }
 
         normalized.push(line.to_owned());
@@ -4089,6 +6854,15 @@ fn sanitize_pending_shell_line(line: &str) -> Option<String> {
     Some(line.to_owned())
 }
 
+fn progress_label(progress: Progress) -> String {
+    match progress {
+        Progress::Normal(percent) => format!("progress: {percent}%"),
+        Progress::Error(percent) => format!("progress: error {percent}%"),
+        Progress::Paused(percent) => format!("progress: paused {percent}%"),
+        Progress::Indeterminate => "progress: busy".to_owned(),
+    }
+}
+
 fn should_hide_pending_line(line: &str) -> bool {
     sanitize_pending_shell_line(line).is_none()
 }
@@ -4100,8 +6874,8 @@ fn extract_powershell_prompt_tail(line: &str) -> Option<&str> {
         return None;
     }
 
-    if bytes[0].to_ascii_lowercase() != b'p'
-        || bytes[1].to_ascii_lowercase() != b's'
+    if !bytes[0].eq_ignore_ascii_case(&b'p')
+        || !bytes[1].eq_ignore_ascii_case(&b's')
         || bytes[2] != b' '
     {
         return None;
@@ -4120,8 +6894,8 @@ fn strip_trailing_powershell_prompt(line: &str) -> &str {
     }
 
     for start in (0..=bytes.len() - 3).rev() {
-        if bytes[start].to_ascii_lowercase() != b'p'
-            || bytes[start + 1].to_ascii_lowercase() != b's'
+        if !bytes[start].eq_ignore_ascii_case(&b'p')
+            || !bytes[start + 1].eq_ignore_ascii_case(&b's')
             || bytes[start + 2] != b' '
         {
             continue;
@@ -4186,22 +6960,62 @@ fn looks_like_windows_path(value: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::{
-        SavedTabSnapshot, SessionState, WorkspaceSnapshot, ai_install_hint,
-        build_ai_block_copy_text, build_ai_launch_attempts, build_command_block_copy_text,
-        build_editor_open_command, build_tab_scoped_claude_session_id,
+        ReplayTabState, SavedPaneSession, SavedTabSnapshot, SessionState, WorkspaceSnapshot,
+        ai_install_hint, build_ai_block_copy_text, build_ai_launch_attempts,
+        build_command_block_copy_text, build_editor_open_command,
+        build_tab_scoped_claude_session_id, encode_key_events,
         ensure_claude_tab_scoped_session_args, ensure_codex_tab_scoped_resume_args,
-        extract_codex_session_id, load_workspace_snapshot_from_disk,
-        normalize_multiline_powershell_command, normalize_windows_shell_newlines,
-        parse_first_file_line_ref, prepare_ai_prompt_transport, sanitize_pending_shell_line,
-        sanitize_shell_output_lines, save_workspace_snapshot_to_disk, should_emit_ai_stderr_line,
-        should_hide_pending_line, trim_single_trailing_newline,
+        extract_codex_session_id, format_replay_time, list_recordings,
+        load_workspace_snapshot_from_disk, normalize_multiline_powershell_command,
+        normalize_windows_shell_newlines, pane_node_from_layout, parse_first_file_line_ref,
+        prepare_ai_prompt_transport, sanitize_pending_shell_line, sanitize_shell_output_lines,
+        save_workspace_snapshot_to_disk, should_emit_ai_stderr_line, should_hide_pending_line,
+        take_confirmable_paste, trim_single_trailing_newline,
     };
+    use crate::panes::{PaneTree, SplitDirection};
+    use eframe::egui;
     use std::fs;
     use std::path::PathBuf;
     use std::time::{SystemTime, UNIX_EPOCH};
+    use terminal_core::emulator::TerminalModes;
+    use terminal_core::input;
+    use terminal_core::style::{CellAttrs, Color, StyledLine};
+    use terminal_core::tmux::Layout;
     use ux_model::ai::{AiBlock, AiTool};
     use ux_model::blocks::CommandBlock;
 
+    #[cfg(unix)]
+    #[test]
+    fn mux_replay_waits_for_the_pane_to_be_laid_out() {
+        use super::{DEFAULT_PANE_SIZE, PaneRuntime};
+        use terminal_core::pty::{PtySession, ShellProfile};
+        use terminal_core::scrollback::ScrollbackConfig;
+
+        let profile = ShellProfile {
+            shell_integration: false,
+            ..ShellProfile::new("/bin/sh")
+        };
+        let (cols, rows) = DEFAULT_PANE_SIZE;
+        let pty = PtySession::spawn(&profile, cols, rows).expect("spawn /bin/sh");
+        let mut pane = PaneRuntime::from_pty(
+            pty,
+            SessionState::new(".".to_owned()),
+            &ScrollbackConfig::default(),
+            DEFAULT_PANE_SIZE,
+        );
+        pane.pending_replay = b"\x1b[1;100HX".to_vec();
+        pane.schedule_resize(40, 10);
+        assert!(pane.emulator.grid().row(0).text().trim().is_empty());
+
+        // The first layout applies without waiting out the debounce.
+        pane.apply_pending_resize().expect("resize");
+        assert_eq!(pane.pty_size, (40, 10));
+        let row = pane.emulator.grid().row(0).text();
+        assert_eq!(row.trim_end().chars().count(), 40);
+        assert!(row.trim_end().ends_with('X'));
+        pane.pty.terminate();
+    }
+
     #[test]
     fn session_snapshot_file_roundtrip() {
         let nonce = SystemTime::now()
@@ -4214,14 +7028,40 @@ mod tests {
         let mut session = SessionState::new("D:\\repo".to_owned());
         session.start_command_block("echo hello".to_owned(), "D:\\repo".to_owned());
         session.push_output_lines(vec!["hello".to_owned()]);
+        let mut styled = StyledLine::plain("ok ");
+        styled.push(
+            'X',
+            CellAttrs {
+                fg: Color::Rgb(1, 2, 3),
+                bg: Color::Indexed(200),
+                bold: true,
+                ..CellAttrs::default()
+            },
+        );
+        session.push_styled_output_lines(vec![styled]);
+
+        let mut tree = PaneTree::default();
+        let right = tree.split_active(SplitDirection::Vertical);
+        tree.split_active(SplitDirection::Horizontal);
+        assert!(tree.set_active(right));
+        let mut side = SessionState::new("D:\\other".to_owned());
+        side.start_command_block("ls".to_owned(), "D:\\other".to_owned());
 
         let snapshot = WorkspaceSnapshot {
-            format_version: 1,
+            format_version: 2,
             active_tab_id: 0,
             tabs: vec![SavedTabSnapshot {
                 tab_id: 0,
                 tab_label: "main".to_owned(),
+                label_pinned: false,
                 session: session.to_snapshot(),
+                pane_tree: Some(tree.clone()),
+                pane_sessions: vec![SavedPaneSession {
+                    pane_id: 0,
+                    session: side.to_snapshot(),
+                    mux_session: Some(7 << 32 | 2),
+                }],
+                mux_session: Some(7 << 32 | 1),
             }],
         };
 
@@ -4233,8 +7073,30 @@ mod tests {
             .expect("session snapshot load should not fail")
             .expect("session snapshot should exist");
         assert_eq!(restored.tabs.len(), 1);
+        let restored_tree = restored.tabs[0]
+            .pane_tree
+            .clone()
+            .expect("pane tree should be saved");
+        assert_eq!(restored_tree.pane_ids(), tree.pane_ids());
+        assert_eq!(restored_tree.active(), right);
+        assert_eq!(restored.tabs[0].pane_sessions.len(), 1);
+        assert_eq!(
+            restored.tabs[0].pane_sessions[0].session.blocks[0].command,
+            "ls"
+        );
+        assert_eq!(restored.tabs[0].mux_session, Some(7 << 32 | 1));
+        assert_eq!(
+            restored.tabs[0].pane_sessions[0].mux_session,
+            Some(7 << 32 | 2)
+        );
         let restored_session = SessionState::from_snapshot(restored.tabs[0].session.clone());
         assert_eq!(restored_session.block_count(), session.block_count());
+        let restored_block = &restored_session.blocks()[0];
+        assert!(restored_block.line_styles(0).is_empty());
+        assert_eq!(
+            restored_block.line_styles(1),
+            session.blocks()[0].line_styles(1)
+        );
 
         let _ = fs::remove_file(&path);
         let _ = fs::remove_dir_all(&base);
@@ -4272,6 +7134,39 @@ mod tests {
         let _ = fs::remove_dir_all(&base);
     }
 
+    #[test]
+    fn recordings_are_listed_and_open_in_replay_tabs() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let base = std::env::temp_dir().join(format!("myterminal-c-casts-{nonce}"));
+        fs::create_dir_all(&base).expect("recording dir should be created");
+        let cast = base.join("build.cast");
+        fs::write(
+            &cast,
+            "{\"version\": 2, \"width\": 20, \"height\": 4}\n[0.5, \"o\", \"make\\r\\n\"]\n[75.25, \"o\", \"done\"]\n",
+        )
+        .expect("cast should be written");
+        fs::write(base.join("notes.txt"), "not a recording").expect("note should be written");
+
+        assert_eq!(
+            list_recordings(&base).expect("recordings should list"),
+            vec![cast.clone()]
+        );
+        let mut replay = ReplayTabState::open(&cast).expect("cast should open");
+        assert_eq!(replay.label(), "replay: build.cast");
+        replay.player.seek(1.0);
+        assert_eq!(
+            replay.player.emulator().grid().row(0).text().trim_end(),
+            "make"
+        );
+        assert_eq!(format_replay_time(replay.player.duration()), "1:15.3");
+        assert_eq!(format_replay_time(0.04), "0:00.0");
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
     #[test]
     fn file_line_reference_parser_detects_relative_path() {
         let nonce = SystemTime::now()
@@ -4301,7 +7196,7 @@ mod tests {
     #[test]
     fn copy_builders_include_key_fields() {
         let mut cmd = CommandBlock::new(10, "cargo check".to_owned(), "D:\\repo".to_owned());
-        cmd.output_lines = vec!["ok".to_owned()];
+        cmd.append_output(&["ok".to_owned()]);
         cmd.bookmarked = true;
         let copied_cmd = build_command_block_copy_text(&cmd);
         assert!(copied_cmd.contains("Command Block #10"));
@@ -4327,7 +7222,11 @@ mod tests {
             "PS C:\\Users\\ldgyu>".to_owned(),
         ];
 
-        let cleaned = sanitize_shell_output_lines(lines, "ls");
+        let lines = lines.into_iter().map(StyledLine::plain).collect();
+        let cleaned: Vec<String> = sanitize_shell_output_lines(lines, "ls")
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
         assert_eq!(
             cleaned,
             vec!["Mode   LastWriteTime".to_owned(), "d----- test".to_owned()]
@@ -4336,9 +7235,20 @@ mod tests {
 
     #[test]
     fn shell_sanitizer_strips_trailing_prompt_suffix() {
-        let lines = vec!["build completePS D:\\MyTerminal-c>".to_owned()];
-        let cleaned = sanitize_shell_output_lines(lines, "cargo build");
-        assert_eq!(cleaned, vec!["build complete".to_owned()]);
+        let mut line = StyledLine::default();
+        for ch in "build completePS D:\\MyTerminal-c>".chars() {
+            line.push(
+                ch,
+                CellAttrs {
+                    fg: Color::Indexed(2),
+                    ..CellAttrs::default()
+                },
+            );
+        }
+        let cleaned = sanitize_shell_output_lines(vec![line], "cargo build");
+        assert_eq!(cleaned.len(), 1);
+        assert_eq!(cleaned[0].text, "build complete");
+        assert_eq!(cleaned[0].ranges[0].end, "build complete".len());
     }
 
     #[test]
@@ -4358,6 +7268,137 @@ mod tests {
         assert_eq!(sanitize_pending_shell_line("PS D:\\MyTerminal-c>"), None);
     }
 
+    #[test]
+    fn key_events_are_encoded_without_duplicate_text() {
+        let key = |key, modifiers| egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        };
+        let events = vec![
+            key(egui::Key::J, egui::Modifiers::NONE),
+            egui::Event::Text("j".to_owned()),
+            key(egui::Key::X, egui::Modifiers::ALT),
+            egui::Event::Text("x".to_owned()),
+            key(egui::Key::ArrowUp, egui::Modifiers::NONE),
+            key(egui::Key::F2, egui::Modifiers::NONE),
+            egui::Event::Copy,
+        ];
+        let modes = TerminalModes {
+            application_cursor_keys: true,
+            ..TerminalModes::default()
+        };
+
+        assert_eq!(
+            encode_key_events(&events, &modes),
+            b"j\x1bx\x1bOA\x1bOQ\x03".to_vec()
+        );
+    }
+
+    #[test]
+    fn multi_line_and_dangerous_pastes_are_held_back() {
+        let mut events = vec![
+            egui::Event::Paste("git status\n".to_owned()),
+            egui::Event::Paste("make\nmake install\n".to_owned()),
+            egui::Event::Paste("cd /tmp && rm -r -f build".to_owned()),
+            egui::Event::Paste("sudo apt upgrade".to_owned()),
+            egui::Event::Paste("rm -r build".to_owned()),
+        ];
+        assert_eq!(
+            take_confirmable_paste(&mut events),
+            vec![
+                "make\nmake install\n".to_owned(),
+                "cd /tmp && rm -r -f build".to_owned(),
+                "sudo apt upgrade".to_owned(),
+            ]
+        );
+        assert_eq!(
+            events,
+            vec![
+                egui::Event::Paste("git status\n".to_owned()),
+                egui::Event::Paste("rm -r build".to_owned()),
+            ]
+        );
+
+        let modes = TerminalModes {
+            bracketed_paste: true,
+            ..TerminalModes::default()
+        };
+        assert_eq!(
+            encode_key_events(&events[..1], &modes),
+            b"\x1b[200~git status\r\x1b[201~".to_vec()
+        );
+    }
+
+    #[test]
+    fn interactive_control_keys_reach_the_shell() {
+        let key = |key, modifiers| egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        };
+        let events = vec![
+            key(egui::Key::D, egui::Modifiers::CTRL),
+            key(egui::Key::Z, egui::Modifiers::CTRL),
+            key(egui::Key::Home, egui::Modifiers::NONE),
+            key(egui::Key::F5, egui::Modifiers::SHIFT),
+            egui::Event::Text("y".to_owned()),
+        ];
+
+        assert_eq!(
+            encode_key_events(&events, &TerminalModes::default()),
+            b"\x04\x1a\x1b[H\x1b[15;2~y".to_vec()
+        );
+    }
+
+    #[test]
+    fn kitty_report_all_keys_encodes_text_and_releases() {
+        let key = |key, pressed, modifiers| egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed,
+            repeat: false,
+            modifiers,
+        };
+        let events = vec![
+            key(egui::Key::J, true, egui::Modifiers::NONE),
+            egui::Event::Text("j".to_owned()),
+            key(egui::Key::J, false, egui::Modifiers::NONE),
+            egui::Event::Copy,
+        ];
+        let modes = TerminalModes {
+            keyboard_flags: input::KITTY_DISAMBIGUATE
+                | input::KITTY_REPORT_EVENTS
+                | input::KITTY_REPORT_ALL_KEYS,
+            ..TerminalModes::default()
+        };
+
+        assert_eq!(
+            encode_key_events(&events, &modes),
+            b"\x1b[106u\x1b[106;1:3u\x1b[99;5u".to_vec()
+        );
+    }
+
+    #[test]
+    fn tmux_layouts_become_pane_splits() {
+        let layout =
+            Layout::parse("5e2a,80x24,0,0{40x24,0,0,4,39x24,41,0[39x12,41,0,5,39x11,41,13,6]}")
+                .expect("layout should parse");
+        let tree = PaneTree::with_root(pane_node_from_layout(&layout), 6);
+        assert_eq!(tree.pane_ids(), vec![4, 5, 6]);
+        assert_eq!(tree.active(), 6);
+
+        let rect = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(790.0, 240.0));
+        let panes = tree.layout(rect, 0.0).panes;
+        assert_eq!(panes[0].1.width(), 400.0);
+        assert_eq!(panes[1].1.min.x, 400.0);
+        assert!(panes[1].1.height() > panes[2].1.height());
+    }
+
     #[test]
     fn codex_launch_attempts_include_npx_fallback() {
         let args = vec!["exec".to_owned(), "hello".to_owned()];
@@ -4398,7 +7439,7 @@ mod tests {
         let prompt = "hello";
         let args = vec!["--print".to_owned(), prompt.to_owned()];
         let (new_args, stdin_payload, routed) =
-            prepare_ai_prompt_transport("claude", &args, Some(&prompt));
+            prepare_ai_prompt_transport("claude", &args, Some(prompt));
         assert!(routed);
         assert_eq!(new_args, vec!["--print".to_owned()]);
         assert_eq!(stdin_payload.as_deref(), Some(prompt));
@@ -4409,7 +7450,7 @@ mod tests {
         let prompt = "hello";
         let args = vec!["exec".to_owned(), prompt.to_owned()];
         let (new_args, stdin_payload, routed) =
-            prepare_ai_prompt_transport("codex", &args, Some(&prompt));
+            prepare_ai_prompt_transport("codex", &args, Some(prompt));
         assert!(routed);
         assert_eq!(new_args, vec!["exec".to_owned(), "-".to_owned()]);
         assert_eq!(stdin_payload.as_deref(), Some(prompt));
@@ -0,0 +1,294 @@
+use std::time::{Duration, Instant};
+
+use crate::config::{BellConfig, BellStyle};
+
+// Length of the visual bell; BELs inside it ring only once.
+pub const BELL_FLASH: Duration = Duration::from_millis(200);
+// Desktop notifications a pane may raise: one per interval, each cut to a
+// readable length.
+const NOTIFICATION_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_TITLE_CHARS: usize = 128;
+const MAX_BODY_CHARS: usize = 1024;
+
+/// Delivers desktop notifications and bell sounds on behalf of terminal
+/// programs.
+pub trait NotificationBackend {
+    fn notify(&self, title: &str, body: &str);
+
+    fn bell(&self);
+}
+
+/// Drops everything; used where no desktop service is available.
+#[derive(Debug, Default)]
+pub struct NoopBackend;
+
+impl NotificationBackend for NoopBackend {
+    fn notify(&self, _title: &str, _body: &str) {}
+
+    fn bell(&self) {}
+}
+
+/// When a pane last rang the bell and last raised a notification.
+#[derive(Debug, Default)]
+pub struct PaneAlerts {
+    pub bell_at: Option<Instant>,
+    notified_at: Option<Instant>,
+}
+
+impl PaneAlerts {
+    /// Rings the bell the way `config` asks. Returns whether the pane's tab
+    /// should be marked for attention.
+    pub fn ring(
+        &mut self,
+        now: Instant,
+        config: &BellConfig,
+        backend: &dyn NotificationBackend,
+    ) -> bool {
+        if self
+            .bell_at
+            .is_some_and(|rang_at| now.duration_since(rang_at) < BELL_FLASH)
+        {
+            return false;
+        }
+        self.bell_at = Some(now);
+        if config.style == BellStyle::Sound {
+            backend.bell();
+        }
+        config.tab_badge
+    }
+
+    /// Sends a notification unless the pane sent one within the last
+    /// interval. Returns the title and body as sent.
+    pub fn notify(
+        &mut self,
+        now: Instant,
+        title: &str,
+        body: &str,
+        backend: &dyn NotificationBackend,
+    ) -> Option<(String, String)> {
+        if self
+            .notified_at
+            .is_some_and(|notified_at| now.duration_since(notified_at) < NOTIFICATION_INTERVAL)
+        {
+            return None;
+        }
+        self.notified_at = Some(now);
+        let title = truncate_chars(title, MAX_TITLE_CHARS);
+        let body = truncate_chars(body, MAX_BODY_CHARS);
+        backend.notify(&title, &body);
+        Some((title, body))
+    }
+}
+
+/// `text` cut to at most `max_chars` characters, ending in an ellipsis when
+/// anything was removed.
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().nth(max_chars).is_none() {
+        return text.to_owned();
+    }
+    let mut out: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// The backend for the current platform.
+pub fn system_backend() -> Box<dyn NotificationBackend> {
+    #[cfg(target_os = "linux")]
+    {
+        Box::new(dbus::DbusBackend::spawn())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Box::new(NoopBackend)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub mod dbus {
+    use std::collections::HashMap;
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    use crossbeam_channel::{Receiver, Sender};
+    use zbus::blocking::Connection;
+    use zbus::zvariant::Value;
+
+    use super::NotificationBackend;
+
+    const APP_NAME: &str = "CtyTerm";
+    // Requests beyond this, while the bus is slow, are dropped.
+    const QUEUE_LEN: usize = 16;
+
+    enum Request {
+        Notify { title: String, body: String },
+        Bell,
+    }
+
+    /// Sends `org.freedesktop.Notifications.Notify` calls on the session bus
+    /// from a worker thread, so a slow or missing bus never stalls a frame.
+    /// The bell sound is played with `canberra-gtk-play` when installed.
+    pub struct DbusBackend {
+        requests: Sender<Request>,
+    }
+
+    impl DbusBackend {
+        pub fn spawn() -> Self {
+            let (requests, receiver) = crossbeam_channel::bounded(QUEUE_LEN);
+            // Without a worker the requests are dropped along with the channel.
+            let _ = thread::Builder::new()
+                .name("desktop-notify".to_owned())
+                .spawn(move || run(receiver));
+            Self { requests }
+        }
+    }
+
+    impl NotificationBackend for DbusBackend {
+        fn notify(&self, title: &str, body: &str) {
+            let _ = self.requests.try_send(Request::Notify {
+                title: title.to_owned(),
+                body: body.to_owned(),
+            });
+        }
+
+        fn bell(&self) {
+            let _ = self.requests.try_send(Request::Bell);
+        }
+    }
+
+    fn run(receiver: Receiver<Request>) {
+        // Connected on first use; a session without a bus only loses
+        // notifications.
+        let mut connection = None;
+        for request in receiver {
+            match request {
+                Request::Notify { title, body } => {
+                    if connection.is_none() {
+                        connection = Connection::session().ok();
+                    }
+                    if let Some(connection) = &connection {
+                        let _ = notify(connection, &title, &body);
+                    }
+                }
+                Request::Bell => {
+                    let _ = Command::new("canberra-gtk-play")
+                        .args(["--id", "bell"])
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .status();
+                }
+            }
+        }
+    }
+
+    fn notify(connection: &Connection, title: &str, body: &str) -> zbus::Result<()> {
+        let actions: Vec<&str> = Vec::new();
+        let hints: HashMap<&str, Value> = HashMap::new();
+        connection.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                APP_NAME,
+                0u32,
+                "utilities-terminal",
+                title,
+                body,
+                actions,
+                hints,
+                -1i32,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::time::{Duration, Instant};
+
+    use super::{BELL_FLASH, NotificationBackend, PaneAlerts, truncate_chars};
+    use crate::config::{BellConfig, BellStyle};
+
+    #[derive(Default)]
+    struct RecordingBackend {
+        notes: RefCell<Vec<(String, String)>>,
+        bells: Cell<usize>,
+    }
+
+    impl NotificationBackend for RecordingBackend {
+        fn notify(&self, title: &str, body: &str) {
+            self.notes
+                .borrow_mut()
+                .push((title.to_owned(), body.to_owned()));
+        }
+
+        fn bell(&self) {
+            self.bells.set(self.bells.get() + 1);
+        }
+    }
+
+    #[test]
+    fn bells_follow_the_style_and_ring_once_per_flash() {
+        let backend = RecordingBackend::default();
+        let mut alerts = PaneAlerts::default();
+        let now = Instant::now();
+        let sound = BellConfig {
+            style: BellStyle::Sound,
+            tab_badge: true,
+        };
+        assert!(alerts.ring(now, &sound, &backend));
+        assert!(!alerts.ring(now + BELL_FLASH / 2, &sound, &backend));
+        assert_eq!(backend.bells.get(), 1);
+        assert!(alerts.ring(now + BELL_FLASH, &sound, &backend));
+        assert_eq!(backend.bells.get(), 2);
+
+        let visual = BellConfig {
+            style: BellStyle::Visual,
+            tab_badge: false,
+        };
+        let mut alerts = PaneAlerts::default();
+        assert!(!alerts.ring(now, &visual, &backend));
+        assert_eq!(alerts.bell_at, Some(now));
+        assert_eq!(backend.bells.get(), 2);
+    }
+
+    #[test]
+    fn notifications_are_rate_limited_and_cut_to_length() {
+        let backend = RecordingBackend::default();
+        let mut alerts = PaneAlerts::default();
+        let now = Instant::now();
+        let long_title = "t".repeat(500);
+        let long_body = "b".repeat(5000);
+        let (title, body) = alerts
+            .notify(now, &long_title, &long_body, &backend)
+            .expect("first notification is sent");
+        assert_eq!(title.chars().count(), 128);
+        assert_eq!(body.chars().count(), 1024);
+
+        assert!(
+            alerts
+                .notify(now + Duration::from_secs(1), "again", "", &backend)
+                .is_none()
+        );
+        assert!(
+            alerts
+                .notify(now + Duration::from_secs(2), "later", "done", &backend)
+                .is_some()
+        );
+        let notes = backend.notes.borrow();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[1], ("later".to_owned(), "done".to_owned()));
+    }
+
+    #[test]
+    fn notification_text_is_cut_to_a_readable_length() {
+        assert_eq!(truncate_chars("build done", 10), "build done");
+        assert_eq!(truncate_chars("빌드가 끝났습니다", 4), "빌드가…");
+        assert_eq!(
+            truncate_chars(&"x".repeat(5000), 1024).chars().count(),
+            1024
+        );
+    }
+}
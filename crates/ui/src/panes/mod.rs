@@ -1,64 +1,442 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+pub type PaneId = u64;
+
+const MIN_SPLIT_RATIO: f32 = 0.1;
+
+/// `Vertical` places the two halves side by side, `Horizontal` stacks them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SplitDirection {
+    Vertical,
+    Horizontal,
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum PaneLayout {
-    Single,
-    VerticalSplit,
-    HorizontalSplit,
+pub enum FocusDirection {
+    Left,
+    Right,
+    Up,
+    Down,
 }
 
-#[derive(Debug, Clone)]
-pub struct PaneGridState {
-    layout: PaneLayout,
-    active_pane: usize,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PaneNode {
+    Leaf(PaneId),
+    Split {
+        direction: SplitDirection,
+        ratio: f32,
+        first: Box<PaneNode>,
+        second: Box<PaneNode>,
+    },
 }
 
-impl Default for PaneGridState {
+impl PaneNode {
+    fn collect_leaves(&self, out: &mut Vec<PaneId>) {
+        match self {
+            Self::Leaf(id) => out.push(*id),
+            Self::Split { first, second, .. } => {
+                first.collect_leaves(out);
+                second.collect_leaves(out);
+            }
+        }
+    }
+
+    fn first_leaf(&self) -> PaneId {
+        match self {
+            Self::Leaf(id) => *id,
+            Self::Split { first, .. } => first.first_leaf(),
+        }
+    }
+
+    fn replace_leaf(&mut self, target: PaneId, replacement: &mut Option<PaneNode>) -> bool {
+        match self {
+            Self::Leaf(id) if *id == target => {
+                if let Some(node) = replacement.take() {
+                    *self = node;
+                }
+                true
+            }
+            Self::Leaf(_) => false,
+            Self::Split { first, second, .. } => {
+                first.replace_leaf(target, replacement) || second.replace_leaf(target, replacement)
+            }
+        }
+    }
+
+    // Removes `target`, letting its sibling take the parent split's place.
+    // Returns the leaf that should receive focus.
+    fn remove_leaf(&mut self, target: PaneId) -> Option<PaneId> {
+        let Self::Split { first, second, .. } = self else {
+            return None;
+        };
+        let survivor = if **first == Self::Leaf(target) {
+            Some(std::mem::replace(second.as_mut(), Self::Leaf(target)))
+        } else if **second == Self::Leaf(target) {
+            Some(std::mem::replace(first.as_mut(), Self::Leaf(target)))
+        } else {
+            None
+        };
+        if let Some(survivor) = survivor {
+            let focus = survivor.first_leaf();
+            *self = survivor;
+            return Some(focus);
+        }
+        first
+            .remove_leaf(target)
+            .or_else(|| second.remove_leaf(target))
+    }
+
+    fn layout(&self, rect: egui::Rect, gap: f32, split_index: &mut usize, out: &mut PaneLayout) {
+        match self {
+            Self::Leaf(id) => out.panes.push((*id, rect)),
+            Self::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => {
+                let index = *split_index;
+                *split_index += 1;
+                let (first_rect, divider, second_rect) = split_rect(rect, *direction, *ratio, gap);
+                out.dividers.push(PaneDivider {
+                    split_index: index,
+                    direction: *direction,
+                    bounds: rect,
+                    rect: divider,
+                });
+                first.layout(first_rect, gap, split_index, out);
+                second.layout(second_rect, gap, split_index, out);
+            }
+        }
+    }
+
+    fn set_ratio(&mut self, target: usize, split_index: &mut usize, value: f32) -> bool {
+        let Self::Split {
+            ratio,
+            first,
+            second,
+            ..
+        } = self
+        else {
+            return false;
+        };
+        if *split_index == target {
+            *ratio = value.clamp(MIN_SPLIT_RATIO, 1.0 - MIN_SPLIT_RATIO);
+            return true;
+        }
+        *split_index += 1;
+        first.set_ratio(target, split_index, value) || second.set_ratio(target, split_index, value)
+    }
+}
+
+fn split_rect(
+    rect: egui::Rect,
+    direction: SplitDirection,
+    ratio: f32,
+    gap: f32,
+) -> (egui::Rect, egui::Rect, egui::Rect) {
+    match direction {
+        SplitDirection::Vertical => {
+            let first_width = ((rect.width() - gap) * ratio).max(0.0);
+            let divider_left = rect.min.x + first_width;
+            (
+                egui::Rect::from_min_max(rect.min, egui::pos2(divider_left, rect.max.y)),
+                egui::Rect::from_min_max(
+                    egui::pos2(divider_left, rect.min.y),
+                    egui::pos2(divider_left + gap, rect.max.y),
+                ),
+                egui::Rect::from_min_max(egui::pos2(divider_left + gap, rect.min.y), rect.max),
+            )
+        }
+        SplitDirection::Horizontal => {
+            let first_height = ((rect.height() - gap) * ratio).max(0.0);
+            let divider_top = rect.min.y + first_height;
+            (
+                egui::Rect::from_min_max(rect.min, egui::pos2(rect.max.x, divider_top)),
+                egui::Rect::from_min_max(
+                    egui::pos2(rect.min.x, divider_top),
+                    egui::pos2(rect.max.x, divider_top + gap),
+                ),
+                egui::Rect::from_min_max(egui::pos2(rect.min.x, divider_top + gap), rect.max),
+            )
+        }
+    }
+}
+
+/// Draggable gap between the two halves of a split. `bounds` is the area the
+/// split divides, used to turn a pointer position back into a ratio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaneDivider {
+    pub split_index: usize,
+    pub direction: SplitDirection,
+    pub bounds: egui::Rect,
+    pub rect: egui::Rect,
+}
+
+impl PaneDivider {
+    pub fn ratio_at(&self, pos: egui::Pos2) -> f32 {
+        match self.direction {
+            SplitDirection::Vertical => (pos.x - self.bounds.min.x) / self.bounds.width().max(1.0),
+            SplitDirection::Horizontal => {
+                (pos.y - self.bounds.min.y) / self.bounds.height().max(1.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PaneLayout {
+    pub panes: Vec<(PaneId, egui::Rect)>,
+    pub dividers: Vec<PaneDivider>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaneTree {
+    root: PaneNode,
+    active: PaneId,
+    #[serde(default)]
+    zoomed: Option<PaneId>,
+    next_id: PaneId,
+}
+
+impl Default for PaneTree {
     fn default() -> Self {
         Self {
-            layout: PaneLayout::Single,
-            active_pane: 0,
+            root: PaneNode::Leaf(0),
+            active: 0,
+            zoomed: None,
+            next_id: 1,
         }
     }
 }
 
-impl PaneGridState {
-    pub fn set_single(&mut self) {
-        self.layout = PaneLayout::Single;
-        self.active_pane = 0;
+impl PaneTree {
+    /// A tree with the given splits, as when mirroring a layout made
+    /// elsewhere.
+    pub fn with_root(root: PaneNode, active: PaneId) -> Self {
+        let mut tree = Self {
+            root,
+            active,
+            zoomed: None,
+            next_id: 0,
+        };
+        tree.normalize();
+        tree
     }
 
-    pub fn split_vertical(&mut self) {
-        self.layout = PaneLayout::VerticalSplit;
+    /// Repairs a tree loaded from disk so the active pane and id counter are
+    /// consistent with its leaves.
+    pub fn normalize(&mut self) {
+        let ids = self.pane_ids();
+        if !ids.contains(&self.active) {
+            self.active = ids[0];
+        }
+        if self.zoomed.is_some_and(|id| !ids.contains(&id)) {
+            self.zoomed = None;
+        }
+        let max_id = ids.iter().copied().max().unwrap_or(0);
+        self.next_id = self.next_id.max(max_id + 1);
+    }
+
+    pub fn pane_ids(&self) -> Vec<PaneId> {
+        let mut ids = Vec::new();
+        self.root.collect_leaves(&mut ids);
+        ids
     }
 
-    pub fn split_horizontal(&mut self) {
-        self.layout = PaneLayout::HorizontalSplit;
+    pub fn pane_count(&self) -> usize {
+        self.pane_ids().len()
     }
 
-    pub fn layout(&self) -> PaneLayout {
-        self.layout
+    pub fn contains(&self, pane_id: PaneId) -> bool {
+        self.pane_ids().contains(&pane_id)
     }
 
-    pub fn pane_count(&self) -> usize {
-        match self.layout {
-            PaneLayout::Single => 1,
-            PaneLayout::VerticalSplit | PaneLayout::HorizontalSplit => 2,
+    pub fn active(&self) -> PaneId {
+        self.active
+    }
+
+    pub fn set_active(&mut self, pane_id: PaneId) -> bool {
+        if !self.contains(pane_id) {
+            return false;
+        }
+        self.active = pane_id;
+        if self.zoomed.is_some() {
+            self.zoomed = Some(pane_id);
         }
+        true
     }
 
-    pub fn active_pane(&self) -> usize {
-        self.active_pane
+    pub fn zoomed(&self) -> Option<PaneId> {
+        self.zoomed
     }
 
-    pub fn set_active_pane(&mut self, pane_idx: usize) {
-        self.active_pane = pane_idx.min(self.pane_count().saturating_sub(1));
+    /// Toggles zoom on the active pane and returns whether it is now zoomed.
+    pub fn toggle_zoom(&mut self) -> bool {
+        self.zoomed = match self.zoomed {
+            Some(_) => None,
+            None if self.pane_count() > 1 => Some(self.active),
+            None => None,
+        };
+        self.zoomed.is_some()
     }
 
-    pub fn label(&self) -> String {
-        let layout = match self.layout {
-            PaneLayout::Single => "single",
-            PaneLayout::VerticalSplit => "vertical",
-            PaneLayout::HorizontalSplit => "horizontal",
+    /// Splits the active pane, focusing and returning the new pane.
+    pub fn split_active(&mut self, direction: SplitDirection) -> PaneId {
+        let new_id = self.next_id;
+        self.next_id += 1;
+        let mut replacement = Some(PaneNode::Split {
+            direction,
+            ratio: 0.5,
+            first: Box::new(PaneNode::Leaf(self.active)),
+            second: Box::new(PaneNode::Leaf(new_id)),
+        });
+        self.root.replace_leaf(self.active, &mut replacement);
+        self.active = new_id;
+        self.zoomed = None;
+        new_id
+    }
+
+    /// Removes a pane. The last remaining pane cannot be closed.
+    pub fn close(&mut self, pane_id: PaneId) -> bool {
+        let Some(focus) = self.root.remove_leaf(pane_id) else {
+            return false;
+        };
+        if self.active == pane_id {
+            self.active = focus;
+        }
+        if self.zoomed.is_some() {
+            self.zoomed = None;
+        }
+        true
+    }
+
+    pub fn layout(&self, rect: egui::Rect, gap: f32) -> PaneLayout {
+        let mut out = PaneLayout::default();
+        if let Some(zoomed) = self.zoomed {
+            out.panes.push((zoomed, rect));
+            return out;
+        }
+        self.root.layout(rect, gap, &mut 0, &mut out);
+        out
+    }
+
+    pub fn set_ratio(&mut self, split_index: usize, ratio: f32) -> bool {
+        self.root.set_ratio(split_index, &mut 0, ratio)
+    }
+
+    /// Moves focus to the nearest pane in `direction`, judged on the unzoomed
+    /// layout.
+    pub fn focus(&mut self, direction: FocusDirection) -> bool {
+        let unit = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(1.0, 1.0));
+        let mut layout = PaneLayout::default();
+        self.root.layout(unit, 0.0, &mut 0, &mut layout);
+        let Some(current) = layout
+            .panes
+            .iter()
+            .find(|(id, _)| *id == self.active)
+            .map(|(_, rect)| *rect)
+        else {
+            return false;
         };
-        format!("panes: {} ({layout})", self.pane_count())
+
+        const EPS: f32 = 1e-4;
+        let best = layout
+            .panes
+            .iter()
+            .filter(|(id, _)| *id != self.active)
+            .filter_map(|(id, rect)| {
+                let (distance, overlap) = match direction {
+                    FocusDirection::Left => (
+                        current.min.x - rect.max.x,
+                        current.y_range().intersection(rect.y_range()).span(),
+                    ),
+                    FocusDirection::Right => (
+                        rect.min.x - current.max.x,
+                        current.y_range().intersection(rect.y_range()).span(),
+                    ),
+                    FocusDirection::Up => (
+                        current.min.y - rect.max.y,
+                        current.x_range().intersection(rect.x_range()).span(),
+                    ),
+                    FocusDirection::Down => (
+                        rect.min.y - current.max.y,
+                        current.x_range().intersection(rect.x_range()).span(),
+                    ),
+                };
+                (distance > -EPS && overlap > EPS).then_some((*id, distance, overlap))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(b.2.total_cmp(&a.2)));
+
+        match best {
+            Some((id, _, _)) => self.set_active(id),
+            None => false,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let count = self.pane_count();
+        if self.zoomed.is_some() {
+            format!("panes: {count} (zoomed)")
+        } else {
+            format!("panes: {count}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FocusDirection, PaneTree, SplitDirection};
+    use eframe::egui;
+
+    #[test]
+    fn split_and_close_keep_tree_consistent() {
+        let mut tree = PaneTree::default();
+        let right = tree.split_active(SplitDirection::Vertical);
+        let bottom_right = tree.split_active(SplitDirection::Horizontal);
+        assert_eq!(tree.pane_ids(), vec![0, right, bottom_right]);
+        assert_eq!(tree.active(), bottom_right);
+
+        assert!(tree.close(bottom_right));
+        assert_eq!(tree.pane_ids(), vec![0, right]);
+        assert_eq!(tree.active(), right);
+
+        assert!(tree.close(0));
+        assert!(!tree.close(right));
+        assert_eq!(tree.pane_ids(), vec![right]);
+    }
+
+    #[test]
+    fn focus_moves_to_adjacent_pane() {
+        let mut tree = PaneTree::default();
+        let right = tree.split_active(SplitDirection::Vertical);
+        let bottom_right = tree.split_active(SplitDirection::Horizontal);
+
+        assert!(tree.focus(FocusDirection::Left));
+        assert_eq!(tree.active(), 0);
+        assert!(!tree.focus(FocusDirection::Left));
+        assert!(tree.focus(FocusDirection::Right));
+        assert!(tree.active() == right || tree.active() == bottom_right);
+        assert!(tree.set_active(right));
+        assert!(tree.focus(FocusDirection::Down));
+        assert_eq!(tree.active(), bottom_right);
+    }
+
+    #[test]
+    fn layout_respects_ratio_and_zoom() {
+        let mut tree = PaneTree::default();
+        let right = tree.split_active(SplitDirection::Vertical);
+        assert!(tree.set_ratio(0, 0.25));
+        let rect = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(404.0, 100.0));
+        let layout = tree.layout(rect, 4.0);
+        assert_eq!(layout.panes[0].1.width(), 100.0);
+        assert_eq!(layout.panes[1].1.min.x, 104.0);
+        assert_eq!(layout.dividers.len(), 1);
+
+        assert!(tree.toggle_zoom());
+        let layout = tree.layout(rect, 4.0);
+        assert_eq!(layout.panes, vec![(right, rect)]);
+        assert!(layout.dividers.is_empty());
     }
 }
@@ -115,11 +115,10 @@ impl SidebarState {
             };
 
             if is_dir {
-                if depth < budget.max_depth {
-                    if let Ok(child_nodes) = Self::scan_directory(&path, depth + 1, budget, entries)
-                    {
-                        node.children = child_nodes;
-                    }
+                if depth < budget.max_depth
+                    && let Ok(child_nodes) = Self::scan_directory(&path, depth + 1, budget, entries)
+                {
+                    node.children = child_nodes;
                 }
             } else if path.is_file() && entries.len() < budget.max_files {
                 entries.push(path.clone());
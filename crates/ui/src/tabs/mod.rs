@@ -1,17 +1,43 @@
 use eframe::egui;
+use terminal_core::emulator::Progress;
 
 use crate::theme;
 
+const PROGRESS_BAR_HEIGHT: f32 = 2.0;
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum TabAction {
     AddTab,
     CloseTab(u64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct TabEntry {
     id: u64,
     label: String,
+    // A label the user typed; shell titles no longer replace it.
+    pinned: bool,
+    title: Option<String>,
+    progress: Option<Progress>,
+    // A pane rang the bell while the tab was in the background.
+    attention: bool,
+}
+
+impl TabEntry {
+    fn new(id: u64, label: String) -> Self {
+        Self {
+            id,
+            label,
+            ..Self::default()
+        }
+    }
+
+    fn display_label(&self) -> &str {
+        match &self.title {
+            Some(title) if !self.pinned => title,
+            _ => &self.label,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -21,19 +47,18 @@ pub struct TabState {
     next_id: u64,
     dragging_tab_id: Option<u64>,
     scroll_to_active: bool,
+    renaming: Option<(u64, String)>,
 }
 
 impl Default for TabState {
     fn default() -> Self {
         Self {
-            tabs: vec![TabEntry {
-                id: 0,
-                label: "main".to_owned(),
-            }],
+            tabs: vec![TabEntry::new(0, "main".to_owned())],
             active_idx: 0,
             next_id: 1,
             dragging_tab_id: None,
             scroll_to_active: true,
+            renaming: None,
         }
     }
 }
@@ -65,18 +90,25 @@ impl TabState {
                                 for (idx, tab) in tabs_snapshot.iter().enumerate() {
                                     let selected = idx == self.active_idx;
                                     let label = if selected {
-                                        format!("{} *", tab.label)
+                                        format!("{} *", tab.display_label())
+                                    } else if tab.attention {
+                                        format!("\u{2022} {}", tab.display_label())
                                     } else {
-                                        tab.label.clone()
+                                        tab.display_label().to_owned()
                                     };
 
                                     ui.horizontal(|ui| {
+                                        if self.show_rename_field(ui, tab.id) {
+                                            return;
+                                        }
                                         let tab_response = ui
                                             .add(
                                                 egui::Button::new(
                                                     egui::RichText::new(label).monospace().color(
                                                         if selected {
                                                             theme::TEXT_BRIGHT
+                                                        } else if tab.attention {
+                                                            theme::WARNING
                                                         } else {
                                                             theme::TEXT_MUTED
                                                         },
@@ -86,10 +118,33 @@ impl TabState {
                                             )
                                             .on_hover_cursor(egui::CursorIcon::Default);
                                         tab_hit_rects.push((tab.id, tab_response.rect));
+                                        if let Some(progress) = tab.progress {
+                                            paint_progress(ui, tab_response.rect, progress);
+                                        }
+                                        if tab_response.double_clicked() {
+                                            self.renaming =
+                                                Some((tab.id, tab.display_label().to_owned()));
+                                        }
+                                        tab_response.context_menu(|ui| {
+                                            if ui.button("Rename Tab").clicked() {
+                                                self.renaming =
+                                                    Some((tab.id, tab.display_label().to_owned()));
+                                                ui.close_menu();
+                                            }
+                                            if ui
+                                                .add_enabled(
+                                                    tab.pinned,
+                                                    egui::Button::new("Use Shell Title"),
+                                                )
+                                                .clicked()
+                                            {
+                                                self.unpin_tab_label(tab.id);
+                                                ui.close_menu();
+                                            }
+                                        });
 
                                         if tab_response.clicked() {
-                                            self.active_idx = idx;
-                                            self.scroll_to_active = true;
+                                            self.activate(idx);
                                         }
                                         if tab_response.drag_started() {
                                             self.dragging_tab_id = Some(tab.id);
@@ -143,10 +198,11 @@ impl TabState {
                 } else if let Some((first_tab_id, first_rect)) = tab_hit_rects.first() {
                     if pointer_pos.x < first_rect.left() && dragging_tab_id != *first_tab_id {
                         move_request = Some((dragging_tab_id, *first_tab_id, false));
-                    } else if let Some((last_tab_id, last_rect)) = tab_hit_rects.last() {
-                        if pointer_pos.x > last_rect.right() && dragging_tab_id != *last_tab_id {
-                            move_request = Some((dragging_tab_id, *last_tab_id, true));
-                        }
+                    } else if let Some((last_tab_id, last_rect)) = tab_hit_rects.last()
+                        && pointer_pos.x > last_rect.right()
+                        && dragging_tab_id != *last_tab_id
+                    {
+                        move_request = Some((dragging_tab_id, *last_tab_id, true));
                     }
                 }
             }
@@ -160,12 +216,39 @@ impl TabState {
         action
     }
 
+    // Inline editor shown in place of the tab button while renaming. Enter
+    // pins the typed label; an empty label goes back to the shell title.
+    fn show_rename_field(&mut self, ui: &mut egui::Ui, tab_id: u64) -> bool {
+        let Some((renaming_id, buffer)) = &mut self.renaming else {
+            return false;
+        };
+        if *renaming_id != tab_id {
+            return false;
+        }
+        let response = ui.add(
+            egui::TextEdit::singleline(buffer)
+                .font(egui::TextStyle::Monospace)
+                .desired_width(140.0),
+        );
+        response.request_focus();
+        if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.renaming = None;
+        } else if response.lost_focus() || ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            let label = buffer.trim().to_owned();
+            self.renaming = None;
+            if label.is_empty() {
+                self.unpin_tab_label(tab_id);
+            } else {
+                self.pin_tab_label(tab_id, label);
+            }
+        }
+        true
+    }
+
     pub fn add_tab(&mut self) -> u64 {
         let tab_id = self.next_id;
-        self.tabs.push(TabEntry {
-            id: tab_id,
-            label: format!("tab-{tab_id}"),
-        });
+        self.tabs
+            .push(TabEntry::new(tab_id, format!("tab-{tab_id}")));
         self.active_idx = self.tabs.len().saturating_sub(1);
         self.scroll_to_active = true;
         self.next_id += 1;
@@ -174,10 +257,7 @@ impl TabState {
 
     pub fn add_tab_with_label(&mut self, label: impl Into<String>) -> u64 {
         let tab_id = self.next_id;
-        self.tabs.push(TabEntry {
-            id: tab_id,
-            label: label.into(),
-        });
+        self.tabs.push(TabEntry::new(tab_id, label.into()));
         self.active_idx = self.tabs.len().saturating_sub(1);
         self.scroll_to_active = true;
         self.next_id += 1;
@@ -193,13 +273,33 @@ impl TabState {
 
     pub fn set_active_by_id(&mut self, tab_id: u64) -> bool {
         if let Some(idx) = self.tabs.iter().position(|tab| tab.id == tab_id) {
-            self.active_idx = idx;
-            self.scroll_to_active = true;
+            self.activate(idx);
             return true;
         }
         false
     }
 
+    fn activate(&mut self, idx: usize) {
+        self.active_idx = idx;
+        self.scroll_to_active = true;
+        if let Some(tab) = self.tabs.get_mut(idx) {
+            tab.attention = false;
+        }
+    }
+
+    /// Badges a background tab after its bell rang; selecting it clears the
+    /// badge. Returns whether the tab was marked.
+    pub fn mark_attention(&mut self, tab_id: u64) -> bool {
+        let active_id = self.active_id();
+        match self.tabs.iter_mut().find(|tab| tab.id == tab_id) {
+            Some(tab) if tab.id != active_id => {
+                tab.attention = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn set_tab_label(&mut self, tab_id: u64, label: impl Into<String>) -> bool {
         if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id) {
             tab.label = label.into();
@@ -208,13 +308,53 @@ impl TabState {
         false
     }
 
+    /// Sets the label and stops shell titles from replacing it.
+    pub fn pin_tab_label(&mut self, tab_id: u64, label: impl Into<String>) -> bool {
+        if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id) {
+            tab.label = label.into();
+            tab.pinned = true;
+            return true;
+        }
+        false
+    }
+
+    pub fn unpin_tab_label(&mut self, tab_id: u64) {
+        if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id) {
+            tab.pinned = false;
+        }
+    }
+
+    pub fn is_label_pinned(&self, tab_id: u64) -> bool {
+        self.tabs.iter().any(|tab| tab.id == tab_id && tab.pinned)
+    }
+
+    /// Records the title the shell or running program reported for the tab.
+    pub fn set_shell_title(&mut self, tab_id: u64, title: Option<&str>) {
+        if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id)
+            && tab.title.as_deref() != title
+        {
+            tab.title = title.map(str::to_owned);
+        }
+    }
+
+    pub fn set_tab_progress(&mut self, tab_id: u64, progress: Option<Progress>) {
+        if let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id) {
+            tab.progress = progress;
+        }
+    }
+
+    pub fn active_progress(&self) -> Option<Progress> {
+        self.tabs.get(self.active_idx).and_then(|tab| tab.progress)
+    }
+
     pub fn active_label(&self) -> &str {
         self.tabs
             .get(self.active_idx)
-            .map(|tab| tab.label.as_str())
+            .map(TabEntry::display_label)
             .unwrap_or("main")
     }
 
+    /// Tab ids with their base labels, as saved in workspace snapshots.
     pub fn entries(&self) -> Vec<(u64, String)> {
         self.tabs
             .iter()
@@ -223,11 +363,9 @@ impl TabState {
     }
 
     pub fn replace_tabs(&mut self, entries: Vec<(u64, String)>, active_id: u64) {
+        self.renaming = None;
         if entries.is_empty() {
-            self.tabs = vec![TabEntry {
-                id: 0,
-                label: "main".to_owned(),
-            }];
+            self.tabs = vec![TabEntry::new(0, "main".to_owned())];
             self.active_idx = 0;
             self.next_id = 1;
             self.dragging_tab_id = None;
@@ -237,7 +375,7 @@ impl TabState {
 
         self.tabs = entries
             .into_iter()
-            .map(|(id, label)| TabEntry { id, label })
+            .map(|(id, label)| TabEntry::new(id, label))
             .collect();
 
         self.active_idx = self
@@ -298,6 +436,38 @@ impl TabState {
     }
 }
 
+/// Thin bar along the bottom of `rect`; indeterminate progress sweeps.
+pub fn paint_progress(ui: &egui::Ui, rect: egui::Rect, progress: Progress) {
+    let track = egui::Rect::from_min_max(
+        egui::pos2(rect.left(), rect.bottom() - PROGRESS_BAR_HEIGHT),
+        rect.right_bottom(),
+    );
+    let color = match progress {
+        Progress::Normal(_) | Progress::Indeterminate => theme::ACCENT_BLUE,
+        Progress::Error(_) => theme::ERROR,
+        Progress::Paused(_) => theme::WARNING,
+    };
+    let painter = ui.painter();
+    painter.rect_filled(track, 0.0, theme::BG_SURFACE_2);
+    let filled = match progress.percent() {
+        Some(percent) => egui::Rect::from_min_size(
+            track.min,
+            egui::vec2(track.width() * f32::from(percent) / 100.0, track.height()),
+        ),
+        None => {
+            let time = ui.input(|i| i.time) as f32;
+            let segment = track.width() * 0.3;
+            let offset = (time * 0.8).fract() * (track.width() + segment) - segment;
+            ui.ctx().request_repaint();
+            egui::Rect::from_min_size(
+                egui::pos2(track.left() + offset, track.top()),
+                egui::vec2(segment, track.height()),
+            )
+        }
+    };
+    painter.rect_filled(filled.intersect(track), 0.0, color);
+}
+
 #[cfg(test)]
 mod tests {
     use super::TabState;
@@ -347,4 +517,33 @@ mod tests {
             vec![0, tab2, tab3, tab1]
         );
     }
+
+    #[test]
+    fn shell_titles_show_until_the_user_pins_a_label() {
+        let mut tabs = TabState::default();
+        tabs.set_shell_title(0, Some("vim notes.md"));
+        assert_eq!(tabs.active_label(), "vim notes.md");
+        assert_eq!(tabs.entries()[0].1, "main");
+
+        assert!(tabs.pin_tab_label(0, "notes"));
+        tabs.set_shell_title(0, Some("bash"));
+        assert_eq!(tabs.active_label(), "notes");
+
+        tabs.unpin_tab_label(0);
+        assert_eq!(tabs.active_label(), "bash");
+        tabs.set_shell_title(0, None);
+        assert_eq!(tabs.active_label(), "notes");
+    }
+
+    #[test]
+    fn bell_badges_background_tabs_until_selected() {
+        let mut tabs = TabState::default();
+        let tab1 = tabs.add_tab();
+        assert!(!tabs.mark_attention(tab1));
+        assert!(tabs.mark_attention(0));
+        assert!(tabs.tabs[0].attention);
+
+        assert!(tabs.set_active_by_id(0));
+        assert!(!tabs.tabs[0].attention);
+    }
 }
@@ -1,4 +1,5 @@
 use eframe::egui;
+use render::text_renderer::TerminalPalette;
 use std::fs;
 
 const NERD_FONT_NAME: &str = "symbols-nerd-font-mono";
@@ -20,6 +21,11 @@ pub const ACCENT_BLUE_SOFT: egui::Color32 = egui::Color32::from_rgb(89, 58, 32);
 pub const SUCCESS: egui::Color32 = egui::Color32::from_rgb(106, 153, 85);
 pub const WARNING: egui::Color32 = egui::Color32::from_rgb(206, 145, 120);
 pub const ERROR: egui::Color32 = egui::Color32::from_rgb(244, 135, 113);
+pub const TERMINAL_PALETTE: TerminalPalette = TerminalPalette {
+    foreground: TEXT_PRIMARY,
+    background: BG_APP,
+    ..TerminalPalette::DEFAULT
+};
 
 pub fn apply(ctx: &egui::Context) {
     apply_fonts(ctx);
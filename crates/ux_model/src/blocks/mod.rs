@@ -1,17 +1,114 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
+use terminal_core::scrollback::{ScrollbackBuffer, ScrollbackConfig};
+use terminal_core::style::{LinkRange, StyledLine, StyledRange};
+
+// Output lines saved per block. The session is autosaved every few seconds,
+// so older lines, which may be compressed or spilled, are not written out.
+pub const PERSISTED_OUTPUT_LINES: usize = 2_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "CommandBlockRecord", into = "CommandBlockRecord")]
 pub struct CommandBlock {
     pub id: u64,
     pub command: String,
-    pub output_lines: Vec<String>,
+    pub output: ScrollbackBuffer,
     pub bookmarked: bool,
     pub exit_code: Option<i32>,
     pub duration_ms: Option<u64>,
     pub working_directory: String,
     pub timestamp_unix_ms: u64,
+    pub fullscreen: Option<FullscreenSession>,
+}
+
+// On-disk shape of a block, with the newest output lines written out.
+#[derive(Serialize, Deserialize)]
+struct CommandBlockRecord {
+    id: u64,
+    command: String,
+    output_lines: Vec<String>,
+    // Lines before `output_lines` that were not saved.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    omitted_lines: usize,
+    // Parallel to `output_lines`; missing entries render unstyled.
+    #[serde(default)]
+    output_styles: Vec<Vec<StyledRange>>,
+    // Parallel to `output_lines` as well, and omitted when no line has links.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    output_links: Vec<Vec<LinkRange>>,
+    bookmarked: bool,
+    exit_code: Option<i32>,
+    duration_ms: Option<u64>,
+    working_directory: String,
+    timestamp_unix_ms: u64,
+    #[serde(default)]
+    fullscreen: Option<FullscreenSession>,
+}
+
+impl From<CommandBlockRecord> for CommandBlock {
+    fn from(record: CommandBlockRecord) -> Self {
+        let mut styles = record.output_styles.into_iter();
+        let mut links = record.output_links.into_iter();
+        let mut output = ScrollbackBuffer::default().with_dropped_lines(record.omitted_lines);
+        output.extend(record.output_lines.into_iter().map(|text| StyledLine {
+            text,
+            ranges: styles.next().unwrap_or_default(),
+            links: links.next().unwrap_or_default(),
+            // Inline images are not saved with the session.
+            images: Vec::new(),
+        }));
+        Self {
+            id: record.id,
+            command: record.command,
+            output,
+            bookmarked: record.bookmarked,
+            exit_code: record.exit_code,
+            duration_ms: record.duration_ms,
+            working_directory: record.working_directory,
+            timestamp_unix_ms: record.timestamp_unix_ms,
+            fullscreen: record.fullscreen,
+        }
+    }
+}
+
+impl From<CommandBlock> for CommandBlockRecord {
+    fn from(block: CommandBlock) -> Self {
+        let mut output_lines = Vec::new();
+        let mut output_styles = Vec::new();
+        let mut output_links = Vec::new();
+        let saved = block.output.tail(PERSISTED_OUTPUT_LINES);
+        let omitted_lines = block.output.dropped_lines() + (block.output.len() - saved.len());
+        for line in saved {
+            output_lines.push(line.text);
+            output_styles.push(line.ranges);
+            output_links.push(line.links);
+        }
+        if output_links.iter().all(Vec::is_empty) {
+            output_links.clear();
+        }
+        Self {
+            id: block.id,
+            command: block.command,
+            output_lines,
+            omitted_lines,
+            output_styles,
+            output_links,
+            bookmarked: block.bookmarked,
+            exit_code: block.exit_code,
+            duration_ms: block.duration_ms,
+            working_directory: block.working_directory,
+            timestamp_unix_ms: block.timestamp_unix_ms,
+            fullscreen: block.fullscreen,
+        }
+    }
+}
+
+/// Summary of an alternate-screen program run, kept instead of its output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FullscreenSession {
+    pub duration_ms: u64,
+    pub last_screen: Vec<String>,
 }
 
 impl CommandBlock {
@@ -19,18 +116,49 @@ impl CommandBlock {
         Self {
             id,
             command,
-            output_lines: Vec::new(),
+            output: ScrollbackBuffer::default(),
             bookmarked: false,
             exit_code: None,
             duration_ms: None,
             working_directory,
             timestamp_unix_ms: unix_ms_now(),
+            fullscreen: None,
         }
     }
 
+    pub fn with_scrollback(mut self, config: ScrollbackConfig) -> Self {
+        self.output.set_config(config);
+        self
+    }
+
     pub fn append_output(&mut self, lines: &[String]) {
-        self.output_lines.extend(lines.iter().cloned());
+        self.output
+            .extend(lines.iter().map(|line| StyledLine::plain(line.as_str())));
+    }
+
+    pub fn append_styled_output(&mut self, lines: Vec<StyledLine>) {
+        self.output.extend(lines);
     }
+
+    pub fn line_styles(&self, idx: usize) -> Vec<StyledRange> {
+        self.output
+            .line(idx)
+            .map(|line| line.ranges)
+            .unwrap_or_default()
+    }
+
+    pub fn output_text(&self) -> impl Iterator<Item = String> + '_ {
+        self.output.iter().map(|line| line.text)
+    }
+
+    pub fn output_contains(&self, query_lowercase: &str) -> bool {
+        self.output
+            .any_text(|text| text.to_lowercase().contains(query_lowercase))
+    }
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
 }
 
 fn unix_ms_now() -> u64 {
@@ -19,8 +19,8 @@ pub fn blocks_to_markdown(blocks: &[CommandBlock], pending_line: &str) -> String
         out.push_str(&format!("- Bookmarked: `{}`\n\n", block.bookmarked));
 
         out.push_str("```text\n");
-        for line in &block.output_lines {
-            out.push_str(line);
+        for line in block.output_text() {
+            out.push_str(&line);
             out.push('\n');
         }
         out.push_str("```\n\n");
@@ -47,7 +47,7 @@ mod tests {
     fn export_contains_block_content() {
         let mut block = CommandBlock::new(7, "echo hi".to_owned(), "D:\\repo".to_owned());
         block.bookmarked = true;
-        block.output_lines.push("hi".to_owned());
+        block.append_output(&["hi".to_owned()]);
 
         let markdown = blocks_to_markdown(&[block], "pending");
         assert!(markdown.contains("Block #7"));
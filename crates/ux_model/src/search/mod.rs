@@ -1,5 +1,15 @@
+use std::collections::BTreeSet;
+
+use crate::ai::AiBlock;
 use crate::blocks::CommandBlock;
 
+/// Ids of the timeline blocks whose text contains a query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimelineMatches {
+    pub command_ids: BTreeSet<u64>,
+    pub ai_ids: BTreeSet<u64>,
+}
+
 pub fn search_blocks(blocks: &[CommandBlock], query: &str) -> Vec<u64> {
     if query.trim().is_empty() {
         return Vec::new();
@@ -9,7 +19,22 @@ pub fn search_blocks(blocks: &[CommandBlock], query: &str) -> Vec<u64> {
     blocks
         .iter()
         .filter(|block| {
-            block.command.to_lowercase().contains(&query)
+            block.command.to_lowercase().contains(&query) || block.output_contains(&query)
+        })
+        .map(|block| block.id)
+        .collect()
+}
+
+pub fn search_ai_blocks(blocks: &[AiBlock], query: &str) -> Vec<u64> {
+    if query.trim().is_empty() {
+        return Vec::new();
+    }
+
+    let query = query.to_lowercase();
+    blocks
+        .iter()
+        .filter(|block| {
+            block.prompt.to_lowercase().contains(&query)
                 || block
                     .output_lines
                     .iter()
@@ -1,9 +1,11 @@
 use crate::ai::{AiBlock, AiTool};
-use crate::blocks::CommandBlock;
+use crate::blocks::{CommandBlock, FullscreenSession};
 use crate::export::blocks_to_markdown;
 use crate::history::CommandHistory;
-use crate::search::search_blocks;
+use crate::search::{TimelineMatches, search_ai_blocks, search_blocks};
 use serde::{Deserialize, Serialize};
+use terminal_core::scrollback::ScrollbackConfig;
+use terminal_core::style::StyledLine;
 
 #[derive(Debug, Clone)]
 pub struct SessionState {
@@ -13,6 +15,9 @@ pub struct SessionState {
     next_block_id: u64,
     next_ai_block_id: u64,
     pending_line: String,
+    scrollback: ScrollbackConfig,
+    // Bumped whenever block text changes, so searches can be cached.
+    generation: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -40,14 +45,64 @@ impl SessionState {
             next_block_id: 0,
             next_ai_block_id: 1,
             pending_line: String::new(),
+            scrollback: ScrollbackConfig::default(),
+            generation: 0,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Applies `config` to every block. Its budgets cover the output of all
+    /// blocks together.
+    pub fn set_scrollback_config(&mut self, config: ScrollbackConfig) {
+        for block in &mut self.blocks {
+            block.output.set_config(config.clone());
+        }
+        self.scrollback = config;
+        self.enforce_scrollback_budget();
+        self.generation += 1;
+    }
+
+    /// Shrinks the oldest blocks first until the output of all blocks fits
+    /// the session's memory and disk budgets.
+    fn enforce_scrollback_budget(&mut self) {
+        let memory_budget = self.scrollback.memory_budget;
+        let mut memory: usize = self.blocks.iter().map(|b| b.output.memory_usage()).sum();
+        for block in &mut self.blocks {
+            if memory <= memory_budget {
+                break;
+            }
+            let own = block.output.memory_usage();
+            block
+                .output
+                .shrink_memory_to(own.saturating_sub(memory - memory_budget));
+            memory = memory - own + block.output.memory_usage();
+        }
+
+        let disk_budget = self.scrollback.disk_budget;
+        let mut disk: u64 = self.blocks.iter().map(|b| b.output.disk_usage()).sum();
+        for block in &mut self.blocks {
+            if disk <= disk_budget {
+                break;
+            }
+            let own = block.output.disk_usage();
+            block
+                .output
+                .shrink_disk_to(own.saturating_sub(disk - disk_budget));
+            disk = disk - own + block.output.disk_usage();
         }
     }
 
     pub fn start_command_block(&mut self, command: String, cwd: String) {
         self.history.push(command.clone());
-        self.blocks
-            .push(CommandBlock::new(self.next_block_id, command, cwd));
+        self.blocks.push(
+            CommandBlock::new(self.next_block_id, command, cwd)
+                .with_scrollback(self.scrollback.clone()),
+        );
         self.next_block_id += 1;
+        self.generation += 1;
     }
 
     pub fn push_output_lines(&mut self, lines: Vec<String>) {
@@ -57,6 +112,20 @@ impl SessionState {
 
         if let Some(last_block) = self.blocks.last_mut() {
             last_block.append_output(&lines);
+            self.enforce_scrollback_budget();
+            self.generation += 1;
+        }
+    }
+
+    pub fn push_styled_output_lines(&mut self, lines: Vec<StyledLine>) {
+        if lines.is_empty() {
+            return;
+        }
+
+        if let Some(last_block) = self.blocks.last_mut() {
+            last_block.append_styled_output(lines);
+            self.enforce_scrollback_budget();
+            self.generation += 1;
         }
     }
 
@@ -71,7 +140,7 @@ impl SessionState {
             if !block.command.is_empty() {
                 all_lines.push(format!("$ {}", block.command));
             }
-            all_lines.extend(block.output_lines.iter().cloned());
+            all_lines.extend(block.output_text());
         }
 
         if !self.pending_line.is_empty() {
@@ -119,6 +188,17 @@ impl SessionState {
         ids
     }
 
+    /// Searches command text and output, AI prompts and AI output of the
+    /// live blocks, reusing their page caches.
+    pub fn search_timeline(&self, query: &str) -> TimelineMatches {
+        TimelineMatches {
+            command_ids: search_blocks(&self.blocks, query).into_iter().collect(),
+            ai_ids: search_ai_blocks(&self.ai_blocks, query)
+                .into_iter()
+                .collect(),
+        }
+    }
+
     pub fn toggle_bookmark(&mut self, block_id: u64) -> Option<bool> {
         let block = self.blocks.iter_mut().find(|b| b.id == block_id)?;
         block.bookmarked = !block.bookmarked;
@@ -128,12 +208,14 @@ impl SessionState {
     pub fn remove_command_block(&mut self, block_id: u64) -> bool {
         let original_len = self.blocks.len();
         self.blocks.retain(|block| block.id != block_id);
+        self.generation += 1;
         self.blocks.len() != original_len
     }
 
     pub fn remove_ai_block(&mut self, ai_block_id: u64) -> bool {
         let original_len = self.ai_blocks.len();
         self.ai_blocks.retain(|block| block.id != ai_block_id);
+        self.generation += 1;
         self.ai_blocks.len() != original_len
     }
 
@@ -141,6 +223,7 @@ impl SessionState {
         self.blocks.clear();
         self.ai_blocks.clear();
         self.pending_line.clear();
+        self.generation += 1;
     }
 
     pub fn bookmarked_count(&self) -> usize {
@@ -157,6 +240,7 @@ impl SessionState {
         self.ai_blocks
             .push(AiBlock::new(ai_id, tool, prompt, context_block_ids));
         self.next_ai_block_id += 1;
+        self.generation += 1;
         ai_id
     }
 
@@ -170,9 +254,65 @@ impl SessionState {
         };
 
         block.append_output_lines(lines);
+        self.generation += 1;
         true
     }
 
+    pub fn complete_last_command_block(
+        &mut self,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+    ) -> bool {
+        let Some(block) = self.blocks.last_mut() else {
+            return false;
+        };
+        if block.exit_code.is_some() || block.duration_ms.is_some() {
+            return false;
+        }
+
+        block.exit_code = exit_code;
+        block.duration_ms = Some(duration_ms);
+        true
+    }
+
+    /// Attaches a finished full-screen session to the running command block,
+    /// or records it as its own block when no command is waiting for it.
+    pub fn record_fullscreen_session(
+        &mut self,
+        cwd: String,
+        duration_ms: u64,
+        mut last_screen: Vec<String>,
+    ) -> u64 {
+        while last_screen
+            .last()
+            .is_some_and(|line| line.trim().is_empty())
+        {
+            last_screen.pop();
+        }
+        let session = FullscreenSession {
+            duration_ms,
+            last_screen,
+        };
+
+        self.generation += 1;
+        if let Some(block) = self.blocks.last_mut()
+            && block.exit_code.is_none()
+            && block.duration_ms.is_none()
+            && block.fullscreen.is_none()
+        {
+            block.fullscreen = Some(session);
+            return block.id;
+        }
+
+        let id = self.next_block_id;
+        let mut block = CommandBlock::new(id, "(full-screen session)".to_owned(), cwd)
+            .with_scrollback(self.scrollback.clone());
+        block.fullscreen = Some(session);
+        self.blocks.push(block);
+        self.next_block_id += 1;
+        id
+    }
+
     pub fn complete_ai_block(
         &mut self,
         ai_block_id: u64,
@@ -201,6 +341,7 @@ impl SessionState {
         };
 
         block.fail(message, duration_ms);
+        self.generation += 1;
         true
     }
 
@@ -224,10 +365,9 @@ impl SessionState {
                 out.push_str(&format!("CWD: {}\n", block.working_directory));
                 out.push_str("Output:\n");
 
-                let start = block.output_lines.len().saturating_sub(max_lines_per_block);
-                for line in &block.output_lines[start..] {
+                for line in block.output.tail(max_lines_per_block) {
                     out.push_str("  ");
-                    out.push_str(line);
+                    out.push_str(&line.text);
                     out.push('\n');
                 }
                 out.push('\n');
@@ -288,7 +428,7 @@ impl SessionState {
         if blocks.first().is_some_and(|block| {
             block.id == 0
                 && block.command == "<shell-session>"
-                && block.output_lines.is_empty()
+                && block.output.is_empty()
                 && !block.bookmarked
         }) {
             blocks.remove(0);
@@ -301,6 +441,8 @@ impl SessionState {
             next_block_id: snapshot.next_block_id,
             next_ai_block_id: snapshot.next_ai_block_id,
             pending_line: snapshot.pending_line,
+            scrollback: ScrollbackConfig::default(),
+            generation: 0,
         }
     }
 }
@@ -308,10 +450,74 @@ impl SessionState {
 #[cfg(test)]
 mod tests {
     use crate::ai::{AiBlockStatus, AiTool};
-    use crate::blocks::CommandBlock;
+    use crate::blocks::{CommandBlock, PERSISTED_OUTPUT_LINES};
+    use terminal_core::scrollback::ScrollbackConfig;
+    use terminal_core::style::{CellAttrs, Color, StyledLine};
 
     use super::SessionState;
 
+    #[test]
+    fn completing_last_command_block_only_applies_once() {
+        let mut session = SessionState::new("/repo".to_owned());
+        assert!(!session.complete_last_command_block(Some(0), 5));
+
+        session.start_command_block("false".to_owned(), "/repo".to_owned());
+        assert!(session.complete_last_command_block(Some(1), 12));
+        assert!(!session.complete_last_command_block(Some(0), 40));
+
+        let block = &session.blocks()[0];
+        assert_eq!(block.exit_code, Some(1));
+        assert_eq!(block.duration_ms, Some(12));
+    }
+
+    #[test]
+    fn fullscreen_session_attaches_to_running_block_once() {
+        let mut session = SessionState::new("/repo".to_owned());
+        session.start_command_block("vim notes.md".to_owned(), "/repo".to_owned());
+        let screen = vec!["# notes".to_owned(), "~".to_owned(), "   ".to_owned()];
+        assert_eq!(
+            session.record_fullscreen_session("/repo".to_owned(), 900, screen.clone()),
+            0
+        );
+        let fullscreen = session.blocks()[0].fullscreen.as_ref().unwrap();
+        assert_eq!(fullscreen.last_screen, vec!["# notes", "~"]);
+
+        // A second run with nothing waiting becomes its own block.
+        assert_eq!(
+            session.record_fullscreen_session("/repo".to_owned(), 10, screen),
+            1
+        );
+        assert_eq!(session.blocks()[1].command, "(full-screen session)");
+    }
+
+    #[test]
+    fn styled_output_stays_aligned_with_plain_lines() {
+        let mut session = SessionState::new("D:\\repo".to_owned());
+        session.start_command_block("cargo build".to_owned(), "D:\\repo".to_owned());
+        session.push_output_lines(vec!["Compiling".to_owned()]);
+
+        let mut error = StyledLine::default();
+        for ch in "error".chars() {
+            error.push(
+                ch,
+                CellAttrs {
+                    fg: Color::Indexed(1),
+                    ..CellAttrs::default()
+                },
+            );
+        }
+        session.push_styled_output_lines(vec![error, StyledLine::plain("done")]);
+
+        let block = &session.blocks()[0];
+        assert_eq!(
+            block.output_text().collect::<Vec<_>>(),
+            vec!["Compiling", "error", "done"]
+        );
+        assert!(block.line_styles(0).is_empty());
+        assert_eq!(block.line_styles(1)[0].attrs.fg, Color::Indexed(1));
+        assert!(block.line_styles(2).is_empty());
+    }
+
     #[test]
     fn bookmark_toggle_changes_state() {
         let mut session = SessionState::new("D:\\repo".to_owned());
@@ -399,6 +605,26 @@ mod tests {
         assert_eq!(restored.pending_line(), session.pending_line());
     }
 
+    #[test]
+    fn snapshot_keeps_only_the_newest_output_lines() {
+        let mut session = SessionState::new("D:\\repo".to_owned());
+        session.start_command_block("seq".to_owned(), "D:\\repo".to_owned());
+        let total = PERSISTED_OUTPUT_LINES + 500;
+        session.push_output_lines((0..total).map(|idx| idx.to_string()).collect());
+
+        let json = serde_json::to_string(&session.to_snapshot()).expect("snapshot serializes");
+        let restored = SessionState::from_snapshot(serde_json::from_str(&json).expect("parses"));
+        let output = &restored.blocks()[0].output;
+        assert_eq!(output.len(), PERSISTED_OUTPUT_LINES);
+        assert_eq!(output.dropped_lines(), 500);
+        assert_eq!(output.line(0).map(|line| line.text), Some("500".to_owned()));
+
+        // Saving the restored block again keeps counting the lost lines.
+        let json = serde_json::to_string(&restored.to_snapshot()).expect("snapshot serializes");
+        let again = SessionState::from_snapshot(serde_json::from_str(&json).expect("parses"));
+        assert_eq!(again.blocks()[0].output.dropped_lines(), 500);
+    }
+
     #[test]
     fn snapshot_restore_drops_legacy_shell_session_placeholder() {
         let mut session = SessionState::new("D:\\repo".to_owned());
@@ -444,4 +670,51 @@ mod tests {
         assert_eq!(session.ai_block_count(), 0);
         assert!(session.pending_line().is_empty());
     }
+
+    #[test]
+    fn timeline_search_matches_live_blocks_and_tracks_changes() {
+        let mut session = SessionState::new("/repo".to_owned());
+        session.start_command_block("cargo test".to_owned(), "/repo".to_owned());
+        session.push_output_lines(vec!["test result: FAILED".to_owned()]);
+        session.start_command_block("ls".to_owned(), "/repo".to_owned());
+        let ai_id = session.start_ai_block(AiTool::CodexCli, "why failed".to_owned(), vec![0]);
+
+        let matches = session.search_timeline("failed");
+        assert_eq!(matches.command_ids.into_iter().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(matches.ai_ids.into_iter().collect::<Vec<_>>(), vec![ai_id]);
+
+        let generation = session.generation();
+        session.set_pending_line("typing".to_owned());
+        session.toggle_bookmark(0);
+        assert_eq!(session.generation(), generation);
+        session.push_output_lines(vec!["failed too".to_owned()]);
+        assert_ne!(session.generation(), generation);
+        assert!(session.search_timeline("failed").command_ids.contains(&1));
+    }
+
+    #[test]
+    fn scrollback_budget_covers_all_blocks_and_evicts_the_oldest_first() {
+        let mut session = SessionState::new("/repo".to_owned());
+        session.set_scrollback_config(ScrollbackConfig {
+            memory_budget: 16 * 1024,
+            disk_budget: 0,
+            page_lines: 8,
+            spill_dir: None,
+        });
+        for block in 0..4 {
+            session.start_command_block(format!("seq {block}"), "/repo".to_owned());
+            session.push_output_lines((0..200).map(|line| format!("{block}:{line}")).collect());
+        }
+
+        let memory: usize = session
+            .blocks()
+            .iter()
+            .map(|block| block.output.memory_usage())
+            .sum();
+        assert!(memory <= 16 * 1024);
+        assert!(session.blocks()[0].output.dropped_lines() > 0);
+        let newest = &session.blocks()[3].output;
+        assert_eq!(newest.dropped_lines(), 0);
+        assert_eq!(newest.tail(1)[0].text, "3:199");
+    }
 }